@@ -0,0 +1,136 @@
+use crate::config::Config;
+use crate::detect;
+use crate::git;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// How a repository's applied identity compares to its detected profile
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// user.name and user.email are the detected profile's
+    Ok,
+    /// The applied identity is not the detected profile's
+    Mismatch,
+    /// No profile matches the repository
+    Undetected,
+}
+
+impl std::fmt::Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Status::Ok => write!(f, "ok"),
+            Status::Mismatch => write!(f, "mismatch"),
+            Status::Undetected => write!(f, "undetected"),
+        }
+    }
+}
+
+/// Audit outcome for one repository
+#[derive(Debug)]
+pub struct AuditEntry {
+    pub repo: PathBuf,
+    /// Profile detection picked for the repository
+    pub expected: Option<String>,
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub status: Status,
+}
+
+/// Git repositories under `root`. Repositories are not descended into, so
+/// nested checkouts and submodules are skipped, as are hidden directories
+/// and symlinks.
+pub fn find_repos(root: &Path) -> Result<Vec<PathBuf>> {
+    let mut repos = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        if dir.join(".git").exists() {
+            repos.push(dir);
+            continue;
+        }
+
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            // Unreadable directories are skipped rather than failing the scan
+            Err(_) if dir != root => continue,
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read {}", dir.display()));
+            }
+        };
+        for entry in entries.flatten() {
+            let hidden = entry.file_name().to_string_lossy().starts_with('.');
+            if !hidden && entry.file_type().is_ok_and(|t| t.is_dir()) {
+                pending.push(entry.path());
+            }
+        }
+    }
+
+    repos.sort();
+    Ok(repos)
+}
+
+/// Compare an applied identity with the expected profile's
+pub fn compare(config: &Config, expected: Option<&str>, name: Option<&str>, email: Option<&str>) -> Status {
+    let Some(profile) = expected.and_then(|e| config.get_profile(e)) else {
+        return Status::Undetected;
+    };
+
+    let email_ok = email.is_some_and(|e| e.eq_ignore_ascii_case(&profile.email));
+    if email_ok && name == Some(profile.name.as_str()) {
+        Status::Ok
+    } else {
+        Status::Mismatch
+    }
+}
+
+/// Detect the profile for a repository and compare it with the identity
+/// git resolves there. `config` is the user config (`Config::load_user`);
+/// the repository's own project config is layered over it. Changes the
+/// working directory to the repository.
+pub fn audit_repo(config: &Config, repo: &Path) -> Result<AuditEntry> {
+    std::env::set_current_dir(repo)
+        .with_context(|| format!("Failed to enter {}", repo.display()))?;
+
+    let config = &config.clone().with_project(repo)?;
+    let expected = detect::detect_profile(config)?.map(|r| r.profile_name);
+    let name = git::get_effective_config("user.name")?;
+    let email = git::get_effective_config("user.email")?;
+    let status = compare(config, expected.as_deref(), name.as_deref(), email.as_deref());
+
+    Ok(AuditEntry {
+        repo: repo.to_path_buf(),
+        expected,
+        name,
+        email,
+        status,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::{Platform, Profile};
+
+    #[test]
+    fn test_compare() {
+        let mut config = Config::default();
+        config.profiles.insert(
+            "work".to_string(),
+            Profile::new(
+                "John Doe".to_string(),
+                "john@acme.com".to_string(),
+                Platform::Github,
+                "~/.ssh/id_work".to_string(),
+                None,
+                None,
+            ),
+        );
+
+        let work = Some("work");
+        assert_eq!(compare(&config, work, Some("John Doe"), Some("John@Acme.com")), Status::Ok);
+        assert_eq!(compare(&config, work, Some("Johnny"), Some("john@acme.com")), Status::Mismatch);
+        assert_eq!(compare(&config, work, Some("John Doe"), None), Status::Mismatch);
+        assert_eq!(compare(&config, None, Some("John Doe"), Some("john@acme.com")), Status::Undetected);
+    }
+}
@@ -0,0 +1,63 @@
+use anyhow::{Context, Result, bail};
+use std::process::{Command, Stdio};
+
+/// Check if tea CLI is installed
+fn is_tea_installed() -> bool {
+    Command::new("tea")
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(false)
+}
+
+fn ensure_tea() -> Result<()> {
+    if !is_tea_installed() {
+        bail!("Gitea CLI (tea) is not installed. Install it from https://gitea.com/gitea/tea");
+    }
+    Ok(())
+}
+
+/// Authenticate tea interactively. tea only prompts when given no flags,
+/// so the URL to enter is printed beforehand.
+pub fn authenticate(host: &str) -> Result<()> {
+    ensure_tea()?;
+
+    println!("Enter https://{} as the instance URL.", host);
+    let status = Command::new("tea")
+        .args(["login", "add"])
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .status()
+        .context("Failed to run tea login add")?;
+
+    if !status.success() {
+        bail!("Gitea authentication failed");
+    }
+
+    Ok(())
+}
+
+/// Authenticate tea with an access token under the profile's name
+pub fn authenticate_with_token(login_name: &str, host: &str, token: &str) -> Result<()> {
+    ensure_tea()?;
+
+    let output = Command::new("tea")
+        .args(["login", "add", "--name", login_name, "--url"])
+        .arg(format!("https://{}", host))
+        .arg("--token")
+        .arg(token)
+        .output()
+        .context("Failed to run tea login add")?;
+
+    if !output.status.success() {
+        bail!(
+            "Gitea authentication failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(())
+}
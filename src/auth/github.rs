@@ -1,8 +1,12 @@
 use anyhow::{Context, Result, bail};
+use serde_json::json;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
 use std::process::{Command, Stdio};
 
 /// Check if gh CLI is installed
-fn is_gh_installed() -> bool {
+pub fn is_gh_installed() -> bool {
     Command::new("gh")
         .arg("--version")
         .stdout(Stdio::null())
@@ -44,3 +48,259 @@ pub fn authenticate(host: Option<&str>) -> Result<()> {
 
     Ok(())
 }
+
+/// Authenticate GitHub CLI with a token, without prompting
+pub fn authenticate_with_token(host: Option<&str>, token: &str) -> Result<()> {
+    if !is_gh_installed() {
+        bail!("GitHub CLI (gh) is not installed. Install it from https://cli.github.com/");
+    }
+
+    let mut cmd = Command::new("gh");
+    cmd.arg("auth").arg("login").arg("--with-token");
+
+    if let Some(h) = host {
+        if h != "github.com" {
+            cmd.arg("--hostname").arg(h);
+        }
+    }
+
+    cmd.arg("--git-protocol").arg("ssh");
+
+    let mut child = cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .context("Failed to run gh auth login")?;
+    child
+        .stdin
+        .take()
+        .context("Failed to open gh stdin")?
+        .write_all(token.as_bytes())?;
+
+    if !child.wait()?.success() {
+        bail!("GitHub authentication failed");
+    }
+
+    Ok(())
+}
+
+/// Run gh against a host, which gh takes from GH_HOST outside `gh auth`.
+/// A token overrides whichever account gh is signed in as.
+fn gh(host: Option<&str>, token: Option<&str>) -> Command {
+    let mut cmd = Command::new("gh");
+    let enterprise = host.filter(|h| *h != "github.com");
+    if let Some(h) = enterprise {
+        cmd.env("GH_HOST", h);
+    }
+    if let Some(token) = token {
+        let var = if enterprise.is_some() { "GH_ENTERPRISE_TOKEN" } else { "GH_TOKEN" };
+        cmd.env(var, token);
+    }
+    cmd
+}
+
+/// Whether gh has a stored login for the host
+pub fn is_authenticated(host: Option<&str>) -> bool {
+    Command::new("gh")
+        .args(["auth", "status", "--hostname", host.unwrap_or("github.com")])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(false)
+}
+
+/// Login of the account gh is currently using (or `token` belongs to), if
+/// it is signed in
+pub fn active_user(host: Option<&str>, token: Option<&str>) -> Result<Option<String>> {
+    if !is_gh_installed() {
+        return Ok(None);
+    }
+
+    let output = gh(host, token)
+        .args(["api", "user", "--jq", ".login"])
+        .output()
+        .context("Failed to run gh api user")?;
+    let login = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    Ok(Some(login).filter(|l| output.status.success() && !l.is_empty()))
+}
+
+/// Run `gh api <endpoint>` and parse the response, or None if the call
+/// fails (signed out, bad token, or missing scope)
+fn api_json(host: Option<&str>, token: Option<&str>, endpoint: &str) -> Result<Option<serde_json::Value>> {
+    let output = gh(host, token)
+        .args(["api", endpoint])
+        .output()
+        .with_context(|| format!("Failed to run gh api {}", endpoint))?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    Ok(serde_json::from_slice(&output.stdout).ok())
+}
+
+/// Account gh is signed in as (or `token` belongs to), with the addresses
+/// the token may see
+pub fn account(host: Option<&str>, token: Option<&str>) -> Result<Option<super::Account>> {
+    if !is_gh_installed() {
+        bail!("GitHub CLI (gh) is not installed. Install it from https://cli.github.com/");
+    }
+
+    let Some(user) = api_json(host, token, "user")? else {
+        return Ok(None);
+    };
+    // Listing all addresses needs the user:email scope
+    let emails = api_json(host, token, "user/emails")?;
+    Ok(super::Account::from_api(&user, "login", &["email"], emails.as_ref()))
+}
+
+/// Make `user` gh's active account on the host
+pub fn switch_user(host: Option<&str>, user: &str) -> Result<()> {
+    if !is_gh_installed() {
+        bail!("GitHub CLI (gh) is not installed. Install it from https://cli.github.com/");
+    }
+
+    let output = Command::new("gh")
+        .args(["auth", "switch", "--hostname", host.unwrap_or("github.com"), "--user", user])
+        .output()
+        .context("Failed to run gh auth switch")?;
+    if !output.status.success() {
+        bail!(
+            "gh auth switch failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(())
+}
+
+/// REST API root for github.com or an Enterprise Server host
+pub fn api_base(host: Option<&str>) -> String {
+    match host {
+        Some(h) if h != "github.com" => format!("https://{}/api/v3", h),
+        _ => "https://api.github.com".to_string(),
+    }
+}
+
+/// Call the REST API through curl and parse the JSON response. The bearer
+/// token goes on stdin so it doesn't show up in the process list.
+pub fn api_request(
+    method: &str,
+    url: &str,
+    bearer: &str,
+    body: Option<&serde_json::Value>,
+) -> Result<serde_json::Value> {
+    let mut cmd = Command::new("curl");
+    cmd.args(["-sS", "-X", method, "-H", "@-"])
+        .args(["-H", "Accept: application/vnd.github+json"]);
+    if let Some(body) = body {
+        cmd.args(["-H", "Content-Type: application/json", "--data"])
+            .arg(body.to_string());
+    }
+
+    let mut child = cmd
+        .arg(url)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run curl. Is curl installed?")?;
+    child
+        .stdin
+        .take()
+        .context("Failed to open curl stdin")?
+        .write_all(format!("Authorization: Bearer {}\n", bearer).as_bytes())?;
+
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        bail!(
+            "Failed to reach {}: {}",
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    serde_json::from_slice(&output.stdout).context("Unexpected response from GitHub")
+}
+
+/// Human-readable reason from a REST error response, including the
+/// per-field validation errors GitHub puts under `errors`
+pub fn api_error(response: &serde_json::Value) -> String {
+    let message = response["message"].as_str().unwrap_or("no message");
+    let details: Vec<&str> = response["errors"]
+        .as_array()
+        .map(|errors| errors.iter().filter_map(|e| e["message"].as_str()).collect())
+        .unwrap_or_default();
+    if details.is_empty() {
+        message.to_string()
+    } else {
+        format!("{} ({})", message, details.join(", "))
+    }
+}
+
+/// Token gh holds for the host, if gh is installed and signed in
+pub fn auth_token(host: Option<&str>) -> Result<Option<String>> {
+    if !is_gh_installed() {
+        return Ok(None);
+    }
+
+    let output = Command::new("gh")
+        .args(["auth", "token", "--hostname", host.unwrap_or("github.com")])
+        .output()
+        .context("Failed to run gh auth token")?;
+    let token = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    Ok(Some(token).filter(|t| output.status.success() && !t.is_empty()))
+}
+
+/// Register a public key with a GitHub account through the REST API, using
+/// `token` or else the one gh is signed in with. GitHub keeps
+/// authentication and signing keys in separate lists, so a key used for
+/// both has to be added twice.
+pub fn upload_key(
+    host: Option<&str>,
+    token: Option<&str>,
+    public_key: &Path,
+    title: &str,
+    signing: bool,
+) -> Result<()> {
+    let token = match token {
+        Some(token) => token.to_string(),
+        None => auth_token(host)?.context(
+            "No GitHub token available. Store one with 'gitid token set' or sign in with 'gh auth login'",
+        )?,
+    };
+    let key = fs::read_to_string(public_key)
+        .with_context(|| format!("Failed to read public key: {}", public_key.display()))?;
+
+    let endpoint = if signing { "user/ssh_signing_keys" } else { "user/keys" };
+    let url = format!("{}/{}", api_base(host), endpoint);
+    let body = json!({ "title": title, "key": key.trim() });
+
+    let response = api_request("POST", &url, &token, Some(&body))?;
+    if response["id"].is_null() {
+        bail!("GitHub key upload failed: {}", api_error(&response));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_base() {
+        assert_eq!(api_base(None), "https://api.github.com");
+        assert_eq!(api_base(Some("github.company.com")), "https://github.company.com/api/v3");
+    }
+
+    #[test]
+    fn test_api_error() {
+        let response = json!({
+            "message": "Validation Failed",
+            "errors": [{ "resource": "PublicKey", "message": "key is already in use" }],
+        });
+        assert_eq!(api_error(&response), "Validation Failed (key is already in use)");
+        assert_eq!(api_error(&json!({ "message": "Bad credentials" })), "Bad credentials");
+    }
+}
@@ -0,0 +1,130 @@
+use super::github;
+use crate::journal;
+use crate::profile::{GithubApp, Profile};
+use crate::secrets;
+use crate::ssh_keys;
+use anyhow::{Context, Result, bail};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use serde_json::json;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Lifetime of the app JWT; GitHub rejects anything over ten minutes
+const JWT_LIFETIME: u64 = 9 * 60;
+
+/// Backdate the JWT so a slightly fast local clock isn't rejected
+const CLOCK_SKEW: u64 = 60;
+
+/// Installation tokens last an hour; treat them as expired a little early
+const TOKEN_LIFETIME: u64 = 55 * 60;
+
+/// Unsigned `header.payload` part of the app's JWT
+fn jwt_signing_input(app_id: u64, now: u64) -> String {
+    let header = json!({ "alg": "RS256", "typ": "JWT" });
+    let payload = json!({
+        "iat": now.saturating_sub(CLOCK_SKEW),
+        "exp": now + JWT_LIFETIME,
+        "iss": app_id.to_string(),
+    });
+    format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(header.to_string()),
+        URL_SAFE_NO_PAD.encode(payload.to_string())
+    )
+}
+
+/// Sign a JWT for the app with its private key (RS256, through openssl)
+fn app_jwt(app: &GithubApp, now: u64) -> Result<String> {
+    let key = ssh_keys::expand_path(&app.private_key);
+    if !key.exists() {
+        bail!("GitHub App private key not found: {}", key.display());
+    }
+
+    let input = jwt_signing_input(app.app_id, now);
+    let mut child = Command::new("openssl")
+        .args(["dgst", "-sha256", "-sign"])
+        .arg(&key)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run openssl. Is OpenSSL installed?")?;
+    child
+        .stdin
+        .take()
+        .context("Failed to open openssl stdin")?
+        .write_all(input.as_bytes())?;
+
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        bail!(
+            "Failed to sign GitHub App JWT: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(format!("{}.{}", input, URL_SAFE_NO_PAD.encode(&output.stdout)))
+}
+
+/// Exchange the app JWT for a fresh installation token
+fn mint_token(host: Option<&str>, app: &GithubApp, now: u64) -> Result<String> {
+    let jwt = app_jwt(app, now)?;
+    let url = format!(
+        "{}/app/installations/{}/access_tokens",
+        github::api_base(host),
+        app.installation_id
+    );
+
+    let response = github::api_request("POST", &url, &jwt, None)?;
+    match response["token"].as_str() {
+        Some(token) => Ok(token.to_string()),
+        None => bail!(
+            "GitHub refused to mint an installation token: {}",
+            github::api_error(&response)
+        ),
+    }
+}
+
+/// Installation token for a GitHub App profile, reusing the cached one
+/// until it is about to expire
+pub fn installation_token(profile_name: &str, profile: &Profile) -> Result<String> {
+    let app = profile
+        .github_app
+        .as_ref()
+        .with_context(|| format!("Profile '{}' has no github_app configured", profile_name))?;
+
+    let now = journal::now();
+    if let Ok(Some((expires, token))) = secrets::get_app_token(profile_name) {
+        if expires > now {
+            return Ok(token);
+        }
+    }
+
+    let token = mint_token(profile.host.as_deref(), app, now)?;
+
+    // Without a keyring every call just mints a new token
+    let _ = secrets::set_app_token(profile_name, now + TOKEN_LIFETIME, &token);
+    Ok(token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jwt_signing_input() {
+        let input = jwt_signing_input(123, 1_000);
+        let (header, payload) = input.split_once('.').unwrap();
+
+        let header: serde_json::Value =
+            serde_json::from_slice(&URL_SAFE_NO_PAD.decode(header).unwrap()).unwrap();
+        assert_eq!(header["alg"], "RS256");
+
+        let payload: serde_json::Value =
+            serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).unwrap()).unwrap();
+        assert_eq!(payload["iss"], "123");
+        assert_eq!(payload["iat"], 940);
+        assert_eq!(payload["exp"], 1_540);
+    }
+}
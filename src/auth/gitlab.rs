@@ -1,8 +1,11 @@
+use crate::journal;
 use anyhow::{Context, Result, bail};
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 
 /// Check if glab CLI is installed
-fn is_glab_installed() -> bool {
+pub fn is_glab_installed() -> bool {
     Command::new("glab")
         .arg("--version")
         .stdout(Stdio::null())
@@ -41,3 +44,194 @@ pub fn authenticate(host: Option<&str>) -> Result<()> {
 
     Ok(())
 }
+
+/// Authenticate GitLab CLI with a token, without prompting
+pub fn authenticate_with_token(host: Option<&str>, token: &str) -> Result<()> {
+    if !is_glab_installed() {
+        bail!("GitLab CLI (glab) is not installed. Install it from https://gitlab.com/gitlab-org/cli");
+    }
+
+    let mut cmd = Command::new("glab");
+    cmd.arg("auth").arg("login").arg("--stdin");
+
+    if let Some(h) = host {
+        if h != "gitlab.com" {
+            cmd.arg("--hostname").arg(h);
+        }
+    }
+
+    let mut child = cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .context("Failed to run glab auth login")?;
+    child
+        .stdin
+        .take()
+        .context("Failed to open glab stdin")?
+        .write_all(token.as_bytes())?;
+
+    if !child.wait()?.success() {
+        bail!("GitLab authentication failed");
+    }
+
+    Ok(())
+}
+
+/// Whether glab has a stored login for the host
+pub fn is_authenticated(host: Option<&str>) -> bool {
+    Command::new("glab")
+        .args(["auth", "status", "--hostname", host.unwrap_or("gitlab.com")])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(false)
+}
+
+/// Run glab against a host, which glab takes from GITLAB_HOST outside
+/// `glab auth`. A token overrides whichever account glab is signed in as.
+fn glab(host: Option<&str>, token: Option<&str>) -> Command {
+    let mut cmd = Command::new("glab");
+    if let Some(h) = host {
+        if h != "gitlab.com" {
+            cmd.env("GITLAB_HOST", h);
+        }
+    }
+    if let Some(token) = token {
+        cmd.env("GITLAB_TOKEN", token);
+    }
+    cmd
+}
+
+/// Register a public key with the authenticated GitLab account.
+/// `usage_type` is one of GitLab's `auth`, `signing`, or `auth_and_signing`.
+pub fn upload_key(
+    host: Option<&str>,
+    token: Option<&str>,
+    public_key: &Path,
+    title: &str,
+    usage_type: &str,
+) -> Result<()> {
+    if !is_glab_installed() {
+        bail!("GitLab CLI (glab) is not installed. Install it from https://gitlab.com/gitlab-org/cli");
+    }
+
+    let mut cmd = glab(host, token);
+    cmd.arg("ssh-key")
+        .arg("add")
+        .arg(public_key)
+        .arg("--title")
+        .arg(title)
+        .arg("--usage-type")
+        .arg(usage_type);
+
+    let output = cmd.output().context("Failed to run glab ssh-key add")?;
+    if !output.status.success() {
+        bail!(
+            "GitLab key upload failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(())
+}
+
+/// Username of the account glab is signed in as (or `token` belongs to),
+/// if any
+pub fn active_user(host: Option<&str>, token: Option<&str>) -> Result<Option<String>> {
+    if !is_glab_installed() {
+        return Ok(None);
+    }
+
+    let output = glab(host, token)
+        .args(["api", "user"])
+        .output().context("Failed to run glab api user")?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    let user: serde_json::Value =
+        serde_json::from_slice(&output.stdout).context("Unexpected output from glab api user")?;
+    Ok(user["username"].as_str().map(str::to_string))
+}
+
+/// Run `glab api <endpoint>` and parse the response, or None if the call
+/// fails (signed out, bad token, or missing scope)
+fn api_json(host: Option<&str>, token: Option<&str>, endpoint: &str) -> Result<Option<serde_json::Value>> {
+    let output = glab(host, token)
+        .args(["api", endpoint])
+        .output()
+        .with_context(|| format!("Failed to run glab api {}", endpoint))?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    Ok(serde_json::from_slice(&output.stdout).ok())
+}
+
+/// Account glab is signed in as (or `token` belongs to), with its primary,
+/// commit and public addresses and any secondary ones
+pub fn account(host: Option<&str>, token: Option<&str>) -> Result<Option<super::Account>> {
+    if !is_glab_installed() {
+        bail!("GitLab CLI (glab) is not installed. Install it from https://gitlab.com/gitlab-org/cli");
+    }
+
+    let Some(user) = api_json(host, token, "user")? else {
+        return Ok(None);
+    };
+    let emails = api_json(host, token, "user/emails")?;
+    Ok(super::Account::from_api(
+        &user,
+        "username",
+        &["email", "commit_email", "public_email"],
+        emails.as_ref(),
+    ))
+}
+
+/// glab's global config file, which `glab config set -g` writes
+pub fn config_path() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("GLAB_CONFIG_DIR") {
+        return Some(PathBuf::from(dir).join("config.yml"));
+    }
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".config")))?;
+    Some(config_home.join("glab-cli").join("config.yml"))
+}
+
+/// Host glab talks to outside repositories of another instance
+pub fn default_host() -> Result<Option<String>> {
+    let output = Command::new("glab")
+        .args(["config", "get", "-g", "host"])
+        .output()
+        .context("Failed to run glab config get")?;
+    let host = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    Ok(Some(host).filter(|h| output.status.success() && !h.is_empty()))
+}
+
+/// Make `host` glab's default. Returns false if it already was. The
+/// previous host is journaled so `gitid rollback` restores it.
+pub fn set_default_host(host: &str) -> Result<bool> {
+    if !is_glab_installed() {
+        bail!("GitLab CLI (glab) is not installed. Install it from https://gitlab.com/gitlab-org/cli");
+    }
+    let previous = default_host()?;
+    if previous.as_deref() == Some(host) {
+        return Ok(false);
+    }
+
+    // Not the config file itself: it holds glab's tokens
+    journal::record(journal::Change::GlabHost { previous })?;
+    let output = Command::new("glab")
+        .args(["config", "set", "-g", "host", host])
+        .output()
+        .context("Failed to run glab config set")?;
+    if !output.status.success() {
+        bail!(
+            "glab config set failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(true)
+}
@@ -0,0 +1,270 @@
+use super::{github, gitlab};
+use crate::profile::Platform;
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// CLI whose signed-in accounts `gitid import --from` reads
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Source {
+    /// GitHub CLI (~/.config/gh/hosts.yml)
+    Gh,
+    /// GitLab CLI (~/.config/glab-cli/config.yml)
+    Glab,
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::Gh => write!(f, "gh"),
+            Source::Glab => write!(f, "glab"),
+        }
+    }
+}
+
+/// An account a CLI is signed in to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CliAccount {
+    pub platform: Platform,
+    pub host: String,
+    pub login: String,
+    /// Token stored in the config file; recent CLIs keep it in the keyring
+    pub token: Option<String>,
+    /// Whether this is the account the CLI uses for the host
+    pub active: bool,
+    /// `ssh` or `https`, as the CLI clones
+    pub git_protocol: Option<String>,
+}
+
+impl CliAccount {
+    /// Host for a profile: None for the platform's public instance
+    pub fn profile_host(&self) -> Option<String> {
+        let public = match self.platform {
+            Platform::Gitlab => "gitlab.com",
+            _ => "github.com",
+        };
+        (!self.host.eq_ignore_ascii_case(public)).then(|| self.host.clone())
+    }
+
+    /// Primary email of the account, asked of the API with the stored
+    /// token, or with the CLI's own login for the active account
+    pub fn email(&self) -> Result<Option<String>> {
+        if self.token.is_none() && !self.active {
+            return Ok(None);
+        }
+        let host = self.profile_host();
+        let account = match self.platform {
+            Platform::Gitlab => gitlab::account(host.as_deref(), self.token.as_deref())?,
+            _ => github::account(host.as_deref(), self.token.as_deref())?,
+        };
+        Ok(account
+            .filter(|a| a.login.eq_ignore_ascii_case(&self.login))
+            .and_then(|a| a.emails.into_iter().next()))
+    }
+}
+
+/// A YAML mapping node: its scalar value, if any, and its children
+#[derive(Debug, Default)]
+struct Node {
+    value: Option<String>,
+    children: Vec<(String, Node)>,
+}
+
+impl Node {
+    fn get(&self, key: &str) -> Option<&Node> {
+        self.children.iter().find(|(k, _)| k == key).map(|(_, node)| node)
+    }
+
+    fn value_of(&self, key: &str) -> Option<String> {
+        self.get(key)?.value.clone()
+    }
+}
+
+/// Parse the nested `key: value` mappings gh and glab write. Lists,
+/// comments and anything fancier are skipped; nothing else is needed to
+/// read hosts and users.
+fn parse_yaml(text: &str) -> Node {
+    fn unquote(value: &str) -> String {
+        let value = value.trim();
+        for quote in ['"', '\''] {
+            if let Some(inner) = value.strip_prefix(quote).and_then(|v| v.strip_suffix(quote)) {
+                return inner.to_string();
+            }
+        }
+        value.to_string()
+    }
+
+    // Open mappings with the indentation of their keys
+    let mut stack: Vec<(usize, String, Node)> = vec![(0, String::new(), Node::default())];
+    let close = |stack: &mut Vec<(usize, String, Node)>| {
+        if let Some((_, key, node)) = stack.pop() {
+            if let Some((_, _, parent)) = stack.last_mut() {
+                parent.children.push((key, node));
+            }
+        }
+    };
+
+    for line in text.lines() {
+        let content = line.trim_start();
+        if content.is_empty() || content.starts_with('#') || content.starts_with('-') {
+            continue;
+        }
+        let indent = line.len() - content.len();
+        let Some((key, value)) = content.split_once(':') else {
+            continue;
+        };
+
+        while stack.len() > 1 && stack.last().is_some_and(|(i, _, _)| *i >= indent) {
+            close(&mut stack);
+        }
+        let value = Some(unquote(value)).filter(|v| !v.is_empty());
+        let node = Node {
+            value,
+            children: Vec::new(),
+        };
+        stack.push((indent, unquote(key), node));
+    }
+    while stack.len() > 1 {
+        close(&mut stack);
+    }
+    stack.pop().map(|(_, _, root)| root).unwrap_or_default()
+}
+
+/// gh's hosts file
+fn gh_hosts_path() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("GH_CONFIG_DIR") {
+        return Some(PathBuf::from(dir).join("hosts.yml"));
+    }
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".config")))?;
+    Some(config_home.join("gh").join("hosts.yml"))
+}
+
+/// Accounts in gh's hosts.yml: every login under `users` (gh 2.40 and
+/// later sign in to several per host), or the single `user`
+fn gh_accounts(root: &Node) -> Vec<CliAccount> {
+    let mut accounts = Vec::new();
+    for (host, entry) in &root.children {
+        let active = entry.value_of("user");
+        let protocol = entry.value_of("git_protocol");
+        let mut logins: Vec<(String, Option<String>)> = entry
+            .get("users")
+            .map(|users| {
+                users
+                    .children
+                    .iter()
+                    .map(|(login, user)| (login.clone(), user.value_of("oauth_token")))
+                    .collect()
+            })
+            .unwrap_or_default();
+        if let Some(ref user) = active {
+            if !logins.iter().any(|(login, _)| login == user) {
+                logins.push((user.clone(), entry.value_of("oauth_token")));
+            }
+        }
+
+        for (login, token) in logins {
+            let is_active = active.as_ref() == Some(&login);
+            // The host-level token belongs to the active login
+            let token = token.or_else(|| entry.value_of("oauth_token").filter(|_| is_active));
+            accounts.push(CliAccount {
+                platform: Platform::Github,
+                host: host.clone(),
+                login,
+                token,
+                active: is_active,
+                git_protocol: protocol.clone(),
+            });
+        }
+    }
+    accounts
+}
+
+/// Accounts under `hosts` in glab's config.yml, one per host
+fn glab_accounts(root: &Node) -> Vec<CliAccount> {
+    let protocol = root.value_of("git_protocol");
+    let Some(hosts) = root.get("hosts") else {
+        return Vec::new();
+    };
+    hosts
+        .children
+        .iter()
+        .filter_map(|(host, entry)| {
+            Some(CliAccount {
+                platform: Platform::Gitlab,
+                host: host.clone(),
+                login: entry.value_of("user")?,
+                token: entry.value_of("token"),
+                active: true,
+                git_protocol: entry.value_of("git_protocol").or_else(|| protocol.clone()),
+            })
+        })
+        .collect()
+}
+
+/// The CLI's config file
+pub fn config_path(source: Source) -> Option<PathBuf> {
+    match source {
+        Source::Gh => gh_hosts_path(),
+        Source::Glab => gitlab::config_path(),
+    }
+}
+
+/// Accounts the CLI is signed in to, read from its config file
+pub fn accounts(source: Source) -> Result<Vec<CliAccount>> {
+    let path = config_path(source).context("Could not determine the CLI's config directory")?;
+    read_accounts(source, &path)
+}
+
+fn read_accounts(source: Source, path: &Path) -> Result<Vec<CliAccount>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let text = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let root = parse_yaml(&text);
+    Ok(match source {
+        Source::Gh => gh_accounts(&root),
+        Source::Glab => glab_accounts(&root),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gh_accounts() {
+        let root = parse_yaml(
+            "github.com:\n    users:\n        jdoe:\n            oauth_token: gho_a\n        jdoe-oss:\n    git_protocol: ssh\n    user: jdoe\ngithub.acme.com:\n    oauth_token: \"gho_b\"\n    git_protocol: https\n    user: jdoe-acme\n",
+        );
+        let accounts = gh_accounts(&root);
+        assert_eq!(accounts.len(), 3);
+        assert_eq!(accounts[0].login, "jdoe");
+        assert!(accounts[0].active);
+        assert_eq!(accounts[0].token.as_deref(), Some("gho_a"));
+        assert_eq!(accounts[0].profile_host(), None);
+        assert_eq!(accounts[1].login, "jdoe-oss");
+        assert!(!accounts[1].active);
+        assert_eq!(accounts[1].token, None);
+        assert_eq!(accounts[2].login, "jdoe-acme");
+        assert_eq!(accounts[2].token.as_deref(), Some("gho_b"));
+        assert_eq!(accounts[2].git_protocol.as_deref(), Some("https"));
+        assert_eq!(accounts[2].profile_host().as_deref(), Some("github.acme.com"));
+    }
+
+    #[test]
+    fn test_glab_accounts() {
+        let root = parse_yaml(
+            "# glab config\ngit_protocol: ssh\nhost: gitlab.com\nhosts:\n  gitlab.com:\n    api_protocol: https\n    user: jdoe\n    token: glpat-x\n  gitlab.acme.com:\n    git_protocol: https\n    user: 'jdoe.acme'\n  gitlab.empty.com:\n    api_protocol: https\naliases:\n  ci: pipeline ci\n",
+        );
+        let accounts = glab_accounts(&root);
+        assert_eq!(accounts.len(), 2);
+        assert_eq!(accounts[0].login, "jdoe");
+        assert_eq!(accounts[0].git_protocol.as_deref(), Some("ssh"));
+        assert_eq!(accounts[1].login, "jdoe.acme");
+        assert_eq!(accounts[1].git_protocol.as_deref(), Some("https"));
+        assert_eq!(accounts[1].profile_host().as_deref(), Some("gitlab.acme.com"));
+    }
+}
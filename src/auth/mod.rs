@@ -1,11 +1,48 @@
+pub mod gitea;
 pub mod github;
+pub mod github_app;
 pub mod gitlab;
+pub mod hosts;
 
 use crate::profile::{Platform, Profile};
-use anyhow::Result;
+use crate::secrets;
+use crate::ssh_keys;
+use anyhow::{Result, bail};
+use clap::ValueEnum;
+use std::process::Command;
+
+/// What an uploaded SSH key may be used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum KeyUsage {
+    /// Pushing and pulling over SSH
+    Auth,
+    /// Verifying SSH-signed commits
+    Signing,
+    /// Both authentication and signing
+    Both,
+}
+
+impl KeyUsage {
+    fn includes_auth(self) -> bool {
+        matches!(self, KeyUsage::Auth | KeyUsage::Both)
+    }
+
+    fn includes_signing(self) -> bool {
+        matches!(self, KeyUsage::Signing | KeyUsage::Both)
+    }
+
+    /// GitLab's usage_type value
+    fn gitlab_usage_type(self) -> &'static str {
+        match self {
+            KeyUsage::Auth => "auth",
+            KeyUsage::Signing => "signing",
+            KeyUsage::Both => "auth_and_signing",
+        }
+    }
+}
 
 /// Authenticate CLI tools for a profile based on its platform
-pub fn authenticate(_profile_name: &str, profile: &Profile) -> Result<()> {
+pub fn authenticate(profile_name: &str, profile: &Profile) -> Result<()> {
     let host = profile.host.as_deref();
 
     match profile.platform {
@@ -21,7 +58,382 @@ pub fn authenticate(_profile_name: &str, profile: &Profile) -> Result<()> {
             println!("\nAuthenticating GitLab...");
             gitlab::authenticate(host)?;
         }
+        Platform::Codeberg | Platform::Gitea => {
+            gitea::authenticate(profile.default_host())?;
+        }
+        Platform::Custom => bail!(
+            "Profile '{}' uses a custom platform, which has no CLI for gitid to authenticate",
+            profile_name
+        ),
+    }
+
+    Ok(())
+}
+
+/// Authenticate CLI tools for a profile with an existing token
+pub fn authenticate_with_token(profile_name: &str, profile: &Profile, token: &str) -> Result<()> {
+    let host = profile.host.as_deref();
+
+    match profile.platform {
+        Platform::Github => github::authenticate_with_token(host, token),
+        Platform::Gitlab => gitlab::authenticate_with_token(host, token),
+        Platform::Both => bail!(
+            "Profile '{}' uses both GitHub and GitLab, which need separate tokens. Use --keyring or authenticate interactively.",
+            profile_name
+        ),
+        Platform::Codeberg | Platform::Gitea => {
+            gitea::authenticate_with_token(profile_name, profile.default_host(), token)
+        }
+        Platform::Custom => bail!(
+            "Profile '{}' uses a custom platform, which has no CLI to hand the token to. Use --keyring instead.",
+            profile_name
+        ),
+    }
+}
+
+/// Accounts the platform CLIs are signed in as that differ from the
+/// profile's username, as (platform, login) pairs
+pub fn account_mismatches(profile: &Profile) -> Result<Vec<(Platform, String)>> {
+    let Some(ref username) = profile.username else {
+        return Ok(Vec::new());
+    };
+    let host = profile.host.as_deref();
+
+    let mut active = Vec::new();
+    if matches!(profile.platform, Platform::Github | Platform::Both) {
+        active.push((Platform::Github, github::active_user(host, None)?));
+    }
+    if matches!(profile.platform, Platform::Gitlab | Platform::Both) {
+        active.push((Platform::Gitlab, gitlab::active_user(host, None)?));
+    }
+
+    Ok(active
+        .into_iter()
+        .filter_map(|(platform, login)| Some((platform, login?)))
+        .filter(|(_, login)| !login.eq_ignore_ascii_case(username))
+        .collect())
+}
+
+/// The profile's keyring token, for API calls that should act as its
+/// account whatever gh/glab are signed in as. 'both' profiles would need one
+/// token per platform, so they use the CLIs' own logins.
+fn keyring_token(profile_name: &str, profile: &Profile) -> Option<String> {
+    if profile.platform == Platform::Both {
+        return None;
+    }
+    secrets::get_token(profile.keyring_account(profile_name)).ok().flatten()
+}
+
+/// Account a token belongs to, asked of the platform's API. None if the
+/// platform has no supported CLI or rejects the token.
+pub fn token_user(profile: &Profile, token: &str) -> Result<Option<String>> {
+    let host = profile.host.as_deref();
+    match profile.platform {
+        Platform::Github => github::active_user(host, Some(token)),
+        Platform::Gitlab => gitlab::active_user(host, Some(token)),
+        _ => Ok(None),
+    }
+}
+
+/// An account as the platform's API reports it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Account {
+    pub login: String,
+    /// Addresses on the account, primary first. Only the public ones when
+    /// the token may not list the rest.
+    pub emails: Vec<String>,
+}
+
+impl Account {
+    /// Read an account from a `/user` response and, when the token may
+    /// read it, the `/user/emails` list
+    fn from_api(
+        user: &serde_json::Value,
+        login_field: &str,
+        email_fields: &[&str],
+        emails: Option<&serde_json::Value>,
+    ) -> Option<Account> {
+        let login = user[login_field].as_str().filter(|l| !l.is_empty())?.to_string();
+
+        let listed: &[serde_json::Value] = emails.and_then(|e| e.as_array()).map_or(&[], |e| e);
+        let (primary, secondary): (Vec<_>, Vec<_>) = listed
+            .iter()
+            .partition(|e| e["primary"].as_bool().unwrap_or(false));
+
+        let mut addresses: Vec<String> = Vec::new();
+        let candidates = primary
+            .iter()
+            .filter_map(|e| e["email"].as_str())
+            .chain(email_fields.iter().filter_map(|f| user[*f].as_str()))
+            .chain(secondary.iter().filter_map(|e| e["email"].as_str()));
+        for email in candidates {
+            if !email.is_empty() && !addresses.iter().any(|a| a.eq_ignore_ascii_case(email)) {
+                addresses.push(email.to_string());
+            }
+        }
+
+        Some(Account {
+            login,
+            emails: addresses,
+        })
+    }
+
+    /// Whether the address is on this account, counting the platform's
+    /// noreply address for the login (e.g. 123+login@users.noreply.github.com)
+    fn has_email(&self, email: &str) -> bool {
+        if self.emails.iter().any(|e| e.eq_ignore_ascii_case(email)) {
+            return true;
+        }
+        let Some((local, domain)) = email.split_once('@') else {
+            return false;
+        };
+        let login = self.login.to_ascii_lowercase();
+        let local = local.to_ascii_lowercase();
+        domain.to_ascii_lowercase().starts_with("users.noreply.")
+            && (local == login
+                || local.ends_with(&format!("+{}", login))
+                || local.ends_with(&format!("-{}", login)))
+    }
+}
+
+/// Account a profile's credentials authenticate as on one platform
+#[derive(Debug)]
+pub struct Whoami {
+    pub platform: Platform,
+    /// Where the credentials came from, e.g. "keyring token" or "gh login"
+    pub source: &'static str,
+    /// None if there are no credentials or the platform rejected them
+    pub account: Option<Account>,
+}
+
+/// Ask each of the profile's platforms which account its keyring token, or
+/// else the CLI's login, belongs to
+pub fn whoami(profile_name: &str, profile: &Profile) -> Result<Vec<Whoami>> {
+    let host = profile.host.as_deref();
+    let token = keyring_token(profile_name, profile);
+    let token = token.as_deref();
+
+    let mut results = Vec::new();
+    if matches!(profile.platform, Platform::Github | Platform::Both) {
+        results.push(Whoami {
+            platform: Platform::Github,
+            source: if token.is_some() { "keyring token" } else { "gh login" },
+            account: github::account(host, token)?,
+        });
+    }
+    if matches!(profile.platform, Platform::Gitlab | Platform::Both) {
+        results.push(Whoami {
+            platform: Platform::Gitlab,
+            source: if token.is_some() { "keyring token" } else { "glab login" },
+            account: gitlab::account(host, token)?,
+        });
+    }
+    if results.is_empty() {
+        bail!(
+            "Profile '{}' uses {}, whose API gitid can't query; only GitHub and GitLab are supported",
+            profile_name,
+            profile.platform
+        );
+    }
+    Ok(results)
+}
+
+/// Ways an account contradicts the profile it is supposed to belong to
+pub fn account_problems(profile: &Profile, account: &Account) -> Vec<String> {
+    let mut problems = Vec::new();
+    if let Some(ref username) = profile.username {
+        if !account.login.eq_ignore_ascii_case(username) {
+            problems.push(format!(
+                "Signed in as '{}', but the profile's username is '{}'",
+                account.login, username
+            ));
+        }
+    }
+    if !account.emails.is_empty() && !account.has_email(&profile.email) {
+        problems.push(format!(
+            "{} is not an address of '{}' ({})",
+            profile.email,
+            account.login,
+            account.emails.join(", ")
+        ));
+    }
+    problems
+}
+
+/// Make the profile's account gh's active one. Returns false if the profile
+/// has no GitHub username to switch to.
+pub fn switch_account(profile: &Profile) -> Result<bool> {
+    match profile.username {
+        Some(ref username) if matches!(profile.platform, Platform::Github | Platform::Both) => {
+            github::switch_user(profile.host.as_deref(), username)?;
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+/// GitLab instance the profile uses, if it is on GitLab
+pub fn gitlab_host(profile: &Profile) -> Option<&str> {
+    match profile.platform {
+        Platform::Gitlab => Some(profile.default_host()),
+        Platform::Both => Some("gitlab.com"),
+        _ => None,
+    }
+}
+
+/// Point glab's default host at the profile's GitLab instance. Returns the
+/// host if it changed, or None if it already was the default or the
+/// profile isn't on GitLab.
+pub fn select_glab_host(profile: &Profile) -> Result<Option<String>> {
+    let Some(host) = gitlab_host(profile) else {
+        return Ok(None);
+    };
+    Ok(gitlab::set_default_host(host)?.then(|| host.to_string()))
+}
+
+/// This machine's hostname, for key titles
+fn machine_name() -> String {
+    Command::new("hostname")
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "unknown-host".to_string())
+}
+
+/// Title shown for the key in the forge's settings
+pub fn key_title(profile_name: &str) -> String {
+    format!("gitid {} ({})", profile_name, machine_name())
+}
+
+/// Web pages where the profile's accounts accept a new SSH key
+pub fn key_settings_urls(profile: &Profile) -> Vec<String> {
+    let host = |default: &str| match profile.host.as_deref() {
+        Some(h) if profile.platform != Platform::Both => h.to_string(),
+        _ => default.to_string(),
+    };
+
+    let mut urls = Vec::new();
+    if matches!(profile.platform, Platform::Github | Platform::Both) {
+        urls.push(format!("https://{}/settings/ssh/new", host("github.com")));
+    }
+    if matches!(profile.platform, Platform::Gitlab | Platform::Both) {
+        urls.push(format!("https://{}/-/user_settings/ssh_keys", host("gitlab.com")));
+    }
+    if matches!(profile.platform, Platform::Codeberg | Platform::Gitea) {
+        urls.push(format!("https://{}/user/settings/keys", profile.default_host()));
+    }
+    urls
+}
+
+/// Upload the profile's public SSH key to its platform(s) for the given usage
+pub fn upload_key(profile_name: &str, profile: &Profile, usage: KeyUsage) -> Result<()> {
+    let public_key = ssh_keys::public_key_path(&profile.ssh_key);
+    if !public_key.exists() {
+        bail!("Public key not found: {}", public_key.display());
+    }
+
+    let host = profile.host.as_deref();
+    let title = key_title(profile_name);
+    let token = keyring_token(profile_name, profile);
+    let token = token.as_deref();
+
+    if matches!(profile.platform, Platform::Github | Platform::Both) {
+        if usage.includes_auth() {
+            github::upload_key(host, token, &public_key, &title, false)?;
+        }
+        if usage.includes_signing() {
+            github::upload_key(host, token, &public_key, &title, true)?;
+        }
+    }
+    if matches!(profile.platform, Platform::Gitlab | Platform::Both) {
+        gitlab::upload_key(host, token, &public_key, &title, usage.gitlab_usage_type())?;
+    }
+    if matches!(profile.platform, Platform::Codeberg | Platform::Gitea) {
+        bail!(
+            "tea cannot upload SSH keys; add {} at {}",
+            public_key.display(),
+            key_settings_urls(profile).join(" ")
+        );
+    }
+    if profile.platform == Platform::Custom {
+        bail!(
+            "Profile '{}' uses a custom platform; add {} through your forge's settings",
+            profile_name,
+            public_key.display()
+        );
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn profile(username: Option<&str>) -> Profile {
+        let mut profile = Profile::new(
+            "John Doe".to_string(),
+            "john@acme.com".to_string(),
+            Platform::Github,
+            "~/.ssh/id_work".to_string(),
+            None,
+            None,
+        );
+        profile.username = username.map(str::to_string);
+        profile
+    }
+
+    #[test]
+    fn test_account_from_api() {
+        let user = json!({ "login": "jdoe", "email": "public@example.com" });
+        let emails = json!([
+            { "email": "other@example.com", "primary": false },
+            { "email": "john@acme.com", "primary": true },
+        ]);
+        let account = Account::from_api(&user, "login", &["email"], Some(&emails)).unwrap();
+        assert_eq!(account.login, "jdoe");
+        assert_eq!(
+            account.emails,
+            ["john@acme.com", "public@example.com", "other@example.com"]
+        );
+
+        // A token that can't list emails still reports the public one
+        let account = Account::from_api(&user, "login", &["email"], None).unwrap();
+        assert_eq!(account.emails, ["public@example.com"]);
+        assert!(Account::from_api(&json!({ "message": "Bad credentials" }), "login", &[], None).is_none());
+    }
+
+    #[test]
+    fn test_account_problems() {
+        let account = Account {
+            login: "jdoe".to_string(),
+            emails: vec!["John@Acme.com".to_string()],
+        };
+        assert!(account_problems(&profile(Some("JDoe")), &account).is_empty());
+
+        let account = Account {
+            login: "someone".to_string(),
+            emails: vec!["someone@example.com".to_string()],
+        };
+        assert_eq!(account_problems(&profile(Some("jdoe")), &account).len(), 2);
+        // Without a username only the email is checked
+        assert_eq!(account_problems(&profile(None), &account).len(), 1);
+
+        // Hidden emails can't be checked, and noreply addresses count
+        let account = Account {
+            login: "jdoe".to_string(),
+            emails: Vec::new(),
+        };
+        assert!(account_problems(&profile(None), &account).is_empty());
+        let mut noreply = profile(None);
+        noreply.email = "123+jdoe@users.noreply.github.com".to_string();
+        let account = Account {
+            login: "jdoe".to_string(),
+            emails: vec!["john@acme.com".to_string()],
+        };
+        assert!(account_problems(&noreply, &account).is_empty());
+    }
+}
@@ -0,0 +1,320 @@
+use crate::config::Config;
+use crate::env;
+use crate::managed;
+use crate::ssh::{self, ConnectionTest};
+use crate::ssh_keys;
+use anyhow::{Context, Result, bail};
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// A local file to copy into the remote home directory
+#[derive(Debug, PartialEq, Eq)]
+pub struct Upload {
+    pub local: PathBuf,
+    /// Path relative to the remote home directory
+    pub remote: String,
+    pub mode: &'static str,
+}
+
+/// Copy the selected profiles into a config that works in another home
+/// directory: key paths, extra keys included, are moved to
+/// `~/.ssh/<file name>`. Also returns the key files to bring along — public
+/// keys always, private keys only with `include_private`.
+pub fn portable_config(
+    config: &Config,
+    names: &[String],
+    include_private: bool,
+) -> Result<(Config, Vec<Upload>)> {
+    let mut portable = Config::default();
+    let mut uploads: Vec<Upload> = Vec::new();
+
+    for name in names {
+        let mut profile = config
+            .get_profile(name)
+            .context(format!("Profile '{}' not found", name))?
+            .clone();
+
+        profile.ssh_key = bring_key(name, &profile.ssh_key, include_private, &mut uploads)?;
+        profile.extra_ssh_keys = profile
+            .extra_ssh_keys
+            .iter()
+            .map(|key| bring_key(name, key, include_private, &mut uploads))
+            .collect::<Result<_>>()?;
+        portable.profiles.insert(name.clone(), profile);
+    }
+
+    if let Some(ref default) = config.default_profile {
+        if portable.has_profile(default) {
+            portable.default_profile = Some(default.clone());
+        }
+    }
+    for (pattern, target) in &config.detect.email_domains {
+        if portable.has_profile(target) {
+            portable
+                .detect
+                .email_domains
+                .insert(pattern.clone(), target.clone());
+        }
+    }
+    for (pattern, target) in &config.detect.directories {
+        if portable.has_profile(target) {
+            portable
+                .detect
+                .directories
+                .insert(pattern.clone(), target.clone());
+        }
+    }
+    portable.policies = config.policies.clone();
+
+    Ok((portable, uploads))
+}
+
+/// Queue one of a profile's keys for upload to `~/.ssh/<file name>` and
+/// return that path for the portable profile
+fn bring_key(name: &str, ssh_key: &str, include_private: bool, uploads: &mut Vec<Upload>) -> Result<String> {
+    let key = ssh_keys::expand_path(ssh_key);
+    let file_name = key
+        .file_name()
+        .and_then(|n| n.to_str())
+        .context(format!("Profile '{}' has no usable path for key '{}'", name, ssh_key))?
+        .to_string();
+    let remote_key = format!(".ssh/{}", file_name);
+    let public = Upload {
+        local: ssh_keys::public_key_path(ssh_key),
+        remote: format!("{}.pub", remote_key),
+        mode: "644",
+    };
+
+    // The public key is always uploaded, so it tells keys apart
+    if uploads
+        .iter()
+        .any(|u| u.remote == public.remote && u.local != public.local)
+    {
+        bail!(
+            "Profiles use different keys named {}; rename one before moving them",
+            file_name
+        );
+    }
+    if !uploads.contains(&public) {
+        if include_private {
+            uploads.push(Upload {
+                local: key,
+                remote: remote_key.clone(),
+                mode: "600",
+            });
+        }
+        uploads.push(public);
+    }
+
+    Ok(format!("~/{}", remote_key))
+}
+
+/// Build the config to install on the remote machine. Public keys are always
+/// copied (so IdentitiesOnly can pick the forwarded agent key), private keys
+/// only with `include_keys`.
+pub fn remote_config(
+    config: &Config,
+    names: &[String],
+    include_keys: bool,
+) -> Result<(Config, Vec<Upload>)> {
+    let (mut remote, uploads) = portable_config(config, names, include_keys)?;
+
+    // GPG homes and netrc tokens stay on this machine
+    for profile in remote.profiles.values_mut() {
+        profile.gnupghome = None;
+        profile.netrc = false;
+    }
+
+    Ok((remote, uploads))
+}
+
+/// `"$HOME"/<path>` for use in a remote shell command
+fn remote_path(relative: &str) -> String {
+    format!("\"$HOME\"/{}", env::shell_quote(relative))
+}
+
+/// Run a shell command on the target over SSH, optionally feeding stdin
+fn run(target: &str, script: &str, input: Option<&[u8]>) -> Result<std::process::Output> {
+    let mut child = Command::new("ssh")
+        .args([target, script])
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run ssh. Is OpenSSH installed?")?;
+
+    if let Some(input) = input {
+        child
+            .stdin
+            .take()
+            .context("Failed to open ssh stdin")?
+            .write_all(input)?;
+    }
+
+    child.wait_with_output().context("Failed to wait for ssh")
+}
+
+/// Like `run`, but fail with the remote's stderr on a non-zero exit
+fn run_checked(target: &str, script: &str, input: Option<&[u8]>) -> Result<String> {
+    let output = run(target, script, input)?;
+    if !output.status.success() {
+        bail!(
+            "Command on {} failed: {}",
+            target,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Write content to a file under the remote home directory
+pub fn upload(target: &str, relative: &str, content: &[u8], mode: &str) -> Result<()> {
+    let path = remote_path(relative);
+    let script = format!(
+        "umask 077 && mkdir -p \"$(dirname {path})\" && cat > {path} && chmod {mode} {path}"
+    );
+    run_checked(target, &script, Some(content))?;
+    Ok(())
+}
+
+/// Copy the key files and gitid config to the remote
+pub fn install_files(target: &str, config: &Config, uploads: &[Upload]) -> Result<()> {
+    for file in uploads {
+        let content = std::fs::read(&file.local)
+            .with_context(|| format!("Failed to read {}", file.local.display()))?;
+        upload(target, &file.remote, &content, file.mode)?;
+    }
+
+    let content = toml::to_string_pretty(config).context("Failed to serialize config")?;
+    upload(target, ".config/gitid/config.toml", content.as_bytes(), "600")
+}
+
+/// Splice the profiles' host entries into the remote ~/.ssh/config
+pub fn sync_ssh_config(target: &str, config: &Config) -> Result<()> {
+    let current = run_checked(
+        target,
+        &format!("cat {} 2>/dev/null || true", remote_path(".ssh/config")),
+        None,
+    )?;
+    let block = ssh::generate_managed_block(config, &current);
+    let (content, _) =
+        managed::splice_block(&current, ssh::MANAGED_START, ssh::MANAGED_END, &block);
+    upload(target, ".ssh/config", content.as_bytes(), "600")
+}
+
+/// Set the remote's global git identity
+pub fn apply_git_identity(target: &str, name: &str, email: &str) -> Result<()> {
+    let script = format!(
+        "git config --global user.name {} && git config --global user.email {}",
+        env::shell_quote(name),
+        env::shell_quote(email)
+    );
+    run_checked(target, &script, None)?;
+    Ok(())
+}
+
+/// Test a host alias from the remote, authenticating through the forwarded agent
+pub fn test_connection(target: &str, alias: &str) -> Result<ConnectionTest> {
+    let output = Command::new("ssh")
+        .args([
+            "-A",
+            target,
+            &format!(
+                "ssh -T -o BatchMode=yes -o ConnectTimeout=10 -o StrictHostKeyChecking=accept-new git@{}",
+                alias
+            ),
+        ])
+        .stdin(Stdio::null())
+        .output()
+        .context("Failed to run ssh. Is OpenSSH installed?")?;
+
+    Ok(ConnectionTest::from_output(&output))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::{Platform, Profile};
+
+    #[test]
+    fn test_remote_config() {
+        let mut config = Config::default();
+        config.default_profile = Some("personal".to_string());
+        config
+            .detect
+            .email_domains
+            .insert("*@company.com".to_string(), "work".to_string());
+        for (name, key) in [("work", "/keys/id_work"), ("personal", "~/.ssh/id_me")] {
+            let mut profile = Profile::new(
+                "John Doe".to_string(),
+                format!("john@{}.com", name),
+                Platform::Github,
+                key.to_string(),
+                None,
+                None,
+            );
+            profile.gnupghome = Some("~/.gnupg-work".to_string());
+            config.profiles.insert(name.to_string(), profile);
+        }
+
+        let (remote, uploads) = remote_config(&config, &["work".to_string()], false).unwrap();
+        assert_eq!(remote.profile_names(), vec!["work"]);
+        assert!(remote.default_profile.is_none());
+        assert_eq!(remote.detect.email_domains.len(), 1);
+
+        let work = remote.get_profile("work").unwrap();
+        assert_eq!(work.ssh_key, "~/.ssh/id_work");
+        assert!(work.gnupghome.is_none());
+        assert_eq!(
+            uploads,
+            vec![Upload {
+                local: PathBuf::from("/keys/id_work.pub"),
+                remote: ".ssh/id_work.pub".to_string(),
+                mode: "644",
+            }]
+        );
+
+        let (_, uploads) = remote_config(&config, &["work".to_string()], true).unwrap();
+        assert_eq!(uploads.len(), 2);
+        assert_eq!(uploads[0].mode, "600");
+    }
+
+    #[test]
+    fn test_portable_config_extra_keys() {
+        let mut config = Config::default();
+        for (name, key, extra) in [
+            ("work", "/keys/id_work", "/keys/id_work_sk"),
+            ("oss", "~/.ssh/id_oss", "/other/id_work_sk"),
+        ] {
+            let mut profile = Profile::new(
+                "John Doe".to_string(),
+                format!("john@{}.com", name),
+                Platform::Github,
+                key.to_string(),
+                None,
+                None,
+            );
+            profile.extra_ssh_keys = vec![extra.to_string()];
+            config.profiles.insert(name.to_string(), profile);
+        }
+
+        let (portable, uploads) = portable_config(&config, &["work".to_string()], true).unwrap();
+        let work = portable.get_profile("work").unwrap();
+        assert_eq!(work.extra_ssh_keys, vec!["~/.ssh/id_work_sk"]);
+        let remotes: Vec<&str> = uploads.iter().map(|u| u.remote.as_str()).collect();
+        assert_eq!(
+            remotes,
+            vec![".ssh/id_work", ".ssh/id_work.pub", ".ssh/id_work_sk", ".ssh/id_work_sk.pub"]
+        );
+        assert_eq!(uploads[2].local, PathBuf::from("/keys/id_work_sk"));
+
+        // Two different extra keys can't share a name in ~/.ssh
+        let both = ["work".to_string(), "oss".to_string()];
+        assert!(portable_config(&config, &both, false).is_err());
+    }
+}
@@ -1,4 +1,15 @@
-use clap::{Parser, Subcommand};
+use crate::auth::KeyUsage;
+use crate::auth::hosts::Source;
+use crate::bundle::ExportFormat;
+use crate::credential::Operation as CredentialOperation;
+use crate::devcontainer::{Format, KeyMode};
+use crate::hooks::HookKind;
+use crate::shell::HookShell;
+use crate::ssh_keys::KeyType;
+use crate::vault::Manager;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use std::path::PathBuf;
 
 #[derive(Parser)]
 #[command(name = "gitid")]
@@ -6,6 +17,18 @@ use clap::{Parser, Subcommand};
 pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
+
+    /// Never prompt: use defaults, or fail when a value has to be given as a flag
+    #[arg(long, global = true)]
+    pub no_input: bool,
+
+    /// Answer yes to every confirmation prompt
+    #[arg(short, long, global = true)]
+    pub yes: bool,
+
+    /// Use this config file instead of $GITID_CONFIG or ~/.config/gitid/config.toml
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
 }
 
 #[derive(Subcommand)]
@@ -26,7 +49,7 @@ pub enum Commands {
         #[arg(long)]
         email: Option<String>,
 
-        /// Platform: github, gitlab, or both
+        /// Platform: github, gitlab, both, codeberg, gitea (also Forgejo), or custom
         #[arg(long)]
         platform: Option<String>,
 
@@ -34,6 +57,22 @@ pub enum Commands {
         #[arg(long)]
         ssh_key: Option<String>,
 
+        /// Generate a new ed25519 SSH key for the profile
+        #[arg(long, conflicts_with = "ssh_key")]
+        generate_key: bool,
+
+        /// Algorithm for a generated SSH key; ed25519-sk needs a FIDO2 security key
+        #[arg(long, value_enum, default_value_t = KeyType::Ed25519)]
+        key_type: KeyType,
+
+        /// Store a generated security key credential on the device (ed25519-sk only)
+        #[arg(long)]
+        resident: bool,
+
+        /// Read the generated key's passphrase from the first line of stdin
+        #[arg(long)]
+        passphrase_stdin: bool,
+
         /// GPG signing key ID (optional)
         #[arg(long)]
         gpg_key: Option<String>,
@@ -41,6 +80,23 @@ pub enum Commands {
         /// Custom host for enterprise instances (optional)
         #[arg(long)]
         host: Option<String>,
+
+        /// GitHub/GitLab username of the account (optional)
+        #[arg(long)]
+        username: Option<String>,
+
+        /// Tag for grouping profiles (repeatable)
+        #[arg(long = "tag", value_name = "TAG")]
+        tags: Vec<String>,
+
+        /// What the profile is for, e.g. "Acme contract until 2025-06"
+        #[arg(long)]
+        description: Option<String>,
+
+        /// Start from the global git config's user.name, user.email, signing
+        /// key and core.sshCommand key
+        #[arg(long)]
+        from_git: bool,
     },
 
     /// Remove a profile
@@ -57,40 +113,700 @@ pub enum Commands {
         clean_ssh: bool,
     },
 
+    /// Rename a profile, moving its SSH aliases to the new name
+    Rename {
+        /// Current profile name
+        old: String,
+
+        /// New profile name
+        new: String,
+    },
+
+    /// Show or set the default profile, used when nothing else picks one
+    Default {
+        /// Profile to make the default (shows the current default if not provided)
+        name: Option<String>,
+
+        /// Clear the default profile
+        #[arg(long, conflicts_with = "name")]
+        unset: bool,
+    },
+
+    /// Show or set the profile pinned to this repository, which detection
+    /// picks over any remote or rule
+    Pin {
+        /// Profile to pin (shows the current pin if not provided)
+        name: Option<String>,
+
+        /// Remove the pin
+        #[arg(long, conflicts_with = "name")]
+        unset: bool,
+    },
+
     /// List all configured profiles
-    List,
+    List {
+        /// Print profiles as a JSON array
+        #[arg(long, conflicts_with = "format")]
+        json: bool,
+
+        /// Print one line per profile from a template, e.g. '{profile}\t{email}'
+        #[arg(long, value_name = "TEMPLATE")]
+        format: Option<String>,
+
+        /// Check each profile's SSH key, GPG key, SSH config entries and gh/glab sign-in
+        #[arg(short, long)]
+        verbose: bool,
+
+        /// Only list profiles with this tag
+        #[arg(long)]
+        tag: Option<String>,
+
+        /// Order of the profiles
+        #[arg(long, value_enum, default_value_t = ListSort::Name)]
+        sort: ListSort,
+    },
+
+    /// Show how often and how recently each profile was applied
+    Stats {
+        /// Print usage as a JSON array
+        #[arg(long)]
+        json: bool,
+    },
 
     /// Switch to a profile
     Use {
-        /// Profile name to switch to (interactive if not provided)
+        /// Profile name to switch to (interactive if not provided), or '-'
+        /// for the one applied before the current one
         name: Option<String>,
 
+        /// Pick among the profiles with this tag
+        #[arg(long, conflicts_with = "name")]
+        tag: Option<String>,
+
         /// Apply globally instead of to current repository
         #[arg(short, long)]
         global: bool,
+
+        /// Also set core.sshCommand so tools that ignore ~/.ssh/config use the profile's key
+        #[arg(long)]
+        ssh_command: bool,
+
+        /// Use this one of the profile's SSH keys (path or file name); sets
+        /// core.sshCommand to it
+        #[arg(long)]
+        key: Option<String>,
+
+        /// Load the profile's key into ssh-agent and remove other profiles' keys
+        #[arg(long, conflicts_with = "no_agent")]
+        agent: bool,
+
+        /// Leave ssh-agent alone even if ssh_agent is set in the config
+        #[arg(long)]
+        no_agent: bool,
+
+        /// Point remotes on the profile's hosts at its SSH alias
+        #[arg(long, conflicts_with_all = ["global", "restore_remote"])]
+        rewrite_remote: bool,
+
+        /// Point remotes that use a gitid SSH alias back at the real host
+        #[arg(long, conflicts_with = "global")]
+        restore_remote: bool,
+    },
+
+    /// Switch back to the profile applied before the current one (same as 'gitid use -')
+    Recent {
+        /// Toggle the global identity instead of the current repository's
+        #[arg(short, long)]
+        global: bool,
+    },
+
+    /// Remove the applied identity so git falls back to the global one
+    Unset {
+        /// Clear the global identity instead of the current repository's
+        #[arg(short, long)]
+        global: bool,
+    },
+
+    /// Detect (or take) a profile, apply it, fix remotes, and load its key
+    Switch {
+        /// Profile name to switch to (detected if not provided)
+        name: Option<String>,
+
+        /// Leave remote URLs unchanged
+        #[arg(long)]
+        no_rewrite: bool,
+
+        /// Don't load the profile's key into ssh-agent
+        #[arg(long)]
+        no_agent: bool,
     },
 
     /// Authenticate CLI tools (gh/glab) for a profile
     Auth {
         /// Profile name to authenticate (interactive if not provided)
         name: Option<String>,
+
+        /// Read a token from stdin instead of running the interactive login
+        #[arg(long, conflicts_with = "token_env")]
+        with_token: bool,
+
+        /// Read the token from this environment variable
+        #[arg(long, value_name = "VAR", conflicts_with = "from_keyring")]
+        token_env: Option<String>,
+
+        /// Log gh/glab in with the token stored for the profile in the OS keyring
+        #[arg(long, conflicts_with_all = ["with_token", "keyring"])]
+        from_keyring: bool,
+
+        /// Store the token in the OS keyring instead of handing it to gh/glab
+        #[arg(long)]
+        keyring: bool,
+
+        /// Also make the profile's GitLab instance glab's default host
+        #[arg(long, conflicts_with = "keyring")]
+        glab_host: bool,
+    },
+
+    /// Check which account a profile's SSH key authenticates as
+    Test {
+        /// Profile name
+        name: String,
+
+        /// Test only this one of the profile's SSH keys (path or file name)
+        #[arg(long)]
+        key: Option<String>,
+    },
+
+    /// Sign and verify a throwaway commit with a profile's signing key
+    SignTest {
+        /// Profile name
+        name: String,
+    },
+
+    /// Ask the platform API which account a profile's token authenticates as
+    Whoami {
+        /// Profile name (the current profile if not provided)
+        name: Option<String>,
     },
 
     /// Show current active profile
     Current {
         /// Machine-readable output for shell prompts
-        #[arg(long)]
+        #[arg(long, conflicts_with = "json")]
         porcelain: bool,
+
+        /// Print the current identity as a JSON object
+        #[arg(long)]
+        json: bool,
     },
 
+    /// Compare the applied identity with the profile detection expects for this repository
+    Status,
+
     /// Auto-detect appropriate profile from repository remote
     Detect {
         /// Automatically apply detected profile without prompting
         #[arg(short, long)]
         auto: bool,
+
+        /// Show every profile's score per remote and what it is made of, without applying anything
+        #[arg(long, conflicts_with = "auto")]
+        explain: bool,
+    },
+
+    /// Clone a repository through the matching profile's SSH alias and apply its identity
+    Clone {
+        /// Repository URL
+        url: String,
+
+        /// Directory to clone into (defaults to the repository name)
+        directory: Option<PathBuf>,
+
+        /// Profile to clone with (detected from the URL if not provided)
+        #[arg(short, long)]
+        profile: Option<String>,
+
+        /// Extra arguments passed to git clone (after --)
+        #[arg(last = true)]
+        args: Vec<String>,
     },
 
     /// Sync SSH config with all profiles
     #[command(name = "ssh-sync")]
-    SshSync,
+    SshSync {
+        /// Show the changes to the managed block without writing them
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Restore the SSH config from a backup taken by ssh-sync
+    #[command(name = "ssh-restore")]
+    SshRestore {
+        /// Timestamp of the backup to restore (the newest if not provided)
+        stamp: Option<u64>,
+
+        /// List the available backups instead
+        #[arg(long, conflicts_with = "stamp")]
+        list: bool,
+    },
+
+    /// Print shell export statements for a profile (eval "$(gitid env work)")
+    Env {
+        /// Profile name
+        name: String,
+    },
+
+    /// Print devcontainer/docker settings that forward a profile's identity
+    Devcontainer {
+        /// Profile name
+        name: String,
+
+        /// How the SSH key reaches the container
+        #[arg(long, value_enum, default_value_t = KeyMode::Agent)]
+        keys: KeyMode,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = Format::Json)]
+        format: Format,
+
+        /// Home directory of the container user
+        #[arg(long, default_value = "/home/vscode")]
+        home: String,
+    },
+
+    /// Manage the current repository's remotes
+    Remote {
+        #[command(subcommand)]
+        action: RemoteAction,
+    },
+
+    /// Copy profiles to another machine over SSH and set up its SSH/git config
+    #[command(name = "remote-install")]
+    RemoteInstall {
+        /// SSH destination, e.g. user@host
+        target: String,
+
+        /// Profile to install (repeatable; defaults to all profiles)
+        #[arg(short, long = "profile")]
+        profiles: Vec<String>,
+
+        /// Also copy private keys (by default only public keys are copied and
+        /// the agent is forwarded)
+        #[arg(long)]
+        include_keys: bool,
+
+        /// Skip the connectivity check
+        #[arg(long)]
+        no_verify: bool,
+    },
+
+    /// Run a command with a profile's identity in the environment
+    Exec {
+        /// Profile name
+        name: String,
+
+        /// Command and arguments to run
+        #[arg(last = true, required = true)]
+        command: Vec<String>,
+    },
+
+    /// Set up the current repository: profile, identity, remotes and checks
+    #[command(name = "setup-repo")]
+    SetupRepo,
+
+    /// Install and run git hooks that guard the repository's identity
+    Hook {
+        #[command(subcommand)]
+        action: HookAction,
+    },
+
+    /// Undo recent changes gitid made to git and SSH config
+    Rollback {
+        /// Number of operations to undo
+        #[arg(default_value_t = 1)]
+        count: usize,
+
+        /// Show recorded operations instead of undoing them
+        #[arg(long)]
+        list: bool,
+
+        /// Skip confirmation prompt
+        #[arg(short, long)]
+        force: bool,
+    },
+
+    /// Write keyring tokens for opted-in profiles into a managed ~/.netrc block
+    #[command(name = "netrc-sync")]
+    NetrcSync,
+
+    /// Git credential helper returning keyring tokens for HTTPS remotes
+    /// (credential.helper = "!gitid credential")
+    Credential {
+        /// Operation git asks for
+        #[arg(value_enum)]
+        operation: CredentialOperation,
+    },
+
+    /// Write per-profile gitconfig fragments and includeIf rules for [detect.directories]
+    #[command(name = "gitconfig-sync")]
+    GitconfigSync,
+
+    /// Turn hand-written includeIf "gitdir:..." sections of the global
+    /// gitconfig into profiles and directory rules
+    Migrate {
+        /// Remove the migrated sections, replacing them with gitid's managed includes
+        #[arg(long)]
+        remove: bool,
+
+        /// Show what would be imported without changing anything
+        #[arg(long, conflicts_with = "remove")]
+        dry_run: bool,
+    },
+
+    /// Manage platform tokens stored in the OS keyring
+    Token {
+        #[command(subcommand)]
+        action: TokenAction,
+    },
+
+    /// Manage profile SSH keys
+    Key {
+        #[command(subcommand)]
+        action: KeyAction,
+    },
+
+    /// Export profiles to stdout, or with their keys as a bundle for another machine
+    Export {
+        /// Write a bundle of config plus key pairs to this file
+        #[arg(long, value_name = "FILE")]
+        bundle: Option<PathBuf>,
+
+        /// Encrypt the bundle with a passphrase (age)
+        #[arg(long, requires = "bundle")]
+        encrypt: bool,
+
+        /// Format of the plain export
+        #[arg(long, value_enum, default_value_t = ExportFormat::Toml, conflicts_with = "bundle")]
+        format: ExportFormat,
+
+        /// Leave out SSH key paths and GnuPG homes, which are machine-specific
+        #[arg(long, conflicts_with = "bundle")]
+        no_key_paths: bool,
+    },
+
+    /// Import profiles exported with 'gitid export', or signed in to with gh or glab
+    Import {
+        /// Plain export to merge into the config ("-" for stdin)
+        #[arg(required_unless_present_any = ["bundle", "from"], conflicts_with_all = ["bundle", "from"])]
+        file: Option<PathBuf>,
+
+        /// Restore a bundle created with 'gitid export --bundle'
+        #[arg(long, value_name = "FILE", conflicts_with = "from")]
+        bundle: Option<PathBuf>,
+
+        /// Create profiles for the accounts gh or glab is signed in to
+        #[arg(long, value_name = "CLI")]
+        from: Option<Source>,
+
+        /// Overwrite differing key files and profiles without asking
+        #[arg(short, long)]
+        force: bool,
+    },
+
+    /// Run a query server that answers current/detect from memory
+    Daemon,
+
+    /// Rewrite the author and committer of recent unpushed commits
+    FixCommits {
+        /// Number of commits to rewrite, counting back from HEAD
+        #[arg(long, default_value_t = 1)]
+        last: usize,
+
+        /// Profile to attribute the commits to (defaults to the detected one)
+        #[arg(short, long)]
+        profile: Option<String>,
+    },
+
+    /// Print a shell hook that applies the detected profile on cd
+    /// (eval "$(gitid shell-init zsh)")
+    ShellInit {
+        /// Shell to print the hook for
+        #[arg(value_enum)]
+        shell: HookShell,
+    },
+
+    /// Run the cd hook installed by 'gitid shell-init'
+    #[command(hide = true)]
+    ShellHook,
+
+    /// Print a shell completion script
+    Completions {
+        /// Shell to generate completions for
+        #[arg(value_enum)]
+        shell: CompletionShell,
+    },
+
+    /// Check every repository under a directory for the wrong identity
+    Audit {
+        /// Directory to scan (defaults to the current directory)
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+
+        /// Apply the detected profile to mismatched repositories
+        #[arg(long)]
+        fix: bool,
+
+        /// Check the repositories recorded by `gitid scan` instead
+        #[arg(long)]
+        registry: bool,
+    },
+
+    /// Detect and apply the matching profile locally in every repository
+    /// under a directory
+    ApplyAll {
+        /// Directory to scan
+        #[arg(required_unless_present = "registry")]
+        dir: Option<PathBuf>,
+
+        /// Use the repositories recorded by `gitid scan` instead
+        #[arg(long, conflicts_with = "dir")]
+        registry: bool,
+
+        /// Show what would be applied without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Record the repositories under the scan roots, with their remotes and
+    /// identities, for `audit --registry` and `apply-all --registry`
+    Scan {
+        /// Directories to scan (defaults to scan_roots from the config)
+        dirs: Vec<PathBuf>,
+    },
+
+    /// Check this machine's setup and optionally repair what's safe to fix
+    Doctor {
+        /// Offer to apply the fixes, then check again (with --yes, apply
+        /// every fix without asking)
+        #[arg(long)]
+        fix: bool,
+    },
+
+    /// Remove everything gitid has set up on this machine
+    Uninstall {
+        /// Also delete the config file and keyring tokens
+        #[arg(long)]
+        purge: bool,
+
+        /// Skip confirmation prompt
+        #[arg(short, long)]
+        force: bool,
+    },
+
+    /// Inspect and validate the configuration file
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ConfigAction {
+    /// Check the config for errors and suspicious cross-profile settings
+    Validate,
+
+    /// Print a JSON Schema for config.toml (for editor completion and validation)
+    Schema {
+        /// Describe a project's .gitid.toml instead
+        #[arg(long)]
+        project: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum KeyAction {
+    /// Print a profile's public key with its fingerprint and type
+    Show {
+        /// Profile name
+        name: String,
+
+        /// Print only the SHA256 fingerprint
+        #[arg(long, conflicts_with = "qr")]
+        fingerprint: bool,
+
+        /// Render as a QR code for scanning with a phone
+        #[arg(long)]
+        qr: bool,
+
+        /// Encode the platform's "add SSH key" page instead of the key itself
+        #[arg(long, requires = "qr")]
+        url: bool,
+    },
+
+    /// Upload a profile's public key to its GitHub/GitLab account
+    Upload {
+        /// Profile name
+        name: String,
+
+        /// Register the key for authentication, commit signing, or both
+        #[arg(long, value_enum, default_value_t = KeyUsage::Auth)]
+        usage: KeyUsage,
+    },
+
+    /// Replace a profile's SSH key with a freshly generated one
+    Rotate {
+        /// Profile name
+        name: String,
+
+        /// Upload the new public key and check that it authenticates
+        #[arg(long)]
+        upload: bool,
+
+        /// Delete the old key once the new one is verified, instead of archiving it
+        #[arg(long, requires = "upload")]
+        delete_old: bool,
+
+        /// Algorithm for the new key
+        #[arg(long, value_enum, default_value_t = KeyType::Ed25519)]
+        key_type: KeyType,
+
+        /// Read the new key's passphrase from the first line of stdin
+        #[arg(long)]
+        passphrase_stdin: bool,
+    },
+
+    /// List the keys profiles use with their algorithm, age and reuse, flagging weak or old ones
+    Audit {
+        /// Flag keys created more than this many days ago
+        #[arg(long, value_name = "DAYS", default_value_t = 730)]
+        max_age: u64,
+
+        /// Only audit the keys of profiles with this tag
+        #[arg(long)]
+        tag: Option<String>,
+    },
+
+    /// Attach an SSH key stored in a password manager to a profile
+    Import {
+        /// Profile name
+        name: String,
+
+        /// Password manager to read the key from
+        #[arg(long)]
+        from: Manager,
+
+        /// Item ID or title (interactive if not provided)
+        #[arg(long)]
+        item: Option<String>,
+
+        /// Also write the private key to disk instead of using the manager's SSH agent
+        #[arg(long)]
+        export: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum TokenAction {
+    /// Store a token for a profile (read from stdin when piped)
+    Set {
+        /// Profile name
+        name: String,
+    },
+
+    /// Delete a profile's stored token
+    Remove {
+        /// Profile name
+        name: String,
+    },
+
+    /// Print a profile's API token, minting a GitHub App installation token for bot profiles
+    Show {
+        /// Profile name
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum RemoteAction {
+    /// Point the remotes at the detected profile's SSH alias, or back at the real host
+    Fix {
+        /// Profile whose alias to use (detected if not provided)
+        #[arg(short, long, conflicts_with = "restore")]
+        profile: Option<String>,
+
+        /// Point remotes on any profile's alias back at the real hostname
+        #[arg(long)]
+        restore: bool,
+
+        /// Only show the rewrites
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum HookAction {
+    /// Install hook shims that call 'gitid hook run' into the current repository
+    Install {
+        /// Hooks to install
+        #[arg(long = "kind", value_enum, default_values_t = [HookKind::PreCommit])]
+        kinds: Vec<HookKind>,
+
+        /// Also install the pre-push guard, which checks remote owners and commit emails (same as --kind pre-push)
+        #[arg(long)]
+        pre_push: bool,
+
+        /// Replace existing hooks that gitid did not install
+        #[arg(short, long)]
+        force: bool,
+    },
+
+    /// Run a hook's logic (called by the installed shims)
+    Run {
+        /// Hook to run
+        #[arg(value_enum)]
+        kind: HookKind,
+
+        /// Arguments git passed to the hook
+        #[arg(last = true)]
+        args: Vec<String>,
+    },
+}
+
+/// Orders `gitid list` can print profiles in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ListSort {
+    /// Alphabetically by profile name
+    Name,
+    /// Most recently applied first
+    Recent,
+}
+
+/// Shells `gitid completions` can generate scripts for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CompletionShell {
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+    Nushell,
+}
+
+/// Write the completion script for a shell to stdout. Needs only the CLI
+/// definition, so it works before any config exists.
+pub fn print_completions(shell: CompletionShell) {
+    let mut cmd = Cli::command();
+    let name = cmd.get_name().to_string();
+    let out = &mut std::io::stdout();
+    match shell {
+        CompletionShell::Bash => clap_complete::generate(Shell::Bash, &mut cmd, name, out),
+        CompletionShell::Zsh => clap_complete::generate(Shell::Zsh, &mut cmd, name, out),
+        CompletionShell::Fish => clap_complete::generate(Shell::Fish, &mut cmd, name, out),
+        CompletionShell::Powershell => {
+            clap_complete::generate(Shell::PowerShell, &mut cmd, name, out)
+        }
+        CompletionShell::Nushell => {
+            clap_complete::generate(clap_complete_nushell::Nushell, &mut cmd, name, out)
+        }
+    }
 }
@@ -1,34 +1,379 @@
-use crate::profile::Profile;
-use anyhow::{Context, Result};
+use crate::policy::Policy;
+use crate::profile::{Profile, SshMode};
+use crate::ssh::SshLayout;
+use anyhow::{Context, Result, bail};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+/// Environment variable overriding the system config location
+pub const SYSTEM_CONFIG_ENV: &str = "GITID_SYSTEM_CONFIG";
+
+/// Environment variable pointing gitid at another user config file
+pub const CONFIG_ENV: &str = "GITID_CONFIG";
+
+/// Project config file looked for in the working directory and its parents
+pub const PROJECT_CONFIG_FILE: &str = ".gitid.toml";
+
+/// Config file chosen for this process with `set_config_path`
+static CONFIG_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// Use `path` as the user config file for the rest of the process, taking
+/// precedence over $GITID_CONFIG (the --config flag)
+pub fn set_config_path(path: PathBuf) {
+    let _ = CONFIG_PATH.set(path);
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub struct Config {
+    /// Profile used when no other profile is selected
     #[serde(skip_serializing_if = "Option::is_none")]
     pub default_profile: Option<String>,
+    /// How `use`, `switch`, `clone` and `setup-repo` point git at a
+    /// profile's key, unless the profile sets its own (default "alias")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ssh_mode: Option<SshMode>,
+    /// Whether `use` loads the profile's key into ssh-agent and removes
+    /// other profiles' keys, unless --agent/--no-agent is given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ssh_agent: Option<bool>,
+    /// Whether `ssh-sync` writes host entries into ~/.ssh/config ("inline",
+    /// the default) or into ~/.ssh/gitid_config ("include")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ssh_layout: Option<SshLayout>,
+    /// How many timestamped backups `ssh-sync` keeps of each SSH config file
+    /// it changes, for `gitid ssh-restore`; no backups when unset or 0
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ssh_backups: Option<usize>,
+    /// Whether `use` and `auth` make a GitLab profile's instance glab's
+    /// default host, so glab follows the active profile
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub glab_host: Option<bool>,
+    /// Directories `gitid scan` records repositories under when it is given
+    /// none, e.g. `["~/src", "~/work"]`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scan_roots: Vec<String>,
+    /// Profiles keyed by profile name
     #[serde(default)]
     pub profiles: HashMap<String, Profile>,
+    /// Rules that feed profile detection
+    #[serde(default, skip_serializing_if = "DetectConfig::is_empty")]
+    pub detect: DetectConfig,
+    /// Identity requirements for repositories on matching remotes
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub policies: Vec<Policy>,
+    /// System layer merged beneath this config, kept so its entries are not
+    /// written back into the user's file
+    #[serde(skip)]
+    pub(crate) system: Option<Box<Config>>,
+    /// Project layer merged over this config, kept for the same reason
+    #[serde(skip)]
+    pub(crate) project: Option<Box<ProjectLayer>>,
+}
+
+/// A `.gitid.toml` checked into a repository (or a directory above
+/// several), so everyone working in it gets the same identity setup
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct ProjectConfig {
+    /// Profile to use here, ahead of anything detection would pick
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    /// Overrides the user config's `ssh_mode`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ssh_mode: Option<SshMode>,
+    /// Overrides the user config's `ssh_agent`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ssh_agent: Option<bool>,
+    /// Overrides the user config's `glab_host`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub glab_host: Option<bool>,
+    /// Detection rules added to the user's. Relative directory patterns are
+    /// relative to the directory holding the file.
+    #[serde(default, skip_serializing_if = "DetectConfig::is_empty")]
+    pub detect: DetectConfig,
+    /// Policies added to the user's
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub policies: Vec<Policy>,
+}
+
+/// Project config merged into a `Config`, with the config as it was before,
+/// so `user_layer` can hand back the user's own values
+#[derive(Debug, Clone)]
+pub(crate) struct ProjectLayer {
+    path: PathBuf,
+    project: ProjectConfig,
+    before: Config,
+}
+
+/// The `[detect]` section of the config
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct DetectConfig {
+    /// Email patterns mapped to profile names, e.g. `"*@acme.com" = "work"`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub email_domains: BTreeMap<String, String>,
+    /// Directory patterns mapped to profile names, e.g. `"~/work/**" = "work"`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub directories: BTreeMap<String, String>,
+    /// Profile for repositories nothing else matches; the default profile
+    /// if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fallback: Option<String>,
+}
+
+impl DetectConfig {
+    pub fn is_empty(&self) -> bool {
+        self.email_domains.is_empty() && self.directories.is_empty() && self.fallback.is_none()
+    }
 }
 
 impl Config {
-    /// Load config from the default location (~/.config/gitid/config.toml)
+    /// Load config from the default location (~/.config/gitid/config.toml),
+    /// layered over the system config if one exists and under the project
+    /// config of the working directory
     pub fn load() -> Result<Self> {
-        let path = Self::config_path()?;
+        let config = Self::load_user()?;
+        match std::env::current_dir() {
+            Ok(cwd) => config.with_project(&cwd),
+            Err(_) => Ok(config),
+        }
+    }
+
+    /// Load the user config over the system config, without a project layer
+    pub fn load_user() -> Result<Self> {
+        let mut config = Self::load_file(&Self::config_path()?)?;
+
+        let system_path = Self::system_config_path();
+        if system_path.exists() {
+            config.merge_system(Self::load_file(&system_path)?);
+        }
+
+        Ok(config)
+    }
+
+    /// This config with the project config for `dir` merged over it, if
+    /// `dir` or one of its parents has one
+    pub fn with_project(mut self, dir: &Path) -> Result<Self> {
+        if let Some(path) = find_project_config(dir) {
+            let content = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read project config: {}", path.display()))?;
+            let project: ProjectConfig = toml::from_str(&content)
+                .with_context(|| format!("Failed to parse project config: {}", path.display()))?;
+            self.merge_project(path, project);
+        }
+        Ok(self)
+    }
+
+    fn load_file(path: &Path) -> Result<Self> {
         if !path.exists() {
             return Ok(Config::default());
         }
 
-        let content = fs::read_to_string(&path)
+        let content = fs::read_to_string(path)
             .with_context(|| format!("Failed to read config file: {}", path.display()))?;
 
         toml::from_str(&content)
             .with_context(|| format!("Failed to parse config file: {}", path.display()))
     }
 
-    /// Save config to the default location
+    /// Fill in profiles and rules from the system layer. Entries in the user
+    /// config win over system entries with the same name.
+    fn merge_system(&mut self, system: Config) {
+        if self.default_profile.is_none() {
+            self.default_profile = system.default_profile.clone();
+        }
+        if self.ssh_mode.is_none() {
+            self.ssh_mode = system.ssh_mode;
+        }
+        if self.ssh_agent.is_none() {
+            self.ssh_agent = system.ssh_agent;
+        }
+        if self.glab_host.is_none() {
+            self.glab_host = system.glab_host;
+        }
+        if self.ssh_layout.is_none() {
+            self.ssh_layout = system.ssh_layout;
+        }
+        if self.ssh_backups.is_none() {
+            self.ssh_backups = system.ssh_backups;
+        }
+        for root in &system.scan_roots {
+            if !self.scan_roots.contains(root) {
+                self.scan_roots.push(root.clone());
+            }
+        }
+        for (name, profile) in &system.profiles {
+            self.profiles
+                .entry(name.clone())
+                .or_insert_with(|| profile.clone());
+        }
+        for (pattern, target) in &system.detect.email_domains {
+            self.detect
+                .email_domains
+                .entry(pattern.clone())
+                .or_insert_with(|| target.clone());
+        }
+        for (pattern, target) in &system.detect.directories {
+            self.detect
+                .directories
+                .entry(pattern.clone())
+                .or_insert_with(|| target.clone());
+        }
+        if self.detect.fallback.is_none() {
+            self.detect.fallback = system.detect.fallback.clone();
+        }
+        for policy in &system.policies {
+            if !self.policies.contains(policy) {
+                self.policies.push(policy.clone());
+            }
+        }
+        self.system = Some(Box::new(system));
+    }
+
+    /// Lay a project config over this one. Its settings, rules and policies
+    /// win over the user's.
+    fn merge_project(&mut self, path: PathBuf, mut project: ProjectConfig) {
+        let before = self.clone();
+        let dir = path.parent().unwrap_or(Path::new(""));
+        project.detect.directories = project
+            .detect
+            .directories
+            .into_iter()
+            .map(|(pattern, target)| (resolve_pattern(dir, &pattern), target))
+            .collect();
+
+        if project.ssh_mode.is_some() {
+            self.ssh_mode = project.ssh_mode;
+        }
+        if project.ssh_agent.is_some() {
+            self.ssh_agent = project.ssh_agent;
+        }
+        if project.glab_host.is_some() {
+            self.glab_host = project.glab_host;
+        }
+        for (pattern, target) in &project.detect.email_domains {
+            self.detect.email_domains.insert(pattern.clone(), target.clone());
+        }
+        for (pattern, target) in &project.detect.directories {
+            self.detect.directories.insert(pattern.clone(), target.clone());
+        }
+        if project.detect.fallback.is_some() {
+            self.detect.fallback = project.detect.fallback.clone();
+        }
+        for policy in &project.policies {
+            if !self.policies.contains(policy) {
+                self.policies.push(policy.clone());
+            }
+        }
+        self.project = Some(Box::new(ProjectLayer { path, project, before }));
+    }
+
+    /// Undo `merge_project` for whatever hasn't changed since
+    fn strip_project(&mut self, layer: &ProjectLayer) {
+        let (project, before) = (&layer.project, &layer.before);
+        if project.ssh_mode.is_some() && self.ssh_mode == project.ssh_mode {
+            self.ssh_mode = before.ssh_mode;
+        }
+        if project.ssh_agent.is_some() && self.ssh_agent == project.ssh_agent {
+            self.ssh_agent = before.ssh_agent;
+        }
+        if project.glab_host.is_some() && self.glab_host == project.glab_host {
+            self.glab_host = before.glab_host;
+        }
+        if project.detect.fallback.is_some() && self.detect.fallback == project.detect.fallback {
+            self.detect.fallback = before.detect.fallback.clone();
+        }
+        let rules = [
+            (&mut self.detect.email_domains, &project.detect.email_domains, &before.detect.email_domains),
+            (&mut self.detect.directories, &project.detect.directories, &before.detect.directories),
+        ];
+        for (current, added, previous) in rules {
+            for (pattern, target) in added {
+                if current.get(pattern) == Some(target) {
+                    match previous.get(pattern) {
+                        Some(old) => current.insert(pattern.clone(), old.clone()),
+                        None => current.remove(pattern),
+                    };
+                }
+            }
+        }
+        self.policies
+            .retain(|p| !project.policies.contains(p) || before.policies.contains(p));
+    }
+
+    /// Project config file merged into this config, if any
+    pub fn project_path(&self) -> Option<&Path> {
+        self.project.as_ref().map(|layer| layer.path.as_path())
+    }
+
+    /// Profile the project config pins, if any
+    pub fn project_profile(&self) -> Option<&str> {
+        self.project.as_ref()?.project.profile.as_deref()
+    }
+
+    /// Default profile set by the system config, if any
+    pub fn system_default(&self) -> Option<String> {
+        self.system.as_ref()?.default_profile.clone()
+    }
+
+    /// The part of this config that belongs in the user's file
+    pub fn user_layer(&self) -> Config {
+        let mut user = self.clone();
+        user.system = None;
+        user.project = None;
+        if let Some(ref layer) = self.project {
+            user.strip_project(layer);
+        }
+
+        if let Some(ref system) = self.system {
+            if user.default_profile == system.default_profile {
+                user.default_profile = None;
+            }
+            if user.ssh_mode == system.ssh_mode {
+                user.ssh_mode = None;
+            }
+            if user.ssh_agent == system.ssh_agent {
+                user.ssh_agent = None;
+            }
+            if user.glab_host == system.glab_host {
+                user.glab_host = None;
+            }
+            if user.ssh_layout == system.ssh_layout {
+                user.ssh_layout = None;
+            }
+            if user.ssh_backups == system.ssh_backups {
+                user.ssh_backups = None;
+            }
+            if user.detect.fallback == system.detect.fallback {
+                user.detect.fallback = None;
+            }
+            user.scan_roots.retain(|r| !system.scan_roots.contains(r));
+            user.profiles
+                .retain(|name, p| system.profiles.get(name) != Some(p));
+            user.detect
+                .email_domains
+                .retain(|pattern, t| system.detect.email_domains.get(pattern) != Some(t));
+            user.detect
+                .directories
+                .retain(|pattern, t| system.detect.directories.get(pattern) != Some(t));
+            user.policies.retain(|p| !system.policies.contains(p));
+        }
+
+        user
+    }
+
+    /// Whether a profile comes unchanged from the system config
+    pub fn is_system_profile(&self, name: &str) -> bool {
+        match (&self.system, self.profiles.get(name)) {
+            (Some(system), Some(profile)) => system.profiles.get(name) == Some(profile),
+            _ => false,
+        }
+    }
+
+    /// Save config to the user config file. Entries from the system layer
+    /// are left out.
     pub fn save(&self) -> Result<()> {
         let path = Self::config_path()?;
 
@@ -38,20 +383,61 @@ impl Config {
                 .with_context(|| format!("Failed to create config directory: {}", parent.display()))?;
         }
 
-        let content = toml::to_string_pretty(self)
+        let content = toml::to_string_pretty(&self.user_layer())
             .context("Failed to serialize config")?;
 
         fs::write(&path, content)
             .with_context(|| format!("Failed to write config file: {}", path.display()))
     }
 
-    /// Get the config file path
+    /// Get the config file path: the one set with `set_config_path`, else
+    /// $GITID_CONFIG, else ~/.config/gitid/config.toml
     pub fn config_path() -> Result<PathBuf> {
+        if let Some(path) = Self::custom_path() {
+            return std::path::absolute(&path)
+                .with_context(|| format!("Invalid config path: {}", path.display()));
+        }
         let config_dir = dirs::config_dir()
             .context("Could not determine config directory")?;
         Ok(config_dir.join("gitid").join("config.toml"))
     }
 
+    /// Config file given with --config or $GITID_CONFIG, if any
+    pub fn custom_path() -> Option<PathBuf> {
+        CONFIG_PATH.get().cloned().or_else(|| {
+            std::env::var_os(CONFIG_ENV)
+                .filter(|p| !p.is_empty())
+                .map(PathBuf::from)
+        })
+    }
+
+    /// Get the system-wide config path ($GITID_SYSTEM_CONFIG, or
+    /// /etc/gitid/config.toml)
+    pub fn system_config_path() -> PathBuf {
+        if let Some(path) = std::env::var_os(SYSTEM_CONFIG_ENV) {
+            return PathBuf::from(path);
+        }
+        if cfg!(windows) {
+            let base = std::env::var_os("ProgramData").unwrap_or_else(|| "C:\\ProgramData".into());
+            PathBuf::from(base).join("gitid").join("config.toml")
+        } else {
+            PathBuf::from("/etc/gitid/config.toml")
+        }
+    }
+
+    /// Get the directory for gitid's runtime state (journal, caches)
+    pub fn state_dir() -> Result<PathBuf> {
+        let state_dir = dirs::state_dir()
+            .or_else(dirs::data_local_dir)
+            .context("Could not determine state directory")?;
+        Ok(state_dir.join("gitid"))
+    }
+
+    /// SSH mode for a profile: its own, else the config-wide one
+    pub fn ssh_mode(&self, profile: &Profile) -> SshMode {
+        profile.ssh_mode.or(self.ssh_mode).unwrap_or_default()
+    }
+
     /// Add a profile to the config
     pub fn add_profile(&mut self, name: String, profile: Profile) -> Result<()> {
         profile.validate()?;
@@ -61,13 +447,62 @@ impl Config {
 
     /// Remove a profile from the config
     pub fn remove_profile(&mut self, name: &str) -> Option<Profile> {
-        // If this was the default profile, clear it
+        // If this was the default or fallback profile, clear it
         if self.default_profile.as_deref() == Some(name) {
             self.default_profile = None;
         }
+        if self.detect.fallback.as_deref() == Some(name) {
+            self.detect.fallback = None;
+        }
         self.profiles.remove(name)
     }
 
+    /// Rename a profile, carrying the default profile, email rules and
+    /// policy references over to the new name
+    pub fn rename_profile(&mut self, old: &str, new: &str) -> Result<()> {
+        if self.has_profile(new) {
+            bail!("Profile '{}' already exists", new);
+        }
+        let profile = self
+            .profiles
+            .remove(old)
+            .with_context(|| format!("Profile '{}' not found", old))?;
+        // The token moves with a profile using its own name as the keyring
+        // account, so profiles sharing that token follow it
+        if profile.keyring.is_none() {
+            for other in self.profiles.values_mut() {
+                if other.keyring.as_deref() == Some(old) {
+                    other.keyring = Some(new.to_string());
+                }
+            }
+        }
+        self.profiles.insert(new.to_string(), profile);
+
+        if self.default_profile.as_deref() == Some(old) {
+            self.default_profile = Some(new.to_string());
+        }
+        for target in self
+            .detect
+            .email_domains
+            .values_mut()
+            .chain(self.detect.directories.values_mut())
+            .chain(self.detect.fallback.as_mut())
+        {
+            if target == old {
+                *target = new.to_string();
+            }
+        }
+        for policy in &mut self.policies {
+            for name in &mut policy.profiles {
+                if name == old {
+                    *name = new.to_string();
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     /// Get a profile by name
     pub fn get_profile(&self, name: &str) -> Option<&Profile> {
         self.profiles.get(name)
@@ -85,6 +520,28 @@ impl Config {
         names
     }
 
+    /// Names of the profiles tagged `tag`, or of all profiles without one,
+    /// sorted alphabetically
+    pub fn tagged_profile_names(&self, tag: Option<&str>) -> Vec<&String> {
+        let mut names = self.profile_names();
+        if let Some(tag) = tag {
+            names.retain(|name| self.profiles[*name].has_tag(tag));
+        }
+        names
+    }
+
+    /// JSON Schema describing the config file format
+    pub fn json_schema() -> Result<String> {
+        let schema = schemars::schema_for!(Config);
+        serde_json::to_string_pretty(&schema).context("Failed to serialize config schema")
+    }
+
+    /// JSON Schema describing the project config file format
+    pub fn project_json_schema() -> Result<String> {
+        let schema = schemars::schema_for!(ProjectConfig);
+        serde_json::to_string_pretty(&schema).context("Failed to serialize project config schema")
+    }
+
     /// Initialize config directory and create empty config if not exists
     pub fn init() -> Result<bool> {
         let path = Self::config_path()?;
@@ -99,12 +556,33 @@ impl Config {
     }
 }
 
+/// Nearest `.gitid.toml` in `dir` or one of its parents
+pub fn find_project_config(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|d| d.join(PROJECT_CONFIG_FILE))
+        .find(|path| path.is_file())
+}
+
+/// Directory pattern from a project config, anchored at the file's
+/// directory unless it is absolute or starts at the home directory
+fn resolve_pattern(dir: &Path, pattern: &str) -> String {
+    if pattern.starts_with('~') || Path::new(pattern).is_absolute() {
+        return pattern.to_string();
+    }
+    let pattern = pattern.trim_start_matches("./");
+    if pattern.is_empty() || pattern == "." {
+        return dir.to_string_lossy().into_owned();
+    }
+    dir.join(pattern).to_string_lossy().into_owned()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::profile::Platform;
 
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_config_serialization() {
         let mut config = Config::default();
         config.default_profile = Some("personal".to_string());
@@ -124,6 +602,46 @@ mod tests {
         assert!(toml_str.contains("[profiles.personal]"));
     }
 
+    #[test]
+    fn test_rename_profile() {
+        let toml_str = r#"
+default_profile = "acme"
+
+[profiles.acme]
+name = "John Doe"
+email = "john@acme.com"
+platform = "github"
+ssh_key = "~/.ssh/id_acme"
+
+[profiles.acme-ci]
+name = "Acme CI"
+email = "ci@acme.com"
+platform = "github"
+ssh_key = "~/.ssh/id_acme_ci"
+keyring = "acme"
+
+[detect.email_domains]
+"*@acme.com" = "acme"
+
+[detect.directories]
+"~/acme/**" = "acme"
+
+[[policies]]
+host = "github.com"
+profiles = ["acme"]
+"#;
+        let mut config: Config = toml::from_str(toml_str).unwrap();
+        config.rename_profile("acme", "globex").unwrap();
+
+        assert!(config.has_profile("globex") && !config.has_profile("acme"));
+        assert_eq!(config.default_profile.as_deref(), Some("globex"));
+        assert_eq!(config.detect.email_domains["*@acme.com"], "globex");
+        assert_eq!(config.detect.directories["~/acme/**"], "globex");
+        assert_eq!(config.profiles["acme-ci"].keyring.as_deref(), Some("globex"));
+        assert_eq!(config.policies[0].profiles, vec!["globex"]);
+        assert!(config.rename_profile("missing", "other").is_err());
+    }
+
     #[test]
     fn test_config_deserialization() {
         let toml_str = r#"
@@ -145,4 +663,215 @@ gpg_key = "ABCD1234"
         assert_eq!(profile.name, "John Doe");
         assert_eq!(profile.gpg_key, Some("ABCD1234".to_string()));
     }
+
+    #[test]
+    fn test_tagged_profile_names() {
+        let toml_str = r#"
+[profiles.acme]
+name = "John Doe"
+email = "john@acme.com"
+platform = "github"
+ssh_key = "~/.ssh/id_acme"
+tags = ["client", "acme"]
+
+[profiles.globex]
+name = "John Doe"
+email = "john@globex.com"
+platform = "gitlab"
+ssh_key = "~/.ssh/id_globex"
+tags = ["Client"]
+
+[profiles.personal]
+name = "John Doe"
+email = "john@example.com"
+platform = "github"
+ssh_key = "~/.ssh/id_ed25519"
+"#;
+
+        let config: Config = toml::from_str(toml_str).unwrap();
+        assert_eq!(config.tagged_profile_names(Some("client")), vec!["acme", "globex"]);
+        assert_eq!(config.tagged_profile_names(Some("acme")), vec!["acme"]);
+        assert!(config.tagged_profile_names(Some("oss")).is_empty());
+        assert_eq!(config.tagged_profile_names(None).len(), 3);
+    }
+
+    #[test]
+    fn test_ssh_mode_precedence() {
+        let toml_str = r#"
+ssh_mode = "command"
+
+[profiles.work]
+name = "John Doe"
+email = "john@company.com"
+platform = "github"
+ssh_key = "~/.ssh/id_work"
+
+[profiles.personal]
+name = "John Doe"
+email = "john@example.com"
+platform = "github"
+ssh_key = "~/.ssh/id_ed25519"
+ssh_mode = "alias"
+"#;
+
+        let mut config: Config = toml::from_str(toml_str).unwrap();
+        assert_eq!(config.ssh_mode(&config.profiles["work"]), SshMode::Command);
+        assert_eq!(config.ssh_mode(&config.profiles["personal"]), SshMode::Alias);
+
+        config.ssh_mode = None;
+        assert_eq!(config.ssh_mode(&config.profiles["work"]), SshMode::Alias);
+    }
+
+    #[test]
+    fn test_detect_section_deserialization() {
+        let toml_str = r#"
+[detect.email_domains]
+"*@acme.com" = "work"
+"#;
+
+        let config: Config = toml::from_str(toml_str).unwrap();
+        assert_eq!(
+            config.detect.email_domains.get("*@acme.com"),
+            Some(&"work".to_string())
+        );
+    }
+
+    #[test]
+    fn test_system_layer_is_not_saved() {
+        let system: Config = toml::from_str(
+            r#"
+default_profile = "acme"
+
+[profiles.acme]
+name = "John Doe"
+email = "john@acme.com"
+platform = "gitlab"
+ssh_key = "~/.ssh/id_acme"
+host = "gitlab.acme.com"
+
+[detect.email_domains]
+"*@acme.com" = "acme"
+
+[[policies]]
+host = "gitlab.acme.com"
+require_signing = true
+"#,
+        )
+        .unwrap();
+
+        let mut config = Config::default();
+        config.profiles.insert(
+            "personal".to_string(),
+            Profile::new(
+                "John Doe".to_string(),
+                "john@example.com".to_string(),
+                Platform::Github,
+                "~/.ssh/id_ed25519".to_string(),
+                None,
+                None,
+            ),
+        );
+        config.merge_system(system);
+
+        assert_eq!(config.default_profile.as_deref(), Some("acme"));
+        assert!(config.is_system_profile("acme"));
+        assert!(!config.is_system_profile("personal"));
+        assert_eq!(config.profile_names().len(), 2);
+
+        let user = config.user_layer();
+        assert!(user.default_profile.is_none());
+        assert_eq!(user.profiles.keys().collect::<Vec<_>>(), vec!["personal"]);
+        assert!(user.detect.is_empty());
+        assert!(user.policies.is_empty());
+        assert_eq!(config.policies.len(), 1);
+
+        // A user edit to a system profile is kept as an override
+        config.profiles.get_mut("acme").unwrap().gpg_key = Some("ABCD1234".to_string());
+        assert!(!config.is_system_profile("acme"));
+        assert!(config.user_layer().profiles.contains_key("acme"));
+    }
+
+    #[test]
+    fn test_project_layer_is_not_saved() {
+        let project: ProjectConfig = toml::from_str(
+            r#"
+profile = "work"
+ssh_mode = "command"
+
+[detect.email_domains]
+"*@acme.com" = "work"
+
+[detect.directories]
+"services/**" = "work"
+"~/acme/**" = "work"
+"#,
+        )
+        .unwrap();
+
+        let mut config = Config {
+            ssh_mode: Some(SshMode::Alias),
+            ..Default::default()
+        };
+        config
+            .detect
+            .email_domains
+            .insert("*@acme.com".to_string(), "personal".to_string());
+        config.merge_project(PathBuf::from("/src/acme/.gitid.toml"), project);
+
+        assert_eq!(config.project_profile(), Some("work"));
+        assert_eq!(config.project_path(), Some(Path::new("/src/acme/.gitid.toml")));
+        assert_eq!(config.ssh_mode, Some(SshMode::Command));
+        assert_eq!(config.detect.email_domains["*@acme.com"], "work");
+        assert!(config.detect.directories.contains_key("/src/acme/services/**"));
+        assert!(config.detect.directories.contains_key("~/acme/**"));
+
+        let user = config.user_layer();
+        assert_eq!(user.ssh_mode, Some(SshMode::Alias));
+        assert_eq!(user.detect.email_domains["*@acme.com"], "personal");
+        assert!(user.detect.directories.is_empty());
+
+        // A change made while the project layer is loaded is the user's
+        config.ssh_mode = Some(SshMode::Alias);
+        config.detect.directories.insert("~/oss/**".to_string(), "personal".to_string());
+        let user = config.user_layer();
+        assert_eq!(user.ssh_mode, Some(SshMode::Alias));
+        assert_eq!(user.detect.directories.keys().collect::<Vec<_>>(), vec!["~/oss/**"]);
+    }
+
+    #[test]
+    fn test_find_project_config() {
+        let root = std::env::temp_dir().join(format!("gitid-project-test-{}", std::process::id()));
+        let nested = root.join("repo").join("src");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(root.join(PROJECT_CONFIG_FILE), "profile = \"work\"\n").unwrap();
+
+        assert_eq!(find_project_config(&nested), Some(root.join(PROJECT_CONFIG_FILE)));
+        let config = Config::default().with_project(&nested).unwrap();
+        assert_eq!(config.project_profile(), Some("work"));
+
+        fs::write(root.join(PROJECT_CONFIG_FILE), "profile = [").unwrap();
+        assert!(Config::default().with_project(&nested).is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_resolve_pattern() {
+        let dir = Path::new("/src/acme");
+        assert_eq!(resolve_pattern(dir, "**"), "/src/acme/**");
+        assert_eq!(resolve_pattern(dir, "./api/**"), "/src/acme/api/**");
+        assert_eq!(resolve_pattern(dir, "."), "/src/acme");
+        assert_eq!(resolve_pattern(dir, "~/work/**"), "~/work/**");
+        assert_eq!(resolve_pattern(dir, "/opt/**"), "/opt/**");
+    }
+
+    #[test]
+    fn test_json_schema() {
+        let schema: serde_json::Value = serde_json::from_str(&Config::json_schema().unwrap()).unwrap();
+        assert_eq!(schema["title"], "Config");
+        assert!(schema["properties"]["profiles"].is_object());
+        assert!(schema["$defs"]["Profile"]["required"]
+            .as_array()
+            .unwrap()
+            .contains(&serde_json::json!("ssh_key")));
+    }
 }
@@ -0,0 +1,182 @@
+use crate::auth::github_app;
+use crate::config::Config;
+use crate::netrc;
+use crate::prompt;
+use crate::secrets;
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+/// Name git looks for when `credential.helper = gitid`: it runs
+/// `git-credential-gitid <operation>`
+pub const HELPER_BINARY: &str = "git-credential-gitid";
+
+/// Operations of the git credential helper protocol
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Operation {
+    /// Return a username and password for the request
+    Get,
+    /// Save credentials git used successfully (ignored; tokens live in the keyring)
+    Store,
+    /// Forget credentials git was refused with (ignored)
+    Erase,
+}
+
+/// Parse the `key=value` lines git writes to a helper, up to the first
+/// blank line
+fn parse_request(input: &str) -> HashMap<String, String> {
+    input
+        .lines()
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+/// Profile whose token answers a request for `host`. Several profiles may
+/// serve a host, so the one git names with `username` wins, then
+/// `current` (the profile applied in the repository), then the default
+/// profile, then the only candidate.
+fn select_profile<'a>(
+    config: &'a Config,
+    host: &str,
+    username: Option<&str>,
+    current: Option<&str>,
+) -> Option<&'a str> {
+    let candidates: Vec<&'a str> = config
+        .profile_names()
+        .into_iter()
+        .filter(|name| {
+            config
+                .get_profile(name)
+                .is_some_and(|p| p.served_hosts().iter().any(|h| h.eq_ignore_ascii_case(host)))
+        })
+        .map(String::as_str)
+        .collect();
+
+    if let Some(username) = username {
+        let named = candidates.iter().find(|name| {
+            config.get_profile(name).is_some_and(|p| {
+                p.is_own_account(username) || p.gcm.as_ref().is_some_and(|g| g.username == username)
+            })
+        });
+        // A username no profile owns belongs to some other helper
+        return named.copied();
+    }
+
+    let preferred = [current, config.default_profile.as_deref()];
+    preferred
+        .into_iter()
+        .flatten()
+        .find_map(|p| candidates.iter().find(|c| **c == p).copied())
+        .or_else(|| (candidates.len() == 1).then(|| candidates[0]))
+}
+
+/// Answer one helper call from git. Requests gitid has no token for get no
+/// answer, so git moves on to the next helper or prompts.
+pub fn run(operation: Operation) -> Result<()> {
+    let mut input = String::new();
+    std::io::stdin()
+        .read_to_string(&mut input)
+        .context("Failed to read the credential request")?;
+    if operation != Operation::Get {
+        return Ok(());
+    }
+
+    let request = parse_request(&input);
+    if !matches!(request.get("protocol").map(String::as_str), Some("https") | Some("http")) {
+        return Ok(());
+    }
+    let Some(host) = request.get("host") else {
+        return Ok(());
+    };
+    // git includes the port in the host when the URL has one
+    let host = host.split(':').next().unwrap_or(host);
+
+    let config = Config::load()?;
+    // git runs helpers in the repository, so the applied identity is known
+    let current = prompt::get_current_profile(&config).ok().flatten();
+    let Some(name) = select_profile(&config, host, request.get("username").map(String::as_str), current.as_deref())
+    else {
+        return Ok(());
+    };
+    let Some(profile) = config.get_profile(name) else {
+        return Ok(());
+    };
+
+    let token = if profile.github_app.is_some() {
+        Some(github_app::installation_token(name, profile)?)
+    } else {
+        secrets::get_token(profile.keyring_account(name))?
+    };
+    let Some(token) = token else {
+        return Ok(());
+    };
+
+    let username = request
+        .get("username")
+        .map(String::as_str)
+        .unwrap_or_else(|| netrc::token_login(profile));
+    let mut stdout = std::io::stdout();
+    write!(stdout, "username={}\npassword={}\n", username, token)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::{Platform, Profile};
+
+    fn profile(email: &str, username: &str, host: Option<&str>) -> Profile {
+        let mut profile = Profile::new(
+            "John Doe".to_string(),
+            email.to_string(),
+            Platform::Github,
+            "~/.ssh/id_ed25519".to_string(),
+            None,
+            host.map(str::to_string),
+        );
+        profile.username = Some(username.to_string());
+        profile
+    }
+
+    fn config() -> Config {
+        let mut config = Config::default();
+        config
+            .profiles
+            .insert("personal".to_string(), profile("john@example.com", "jdoe", None));
+        config
+            .profiles
+            .insert("oss".to_string(), profile("john@oss.org", "jdoe-oss", None));
+        config.profiles.insert(
+            "work".to_string(),
+            profile("john@acme.com", "jdoe-acme", Some("github.acme.com")),
+        );
+        config
+    }
+
+    #[test]
+    fn test_parse_request() {
+        let request = parse_request("protocol=https\nhost=github.com\nusername=jdoe\n\nignored=1\n");
+        assert_eq!(request.len(), 3);
+        assert_eq!(request["host"], "github.com");
+        assert_eq!(request["username"], "jdoe");
+    }
+
+    #[test]
+    fn test_select_profile() {
+        let mut config = config();
+        assert_eq!(select_profile(&config, "github.acme.com", None, None), Some("work"));
+        assert_eq!(select_profile(&config, "gitlab.com", None, None), None);
+
+        // Two profiles serve github.com
+        assert_eq!(select_profile(&config, "github.com", None, None), None);
+        assert_eq!(select_profile(&config, "github.com", Some("JDoe-OSS"), None), Some("oss"));
+        assert_eq!(select_profile(&config, "github.com", Some("someone"), None), None);
+        assert_eq!(select_profile(&config, "github.com", None, Some("personal")), Some("personal"));
+        // The current profile only counts if it serves the host
+        config.default_profile = Some("oss".to_string());
+        assert_eq!(select_profile(&config, "github.com", None, Some("work")), Some("oss"));
+    }
+}
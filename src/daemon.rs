@@ -0,0 +1,300 @@
+use crate::config::{self, Config};
+use crate::detect;
+use crate::prompt;
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Set to skip the query server and always compute answers in-process
+pub const NO_DAEMON_ENV: &str = "GITID_NO_DAEMON";
+
+/// How long the CLI waits for the server before computing the answer itself
+const CLIENT_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// An answer from the query server
+#[derive(Debug, PartialEq, Eq)]
+pub enum Reply {
+    Found(String),
+    Nothing,
+}
+
+impl Reply {
+    fn encode(answer: Option<&str>) -> String {
+        match answer {
+            Some(value) => format!("ok\t{}\n", value),
+            None => "none\n".to_string(),
+        }
+    }
+
+    fn decode(line: &str) -> Option<Self> {
+        let line = line.trim_end_matches('\n');
+        if line == "none" {
+            return Some(Reply::Nothing);
+        }
+        line.strip_prefix("ok\t")
+            .map(|value| Reply::Found(value.to_string()))
+    }
+}
+
+/// Socket the query server listens on. Servers for a config given with
+/// --config or $GITID_CONFIG get their own socket, so answers from one
+/// config never reach clients using another.
+pub fn socket_path() -> Result<PathBuf> {
+    let name = match Config::custom_path() {
+        Some(_) => {
+            let mut hasher = DefaultHasher::new();
+            Config::config_path()?.hash(&mut hasher);
+            format!("daemon-{:016x}.sock", hasher.finish())
+        }
+        None => "daemon.sock".to_string(),
+    };
+    Ok(Config::state_dir()?.join(name))
+}
+
+/// Ask a running server. Returns None if no server is running, it fails,
+/// or it doesn't answer in time, so the caller can fall back.
+pub fn query(command: &str) -> Option<Reply> {
+    if std::env::var_os(NO_DAEMON_ENV).is_some() {
+        return None;
+    }
+    let cwd = std::env::current_dir().ok()?;
+    ask(command, &cwd).ok().flatten()
+}
+
+#[cfg(unix)]
+fn ask(command: &str, cwd: &Path) -> Result<Option<Reply>> {
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixStream;
+
+    let mut stream = UnixStream::connect(socket_path()?)?;
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+    writeln!(stream, "{}\t{}", command, cwd.display())?;
+
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    Ok(Reply::decode(&line))
+}
+
+#[cfg(not(unix))]
+fn ask(_command: &str, _cwd: &Path) -> Result<Option<Reply>> {
+    Ok(None)
+}
+
+/// Find the common git directory for `start` by walking up the filesystem,
+/// without spawning git. Handles worktrees (`.git` files).
+pub(crate) fn find_common_dir(start: &Path) -> Option<PathBuf> {
+    let mut dir = Some(start);
+    while let Some(current) = dir {
+        let dot_git = current.join(".git");
+        let git_dir = if dot_git.is_dir() {
+            Some(dot_git)
+        } else if dot_git.is_file() {
+            fs::read_to_string(&dot_git)
+                .ok()
+                .and_then(|c| c.trim().strip_prefix("gitdir:").map(|p| current.join(p.trim())))
+        } else {
+            None
+        };
+
+        if let Some(git_dir) = git_dir {
+            return Some(match fs::read_to_string(git_dir.join("commondir")) {
+                Ok(common) => git_dir.join(common.trim()),
+                Err(_) => git_dir,
+            });
+        }
+        dir = current.parent();
+    }
+    None
+}
+
+/// Global gitconfig files, which decide the identity outside local config
+pub(crate) fn global_git_configs() -> Vec<PathBuf> {
+    if let Some(path) = std::env::var_os("GIT_CONFIG_GLOBAL") {
+        return vec![PathBuf::from(path)];
+    }
+    let mut paths = Vec::new();
+    if let Some(home) = dirs::home_dir() {
+        paths.push(home.join(".gitconfig"));
+    }
+    if let Some(config) = dirs::config_dir() {
+        paths.push(config.join("git").join("config"));
+    }
+    paths
+}
+
+/// Hash of the git config files (and project config) an answer for this
+/// repo depends on
+fn repo_key(common_dir: Option<&Path>, project: Option<&Path>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    if let Some(dir) = common_dir {
+        fs::read(dir.join("config")).unwrap_or_default().hash(&mut hasher);
+    }
+    if let Some(path) = project {
+        path.hash(&mut hasher);
+        fs::read(path).unwrap_or_default().hash(&mut hasher);
+    }
+    for path in global_git_configs() {
+        fs::read(path).unwrap_or_default().hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// In-memory state of a running server
+struct Server {
+    config: Config,
+    config_stamp: Vec<Option<SystemTime>>,
+    answers: HashMap<(String, PathBuf), (u64, Option<String>)>,
+}
+
+fn config_stamp() -> Result<Vec<Option<SystemTime>>> {
+    let paths = [Config::config_path()?, Config::system_config_path()];
+    Ok(paths
+        .iter()
+        .map(|p| fs::metadata(p).and_then(|m| m.modified()).ok())
+        .collect())
+}
+
+impl Server {
+    fn new() -> Result<Self> {
+        Ok(Self {
+            config: Config::load_user()?,
+            config_stamp: config_stamp()?,
+            answers: HashMap::new(),
+        })
+    }
+
+    /// Answer one query, reusing the cached answer while nothing it depends
+    /// on has changed
+    fn answer(&mut self, command: &str, cwd: &Path) -> Result<Option<String>> {
+        let stamp = config_stamp()?;
+        if stamp != self.config_stamp {
+            self.config = Config::load_user()?;
+            self.config_stamp = stamp;
+            self.answers.clear();
+        }
+
+        let common_dir = find_common_dir(cwd);
+        let project = config::find_project_config(cwd);
+        let key = repo_key(common_dir.as_deref(), project.as_deref());
+        let slot = (
+            command.to_string(),
+            common_dir.unwrap_or_else(|| cwd.to_path_buf()),
+        );
+        if let Some((cached_key, answer)) = self.answers.get(&slot) {
+            if *cached_key == key {
+                return Ok(answer.clone());
+            }
+        }
+
+        // git runs in the process working directory, and queries are
+        // handled one at a time
+        std::env::set_current_dir(cwd)
+            .with_context(|| format!("Failed to enter {}", cwd.display()))?;
+        // The project layer depends on where the query comes from
+        let config = self.config.clone().with_project(cwd)?;
+        let answer = match command {
+            "current" => prompt::get_current_profile(&config)?,
+            // JSON keeps the tied profiles, which callers ask the user about
+            "detect" => detect::detect_profile_cached(&config)?
+                .map(|r| serde_json::to_string(&r))
+                .transpose()?,
+            other => anyhow::bail!("Unknown query '{}'", other),
+        };
+
+        self.answers.insert(slot, (key, answer.clone()));
+        Ok(answer)
+    }
+}
+
+/// Run the query server in the foreground until killed, calling
+/// `on_listening` with the socket path once it accepts connections
+#[cfg(unix)]
+pub fn serve(on_listening: impl FnOnce(&Path)) -> Result<()> {
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    let path = socket_path()?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create state directory: {}", parent.display()))?;
+    }
+    if path.exists() {
+        if UnixStream::connect(&path).is_ok() {
+            anyhow::bail!("A gitid daemon is already listening on {}", path.display());
+        }
+        // Left behind by a server that was killed
+        fs::remove_file(&path)?;
+    }
+
+    let listener = UnixListener::bind(&path)
+        .with_context(|| format!("Failed to listen on {}", path.display()))?;
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+    }
+    on_listening(&path);
+
+    let mut server = Server::new()?;
+    for stream in listener.incoming() {
+        let Ok(mut stream) = stream else { continue };
+        let _ = stream.set_read_timeout(Some(Duration::from_secs(1)));
+
+        let mut line = String::new();
+        if BufReader::new(&stream).read_line(&mut line).is_err() {
+            continue;
+        }
+        let Some((command, cwd)) = line.trim_end_matches('\n').split_once('\t') else {
+            continue;
+        };
+
+        let reply = match server.answer(command, Path::new(cwd)) {
+            Ok(answer) => Reply::encode(answer.as_deref()),
+            Err(e) => format!("error\t{}\n", e),
+        };
+        let _ = stream.write_all(reply.as_bytes());
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn serve(_on_listening: impl FnOnce(&Path)) -> Result<()> {
+    anyhow::bail!("The query server needs unix sockets and is not available on this platform")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reply_roundtrip() {
+        assert_eq!(
+            Reply::decode(&Reply::encode(Some("work"))),
+            Some(Reply::Found("work".to_string()))
+        );
+        assert_eq!(Reply::decode(&Reply::encode(None)), Some(Reply::Nothing));
+        assert_eq!(Reply::decode("error\tboom\n"), None);
+    }
+
+    #[test]
+    fn test_find_common_dir_in_worktree() {
+        let root = std::env::temp_dir().join(format!("gitid-daemon-test-{}", std::process::id()));
+        let main_git = root.join("main").join(".git");
+        let wt_git = main_git.join("worktrees").join("feature");
+        let worktree = root.join("feature").join("src");
+        fs::create_dir_all(&wt_git).unwrap();
+        fs::create_dir_all(&worktree).unwrap();
+        fs::write(wt_git.join("commondir"), "../..\n").unwrap();
+        fs::write(root.join("feature").join(".git"), format!("gitdir: {}\n", wt_git.display())).unwrap();
+
+        let common = find_common_dir(&worktree).unwrap();
+        assert_eq!(common.canonicalize().unwrap(), main_git.canonicalize().unwrap());
+        assert_eq!(find_common_dir(&root.join("main")), Some(main_git));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
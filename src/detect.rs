@@ -1,55 +1,393 @@
 use crate::config::Config;
-use crate::git::{self, RemoteUrl};
+use crate::daemon::{self, Reply};
+use crate::git::{self, ConfigScope, RemoteUrl};
+use crate::journal;
+use crate::pattern::glob_match;
 use crate::profile::{Platform, Profile};
+use crate::ssh_keys;
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+/// Bonus for the profile the repo's configured email maps to, enough to
+/// break ties between otherwise equal remote matches
+const EMAIL_DOMAIN_BONUS: u32 = 10;
+
+/// Bonus for the profile a directory rule assigns the repo's path to,
+/// enough to pick between profiles with equal host matches but not to
+/// override a remote that uses another profile's SSH alias. It is also
+/// the whole score of a repo with no remotes.
+const DIRECTORY_BONUS: u32 = 40;
+
+/// Bonus when the remote's owner is the profile's own account, enough to
+/// pick between profiles on the same platform
+const USERNAME_BONUS: u32 = 30;
+
+/// Bonus when the remote's owner is one the profile's allow_owners names,
+/// e.g. the work profile for the employer's organization; less than the
+/// profile's own account
+const OWNER_RULE_BONUS: u32 = 20;
+
+/// Bonus for the profile most recent commits are by when several profiles
+/// match the remotes, enough to beat an allow_owners hint (a personal fork
+/// under a work organization) but not the profile's own account
+const HISTORY_BONUS: u32 = 25;
+
+/// Score of a pinned profile, above any match
+const PIN_SCORE: u32 = 1000;
+
+/// Local git config key holding the profile `gitid pin` chose for a
+/// repository
+pub const PIN_KEY: &str = "gitid.profile";
+
+/// Number of recent commits sampled when checking history
+const HISTORY_SAMPLE: usize = 50;
 
 /// Detection result with scoring
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct DetectionResult {
     pub profile_name: String,
     pub score: u32,
     pub reason: String,
+    /// Other profiles with the same score, by name; `profile_name` is only
+    /// the first of them alphabetically
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tied: Vec<DetectionResult>,
+}
+
+impl DetectionResult {
+    /// Every profile on the top score, this one first
+    pub fn candidates(&self) -> Vec<&DetectionResult> {
+        std::iter::once(self).chain(&self.tied).collect()
+    }
+
+    /// Why detection can't choose, naming the tied profiles; None if it can
+    pub fn tie_message(&self) -> Option<String> {
+        if self.tied.is_empty() {
+            return None;
+        }
+        let names: Vec<String> = self
+            .candidates()
+            .iter()
+            .map(|c| format!("'{}'", c.profile_name))
+            .collect();
+        Some(format!(
+            "Profiles {} match this repository equally; pin one with 'gitid pin <profile>' or add a rule",
+            names.join(", ")
+        ))
+    }
+}
+
+/// Highest-scoring candidate, with any others on the same score as its
+/// ties. Candidates are ordered by name first, so the pick never depends
+/// on the order profiles come out of the config's map.
+fn best_of(mut candidates: Vec<DetectionResult>) -> Option<DetectionResult> {
+    candidates.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.profile_name.cmp(&b.profile_name)));
+    let mut candidates = candidates.into_iter();
+    let mut best = candidates.next()?;
+    best.tied = candidates.take_while(|c| c.score == best.score).collect();
+    Some(best)
+}
+
+/// One contribution to a profile's score, e.g. +50 for the profile's host
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Factor {
+    pub points: u32,
+    pub label: String,
+}
+
+impl Factor {
+    fn new(points: u32, label: impl Into<String>) -> Self {
+        Factor {
+            points,
+            label: label.into(),
+        }
+    }
+}
+
+/// How one profile scored against one remote; no factors means no match
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileScore {
+    pub profile_name: String,
+    pub factors: Vec<Factor>,
+}
+
+impl ProfileScore {
+    pub fn score(&self) -> u32 {
+        self.factors.iter().map(|f| f.points).sum()
+    }
+}
+
+/// Every profile's score against one remote, by profile name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteScores {
+    pub remote: String,
+    pub url: String,
+    pub scores: Vec<ProfileScore>,
+}
+
+/// Everything detection weighed for the current repository, for
+/// `gitid detect --explain`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Explanation {
+    /// Pin that decides before any scoring
+    pub pin: Option<DetectionResult>,
+    pub remotes: Vec<RemoteScores>,
+    /// Profile the commit history favoured among several matches
+    pub history: Option<(String, Factor)>,
+    /// What detection decides; the pin if there is one
+    pub result: Option<DetectionResult>,
 }
 
 /// Detect the best matching profile for the current repository
 pub fn detect_profile(config: &Config) -> Result<Option<DetectionResult>> {
-    // Get all remotes
-    let remotes = git::list_remotes()?;
-    if remotes.is_empty() {
-        return Ok(None);
+    if let Some(result) = repo_pin(config)?.or_else(|| project_pin(config)) {
+        return Ok(Some(result));
     }
+    Ok(score_repository(config)?.result)
+}
+
+/// Score every profile against the current repository, factor by factor,
+/// as `detect_profile` does but without stopping at a pin
+pub fn explain(config: &Config) -> Result<Explanation> {
+    let pin = repo_pin(config)?.or_else(|| project_pin(config));
+    let mut explanation = score_repository(config)?;
+    if pin.is_some() {
+        explanation.result = pin.clone();
+        explanation.pin = pin;
+    }
+    Ok(explanation)
+}
+
+/// Detection for the current repository from its remotes, path and history
+fn score_repository(config: &Config) -> Result<Explanation> {
+    let mut explanation = Explanation::default();
+
+    // Each profile's best score over all remotes
+    let mut candidates: Vec<DetectionResult> = Vec::new();
 
-    let mut best_match: Option<DetectionResult> = None;
+    // The email already configured in the repo hints at the intended profile
+    let email_hint = git::get_config("user.email", ConfigScope::Local)?.and_then(|email| email_hint(config, &email));
+
+    // So does where the repo lives, and for repos without remotes that is
+    // the only signal
+    let directory_hint = match git::repo_root()? {
+        Some(root) => profile_for_path(config, &root),
+        None => None,
+    };
 
     // Check each remote
-    for remote in remotes {
-        if let Some(url_str) = git::get_remote_url(&remote)? {
-            if let Some(remote_url) = RemoteUrl::parse(&url_str) {
-                // Score each profile against this remote
-                for (name, profile) in &config.profiles {
-                    let score = score_profile(&remote_url, name, profile);
-                    if score > 0 {
-                        let reason = format_match_reason(&remote_url, profile);
-
-                        if best_match.as_ref().map_or(true, |m| score > m.score) {
-                            best_match = Some(DetectionResult {
-                                profile_name: name.clone(),
-                                score,
-                                reason,
-                            });
-                        }
+    for remote in git::list_remotes()? {
+        let Some(url) = git::get_remote_url(&remote)? else {
+            continue;
+        };
+        let Some(remote_url) = RemoteUrl::parse(&url) else {
+            continue;
+        };
+
+        // Score each profile against this remote
+        let mut scores = Vec::new();
+        for name in config.profile_names() {
+            let Some(profile) = config.get_profile(name) else {
+                continue;
+            };
+            let mut factors = score_factors(&remote_url, name, profile);
+            if !factors.is_empty() {
+                let mut reason = format_match_reason(&remote_url, profile);
+                if let Some((_, note)) = email_hint.as_ref().filter(|(p, _)| p == name) {
+                    factors.push(Factor::new(EMAIL_DOMAIN_BONUS, *note));
+                    reason.push_str(&format!(" ({})", note));
+                }
+                if let Some((pattern, _)) = directory_hint.filter(|(_, p)| *p == name) {
+                    let note = format!("path matches '{}'", pattern);
+                    reason.push_str(&format!(" ({})", note));
+                    factors.push(Factor::new(DIRECTORY_BONUS, note));
+                }
+
+                let score = factors.iter().map(|f| f.points).sum();
+                match candidates.iter_mut().find(|c| c.profile_name == *name) {
+                    Some(candidate) if score > candidate.score => {
+                        candidate.score = score;
+                        candidate.reason = reason;
                     }
+                    Some(_) => {}
+                    None => candidates.push(DetectionResult {
+                        profile_name: name.clone(),
+                        score,
+                        reason,
+                        tied: Vec::new(),
+                    }),
                 }
             }
+            scores.push(ProfileScore {
+                profile_name: name.clone(),
+                factors,
+            });
+        }
+        explanation.remotes.push(RemoteScores { remote, url, scores });
+    }
+
+    // With several profiles on the remotes, whose addresses the history is
+    // by tells them apart
+    if candidates.len() > 1 {
+        let emails = git::recent_author_emails(HISTORY_SAMPLE)?;
+        if let Some((name, count)) = dominant_profile(config, &emails) {
+            if let Some(candidate) = candidates.iter_mut().find(|c| c.profile_name == name) {
+                let note = format!("{}/{} recent commits are by its addresses", count, emails.len());
+                candidate.score += HISTORY_BONUS;
+                candidate.reason.push_str(&format!(" ({})", note));
+                explanation.history = Some((name.to_string(), Factor::new(HISTORY_BONUS, note)));
+            }
         }
     }
 
-    Ok(best_match)
+    explanation.result = match directory_hint {
+        Some((pattern, name)) if candidates.iter().all(|c| DIRECTORY_BONUS > c.score) => Some(DetectionResult {
+            profile_name: name.to_string(),
+            score: DIRECTORY_BONUS,
+            reason: format!("Repository path matches directory rule '{}'", pattern),
+            tied: Vec::new(),
+        }),
+        _ => best_of(candidates),
+    };
+    Ok(explanation)
+}
+
+/// Profile pinned in the current repository with `gitid pin`, if any
+pub fn pinned_profile() -> Result<Option<String>> {
+    git::get_config(PIN_KEY, ConfigScope::Local)
+}
+
+/// The repository's pin as a detection result. It is taken over any match
+/// and over a project pin; one naming a missing profile is ignored.
+fn repo_pin(config: &Config) -> Result<Option<DetectionResult>> {
+    Ok(pinned_profile()?
+        .filter(|name| config.has_profile(name))
+        .map(|name| DetectionResult {
+            profile_name: name,
+            score: PIN_SCORE,
+            reason: "Pinned to this repository with `gitid pin`".to_string(),
+            tied: Vec::new(),
+        }))
+}
+
+/// Profile pinned by the project config, which is taken over any match.
+/// A pin naming a profile this user doesn't have is ignored.
+fn project_pin(config: &Config) -> Option<DetectionResult> {
+    let name = config.project_profile().filter(|name| config.has_profile(name))?;
+    let path = config.project_path()?;
+    Some(DetectionResult {
+        profile_name: name.to_string(),
+        score: PIN_SCORE,
+        reason: format!("Pinned by {}", path.display()),
+        tied: Vec::new(),
+    })
+}
+
+/// Best profile for a remote URL on its own, e.g. before cloning it
+pub fn detect_for_url(config: &Config, url: &str) -> Option<DetectionResult> {
+    let remote_url = RemoteUrl::parse(url)?;
+    let candidates = config
+        .profiles
+        .iter()
+        .filter_map(|(name, profile)| {
+            let score = score_profile(&remote_url, name, profile);
+            (score > 0).then(|| DetectionResult {
+                profile_name: name.clone(),
+                score,
+                reason: format_match_reason(&remote_url, profile),
+                tied: Vec::new(),
+            })
+        })
+        .collect();
+    best_of(candidates)
+}
+
+/// Detection outcome stored in `.git/gitid/detect.toml`
+#[derive(Debug, Serialize, Deserialize)]
+struct DetectCache {
+    /// Hash of the repo's git config and the gitid config it was computed from
+    key: String,
+    timestamp: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    result: Option<DetectionResult>,
+}
+
+fn cache_path(common_dir: &Path) -> PathBuf {
+    common_dir.join("gitid").join("detect.toml")
+}
+
+/// Cache key covering everything detection reads: the repo's remotes and
+/// local user.email (both in its config file), its path (worktrees share
+/// the cache) and the gitid config, including any project pin. Recent
+/// history is left out; it only separates profiles that match equally,
+/// and every commit would otherwise throw the result away.
+fn cache_key(repo_config: &[u8], root: &Path, config: &Config) -> String {
+    let mut hasher = DefaultHasher::new();
+    repo_config.hash(&mut hasher);
+    root.hash(&mut hasher);
+    // Profiles are a HashMap, whose order differs from run to run, so they
+    // are hashed sorted and the rest of the config without them
+    let profiles: BTreeMap<&String, &Profile> = config.profiles.iter().collect();
+    toml::to_string(&profiles).unwrap_or_default().hash(&mut hasher);
+    let mut rest = config.clone();
+    rest.profiles.clear();
+    toml::to_string(&rest).unwrap_or_default().hash(&mut hasher);
+    config.project_path().hash(&mut hasher);
+    config.project_profile().hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+/// Like `detect_profile`, but reuses the result stored in the repository's
+/// git directory until its config (and so its remotes) or the gitid config
+/// changes
+pub fn detect_profile_cached(config: &Config) -> Result<Option<DetectionResult>> {
+    let Some(common_dir) = git::common_dir()? else {
+        return Ok(None);
+    };
+
+    let repo_config = fs::read(common_dir.join("config")).unwrap_or_default();
+    let root = git::repo_root()?.unwrap_or_default();
+    let key = cache_key(&repo_config, &root, config);
+    let path = cache_path(&common_dir);
+
+    let cached = fs::read_to_string(&path)
+        .ok()
+        .and_then(|content| toml::from_str::<DetectCache>(&content).ok());
+    if let Some(cache) = cached.filter(|c| c.key == key) {
+        return Ok(cache.result);
+    }
+
+    let result = detect_profile(config)?;
+
+    // The cache is best-effort; a read-only repo just recomputes every time
+    let cache = DetectCache {
+        key,
+        timestamp: journal::now(),
+        result: result.clone(),
+    };
+    if let Ok(content) = toml::to_string(&cache) {
+        let _ = fs::create_dir_all(common_dir.join("gitid"))
+            .and_then(|_| fs::write(&path, content));
+    }
+
+    Ok(result)
 }
 
 /// Score how well a profile matches a remote URL
 fn score_profile(remote_url: &RemoteUrl, profile_name: &str, profile: &Profile) -> u32 {
-    let mut score = 0u32;
+    score_factors(remote_url, profile_name, profile)
+        .iter()
+        .map(|f| f.points)
+        .sum()
+}
+
+/// What a profile's score against a remote URL is made of
+fn score_factors(remote_url: &RemoteUrl, profile_name: &str, profile: &Profile) -> Vec<Factor> {
+    let mut factors = Vec::new();
 
     let profile_host = profile.default_host();
     let remote_host = &remote_url.host;
@@ -58,7 +396,7 @@ fn score_profile(remote_url: &RemoteUrl, profile_name: &str, profile: &Profile)
     let ssh_alias = profile.ssh_host_alias(profile_name);
     if remote_host == &ssh_alias {
         // Direct alias match - highest score
-        score += 100;
+        factors.push(Factor::new(100, format!("SSH alias '{}'", ssh_alias)));
     }
 
     // Check platform-specific aliases for 'both' platform
@@ -66,34 +404,54 @@ fn score_profile(remote_url: &RemoteUrl, profile_name: &str, profile: &Profile)
         let github_alias = format!("github-{}", profile_name);
         let gitlab_alias = format!("gitlab-{}", profile_name);
         if remote_host == &github_alias || remote_host == &gitlab_alias {
-            score += 100;
+            factors.push(Factor::new(100, format!("SSH alias '{}'", remote_host)));
         }
     }
 
     // Check if the remote host matches the profile's configured host
     if remote_host == profile_host {
-        score += 50;
+        factors.push(Factor::new(50, format!("profile host '{}'", profile_host)));
     }
 
     // Check platform compatibility
-    let is_github = remote_host.contains("github");
-    let is_gitlab = remote_host.contains("gitlab");
-
-    match profile.platform {
-        Platform::Github if is_github => score += 20,
-        Platform::Gitlab if is_gitlab => score += 20,
-        Platform::Both if is_github || is_gitlab => score += 15,
+    match Platform::from_host(remote_host) {
+        Some(ref hinted) if *hinted == profile.platform => {
+            factors.push(Factor::new(20, format!("{} platform", hinted.display_name())));
+        }
+        Some(ref hinted) if profile.platform.serves(hinted) => {
+            factors.push(Factor::new(15, format!("serves {}", hinted.display_name())));
+        }
         _ => {}
     }
 
     // Check for custom host match (enterprise instances)
     if let Some(ref custom_host) = profile.host {
         if remote_host == custom_host || remote_host.contains(custom_host.as_str()) {
-            score += 80;
+            factors.push(Factor::new(80, format!("custom host '{}'", custom_host)));
+        }
+    }
+
+    // The owner only tells profiles apart once the host already matches
+    if !factors.is_empty() {
+        if is_own_repo(remote_url, profile) {
+            factors.push(Factor::new(USERNAME_BONUS, "owner matches username"));
+        }
+        if is_allowed_owner(remote_url, profile) {
+            factors.push(Factor::new(OWNER_RULE_BONUS, "owner is in allow_owners"));
         }
     }
 
-    score
+    factors
+}
+
+fn is_own_repo(remote_url: &RemoteUrl, profile: &Profile) -> bool {
+    remote_url.owner().is_some_and(|o| profile.is_own_account(o))
+}
+
+fn is_allowed_owner(remote_url: &RemoteUrl, profile: &Profile) -> bool {
+    remote_url
+        .owner()
+        .is_some_and(|o| profile.allow_owners.iter().any(|p| glob_match(p, o)))
 }
 
 /// Format a human-readable reason for the match
@@ -101,26 +459,157 @@ fn format_match_reason(remote_url: &RemoteUrl, profile: &Profile) -> String {
     let host = &remote_url.host;
     let profile_host = profile.default_host();
 
-    if host == profile_host {
+    let hinted = Platform::from_host(host).filter(|p| profile.platform.serves(p));
+    let reason = if host == profile_host {
         format!("Remote host '{}' matches profile host", host)
-    } else if host.contains("github") && matches!(profile.platform, Platform::Github | Platform::Both)
-    {
-        format!("GitHub repository detected ({})", host)
-    } else if host.contains("gitlab") && matches!(profile.platform, Platform::Gitlab | Platform::Both)
-    {
-        format!("GitLab repository detected ({})", host)
+    } else if let Some(platform) = hinted {
+        format!("{} repository detected ({})", platform.display_name(), host)
     } else {
         format!("Host '{}' matched", host)
+    };
+
+    let mut reason = reason;
+    if is_own_repo(remote_url, profile) {
+        reason.push_str(" (owner matches username)");
+    }
+    if is_allowed_owner(remote_url, profile) {
+        reason.push_str(" (owner is in allow_owners)");
     }
+    reason
 }
 
-/// Detect profile and return matching information
-pub fn detect_and_suggest(config: &Config) -> Result<Option<(String, String)>> {
-    if let Some(result) = detect_profile(config)? {
-        Ok(Some((result.profile_name, result.reason)))
+/// Whether an `[detect.email_domains]` pattern covers an address. A
+/// pattern starting with `@` is shorthand for every address at that domain.
+fn email_rule_matches(pattern: &str, email: &str) -> bool {
+    match pattern.strip_prefix('@') {
+        Some(domain) => email
+            .rsplit_once('@')
+            .is_some_and(|(_, at)| glob_match(domain, at)),
+        None => glob_match(pattern, email),
+    }
+}
+
+/// Profile an email address maps to via `[detect.email_domains]` rules.
+/// Rules are tried in sorted pattern order; the first match wins.
+pub fn profile_for_email<'a>(config: &'a Config, email: &str) -> Option<&'a str> {
+    config
+        .detect
+        .email_domains
+        .iter()
+        .find(|(pattern, _)| email_rule_matches(pattern, email))
+        .map(|(_, profile)| profile.as_str())
+}
+
+/// Profile an address belongs to: the only profile with exactly that
+/// address, else the one an email rule maps it to. The flag says whether
+/// it was the exact address.
+fn profile_of_email<'a>(config: &'a Config, email: &str) -> Option<(&'a str, bool)> {
+    let owners: Vec<&String> = config
+        .profile_names()
+        .into_iter()
+        .filter(|name| config.get_profile(name).is_some_and(|p| p.email.eq_ignore_ascii_case(email)))
+        .collect();
+    if let [name] = owners[..] {
+        return Some((name.as_str(), true));
+    }
+    profile_for_email(config, email).map(|name| (name, false))
+}
+
+/// Profile the repository's configured email points at, with the note to
+/// add to its reason
+fn email_hint(config: &Config, email: &str) -> Option<(String, &'static str)> {
+    let (name, exact) = profile_of_email(config, email)?;
+    let note = if exact {
+        "repo email is the profile's"
     } else {
-        Ok(None)
+        "repo email matches a domain rule"
+    };
+    Some((name.to_string(), note))
+}
+
+/// Directory rule and profile a repository path falls under via
+/// `[detect.directories]`. The longest matching pattern wins, the same rule
+/// `gitconfig-sync` gives git by writing it last.
+pub fn profile_for_path<'a>(config: &'a Config, path: &Path) -> Option<(&'a str, &'a str)> {
+    let path = path.to_string_lossy();
+    config
+        .detect
+        .directories
+        .iter()
+        .filter(|(_, profile)| config.has_profile(profile))
+        .filter(|(pattern, _)| {
+            glob_match(&ssh_keys::expand_path(pattern).to_string_lossy(), &path)
+        })
+        .max_by_key(|(pattern, _)| pattern.len())
+        .map(|(pattern, profile)| (pattern.as_str(), profile.as_str()))
+}
+
+/// Profile that most of the given emails belong to, with its count, if it
+/// covers a majority of them
+fn dominant_profile<'a>(config: &'a Config, emails: &[String]) -> Option<(&'a str, usize)> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for email in emails {
+        if let Some((profile, _)) = profile_of_email(config, email) {
+            *counts.entry(profile).or_default() += 1;
+        }
     }
+
+    counts
+        .into_iter()
+        .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(a.0)))
+        .filter(|(_, count)| count * 2 > emails.len())
+}
+
+/// Check whether applying `profile_name` contradicts the repository's
+/// recent history, returning a warning message if so
+pub fn history_mismatch(config: &Config, profile_name: &str) -> Result<Option<String>> {
+    if config.detect.email_domains.is_empty() {
+        return Ok(None);
+    }
+
+    let emails = git::recent_author_emails(HISTORY_SAMPLE)?;
+    Ok(match dominant_profile(config, &emails) {
+        Some((dominant, count)) if dominant != profile_name => Some(format!(
+            "Applying '{}' but {}/{} recent commits are by addresses mapped to '{}'",
+            profile_name,
+            count,
+            emails.len(),
+            dominant
+        )),
+        _ => None,
+    })
+}
+
+/// Profile for a repository nothing matches, with the reason to show:
+/// `detect.fallback`, else the default profile. None if neither names a
+/// configured profile.
+pub fn fallback_profile(config: &Config) -> Option<(String, String)> {
+    let configured = |name: &&String| config.has_profile(name);
+    if let Some(name) = config.detect.fallback.as_ref().filter(configured) {
+        return Some((name.clone(), "No profile matched; using the fallback profile".to_string()));
+    }
+    config
+        .default_profile
+        .as_ref()
+        .filter(configured)
+        .map(|name| (name.clone(), "No profile matched; using the default profile".to_string()))
+}
+
+/// Detect the profile for the current repository, asking the daemon
+/// first. Ties come back in `tied` for the caller to resolve.
+pub fn detect_and_suggest(config: &Config) -> Result<Option<DetectionResult>> {
+    match daemon::query("detect") {
+        Some(Reply::Found(answer)) => {
+            // An older daemon answers in another format; detect locally then
+            if let Ok(result) = serde_json::from_str(&answer) {
+                return Ok(Some(result));
+            }
+        }
+        Some(Reply::Nothing) => return Ok(None),
+        None => {}
+    }
+
+    detect_profile_cached(config)
 }
 
 #[cfg(test)]
@@ -131,6 +620,7 @@ mod tests {
     fn test_score_github_profile() {
         let remote_url = RemoteUrl {
             host: "github.com".to_string(),
+            path: "owner/repo.git".to_string(),
         };
 
         let profile = Profile::new(
@@ -144,12 +634,23 @@ mod tests {
 
         let score = score_profile(&remote_url, "personal", &profile);
         assert!(score > 0);
+
+        let factors = score_factors(&remote_url, "personal", &profile);
+        assert_eq!(
+            factors,
+            vec![
+                Factor::new(50, "profile host 'github.com'"),
+                Factor::new(20, "GitHub platform")
+            ]
+        );
+        assert_eq!(factors.iter().map(|f| f.points).sum::<u32>(), score);
     }
 
     #[test]
     fn test_score_ssh_alias_match() {
         let remote_url = RemoteUrl {
             host: "github-work".to_string(),
+            path: "company/project.git".to_string(),
         };
 
         let profile = Profile::new(
@@ -164,4 +665,288 @@ mod tests {
         let score = score_profile(&remote_url, "work", &profile);
         assert!(score >= 100);
     }
+
+    #[test]
+    fn test_score_prefers_own_account() {
+        let remote_url = RemoteUrl {
+            host: "github.com".to_string(),
+            path: "jdoe/dotfiles.git".to_string(),
+        };
+
+        let work = Profile::new(
+            "John Doe".to_string(),
+            "john@company.com".to_string(),
+            Platform::Github,
+            "~/.ssh/id_work".to_string(),
+            None,
+            None,
+        );
+        let mut personal = work.clone();
+        personal.username = Some("JDoe".to_string());
+
+        assert!(score_profile(&remote_url, "personal", &personal) > score_profile(&remote_url, "work", &work));
+        assert!(format_match_reason(&remote_url, &personal).ends_with("(owner matches username)"));
+
+        // An org in allow_owners counts for less than the own account
+        let mut oss = work.clone();
+        oss.allow_owners = vec!["jd*".to_string()];
+        let oss_score = score_profile(&remote_url, "oss", &oss);
+        assert!(oss_score > score_profile(&remote_url, "work", &work));
+        assert!(oss_score < score_profile(&remote_url, "personal", &personal));
+        assert!(format_match_reason(&remote_url, &oss).ends_with("(owner is in allow_owners)"));
+    }
+
+    #[test]
+    fn test_score_forgejo_host() {
+        let remote_url = RemoteUrl {
+            host: "forgejo.acme.internal".to_string(),
+            path: "infra/deploy.git".to_string(),
+        };
+
+        let github = Profile::new(
+            "John Doe".to_string(),
+            "john@acme.com".to_string(),
+            Platform::Github,
+            "~/.ssh/id_work".to_string(),
+            None,
+            None,
+        );
+        let gitea = Profile {
+            platform: Platform::Gitea,
+            ..github.clone()
+        };
+
+        assert_eq!(score_profile(&remote_url, "work", &github), 0);
+        assert!(score_profile(&remote_url, "forge", &gitea) > 0);
+        assert_eq!(
+            format_match_reason(&remote_url, &gitea),
+            "Gitea/Forgejo repository detected (forgejo.acme.internal)"
+        );
+    }
+
+    #[test]
+    fn test_cache_key_tracks_inputs() {
+        let config = Config::default();
+        let root = Path::new("/src/repo");
+        let key = cache_key(b"[remote \"origin\"]", root, &config);
+        assert_eq!(key, cache_key(b"[remote \"origin\"]", root, &config));
+        assert_ne!(key, cache_key(b"[remote \"upstream\"]", root, &config));
+        assert_ne!(key, cache_key(b"[remote \"origin\"]", Path::new("/src/worktree"), &config));
+
+        let mut other = Config::default();
+        other.detect.email_domains.insert("*@acme.com".to_string(), "work".to_string());
+        assert_ne!(key, cache_key(b"[remote \"origin\"]", root, &other));
+
+        // Insertion order doesn't change the key
+        let profile = |email: &str| {
+            Profile::new(
+                "Jane Doe".to_string(),
+                email.to_string(),
+                Platform::Github,
+                "~/.ssh/id_ed25519".to_string(),
+                None,
+                None,
+            )
+        };
+        let names = ["work", "personal", "oss", "acme", "client"];
+        let mut forward = Config::default();
+        for name in names {
+            forward.profiles.insert(name.to_string(), profile(&format!("{}@x.com", name)));
+        }
+        let mut backward = Config::default();
+        for name in names.iter().rev() {
+            backward.profiles.insert(name.to_string(), profile(&format!("{}@x.com", name)));
+        }
+        assert_eq!(cache_key(b"", root, &forward), cache_key(b"", root, &backward));
+    }
+
+    #[test]
+    fn test_project_pin() {
+        let dir = std::env::temp_dir().join(format!("gitid-pin-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(".gitid.toml"), "profile = \"work\"\n").unwrap();
+        let mut config = Config::default().with_project(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        // Unknown to this user, so detection carries on without it
+        assert!(project_pin(&config).is_none());
+
+        config.profiles.insert(
+            "work".to_string(),
+            Profile::new(
+                "John Doe".to_string(),
+                "john@acme.com".to_string(),
+                Platform::Github,
+                "~/.ssh/id_work".to_string(),
+                None,
+                None,
+            ),
+        );
+        let result = project_pin(&config).unwrap();
+        assert_eq!(result.profile_name, "work");
+        assert!(result.reason.starts_with("Pinned by "));
+    }
+
+    #[test]
+    fn test_email_domain_rules() {
+        let mut config = Config::default();
+        config
+            .detect
+            .email_domains
+            .insert("*@acme.com".to_string(), "work".to_string());
+
+        assert_eq!(profile_for_email(&config, "jane@acme.com"), Some("work"));
+        assert_eq!(profile_for_email(&config, "jane@example.com"), None);
+
+        // `@domain` is shorthand for every address there
+        config
+            .detect
+            .email_domains
+            .insert("@example.com".to_string(), "personal".to_string());
+        assert_eq!(profile_for_email(&config, "Jane@Example.com"), Some("personal"));
+        assert_eq!(profile_for_email(&config, "jane@example.com.evil"), None);
+
+        // A profile's own address points at it before any rule
+        let mut oss = Profile::new(
+            "Jane".to_string(),
+            "jane@acme.com".to_string(),
+            Platform::Github,
+            "~/.ssh/id_oss".to_string(),
+            None,
+            None,
+        );
+        config.profiles.insert("oss".to_string(), oss.clone());
+        assert_eq!(email_hint(&config, "jane@acme.com").unwrap().0, "oss");
+        assert_eq!(email_hint(&config, "joe@acme.com").unwrap().0, "work");
+
+        // Unless several profiles share it
+        oss.name = "Jane Doe".to_string();
+        config.profiles.insert("oss-2".to_string(), oss);
+        assert_eq!(email_hint(&config, "jane@acme.com").unwrap().0, "work");
+
+        let history: Vec<String> = ["a@acme.com", "b@acme.com", "me@example.com"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(dominant_profile(&config, &history), Some(("work", 2)));
+        assert_eq!(dominant_profile(&config, &history[1..]), None);
+    }
+
+    #[test]
+    fn test_dominant_profile_by_address() {
+        let mut config = Config::default();
+        for (name, email) in [("personal", "jane@example.com"), ("work", "jane@acme.com")] {
+            config.profiles.insert(
+                name.to_string(),
+                Profile::new(
+                    "Jane Doe".to_string(),
+                    email.to_string(),
+                    Platform::Github,
+                    format!("~/.ssh/id_{}", name),
+                    None,
+                    None,
+                ),
+            );
+        }
+
+        // A personal fork under a work organization: no rules, but most
+        // commits are by the personal address
+        let history: Vec<String> = ["jane@example.com", "Jane@Example.com", "bot@ci.acme.com"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(dominant_profile(&config, &history), Some(("personal", 2)));
+    }
+
+    #[test]
+    fn test_directory_rules() {
+        let mut config = Config::default();
+        for name in ["work", "oss"] {
+            config.profiles.insert(
+                name.to_string(),
+                Profile::new(
+                    "John Doe".to_string(),
+                    format!("john@{}.com", name),
+                    Platform::Github,
+                    format!("~/.ssh/id_{}", name),
+                    None,
+                    None,
+                ),
+            );
+        }
+        let rules = &mut config.detect.directories;
+        rules.insert("/src/work/**".to_string(), "work".to_string());
+        rules.insert("/src/work/oss/**".to_string(), "oss".to_string());
+        rules.insert("/src/**".to_string(), "missing".to_string());
+
+        assert_eq!(
+            profile_for_path(&config, Path::new("/src/work/api")),
+            Some(("/src/work/**", "work"))
+        );
+        assert_eq!(
+            profile_for_path(&config, Path::new("/src/work/oss/lib")),
+            Some(("/src/work/oss/**", "oss"))
+        );
+        assert_eq!(profile_for_path(&config, Path::new("/src/home/dotfiles")), None);
+    }
+
+    #[test]
+    fn test_detect_for_url_ties() {
+        let mut config = Config::default();
+        for name in ["work", "oss", "personal"] {
+            config.profiles.insert(
+                name.to_string(),
+                Profile::new(
+                    "John Doe".to_string(),
+                    format!("john@{}.com", name),
+                    Platform::Github,
+                    format!("~/.ssh/id_{}", name),
+                    None,
+                    None,
+                ),
+            );
+        }
+
+        let result = detect_for_url(&config, "git@github.com:acme/app.git").unwrap();
+        assert_eq!(result.profile_name, "oss");
+        let names: Vec<_> = result.candidates().iter().map(|c| c.profile_name.as_str()).collect();
+        assert_eq!(names, vec!["oss", "personal", "work"]);
+        assert!(result.tie_message().unwrap().contains("'oss', 'personal', 'work'"));
+        // The daemon hands ties over as JSON
+        let answer = serde_json::to_string(&result).unwrap();
+        assert_eq!(serde_json::from_str::<DetectionResult>(&answer).unwrap(), result);
+
+        let result = detect_for_url(&config, "git@github-work:acme/app.git").unwrap();
+        assert_eq!(result.profile_name, "work");
+        assert_eq!(result.tie_message(), None);
+    }
+
+    #[test]
+    fn test_fallback_profile() {
+        let mut config = Config::default();
+        for name in ["work", "personal"] {
+            config.profiles.insert(
+                name.to_string(),
+                Profile::new(
+                    "John Doe".to_string(),
+                    format!("john@{}.com", name),
+                    Platform::Github,
+                    format!("~/.ssh/id_{}", name),
+                    None,
+                    None,
+                ),
+            );
+        }
+        assert_eq!(fallback_profile(&config), None);
+
+        config.default_profile = Some("personal".to_string());
+        assert_eq!(fallback_profile(&config).unwrap().0, "personal");
+
+        config.detect.fallback = Some("work".to_string());
+        assert_eq!(fallback_profile(&config).unwrap().0, "work");
+
+        // A fallback naming no profile leaves the default in charge
+        config.detect.fallback = Some("missing".to_string());
+        assert_eq!(fallback_profile(&config).unwrap().0, "personal");
+    }
 }
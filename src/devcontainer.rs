@@ -0,0 +1,226 @@
+use crate::config::Config;
+use crate::env;
+use crate::profile::{Profile, SigningFormat};
+use crate::ssh_keys;
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde_json::json;
+use std::fs;
+
+/// Where the SSH agent socket is mounted inside the container
+const AGENT_SOCKET: &str = "/ssh-agent";
+
+/// How the profile's SSH key reaches the container
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum KeyMode {
+    /// Forward the host's SSH agent (no key material enters the container)
+    Agent,
+    /// Bind-mount the private key read-only
+    Mount,
+}
+
+/// Output format for the generated settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// `containerEnv` and `mounts` for devcontainer.json
+    Json,
+    /// `docker run` flags
+    Docker,
+}
+
+/// A read-only or read-write bind mount
+#[derive(Debug, PartialEq, Eq)]
+pub struct Mount {
+    pub source: String,
+    pub target: String,
+    pub readonly: bool,
+}
+
+/// Environment and mounts that give a container the profile's identity
+#[derive(Debug, Default)]
+pub struct ContainerSpec {
+    pub env: Vec<(String, String)>,
+    pub mounts: Vec<Mount>,
+}
+
+/// Build the container settings for a profile. `agent_socket` is the host
+/// side of the agent mount; `home` is the container user's home directory.
+pub fn container_spec(
+    profile_name: &str,
+    profile: &Profile,
+    keys: KeyMode,
+    agent_socket: &str,
+    home: &str,
+) -> Result<ContainerSpec> {
+    let mut spec = ContainerSpec::default();
+    let mut git_config = vec![
+        ("user.name", profile.name.clone()),
+        ("user.email", profile.email.clone()),
+    ];
+    let signing = profile.signing_key();
+    if let Some((format, ref key)) = signing {
+        if format == SigningFormat::Gpg {
+            git_config.push(("user.signingkey", key.clone()));
+        } else {
+            git_config.push(("gpg.format", "ssh".to_string()));
+        }
+        git_config.push(("commit.gpgsign", "true".to_string()));
+    }
+    let ssh_signing = matches!(signing, Some((SigningFormat::Ssh, _)));
+
+    match keys {
+        KeyMode::Agent => {
+            // The host's key path doesn't exist in the container, so name
+            // the key literally and let ssh-keygen find it in the agent
+            if ssh_signing {
+                let public_key = ssh_keys::public_key_path(&profile.ssh_key);
+                let key = fs::read_to_string(&public_key)
+                    .with_context(|| format!("Failed to read public key: {}", public_key.display()))?;
+                git_config.push(("user.signingkey", format!("key::{}", key.trim())));
+            }
+            spec.mounts.push(Mount {
+                source: agent_socket.to_string(),
+                target: AGENT_SOCKET.to_string(),
+                readonly: false,
+            });
+            spec.env
+                .push(("SSH_AUTH_SOCK".to_string(), AGENT_SOCKET.to_string()));
+        }
+        KeyMode::Mount => {
+            let target = format!("{}/.ssh/gitid_{}", home, profile_name);
+            spec.mounts.push(Mount {
+                source: ssh_keys::expand_path(&profile.ssh_key).display().to_string(),
+                target: target.clone(),
+                readonly: true,
+            });
+            git_config.push((
+                "core.sshCommand",
+                format!("ssh -i {} -o IdentitiesOnly=yes", env::shell_quote(&target)),
+            ));
+            if ssh_signing {
+                git_config.push(("user.signingkey", target));
+            }
+        }
+    }
+
+    let config_path = Config::config_path()?;
+    if config_path.exists() {
+        spec.mounts.push(Mount {
+            source: config_path.display().to_string(),
+            target: format!("{}/.config/gitid/config.toml", home),
+            readonly: true,
+        });
+    }
+
+    // Git reads GIT_CONFIG_COUNT/KEY_n/VALUE_n as extra config, so no
+    // gitconfig file has to exist in the image
+    spec.env
+        .push(("GITID_PROFILE".to_string(), profile_name.to_string()));
+    spec.env.extend(env::git_config_env(&git_config));
+
+    Ok(spec)
+}
+
+/// Render the spec as a devcontainer.json fragment
+pub fn render_json(spec: &ContainerSpec) -> Result<String> {
+    let env: serde_json::Map<String, serde_json::Value> = spec
+        .env
+        .iter()
+        .map(|(k, v)| (k.clone(), json!(v)))
+        .collect();
+    let mounts: Vec<String> = spec
+        .mounts
+        .iter()
+        .map(|m| {
+            let mut mount = format!("source={},target={},type=bind", m.source, m.target);
+            if m.readonly {
+                mount.push_str(",readonly");
+            }
+            mount
+        })
+        .collect();
+
+    serde_json::to_string_pretty(&json!({ "containerEnv": env, "mounts": mounts }))
+        .context("Failed to serialize devcontainer settings")
+}
+
+/// Render the spec as `docker run` flags, one per line
+pub fn render_docker(spec: &ContainerSpec) -> String {
+    let mut lines = Vec::new();
+    for m in &spec.mounts {
+        let suffix = if m.readonly { ":ro" } else { "" };
+        lines.push(format!(
+            "-v {}",
+            env::shell_quote(&format!("{}:{}{}", m.source, m.target, suffix))
+        ));
+    }
+    for (key, value) in &spec.env {
+        lines.push(format!("-e {}", env::shell_quote(&format!("{}={}", key, value))));
+    }
+    lines.join(" \\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::Platform;
+
+    fn work() -> Profile {
+        Profile::new(
+            "John Doe".to_string(),
+            "john@company.com".to_string(),
+            Platform::Github,
+            "/keys/id_work".to_string(),
+            Some("ABCD1234".to_string()),
+            None,
+        )
+    }
+
+    fn value<'a>(spec: &'a ContainerSpec, key: &str) -> Option<&'a str> {
+        spec.env
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    #[test]
+    fn test_agent_spec() {
+        let spec = container_spec("work", &work(), KeyMode::Agent, "/tmp/agent.sock", "/home/vscode").unwrap();
+        assert_eq!(value(&spec, "SSH_AUTH_SOCK"), Some(AGENT_SOCKET));
+        assert_eq!(value(&spec, "GIT_CONFIG_COUNT"), Some("4"));
+        assert_eq!(value(&spec, "GIT_CONFIG_KEY_1"), Some("user.email"));
+        assert_eq!(value(&spec, "GIT_CONFIG_VALUE_1"), Some("john@company.com"));
+        assert_eq!(spec.mounts[0].source, "/tmp/agent.sock");
+    }
+
+    #[test]
+    fn test_mount_spec() {
+        let spec = container_spec("work", &work(), KeyMode::Mount, "", "/home/vscode").unwrap();
+        assert!(value(&spec, "SSH_AUTH_SOCK").is_none());
+        assert_eq!(
+            spec.mounts[0],
+            Mount {
+                source: "/keys/id_work".to_string(),
+                target: "/home/vscode/.ssh/gitid_work".to_string(),
+                readonly: true,
+            }
+        );
+        assert_eq!(value(&spec, "GIT_CONFIG_KEY_4"), Some("core.sshCommand"));
+
+        let docker = render_docker(&spec);
+        assert!(docker.starts_with("-v /keys/id_work:/home/vscode/.ssh/gitid_work:ro"));
+        assert!(docker.contains("-e 'GIT_CONFIG_VALUE_0=John Doe'"));
+    }
+
+    #[test]
+    fn test_ssh_signing_uses_mounted_key() {
+        let profile = Profile {
+            signing: Some(SigningFormat::Ssh),
+            ..work()
+        };
+        let spec = container_spec("work", &profile, KeyMode::Mount, "", "/home/vscode").unwrap();
+        assert_eq!(value(&spec, "GIT_CONFIG_KEY_2"), Some("gpg.format"));
+        assert_eq!(value(&spec, "GIT_CONFIG_KEY_5"), Some("user.signingkey"));
+        assert_eq!(value(&spec, "GIT_CONFIG_VALUE_5"), Some("/home/vscode/.ssh/gitid_work"));
+    }
+}
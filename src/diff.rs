@@ -0,0 +1,140 @@
+/// Lines of unchanged context shown around each change
+const CONTEXT: usize = 3;
+
+/// One line of a line-by-line diff
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Line<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Shortest edit between two lists of lines, from their longest common
+/// subsequence. Config files are small, so the quadratic table is fine.
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Line<'a>> {
+    // common[i][j]: length of the LCS of old[i..] and new[j..]
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut lines = Vec::new();
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push(Line::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || common[i + 1][j] >= common[i][j + 1]) {
+            // Removals come first, as in `diff -u`
+            lines.push(Line::Removed(old[i]));
+            i += 1;
+        } else {
+            lines.push(Line::Added(new[j]));
+            j += 1;
+        }
+    }
+    lines
+}
+
+/// Unified diff of two texts with three lines of context, as `diff -u`
+/// prints it. None when the texts have the same lines.
+pub fn unified_diff(old: &str, new: &str, old_label: &str, new_label: &str) -> Option<String> {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let lines = diff_lines(&old_lines, &new_lines);
+
+    let changes: Vec<usize> = (0..lines.len())
+        .filter(|&i| !matches!(lines[i], Line::Same(_)))
+        .collect();
+    if changes.is_empty() {
+        return None;
+    }
+
+    // Lines of each side consumed before each diff line, for hunk headers
+    let mut before = Vec::with_capacity(lines.len());
+    let (mut old_no, mut new_no) = (0, 0);
+    for line in &lines {
+        before.push((old_no, new_no));
+        match line {
+            Line::Same(_) => {
+                old_no += 1;
+                new_no += 1;
+            }
+            Line::Removed(_) => old_no += 1,
+            Line::Added(_) => new_no += 1,
+        }
+    }
+
+    // Changes closer together than twice the context share a hunk
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for &change in &changes {
+        let start = change.saturating_sub(CONTEXT);
+        let end = (change + CONTEXT + 1).min(lines.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut out = format!("--- {}\n+++ {}\n", old_label, new_label);
+    for (start, end) in hunks {
+        let hunk = &lines[start..end];
+        let old_len = hunk.iter().filter(|l| !matches!(l, Line::Added(_))).count();
+        let new_len = hunk.iter().filter(|l| !matches!(l, Line::Removed(_))).count();
+        // An empty side is numbered by the line before it
+        let (old_start, new_start) = before[start];
+        let old_start = if old_len == 0 { old_start } else { old_start + 1 };
+        let new_start = if new_len == 0 { new_start } else { new_start + 1 };
+
+        out.push_str(&format!("@@ -{},{} +{},{} @@\n", old_start, old_len, new_start, new_len));
+        for line in hunk {
+            let (prefix, text) = match line {
+                Line::Same(text) => (' ', text),
+                Line::Removed(text) => ('-', text),
+                Line::Added(text) => ('+', text),
+            };
+            out.push(prefix);
+            out.push_str(text);
+            out.push('\n');
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unified_diff() {
+        let old = "Host a\n  HostName a.com\n  User git\n  Port 22\n";
+        let new = "Host a\n  HostName a.com\n  User git\n  Port 2222\n";
+        assert_eq!(
+            unified_diff(old, new, "current", "generated").unwrap(),
+            "--- current\n+++ generated\n@@ -1,4 +1,4 @@\n Host a\n   HostName a.com\n   User git\n-  Port 22\n+  Port 2222\n"
+        );
+        assert!(unified_diff(old, old, "current", "generated").is_none());
+    }
+
+    #[test]
+    fn test_unified_diff_hunks() {
+        let old: String = (1..=20).map(|i| format!("{}\n", i)).collect();
+        let new: String = (1..=20)
+            .filter(|&i| i != 18)
+            .map(|i| if i == 2 { "two\n".to_string() } else { format!("{}\n", i) })
+            .collect();
+        let diff = unified_diff(&old, &new, "a", "b").unwrap();
+        assert!(diff.contains("@@ -1,5 +1,5 @@\n 1\n-2\n+two\n 3\n"));
+        assert!(diff.contains("@@ -15,6 +15,5 @@\n 15\n 16\n 17\n-18\n 19\n 20\n"));
+
+        // Everything added to an empty file
+        assert_eq!(unified_diff("", "x\n", "a", "b").unwrap(), "--- a\n+++ b\n@@ -0,0 +1,1 @@\n+x\n");
+    }
+}
@@ -0,0 +1,474 @@
+use crate::auth::{github, gitlab};
+use crate::config::Config;
+use crate::git::{self, ConfigScope};
+use crate::gpg;
+use crate::lint::{self, Severity};
+use crate::managed;
+use crate::policy;
+use crate::profile::{Platform, SigningFormat};
+use crate::prompt;
+use crate::ssh;
+use crate::ssh_keys;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A repair doctor can make without guessing at the user's intent
+#[derive(Debug, PartialEq, Eq)]
+pub enum Fix {
+    /// Tighten a file or directory's permissions
+    Chmod { path: PathBuf, mode: u32 },
+    /// Regenerate the managed SSH config block
+    SyncSsh,
+    /// Load a profile's key into ssh-agent
+    AgentAdd { profile: String, key: String },
+    /// Stop git from inventing an identity in unconfigured repos
+    UseConfigOnly,
+    /// Drop a global include whose file no longer exists
+    RemoveInclude { key: String, path: String },
+}
+
+impl Fix {
+    pub fn describe(&self) -> String {
+        match self {
+            Fix::Chmod { path, mode } => format!("chmod {:o} {}", mode, path.display()),
+            Fix::SyncSsh => "Regenerate the gitid block in ~/.ssh/config".to_string(),
+            Fix::AgentAdd { profile, key } => format!("ssh-add {} (profile '{}')", key, profile),
+            Fix::UseConfigOnly => "Set global user.useConfigOnly = true".to_string(),
+            Fix::RemoveInclude { key, path } => format!("Remove {} = {} from the global gitconfig", key, path),
+        }
+    }
+
+    pub fn apply(&self, config: &Config) -> Result<()> {
+        match self {
+            Fix::Chmod { path, mode } => set_mode(path, *mode),
+            Fix::SyncSsh => ssh::sync_ssh_config(config).map(|_| ()),
+            Fix::AgentAdd { key, .. } => ssh_keys::agent_add(key).map(|_| ()),
+            Fix::UseConfigOnly => git::set_config("user.useConfigOnly", "true", ConfigScope::Global),
+            Fix::RemoveInclude { key, path } => git::unset_global_value(key, path),
+        }
+    }
+}
+
+/// A problem found on this machine, with its repair if one is safe or
+/// what to do about it if not
+#[derive(Debug)]
+pub struct Finding {
+    pub severity: Severity,
+    pub message: String,
+    pub fix: Option<Fix>,
+    pub hint: Option<String>,
+}
+
+impl Finding {
+    fn new(severity: Severity, message: String, fix: Option<Fix>) -> Self {
+        Self {
+            severity,
+            message,
+            fix,
+            hint: None,
+        }
+    }
+
+    /// A finding only the user can resolve
+    fn manual(severity: Severity, message: String, hint: String) -> Self {
+        Self {
+            severity,
+            message,
+            fix: None,
+            hint: Some(hint),
+        }
+    }
+}
+
+/// Finding for a config file that failed to load, when there is no config
+/// to diagnose against
+pub fn config_error(error: &anyhow::Error) -> Finding {
+    Finding::manual(
+        Severity::Error,
+        format!("Config does not load: {:#}", error),
+        format!("Fix {}", Config::config_path().map(|p| p.display().to_string()).unwrap_or_default()),
+    )
+}
+
+/// Check the machine against the config
+pub fn diagnose(config: &Config) -> Result<Vec<Finding>> {
+    let mut findings = Vec::new();
+
+    check_config(config, &mut findings);
+    check_keys_exist(config, &mut findings);
+    check_permissions(config, &mut findings)?;
+    check_ssh_block(config, &mut findings)?;
+    check_agent(config, &mut findings)?;
+    check_use_config_only(config, &mut findings)?;
+    check_includes(&mut findings)?;
+    check_gpg_keys(config, &mut findings);
+    check_cli_auth(config, &mut findings);
+    check_policies(config, &mut findings)?;
+
+    Ok(findings)
+}
+
+/// The problems `config validate` reports
+fn check_config(config: &Config, findings: &mut Vec<Finding>) {
+    for issue in lint::lint_config(config) {
+        let message = if issue.profiles.is_empty() {
+            issue.message
+        } else {
+            format!("{} ({})", issue.message, issue.profiles.join(", "))
+        };
+        findings.push(Finding::manual(issue.severity, message, issue.explanation));
+    }
+}
+
+/// Keys held only by an agent (e.g. a password manager) leave just the
+/// public half on disk, which is fine
+fn check_keys_exist(config: &Config, findings: &mut Vec<Finding>) {
+    for name in config.profile_names() {
+        let Some(profile) = config.get_profile(name) else {
+            continue;
+        };
+        for path in profile.ssh_key_paths() {
+            let private_key = ssh_keys::expand_path(path);
+            if !private_key.exists() && !ssh_keys::public_key_path(path).exists() {
+                findings.push(Finding::manual(
+                    Severity::Error,
+                    format!("SSH key {} for profile '{}' does not exist", path, name),
+                    format!(
+                        "Generate it with 'ssh-keygen -t ed25519 -f {}', import it with 'gitid key import {}', or point ssh_key at an existing key",
+                        private_key.display(),
+                        name
+                    ),
+                ));
+            }
+        }
+    }
+}
+
+fn check_gpg_keys(config: &Config, findings: &mut Vec<Finding>) {
+    let signing: Vec<_> = config
+        .profile_names()
+        .into_iter()
+        .filter_map(|name| Some((name, config.get_profile(name)?)))
+        .filter(|(_, p)| {
+            matches!(p.signing_key(), Some((SigningFormat::Gpg, ref k)) if !k.trim().is_empty())
+        })
+        .collect();
+    if signing.is_empty() {
+        return;
+    }
+
+    if !gpg::is_installed() {
+        findings.push(Finding::manual(
+            Severity::Error,
+            "Profiles sign commits but gpg is not installed".to_string(),
+            "Install GnuPG, or remove gpg_key from the profiles".to_string(),
+        ));
+        return;
+    }
+
+    for (name, profile) in signing {
+        let key = profile.gpg_key.as_deref().unwrap_or_default();
+        if !gpg::has_secret_key(key, profile.gnupghome.as_deref()) {
+            findings.push(Finding::manual(
+                Severity::Error,
+                format!("Signing key {} for profile '{}' is not in the GnuPG keyring", key, name),
+                "Import the secret key with 'gpg --import', or set gpg_key to a key from 'gpg --list-secret-keys'".to_string(),
+            ));
+        }
+    }
+}
+
+/// gh/glab must be installed and signed in for `auth`, key uploads and
+/// account switching; each (platform, host) is checked once
+fn check_cli_auth(config: &Config, findings: &mut Vec<Finding>) {
+    let mut needed: Vec<(Platform, String, String)> = Vec::new();
+    for name in config.profile_names() {
+        let Some(profile) = config.get_profile(name) else {
+            continue;
+        };
+        // App profiles authenticate with installation tokens instead
+        if profile.github_app.is_some() {
+            continue;
+        }
+        let hosts = match profile.platform {
+            Platform::Github | Platform::Gitlab => vec![(profile.platform.clone(), profile.default_host())],
+            Platform::Both => vec![(Platform::Github, "github.com"), (Platform::Gitlab, "gitlab.com")],
+            _ => Vec::new(),
+        };
+        for (platform, host) in hosts {
+            if !needed.iter().any(|(p, h, _)| *p == platform && h == host) {
+                needed.push((platform, host.to_string(), name.clone()));
+            }
+        }
+    }
+
+    for (platform, cli, url) in [
+        (Platform::Github, "gh", "https://cli.github.com/"),
+        (Platform::Gitlab, "glab", "https://gitlab.com/gitlab-org/cli"),
+    ] {
+        let hosts: Vec<_> = needed.iter().filter(|(p, _, _)| *p == platform).collect();
+        if hosts.is_empty() {
+            continue;
+        }
+
+        let installed = match platform {
+            Platform::Github => github::is_gh_installed(),
+            _ => gitlab::is_glab_installed(),
+        };
+        if !installed {
+            findings.push(Finding::manual(
+                Severity::Warning,
+                format!("{} is not installed, so {} profiles can't authenticate", cli, platform.display_name()),
+                format!("Install it from {}", url),
+            ));
+            continue;
+        }
+
+        for (_, host, name) in hosts {
+            let authenticated = match platform {
+                Platform::Github => github::is_authenticated(Some(host)),
+                _ => gitlab::is_authenticated(Some(host)),
+            };
+            if !authenticated {
+                findings.push(Finding::manual(
+                    Severity::Warning,
+                    format!("{} is not signed in to {}", cli, host),
+                    format!("Run 'gitid auth {}'", name),
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn mode_of(path: &Path) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).ok().map(|m| m.permissions().mode() & 0o777)
+}
+
+#[cfg(not(unix))]
+fn mode_of(_path: &Path) -> Option<u32> {
+    None
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+        .with_context(|| format!("Failed to change permissions of {}", path.display()))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> Result<()> {
+    Ok(())
+}
+
+/// Flag a path whose mode has any of the `forbidden` bits set
+fn check_mode(path: &Path, forbidden: u32, wanted: u32, severity: Severity, what: &str, findings: &mut Vec<Finding>) {
+    if let Some(mode) = mode_of(path).filter(|m| m & forbidden != 0) {
+        findings.push(Finding::new(
+            severity,
+            format!("{} {} has mode {:o}", what, path.display(), mode),
+            Some(Fix::Chmod {
+                path: path.to_path_buf(),
+                mode: wanted,
+            }),
+        ));
+    }
+}
+
+/// ssh refuses private keys other users can read, and config files or
+/// directories other users can write
+fn check_permissions(config: &Config, findings: &mut Vec<Finding>) -> Result<()> {
+    check_mode(&ssh_keys::ssh_dir()?, 0o022, 0o700, Severity::Warning, "SSH directory", findings);
+    check_mode(&ssh::ssh_config_path()?, 0o022, 0o600, Severity::Error, "SSH config", findings);
+
+    let mut keys: Vec<PathBuf> = config
+        .profiles
+        .values()
+        .map(|p| ssh_keys::expand_path(&p.ssh_key))
+        .collect();
+    keys.sort();
+    keys.dedup();
+    for key in keys {
+        check_mode(&key, 0o077, 0o600, Severity::Error, "Private key", findings);
+    }
+
+    Ok(())
+}
+
+fn check_ssh_block(config: &Config, findings: &mut Vec<Finding>) -> Result<()> {
+    if config.profiles.is_empty() {
+        return Ok(());
+    }
+
+    let path = ssh::managed_config_path(config)?;
+    let current = fs::read_to_string(&path).unwrap_or_default();
+    let expected = ssh::generate_managed_block(config, &current);
+    let message = match managed::find_block(&current, ssh::MANAGED_START, ssh::MANAGED_END) {
+        Some(block) if block == expected => None,
+        Some(_) => Some(format!("The gitid block in {} is out of date", path.display())),
+        None => Some(format!("{} has no gitid block, so profile aliases don't resolve", path.display())),
+    };
+    if let Some(message) = message {
+        findings.push(Finding::new(Severity::Error, message, Some(Fix::SyncSsh)));
+    }
+
+    if config.ssh_layout == Some(ssh::SshLayout::Include) {
+        let main = fs::read_to_string(ssh::ssh_config_path()?).unwrap_or_default();
+        if !ssh::has_include(&main) {
+            findings.push(Finding::new(
+                Severity::Error,
+                format!("~/.ssh/config does not include ~/.ssh/gitid_config (\"{}\")", ssh::INCLUDE_LINE),
+                Some(Fix::SyncSsh),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// The profile in use here (or the default) should have its key loaded
+fn check_agent(config: &Config, findings: &mut Vec<Finding>) -> Result<()> {
+    if std::env::var_os("SSH_AUTH_SOCK").is_none() {
+        return Ok(());
+    }
+
+    let current = if git::is_git_repo() {
+        prompt::get_current_profile(config)?
+    } else {
+        None
+    };
+    let Some(name) = current.or_else(|| config.default_profile.clone()) else {
+        return Ok(());
+    };
+    let Some(profile) = config.get_profile(&name) else {
+        return Ok(());
+    };
+
+    // An unreachable agent or a key held elsewhere (e.g. a password
+    // manager) is nothing doctor can fix
+    if !ssh_keys::expand_path(&profile.ssh_key).exists() {
+        return Ok(());
+    }
+    if let Ok(false) = ssh_keys::agent_has(&profile.ssh_key) {
+        findings.push(Finding::new(
+            Severity::Warning,
+            format!("ssh-agent does not hold the key for profile '{}'", name),
+            Some(Fix::AgentAdd {
+                profile: name,
+                key: profile.ssh_key.clone(),
+            }),
+        ));
+    }
+
+    Ok(())
+}
+
+fn check_use_config_only(config: &Config, findings: &mut Vec<Finding>) -> Result<()> {
+    if config.profiles.is_empty() {
+        return Ok(());
+    }
+
+    if git::get_effective_config("user.useConfigOnly")?.as_deref() != Some("true") {
+        findings.push(Finding::new(
+            Severity::Warning,
+            "user.useConfigOnly is off, so git invents an identity in repos no profile was applied to".to_string(),
+            Some(Fix::UseConfigOnly),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Policies the current repository's identity breaks, when run inside one
+fn check_policies(config: &Config, findings: &mut Vec<Finding>) -> Result<()> {
+    if config.policies.is_empty() || !git::is_git_repo() {
+        return Ok(());
+    }
+
+    let violations = policy::evaluate_repository(config)?;
+    if violations.is_empty() {
+        return Ok(());
+    }
+    let compliant = policy::compliant_profiles(config, &git::remote_urls()?);
+    let hint = match compliant.first() {
+        Some(name) => format!("Apply a profile that meets it, e.g. 'gitid use {} --local'", name),
+        None => "No configured profile meets it; add one or adjust the policy".to_string(),
+    };
+    for violation in violations {
+        findings.push(Finding::manual(
+            Severity::Error,
+            format!("This repository breaks {}", violation),
+            hint.clone(),
+        ));
+    }
+    Ok(())
+}
+
+/// Resolve an include path the way git does: `~/` is the home directory and
+/// relative paths are relative to the including file
+pub(crate) fn resolve_include(path: &str, global_config: &Path) -> PathBuf {
+    let expanded = ssh_keys::expand_path(path);
+    if expanded.is_absolute() {
+        return expanded;
+    }
+    global_config
+        .parent()
+        .map(|dir| dir.join(&expanded))
+        .unwrap_or(expanded)
+}
+
+fn check_includes(findings: &mut Vec<Finding>) -> Result<()> {
+    let global_config = git::global_config_path()?;
+
+    for (key, path) in git::global_includes()? {
+        if !resolve_include(&path, &global_config).exists() {
+            findings.push(Finding::new(
+                Severity::Warning,
+                format!("{} points at missing file {}", key, path),
+                Some(Fix::RemoveInclude { key, path }),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_include() {
+        let global = Path::new("/home/john/.gitconfig");
+        assert_eq!(
+            resolve_include("work.gitconfig", global),
+            PathBuf::from("/home/john/work.gitconfig")
+        );
+        assert_eq!(resolve_include("/etc/gitconfig", global), PathBuf::from("/etc/gitconfig"));
+    }
+
+    #[test]
+    fn test_missing_key_reported() {
+        let mut config = Config::default();
+        config.profiles.insert(
+            "work".to_string(),
+            crate::profile::Profile::new(
+                "John Doe".to_string(),
+                "john@company.com".to_string(),
+                Platform::Github,
+                "/nonexistent/gitid/id_work".to_string(),
+                None,
+                None,
+            ),
+        );
+
+        let mut findings = Vec::new();
+        check_keys_exist(&config, &mut findings);
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].severity, Severity::Error);
+        assert!(findings[0].hint.as_deref().unwrap().contains("gitid key import work"));
+    }
+}
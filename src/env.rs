@@ -0,0 +1,169 @@
+use crate::profile::{Profile, SigningFormat};
+use crate::ssh_keys;
+
+/// Environment variables that make git (and gpg) act as the profile
+/// without touching any config files
+pub fn profile_env(profile_name: &str, profile: &Profile) -> Vec<(String, String)> {
+    let mut vars = vec![
+        ("GITID_PROFILE".to_string(), profile_name.to_string()),
+        ("GIT_SSH_COMMAND".to_string(), ssh_command(profile)),
+        ("GIT_AUTHOR_NAME".to_string(), profile.name.clone()),
+        ("GIT_AUTHOR_EMAIL".to_string(), profile.email.clone()),
+        ("GIT_COMMITTER_NAME".to_string(), profile.name.clone()),
+        ("GIT_COMMITTER_EMAIL".to_string(), profile.email.clone()),
+    ];
+
+    if let Some(ref home) = profile.gnupghome {
+        vars.push((
+            "GNUPGHOME".to_string(),
+            ssh_keys::expand_path(home).display().to_string(),
+        ));
+    }
+
+    vars
+}
+
+/// Extra git config passed through GIT_CONFIG_COUNT/KEY_n/VALUE_n, which
+/// git reads on top of its config files
+pub fn git_config_env(settings: &[(&str, String)]) -> Vec<(String, String)> {
+    let mut vars = vec![("GIT_CONFIG_COUNT".to_string(), settings.len().to_string())];
+    for (i, (key, value)) in settings.iter().enumerate() {
+        vars.push((format!("GIT_CONFIG_KEY_{}", i), key.to_string()));
+        vars.push((format!("GIT_CONFIG_VALUE_{}", i), value.clone()));
+    }
+    vars
+}
+
+/// Environment for commands that create commits as the profile, with its
+/// signing settings overriding whatever the repository has configured
+pub fn commit_env(profile_name: &str, profile: &Profile) -> Vec<(String, String)> {
+    let signing = match profile.signing_key() {
+        Some((format, key)) => vec![
+            ("commit.gpgsign", "true".to_string()),
+            ("user.signingkey", key),
+            (
+                "gpg.format",
+                match format {
+                    SigningFormat::Gpg => "openpgp",
+                    SigningFormat::Ssh => "ssh",
+                }
+                .to_string(),
+            ),
+        ],
+        None => vec![("commit.gpgsign", "false".to_string())],
+    };
+
+    let mut vars = profile_env(profile_name, profile);
+    vars.extend(git_config_env(&signing));
+    vars
+}
+
+/// SSH command that forces the profile's keys, for tools that bypass
+/// `~/.ssh/config` (used for GIT_SSH_COMMAND and core.sshCommand)
+pub fn ssh_command(profile: &Profile) -> String {
+    let mut command = "ssh".to_string();
+    for path in profile.ssh_key_paths() {
+        let key = ssh_keys::expand_path(path);
+        command.push_str(&format!(" -i {}", shell_quote(&key.display().to_string())));
+    }
+    command.push_str(" -o IdentitiesOnly=yes");
+    for option in ssh_option_args(profile) {
+        command.push_str(&format!(" -o {}", shell_quote(&option)));
+    }
+    command
+}
+
+/// The profile's ssh_options as `Key=Value` arguments for `ssh -o`,
+/// leaving out those the remote URL or the `-i` flags already decide
+pub fn ssh_option_args(profile: &Profile) -> Vec<String> {
+    let fixed = ["HostName", "User", "IdentityFile", "IdentitiesOnly"];
+    profile
+        .ssh_options
+        .iter()
+        .filter(|(option, _)| !fixed.iter().any(|f| f.eq_ignore_ascii_case(option)))
+        .map(|(option, value)| format!("{}={}", option, value))
+        .collect()
+}
+
+/// Whether an sshCommand value looks like one gitid generated
+pub fn is_managed_ssh_command(value: &str) -> bool {
+    value.starts_with("ssh -i ") && value.contains("-o IdentitiesOnly=yes")
+}
+
+/// Quote a value for POSIX shells, leaving simple words untouched
+pub fn shell_quote(value: &str) -> String {
+    let plain = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "@%+=:,./-_~".contains(c));
+    if plain {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', "'\\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::Platform;
+
+    #[test]
+    fn test_profile_env() {
+        let mut profile = Profile::new(
+            "John Doe".to_string(),
+            "john@company.com".to_string(),
+            Platform::Github,
+            "~/.ssh/id_work".to_string(),
+            None,
+            None,
+        );
+
+        let vars = profile_env("work", &profile);
+        assert!(vars.contains(&("GIT_AUTHOR_EMAIL".to_string(), "john@company.com".to_string())));
+        assert!(!vars.iter().any(|(k, _)| k == "GNUPGHOME"));
+
+        let ssh = vars.iter().find(|(k, _)| k == "GIT_SSH_COMMAND").unwrap();
+        assert!(ssh.1.ends_with("id_work -o IdentitiesOnly=yes"));
+        assert!(is_managed_ssh_command(&ssh.1));
+
+        profile.gnupghome = Some("/opt/work-gnupg".to_string());
+        let vars = profile_env("work", &profile);
+        assert!(vars.contains(&("GNUPGHOME".to_string(), "/opt/work-gnupg".to_string())));
+
+        profile.ssh_options.insert("ProxyJump".to_string(), "bastion".to_string());
+        profile.ssh_options.insert("User".to_string(), "jdoe".to_string());
+        let command = ssh_command(&profile);
+        assert!(command.ends_with("id_work -o IdentitiesOnly=yes -o ProxyJump=bastion"));
+        assert!(is_managed_ssh_command(&command));
+    }
+
+    #[test]
+    fn test_commit_env() {
+        let mut profile = Profile::new(
+            "John Doe".to_string(),
+            "john@company.com".to_string(),
+            Platform::Github,
+            "/keys/id_work".to_string(),
+            None,
+            None,
+        );
+
+        let vars = commit_env("work", &profile);
+        assert!(vars.contains(&("GIT_COMMITTER_EMAIL".to_string(), "john@company.com".to_string())));
+        assert!(vars.contains(&("GIT_CONFIG_COUNT".to_string(), "1".to_string())));
+        assert!(vars.contains(&("GIT_CONFIG_VALUE_0".to_string(), "false".to_string())));
+
+        profile.signing = Some(SigningFormat::Ssh);
+        let vars = commit_env("work", &profile);
+        assert!(vars.contains(&("GIT_CONFIG_VALUE_1".to_string(), "/keys/id_work.pub".to_string())));
+        assert!(vars.contains(&("GIT_CONFIG_VALUE_2".to_string(), "ssh".to_string())));
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("/home/me/.ssh/id_work"), "/home/me/.ssh/id_work");
+        assert_eq!(shell_quote("John Doe"), "'John Doe'");
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+    }
+}
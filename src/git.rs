@@ -1,8 +1,15 @@
+use crate::config::Config;
+use crate::journal::{self, Change};
+use crate::profile::{Profile, SigningFormat};
+use crate::usage;
 use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 /// Scope for git config operations
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum ConfigScope {
     Local,
     Global,
@@ -32,30 +39,254 @@ pub fn get_config(key: &str, scope: ConfigScope) -> Result<Option<String>> {
     }
 }
 
+/// Get a git config value as git itself resolves it (all scopes)
+pub fn get_effective_config(key: &str) -> Result<Option<String>> {
+    let output = Command::new("git")
+        .args(["config", "--get", key])
+        .output()
+        .context("Failed to execute git config")?;
+
+    if output.status.success() {
+        let value = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        Ok(Some(value))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Build a `git config` command, optionally targeting another repository
+fn config_command(repo: Option<&Path>, scope: ConfigScope) -> Command {
+    let mut cmd = Command::new("git");
+    if let Some(repo) = repo {
+        cmd.arg("-C").arg(repo);
+    }
+    cmd.args(["config", scope.flag()]);
+    cmd
+}
+
 /// Set a git config value
 pub fn set_config(key: &str, value: &str, scope: ConfigScope) -> Result<()> {
-    let status = Command::new("git")
-        .args(["config", scope.flag(), key, value])
+    let previous = get_config(key, scope)?;
+    if previous.as_deref() == Some(value) {
+        return Ok(());
+    }
+
+    let status = config_command(None, scope)
+        .args([key, value])
         .status()
         .context("Failed to execute git config")?;
 
     if !status.success() {
         bail!("Failed to set git config {} = {}", key, value);
     }
-    Ok(())
+
+    journal::record(Change::GitConfig {
+        scope,
+        repo: journal_repo(scope)?,
+        key: key.to_string(),
+        previous,
+    })
 }
 
 /// Unset a git config value
 pub fn unset_config(key: &str, scope: ConfigScope) -> Result<()> {
-    Command::new("git")
-        .args(["config", scope.flag(), "--unset", key])
+    // Don't fail if the key doesn't exist
+    let Some(previous) = get_config(key, scope)? else {
+        return Ok(());
+    };
+
+    config_command(None, scope)
+        .args(["--unset", key])
         .status()
         .context("Failed to execute git config")?;
 
-    // Don't fail if the key doesn't exist
+    journal::record(Change::GitConfig {
+        scope,
+        repo: journal_repo(scope)?,
+        key: key.to_string(),
+        previous: Some(previous),
+    })
+}
+
+/// Put a config key back to a previous value without journaling it
+pub fn restore_config(
+    repo: Option<&Path>,
+    key: &str,
+    previous: Option<&str>,
+    scope: ConfigScope,
+) -> Result<()> {
+    let mut cmd = config_command(repo, scope);
+    match previous {
+        Some(value) => cmd.args([key, value]),
+        None => cmd.args(["--unset", key]),
+    };
+
+    let status = cmd.status().context("Failed to execute git config")?;
+
+    // Exit code 5 means the key was already unset
+    let already_unset = previous.is_none() && status.code() == Some(5);
+    if !status.success() && !already_unset {
+        bail!("Failed to restore git config {}", key);
+    }
+    Ok(())
+}
+
+/// Repository a local config change applies to, for the journal
+fn journal_repo(scope: ConfigScope) -> Result<Option<PathBuf>> {
+    match scope {
+        ConfigScope::Local => repo_root(),
+        ConfigScope::Global => Ok(None),
+    }
+}
+
+/// Path of the global config file git reads and writes
+pub fn global_config_path() -> Result<PathBuf> {
+    if let Some(path) = std::env::var_os("GIT_CONFIG_GLOBAL") {
+        return Ok(PathBuf::from(path));
+    }
+
+    let home = dirs::home_dir().context("Could not determine home directory")?;
+    let dotfile = home.join(".gitconfig");
+    if dotfile.exists() {
+        return Ok(dotfile);
+    }
+
+    // git falls back to the XDG location only when ~/.gitconfig is absent
+    let xdg = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home.join(".config"))
+        .join("git")
+        .join("config");
+    Ok(if xdg.exists() { xdg } else { dotfile })
+}
+
+/// `include.path` and `includeIf.<condition>.path` entries in the global
+/// config, as (key, path) pairs
+pub fn global_includes() -> Result<Vec<(String, String)>> {
+    let output = Command::new("git")
+        .args(["config", "--global", "-z", "--get-regexp", r"^include(if\..*)?\.path$"])
+        .output()
+        .context("Failed to execute git config")?;
+
+    // -z separates key and value with a newline, so conditions containing
+    // spaces survive
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .split('\0')
+        .filter_map(|entry| entry.split_once('\n'))
+        .map(|(key, path)| (key.to_string(), path.to_string()))
+        .collect())
+}
+
+/// Entries at `scope` whose keys match `pattern`, as (key, value) pairs
+/// with the section and variable names lowercased as git reports them
+pub fn config_entries(pattern: &str, scope: ConfigScope) -> Result<Vec<(String, String)>> {
+    let output = Command::new("git")
+        .args(["config", scope.flag(), "-z", "--get-regexp", pattern])
+        .output()
+        .context("Failed to execute git config")?;
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .split('\0')
+        .filter_map(|entry| entry.split_once('\n'))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect())
+}
+
+/// Every entry in a config file, e.g. a repository's `.git/config` read
+/// from outside it, as (key, value) pairs named the way git reports them
+pub fn file_config_entries(file: &Path) -> Result<Vec<(String, String)>> {
+    let output = Command::new("git")
+        .args(["config", "--file"])
+        .arg(file)
+        .args(["-z", "--list"])
+        .output()
+        .context("Failed to execute git config")?;
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .split('\0')
+        .filter_map(|entry| entry.split_once('\n'))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect())
+}
+
+/// Remove every value of `key` from a config file, without journaling
+pub fn unset_file_config(file: &Path, key: &str) -> Result<()> {
+    let status = Command::new("git")
+        .args(["config", "--file"])
+        .arg(file)
+        .args(["--unset-all", key])
+        .status()
+        .context("Failed to execute git config")?;
+
+    // Exit code 5 means the key was already unset
+    if !status.success() && status.code() != Some(5) {
+        bail!("Failed to unset git config {} in {}", key, file.display());
+    }
     Ok(())
 }
 
+/// Remove one value of a possibly multi-valued global key, journaling the
+/// whole file since a single-key record can't restore one of many values
+pub fn unset_global_value(key: &str, value: &str) -> Result<()> {
+    journal::record_file(&global_config_path()?)?;
+
+    let status = Command::new("git")
+        .args(["config", "--global", "--fixed-value", "--unset", key, value])
+        .status()
+        .context("Failed to execute git config")?;
+
+    if !status.success() {
+        bail!("Failed to unset git config {} = {}", key, value);
+    }
+    Ok(())
+}
+
+/// Remove a whole section (e.g. `includeIf.gitdir:~/work/`) from the global
+/// config, journaling the file
+pub fn remove_global_section(section: &str) -> Result<()> {
+    journal::record_file(&global_config_path()?)?;
+
+    let status = Command::new("git")
+        .args(["config", "--global", "--remove-section", section])
+        .status()
+        .context("Failed to execute git config")?;
+
+    if !status.success() {
+        bail!("Failed to remove git config section {}", section);
+    }
+    Ok(())
+}
+
+/// Get the top-level directory of the current repository
+pub fn repo_root() -> Result<Option<PathBuf>> {
+    let output = Command::new("git")
+        .args(["rev-parse", "--show-toplevel"])
+        .output()
+        .context("Failed to execute git rev-parse")?;
+
+    if output.status.success() {
+        let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        Ok(Some(PathBuf::from(path)))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Get the repository's common git directory (shared by all worktrees)
+pub fn common_dir() -> Result<Option<PathBuf>> {
+    let output = Command::new("git")
+        .args(["rev-parse", "--path-format=absolute", "--git-common-dir"])
+        .output()
+        .context("Failed to execute git rev-parse")?;
+
+    if output.status.success() {
+        let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        Ok(Some(PathBuf::from(path)))
+    } else {
+        Ok(None)
+    }
+}
+
 /// Check if we're inside a git repository
 pub fn is_git_repo() -> bool {
     Command::new("git")
@@ -100,10 +331,139 @@ pub fn list_remotes() -> Result<Vec<String>> {
     Ok(remotes)
 }
 
+/// Author emails of the most recent commits on the current branch
+pub fn recent_author_emails(limit: usize) -> Result<Vec<String>> {
+    let output = Command::new("git")
+        .args(["log", "--format=%ae", "-n", &limit.to_string()])
+        .output()
+        .context("Failed to execute git log")?;
+
+    // No commits yet
+    if !output.status.success() {
+        return Ok(Vec::new());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect())
+}
+
+/// A commit's emails, as shown by `git log`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommitIdentity {
+    pub hash: String,
+    pub author_email: String,
+    pub committer_email: String,
+}
+
+/// Author and committer emails of the commits `git log` lists for the given
+/// revisions, e.g. `["a..b"]` or `["b", "--not", "--remotes"]`
+pub fn commit_identities(revisions: &[&str]) -> Result<Vec<CommitIdentity>> {
+    let output = Command::new("git")
+        .args(["log", "--format=%h%x09%ae%x09%ce"])
+        .args(revisions)
+        .output()
+        .context("Failed to execute git log")?;
+
+    if !output.status.success() {
+        bail!(
+            "git log failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            Some(CommitIdentity {
+                hash: fields.next()?.to_string(),
+                author_email: fields.next()?.to_string(),
+                committer_email: fields.next()?.to_string(),
+            })
+        })
+        .collect())
+}
+
+/// Count the commits `git rev-list` lists for the given revisions
+pub fn count_commits(revisions: &[&str]) -> Result<usize> {
+    let output = Command::new("git")
+        .args(["rev-list", "--count"])
+        .args(revisions)
+        .output()
+        .context("Failed to execute git rev-list")?;
+
+    if !output.status.success() {
+        bail!(
+            "git rev-list failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse()
+        .context("Unexpected output from git rev-list")
+}
+
+/// Object name HEAD points at
+pub fn head_commit() -> Result<String> {
+    let output = Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .context("Failed to execute git rev-parse")?;
+
+    if !output.status.success() {
+        bail!("The repository has no commits yet");
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Replay commits from `base` (or from the root commit) with `env` set,
+/// amending each one so its author is reset to the committer identity the
+/// environment gives. Hooks are skipped for the amends.
+pub fn rewrite_commits(base: Option<&str>, env: &[(String, String)]) -> Result<()> {
+    let mut cmd = Command::new("git");
+    cmd.args([
+        "rebase",
+        "--rebase-merges",
+        "--exec",
+        "git commit --amend --no-edit --allow-empty --reset-author --no-verify",
+    ]);
+    match base {
+        Some(base) => cmd.arg(base),
+        None => cmd.arg("--root"),
+    };
+
+    let status = cmd
+        .envs(env.iter().map(|(k, v)| (k, v)))
+        .status()
+        .context("Failed to execute git rebase")?;
+    if !status.success() {
+        bail!("git rebase stopped; resolve it and run 'git rebase --continue', or 'git rebase --abort'");
+    }
+    Ok(())
+}
+
+/// All remotes with their URLs
+pub fn remote_urls() -> Result<Vec<(String, String)>> {
+    let mut urls = Vec::new();
+    for remote in list_remotes()? {
+        if let Some(url) = get_remote_url(&remote)? {
+            urls.push((remote, url));
+        }
+    }
+    Ok(urls)
+}
+
 /// Parsed remote URL information
 #[derive(Debug, Clone)]
 pub struct RemoteUrl {
     pub host: String,
+    /// Repository path after the host (e.g. "owner/repo.git")
+    pub path: String,
 }
 
 impl RemoteUrl {
@@ -113,9 +473,10 @@ impl RemoteUrl {
         // SSH with alias: git@github-work:owner/repo.git
         if url.starts_with("git@") {
             let without_prefix = url.strip_prefix("git@")?;
-            let (host, _path) = without_prefix.split_once(':')?;
+            let (host, path) = without_prefix.split_once(':')?;
             return Some(RemoteUrl {
                 host: host.to_string(),
+                path: path.to_string(),
             });
         }
 
@@ -132,35 +493,178 @@ impl RemoteUrl {
 
             return Some(RemoteUrl {
                 host: parts[0].to_string(),
+                path: parts.get(1).unwrap_or(&"").to_string(),
             });
         }
 
         None
     }
+
+    /// Repository owner (user, org or group), if the path has one
+    pub fn owner(&self) -> Option<&str> {
+        let (owner, _) = self.path.split_once('/')?;
+        Some(owner).filter(|o| !o.is_empty())
+    }
+
+    /// Repository name as `git clone` would name its directory
+    pub fn repo_name(&self) -> Option<&str> {
+        let last = self.path.trim_end_matches('/').rsplit('/').next()?;
+        let name = last.strip_suffix(".git").unwrap_or(last);
+        Some(name).filter(|n| !n.is_empty())
+    }
+
+    /// SSH form of this URL pointing at a different host or alias
+    pub fn with_ssh_host(&self, host: &str) -> String {
+        format!("git@{}:{}", host, self.path)
+    }
 }
 
-/// Apply a profile's git configuration
-pub fn apply_profile(
-    name: &str,
-    email: &str,
-    gpg_key: Option<&str>,
-    scope: ConfigScope,
-) -> Result<()> {
-    set_config("user.name", name, scope)?;
-    set_config("user.email", email, scope)?;
+/// Remote names and URLs of another repository (a work tree or git dir)
+pub fn remote_urls_in(repo: &Path) -> Result<Vec<(String, String)>> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(["config", "--local", "--get-regexp", r"^remote\..*\.url$"])
+        .output()
+        .context("Failed to execute git config")?;
 
-    if let Some(key) = gpg_key {
-        set_config("user.signingkey", key, scope)?;
-        set_config("commit.gpgsign", "true", scope)?;
-    } else {
-        // Remove GPG settings if no key is specified
-        unset_config("user.signingkey", scope)?;
-        unset_config("commit.gpgsign", scope)?;
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let (key, url) = line.split_once(' ')?;
+            let remote = key.strip_prefix("remote.")?.strip_suffix(".url")?;
+            Some((remote.to_string(), url.to_string()))
+        })
+        .collect())
+}
+
+/// Set the URL of a remote in the current repository
+pub fn set_remote_url(remote: &str, url: &str) -> Result<()> {
+    set_config(&format!("remote.{}.url", remote), url, ConfigScope::Local)
+}
+
+/// Apply a profile's git configuration. `config` is needed to clear what
+/// other profiles set.
+pub fn apply_profile(config: &Config, profile_name: &str, profile: &Profile, scope: ConfigScope) -> Result<()> {
+    set_config("user.name", &profile.name, scope)?;
+    set_config("user.email", &profile.email, scope)?;
+
+    match profile.signing_key() {
+        Some((format, key)) => {
+            set_config("user.signingkey", &key, scope)?;
+            set_config("commit.gpgsign", "true", scope)?;
+            if format == SigningFormat::Ssh {
+                set_config("gpg.format", "ssh", scope)?;
+            } else {
+                unset_config("gpg.format", scope)?;
+            }
+        }
+        None => {
+            // Remove signing settings if the profile doesn't sign
+            unset_config("user.signingkey", scope)?;
+            unset_config("commit.gpgsign", scope)?;
+            unset_config("gpg.format", scope)?;
+        }
+    }
+
+    apply_credential_settings(config, profile, scope)?;
+    apply_sendemail_settings(profile_name, profile, scope)?;
+
+    // Usage statistics are best-effort; they never fail a switch
+    let _ = usage::record(profile_name, scope);
+    Ok(())
+}
+
+/// Prefix of the `sendemail.<identity>` blocks gitid writes, so it can tell
+/// its identities from hand-written ones
+const SENDEMAIL_IDENTITY_PREFIX: &str = "gitid-";
+
+/// Write the profile's `sendemail.gitid-<name>` block and select it with
+/// sendemail.identity, or deselect a gitid identity if the profile has none
+fn apply_sendemail_settings(profile_name: &str, profile: &Profile, scope: ConfigScope) -> Result<()> {
+    let Some(ref sendemail) = profile.sendemail else {
+        return match get_config("sendemail.identity", scope)? {
+            Some(identity) if identity.starts_with(SENDEMAIL_IDENTITY_PREFIX) => {
+                unset_config("sendemail.identity", scope)
+            }
+            _ => Ok(()),
+        };
+    };
+
+    let identity = format!("{}{}", SENDEMAIL_IDENTITY_PREFIX, profile_name);
+    for (key, value) in sendemail.settings() {
+        let full_key = format!("sendemail.{}.{}", identity, key);
+        match value {
+            Some(value) => set_config(&full_key, &value, scope)?,
+            None => unset_config(&full_key, scope)?,
+        }
     }
+    set_config("sendemail.identity", &identity, scope)
+}
 
+/// Point Git Credential Manager at the profile's account for each host it
+/// serves, clearing any account a previous profile selected
+fn apply_credential_settings(config: &Config, profile: &Profile, scope: ConfigScope) -> Result<()> {
+    // Another profile's account selection would stay in force on its host
+    let own = profile.credential_keys();
+    let mut others: Vec<String> = config
+        .profiles
+        .values()
+        .flat_map(Profile::credential_keys)
+        .filter(|key| !own.contains(key))
+        .collect();
+    others.sort();
+    others.dedup();
+    for key in others {
+        unset_config(&key, scope)?;
+    }
+
+    for host in profile.served_hosts() {
+        let username_key = format!("credential.https://{}.username", host);
+        let provider_key = format!("credential.https://{}.provider", host);
+
+        match profile.gcm {
+            Some(ref gcm) => set_config(&username_key, &gcm.username, scope)?,
+            None => unset_config(&username_key, scope)?,
+        }
+        match profile.gcm_provider(host) {
+            Some(provider) => set_config(&provider_key, &provider, scope)?,
+            None => unset_config(&provider_key, scope)?,
+        }
+    }
     Ok(())
 }
 
+/// Identity and signing keys `apply_profile` writes
+const IDENTITY_KEYS: [&str; 5] = [
+    "user.name",
+    "user.email",
+    "user.signingkey",
+    "commit.gpgsign",
+    "gpg.format",
+];
+
+/// Remove an applied identity from a scope, along with the sendemail
+/// identity gitid selected, so the next scope's settings show through.
+/// Returns the keys that were removed.
+pub fn clear_identity(scope: ConfigScope) -> Result<Vec<String>> {
+    let mut removed = Vec::new();
+    for key in IDENTITY_KEYS {
+        if get_config(key, scope)?.is_some() {
+            unset_config(key, scope)?;
+            removed.push(key.to_string());
+        }
+    }
+
+    if let Some(identity) = get_config("sendemail.identity", scope)? {
+        if identity.starts_with(SENDEMAIL_IDENTITY_PREFIX) {
+            unset_config("sendemail.identity", scope)?;
+            removed.push("sendemail.identity".to_string());
+        }
+    }
+    Ok(removed)
+}
+
 /// Get current git user configuration
 pub fn get_current_user(scope: ConfigScope) -> Result<(Option<String>, Option<String>)> {
     let name = get_config("user.name", scope)?;
@@ -176,12 +680,15 @@ mod tests {
     fn test_parse_ssh_url() {
         let url = RemoteUrl::parse("git@github.com:owner/repo.git").unwrap();
         assert_eq!(url.host, "github.com");
+        assert_eq!(url.path, "owner/repo.git");
+        assert_eq!(url.owner(), Some("owner"));
     }
 
     #[test]
     fn test_parse_https_url() {
         let url = RemoteUrl::parse("https://github.com/owner/repo.git").unwrap();
         assert_eq!(url.host, "github.com");
+        assert_eq!(url.with_ssh_host("github-work"), "git@github-work:owner/repo.git");
     }
 
     #[test]
@@ -189,4 +696,12 @@ mod tests {
         let url = RemoteUrl::parse("git@github-work:company/project.git").unwrap();
         assert_eq!(url.host, "github-work");
     }
+
+    #[test]
+    fn test_repo_name() {
+        let url = RemoteUrl::parse("git@gitlab.com:group/sub/project.git").unwrap();
+        assert_eq!(url.repo_name(), Some("project"));
+        let url = RemoteUrl::parse("https://github.com/owner/repo/").unwrap();
+        assert_eq!(url.repo_name(), Some("repo"));
+    }
 }
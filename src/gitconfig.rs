@@ -0,0 +1,211 @@
+use crate::config::Config;
+use crate::env;
+use crate::git;
+use crate::journal;
+use crate::managed;
+use crate::profile::{Profile, SigningFormat};
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const MANAGED_START: &str = "# === GITID MANAGED START ===";
+pub const MANAGED_END: &str = "# === GITID MANAGED END ===";
+
+/// Extension of the per-profile fragment files
+const FRAGMENT_EXT: &str = "gitconfig";
+
+/// git's XDG config directory (~/.config/git)
+pub fn git_config_dir() -> Result<PathBuf> {
+    let base = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => dirs::home_dir()
+            .context("Could not determine home directory")?
+            .join(".config"),
+    };
+    Ok(base.join("git"))
+}
+
+/// Directory holding the per-profile fragments (~/.config/git/gitid)
+pub fn fragments_dir() -> Result<PathBuf> {
+    Ok(git_config_dir()?.join("gitid"))
+}
+
+/// Quote a value for a gitconfig file when git would otherwise mangle it
+fn quote_value(value: &str) -> String {
+    let plain = !value.starts_with(' ')
+        && !value.ends_with(' ')
+        && !value.contains(['"', '\\', ';', '#']);
+    if plain {
+        return value.to_string();
+    }
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Generate the fragment a profile's directories include
+fn generate_fragment(profile: &Profile) -> String {
+    let mut fragment = String::from("# Generated by gitid; changes are overwritten by 'gitid gitconfig-sync'\n");
+
+    fragment.push_str("[user]\n");
+    fragment.push_str(&format!("\tname = {}\n", quote_value(&profile.name)));
+    fragment.push_str(&format!("\temail = {}\n", quote_value(&profile.email)));
+    if let Some((format, key)) = profile.signing_key() {
+        fragment.push_str(&format!("\tsigningkey = {}\n", quote_value(&key)));
+        fragment.push_str("[commit]\n\tgpgsign = true\n");
+        if format == SigningFormat::Ssh {
+            fragment.push_str("[gpg]\n\tformat = ssh\n");
+        }
+    }
+
+    // Plain forge URLs bypass the SSH aliases, so pin the key here too
+    fragment.push_str("[core]\n");
+    fragment.push_str(&format!(
+        "\tsshCommand = {}\n",
+        quote_value(&env::ssh_command(profile))
+    ));
+
+    fragment
+}
+
+/// Directory rules that name a configured profile, least specific first.
+/// git applies includes in order, so a nested rule must come after the
+/// broader one it overrides.
+fn directory_rules(config: &Config) -> Vec<(&str, &str)> {
+    let mut rules: Vec<(&str, &str)> = config
+        .detect
+        .directories
+        .iter()
+        .filter(|(_, target)| config.has_profile(target))
+        .map(|(pattern, target)| (pattern.as_str(), target.as_str()))
+        .collect();
+    rules.sort_by_key(|(pattern, _)| pattern.len());
+    rules
+}
+
+/// Generate the managed block of includeIf sections
+fn generate_managed_block(rules: &[(&str, &str)], fragments: &Path) -> String {
+    let mut block = String::new();
+    block.push_str(MANAGED_START);
+    block.push('\n');
+
+    for (pattern, target) in rules {
+        let path = fragments.join(format!("{}.{}", target, FRAGMENT_EXT));
+        block.push_str(&format!(
+            "[includeIf \"gitdir:{}\"]\n",
+            pattern.replace('\\', "\\\\").replace('"', "\\\"")
+        ));
+        block.push_str(&format!("\tpath = {}\n", quote_value(&path.display().to_string())));
+    }
+
+    block.push_str(MANAGED_END);
+    block
+}
+
+/// Write a file only if its content changed, journaling the old version
+fn write_if_changed(path: &Path, content: &str) -> Result<bool> {
+    if fs::read_to_string(path).ok().as_deref() == Some(content) {
+        return Ok(false);
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    journal::record_file(path)?;
+    fs::write(path, content).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(true)
+}
+
+/// Write a fragment for every profile, remove fragments of deleted profiles,
+/// and point the global gitconfig at them through directory rules.
+/// Returns the number of fragments and includeIf rules written.
+pub fn sync_gitconfig(config: &Config) -> Result<(usize, usize)> {
+    let fragments = fragments_dir()?;
+
+    for name in config.profile_names() {
+        if let Some(profile) = config.get_profile(name) {
+            let path = fragments.join(format!("{}.{}", name, FRAGMENT_EXT));
+            write_if_changed(&path, &generate_fragment(profile))?;
+        }
+    }
+
+    if let Ok(entries) = fs::read_dir(&fragments) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let stale = path.extension().is_some_and(|ext| ext == FRAGMENT_EXT)
+                && path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .is_some_and(|stem| !config.has_profile(stem));
+            if stale {
+                journal::record_file(&path)?;
+                fs::remove_file(&path)
+                    .with_context(|| format!("Failed to remove {}", path.display()))?;
+            }
+        }
+    }
+
+    let rules = directory_rules(config);
+    let block = generate_managed_block(&rules, &fragments);
+
+    let path = git::global_config_path()?;
+    let current = if path.exists() {
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?
+    } else {
+        String::new()
+    };
+    let (content, _) = managed::splice_block(&current, MANAGED_START, MANAGED_END, &block);
+    write_if_changed(&path, &content)?;
+
+    Ok((config.profiles.len(), rules.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::Platform;
+
+    #[test]
+    fn test_generate_fragment() {
+        let profile = Profile::new(
+            "John \"JD\" Doe".to_string(),
+            "john@company.com".to_string(),
+            Platform::Github,
+            "/keys/id_work".to_string(),
+            Some("ABC123".to_string()),
+            None,
+        );
+
+        let fragment = generate_fragment(&profile);
+        assert!(fragment.contains("\tname = \"John \\\"JD\\\" Doe\"\n"));
+        assert!(fragment.contains("\temail = john@company.com\n"));
+        assert!(fragment.contains("[commit]\n\tgpgsign = true\n"));
+        assert!(fragment.contains("\tsshCommand = ssh -i /keys/id_work -o IdentitiesOnly=yes\n"));
+    }
+
+    #[test]
+    fn test_rules_order_nested_last() {
+        let mut config = Config::default();
+        for name in ["work", "oss"] {
+            config.profiles.insert(
+                name.to_string(),
+                Profile::new(
+                    "John Doe".to_string(),
+                    format!("john@{}.com", name),
+                    Platform::Github,
+                    format!("~/.ssh/id_{}", name),
+                    None,
+                    None,
+                ),
+            );
+        }
+        config.detect.directories.insert("~/work/**".to_string(), "work".to_string());
+        config.detect.directories.insert("~/work/oss/**".to_string(), "oss".to_string());
+        config.detect.directories.insert("~/gone/**".to_string(), "missing".to_string());
+
+        let rules = directory_rules(&config);
+        assert_eq!(rules, vec![("~/work/**", "work"), ("~/work/oss/**", "oss")]);
+
+        let block = generate_managed_block(&rules, Path::new("/cfg/git/gitid"));
+        assert!(block.contains("[includeIf \"gitdir:~/work/oss/**\"]\n\tpath = /cfg/git/gitid/oss.gitconfig\n"));
+    }
+}
@@ -0,0 +1,335 @@
+use crate::ssh_keys;
+use anyhow::{Context, Result, bail};
+use std::process::{Command, Stdio};
+
+/// Build a gpg command, using an alternate GnuPG home if given
+fn gpg_command(gnupghome: Option<&str>) -> Command {
+    let mut cmd = Command::new("gpg");
+    if let Some(home) = gnupghome {
+        cmd.arg("--homedir").arg(ssh_keys::expand_path(home));
+    }
+    cmd
+}
+
+/// Check if gpg is installed
+pub fn is_installed() -> bool {
+    Command::new("gpg")
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(false)
+}
+
+/// Check if a secret key for the given ID is in the keyring
+pub fn has_secret_key(key_id: &str, gnupghome: Option<&str>) -> bool {
+    gpg_command(gnupghome)
+        .args(["--list-secret-keys", key_id])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(false)
+}
+
+/// A secret key from `gpg --list-secret-keys --with-colons`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecretKey {
+    pub fingerprint: String,
+    /// Long (16 hex digit) key ID
+    pub key_id: String,
+    /// gpg's validity flag: `e` expired, `r` revoked, `u` ultimate, ...
+    pub validity: String,
+    /// Expiry as a Unix timestamp, None if the key doesn't expire
+    pub expires: Option<u64>,
+    /// Whether the key or one of its subkeys can sign
+    pub can_sign: bool,
+    pub uids: Vec<String>,
+    /// Subkey fingerprints and whether each can sign
+    pub subkeys: Vec<(String, bool)>,
+}
+
+impl SecretKey {
+    /// Addresses in the key's user IDs (`Name <email>`)
+    pub fn emails(&self) -> Vec<&str> {
+        self.uids
+            .iter()
+            .filter_map(|uid| uid.rsplit_once('<')?.1.strip_suffix('>'))
+            .collect()
+    }
+
+    /// Whether the key can sign commits: signing-capable, not expired or revoked
+    pub fn usable(&self) -> bool {
+        self.can_sign && !matches!(self.validity.as_str(), "e" | "r")
+    }
+
+    /// One line for a picker: key ID, first user ID and expiry relative to `now`
+    pub fn describe(&self, now: u64) -> String {
+        let expiry = match self.expires {
+            None => "no expiry".to_string(),
+            Some(t) if t <= now => "expired".to_string(),
+            Some(t) => format!("expires in {} days", (t - now) / 86400),
+        };
+        let uid = self.uids.first().map(String::as_str).unwrap_or("no user ID");
+        format!("{}  {}  ({})", self.key_id, uid, expiry)
+    }
+
+    /// Whether gpg would pick this key for `spec`: a key ID or fingerprint
+    /// of the key or a subkey (optionally `0x`-prefixed or `!`-suffixed),
+    /// or part of a user ID. Returns whether the match signs.
+    fn matches(&self, spec: &str) -> Option<bool> {
+        let id = spec.trim().trim_end_matches('!');
+        let id = id.strip_prefix("0x").unwrap_or(id).to_ascii_uppercase();
+        if id.len() >= 8 && id.chars().all(|c| c.is_ascii_hexdigit()) {
+            if self.fingerprint.ends_with(&id) {
+                return Some(self.can_sign);
+            }
+            return self.subkeys.iter().find(|(fpr, _)| fpr.ends_with(&id)).map(|(_, sign)| *sign);
+        }
+        let spec = spec.trim().trim_start_matches(['<', '=']).trim_end_matches('>').to_lowercase();
+        self.uids
+            .iter()
+            .any(|uid| uid.to_lowercase().contains(&spec))
+            .then_some(self.can_sign)
+    }
+}
+
+/// Parse `--with-colons` output into its secret keys
+fn parse_secret_keys(text: &str) -> Vec<SecretKey> {
+    let mut keys: Vec<SecretKey> = Vec::new();
+    // Whether the next fpr record belongs to a subkey
+    let mut in_subkey = false;
+
+    for line in text.lines() {
+        let fields: Vec<&str> = line.split(':').collect();
+        let field = |i: usize| fields.get(i).copied().unwrap_or("");
+        match field(0) {
+            "sec" => {
+                keys.push(SecretKey {
+                    fingerprint: String::new(),
+                    key_id: field(4).to_string(),
+                    validity: field(1).to_string(),
+                    expires: field(6).parse().ok(),
+                    // Capital letters are what the whole key, subkeys included, can do
+                    can_sign: field(11).contains('S'),
+                    uids: Vec::new(),
+                    subkeys: Vec::new(),
+                });
+                in_subkey = false;
+            }
+            "ssb" => {
+                if let Some(key) = keys.last_mut() {
+                    key.subkeys.push((String::new(), field(11).contains('s')));
+                }
+                in_subkey = true;
+            }
+            "fpr" => {
+                let Some(key) = keys.last_mut() else {
+                    continue;
+                };
+                let fingerprint = field(9).to_string();
+                match key.subkeys.last_mut() {
+                    Some(subkey) if in_subkey => subkey.0 = fingerprint,
+                    _ if key.fingerprint.is_empty() => key.fingerprint = fingerprint,
+                    _ => {}
+                }
+            }
+            "uid" => {
+                if let Some(key) = keys.last_mut() {
+                    key.uids.push(field(9).to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+    keys
+}
+
+/// Secret keys in the keyring
+pub fn secret_keys(gnupghome: Option<&str>) -> Result<Vec<SecretKey>> {
+    let output = gpg_command(gnupghome)
+        .args(["--list-secret-keys", "--with-colons", "--fixed-list-mode"])
+        .output()
+        .context("Failed to run gpg. Is GnuPG installed?")?;
+    if !output.status.success() {
+        bail!(
+            "gpg failed to list secret keys: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(parse_secret_keys(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Reasons `key_id` may not work for signing as `email`: missing from the
+/// keyring, unable to sign, expired or revoked, or without a user ID for
+/// the address
+pub fn signing_key_problems(key_id: &str, email: &str, gnupghome: Option<&str>) -> Result<Vec<String>> {
+    if !is_installed() {
+        return Ok(vec!["gpg is not installed, so the key can't be checked".to_string()]);
+    }
+    Ok(key_problems(&secret_keys(gnupghome)?, key_id, email))
+}
+
+fn key_problems(keys: &[SecretKey], key_id: &str, email: &str) -> Vec<String> {
+    let Some((key, can_sign)) = keys.iter().find_map(|k| Some((k, k.matches(key_id)?))) else {
+        return vec![format!("No secret key {} in the GnuPG keyring", key_id)];
+    };
+
+    let mut problems = Vec::new();
+    if !can_sign {
+        problems.push(format!("Key {} has no signing capability", key_id));
+    }
+    match key.validity.as_str() {
+        "e" => problems.push(format!("Key {} has expired", key_id)),
+        "r" => problems.push(format!("Key {} has been revoked", key_id)),
+        _ => {}
+    }
+    if !key.emails().iter().any(|e| e.eq_ignore_ascii_case(email)) {
+        problems.push(format!(
+            "Key {} has no user ID for {} (it has {}), so forges won't show commits as verified",
+            key_id,
+            email,
+            key.uids.join(", ")
+        ));
+    }
+    problems
+}
+
+/// Sign a short buffer with `key_id`, the way git does, and return gpg's
+/// error output if that fails. git only reports "gpg failed to sign the
+/// data", so this is what says why.
+pub fn sign_error(key_id: &str, gnupghome: Option<&str>) -> Result<Option<String>> {
+    use std::io::Write;
+
+    let mut child = gpg_command(gnupghome)
+        .args(["--status-fd=2", "-bsau", key_id])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run gpg. Is GnuPG installed?")?;
+    child
+        .stdin
+        .take()
+        .context("Failed to open gpg stdin")?
+        .write_all(b"gitid sign-test\n")?;
+    let output = child.wait_with_output()?;
+    if output.status.success() {
+        return Ok(None);
+    }
+
+    let errors: Vec<&str> = std::str::from_utf8(&output.stderr)
+        .unwrap_or_default()
+        .lines()
+        .filter(|line| !line.starts_with("[GNUPG:]"))
+        .collect();
+    Ok(Some(errors.join("\n")))
+}
+
+/// Fingerprint from the KEY_CREATED line of gpg's status output
+fn created_fingerprint(status: &str) -> Option<String> {
+    status.lines().find_map(|line| {
+        let mut fields = line.strip_prefix("[GNUPG:] KEY_CREATED ")?.split_whitespace();
+        fields.next()?;
+        fields.next().map(str::to_string)
+    })
+}
+
+/// Generate an ed25519 signing key for `Name <email>` and return its
+/// fingerprint. gpg asks for the passphrase itself through pinentry.
+pub fn generate_key(name: &str, email: &str) -> Result<String> {
+    let output = Command::new("gpg")
+        .args(["--status-fd", "1", "--quick-generate-key"])
+        .arg(format!("{} <{}>", name, email))
+        .args(["ed25519", "sign", "0"])
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()
+        .context("Failed to run gpg. Is GnuPG installed?")?;
+
+    if !output.status.success() {
+        bail!("gpg failed to generate a key");
+    }
+
+    created_fingerprint(&String::from_utf8_lossy(&output.stdout))
+        .context("gpg did not report the new key's fingerprint")
+}
+
+/// Delete a key pair by fingerprint, without prompting
+pub fn delete_key(fingerprint: &str) -> Result<()> {
+    let output = Command::new("gpg")
+        .args(["--batch", "--yes", "--delete-secret-and-public-key", fingerprint])
+        .output()
+        .context("Failed to run gpg. Is GnuPG installed?")?;
+
+    if !output.status.success() {
+        bail!(
+            "Failed to delete GPG key {}: {}",
+            fingerprint,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_created_fingerprint() {
+        let status = "[GNUPG:] KEY_CONSIDERED 0123 0\n\
+                      [GNUPG:] KEY_CREATED P 3AA5C34371567BD2ABCDEF0123456789ABCDEF01\n";
+        assert_eq!(
+            created_fingerprint(status).as_deref(),
+            Some("3AA5C34371567BD2ABCDEF0123456789ABCDEF01")
+        );
+        assert_eq!(created_fingerprint("[GNUPG:] PROGRESS primegen"), None);
+    }
+
+    const LISTING: &str = "sec:u:255:22:0123456789ABCDEF:1700000000:1900000000::u:::cSC:::+:::ed25519:::0:\n\
+                           fpr:::::::::AAAABBBBCCCCDDDD0123456789ABCDEF:\n\
+                           grp:::::::::1111:\n\
+                           uid:u::::1700000000::2222::John Doe <john@acme.com>::::::::::0:\n\
+                           ssb:u:255:18:FEDCBA9876543210:1700000000::::::e:::+:::cv25519::\n\
+                           fpr:::::::::EEEEFFFF0000111122223333FEDCBA9876543210:\n\
+                           sec:e:3072:1:1111222233334444:1500000000:1600000000::u:::cE:::+::::::0:\n\
+                           fpr:::::::::99998888777766661111222233334444:\n\
+                           uid:e::::1500000000::3333::Old Key <old@example.com>::::::::::0:\n";
+
+    #[test]
+    fn test_parse_secret_keys() {
+        let keys = parse_secret_keys(LISTING);
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[0].fingerprint, "AAAABBBBCCCCDDDD0123456789ABCDEF");
+        assert_eq!(keys[0].key_id, "0123456789ABCDEF");
+        assert_eq!(keys[0].expires, Some(1900000000));
+        assert!(keys[0].can_sign);
+        assert_eq!(keys[0].emails(), vec!["john@acme.com"]);
+        assert_eq!(keys[0].subkeys, vec![("EEEEFFFF0000111122223333FEDCBA9876543210".to_string(), false)]);
+        assert!(!keys[1].can_sign);
+
+        assert_eq!(keys[0].matches("0x0123456789abcdef"), Some(true));
+        assert_eq!(keys[0].matches("FEDCBA9876543210!"), Some(false));
+        assert_eq!(keys[0].matches("john@acme.com"), Some(true));
+        assert_eq!(keys[0].matches("DEADBEEF"), None);
+
+        assert!(keys[0].usable());
+        assert!(!keys[1].usable());
+        assert_eq!(
+            keys[0].describe(1900000000 - 10 * 86400),
+            "0123456789ABCDEF  John Doe <john@acme.com>  (expires in 10 days)"
+        );
+    }
+
+    #[test]
+    fn test_key_problems() {
+        let keys = parse_secret_keys(LISTING);
+        assert!(key_problems(&keys, "0123456789ABCDEF", "John@Acme.com").is_empty());
+        assert_eq!(key_problems(&keys, "0123456789ABCDEF", "john@example.com").len(), 1);
+        assert_eq!(key_problems(&keys, "1111222233334444", "old@example.com").len(), 2);
+        assert!(key_problems(&keys, "DEADBEEF", "john@acme.com")[0].starts_with("No secret key"));
+    }
+}
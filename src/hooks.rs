@@ -0,0 +1,430 @@
+use crate::config::Config;
+use crate::detect;
+use crate::git;
+use crate::journal;
+use crate::policy;
+use crate::prompt;
+use crate::remote;
+use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Comment identifying hook scripts that gitid installed
+const SHIM_MARKER: &str = "# gitid-managed hook";
+
+/// Set to bypass every gitid hook for a single command
+pub const SKIP_ENV: &str = "GITID_SKIP_HOOKS";
+
+/// Git hooks that gitid implements
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum HookKind {
+    /// Block commits by another profile's email or that break a policy
+    PreCommit,
+    /// Block pushes to owners the profile rules out, or that publish
+    /// another profile's email
+    PrePush,
+}
+
+impl HookKind {
+    /// File name git looks for in the hooks directory
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            HookKind::PreCommit => "pre-commit",
+            HookKind::PrePush => "pre-push",
+        }
+    }
+}
+
+/// Hook files gitid has installed, so they can be found again on uninstall
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct HookRegistry {
+    #[serde(default)]
+    paths: Vec<PathBuf>,
+}
+
+impl HookRegistry {
+    fn path() -> Result<PathBuf> {
+        Ok(Config::state_dir()?.join("hooks.toml"))
+    }
+
+    fn load() -> Result<Self> {
+        let path = Self::path()?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        toml::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    fn save(&self) -> Result<()> {
+        let path = Self::path()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create state directory: {}", parent.display()))?;
+        }
+        let content = toml::to_string_pretty(self).context("Failed to serialize hook registry")?;
+        fs::write(&path, content).with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+/// Whether the file at `path` is a hook gitid installed
+pub fn is_managed_hook(path: &Path) -> bool {
+    fs::read_to_string(path)
+        .map(|content| content.contains(SHIM_MARKER))
+        .unwrap_or(false)
+}
+
+/// Installed gitid hooks that still exist on disk
+pub fn installed_hooks() -> Result<Vec<PathBuf>> {
+    Ok(HookRegistry::load()?
+        .paths
+        .into_iter()
+        .filter(|p| is_managed_hook(p))
+        .collect())
+}
+
+/// Git directories of the repositories with gitid hooks installed
+pub fn hooked_repos() -> Result<Vec<PathBuf>> {
+    let mut repos: Vec<PathBuf> = installed_hooks()?
+        .iter()
+        .filter_map(|hook| Some(hook.parent()?.parent()?.to_path_buf()))
+        .collect();
+    repos.sort();
+    repos.dedup();
+    Ok(repos)
+}
+
+/// Hook script that hands straight off to `gitid hook run`, so hook logic
+/// is upgraded with the binary instead of living in each repository
+pub fn shim_script(kind: HookKind) -> String {
+    format!(
+        "#!/bin/sh\n{}\n# Logic lives in gitid; reinstall with 'gitid hook install'.\nexec gitid hook run {} -- \"$@\"\n",
+        SHIM_MARKER,
+        kind.file_name()
+    )
+}
+
+/// Get the hooks directory for the current repository (honors core.hooksPath)
+pub fn hooks_dir() -> Result<PathBuf> {
+    let output = std::process::Command::new("git")
+        .args(["rev-parse", "--path-format=absolute", "--git-path", "hooks"])
+        .output()
+        .context("Failed to execute git rev-parse")?;
+
+    if !output.status.success() {
+        bail!("Not in a git repository");
+    }
+
+    Ok(PathBuf::from(
+        String::from_utf8_lossy(&output.stdout).trim(),
+    ))
+}
+
+/// Install the shim for a hook. An existing hook that gitid didn't write
+/// is only replaced with `force`.
+pub fn install(kind: HookKind, force: bool) -> Result<PathBuf> {
+    let dir = hooks_dir()?;
+    let path = dir.join(kind.file_name());
+
+    if path.exists() && !force && !is_managed_hook(&path) {
+        bail!(
+            "{} already exists and was not installed by gitid. Use --force to replace it.",
+            path.display()
+        );
+    }
+
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create hooks directory: {}", dir.display()))?;
+
+    journal::record_file(&path)?;
+    fs::write(&path, shim_script(kind))
+        .with_context(|| format!("Failed to write hook: {}", path.display()))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+    }
+
+    let mut registry = HookRegistry::load()?;
+    if !registry.paths.contains(&path) {
+        registry.paths.push(path.clone());
+        registry.save()?;
+    }
+
+    Ok(path)
+}
+
+/// Run the logic for a hook. Returning an error makes git abort.
+pub fn run(kind: HookKind, args: &[String]) -> Result<()> {
+    if std::env::var_os(SKIP_ENV).is_some() {
+        return Ok(());
+    }
+
+    let config = Config::load()?;
+    match kind {
+        HookKind::PreCommit => pre_commit(&config),
+        HookKind::PrePush => pre_push(&config, args),
+    }
+}
+
+/// Block commits whose author email doesn't belong to the detected profile,
+/// or that break a policy for this repository's remotes
+fn pre_commit(config: &Config) -> Result<()> {
+    let email = git::get_effective_config("user.email")?;
+
+    if let Some(result) = detect::detect_profile_cached(config)? {
+        // With a tie, any of the tied profiles' emails will do
+        let tied_email = result.tied.iter().any(|t| {
+            config
+                .get_profile(&t.profile_name)
+                .is_some_and(|p| email.as_deref().is_some_and(|e| e.eq_ignore_ascii_case(&p.email)))
+        });
+        if let (Some(profile), false) = (config.get_profile(&result.profile_name), tied_email) {
+            check_commit_identity(&result.profile_name, &profile.email, email.as_deref())?;
+        }
+    }
+
+    let violations = policy::evaluate_repository(config)?;
+    if !violations.is_empty() {
+        let lines: Vec<String> = violations.iter().map(|v| v.to_string()).collect();
+        bail!(
+            "Commit blocked by gitid:\n  {}\nSet {}=1 to bypass once.",
+            lines.join("\n  "),
+            SKIP_ENV
+        );
+    }
+
+    Ok(())
+}
+
+/// Compare the configured email with the one the expected profile uses
+fn check_commit_identity(profile_name: &str, expected: &str, actual: Option<&str>) -> Result<()> {
+    if actual.is_some_and(|a| a.eq_ignore_ascii_case(expected)) {
+        return Ok(());
+    }
+
+    bail!(
+        "Commit blocked by gitid: user.email is {} but this repository matches profile '{}' <{}>.\n\
+         Run 'gitid use {}', or set {}=1 to bypass once.",
+        actual.unwrap_or("unset"),
+        profile_name,
+        expected,
+        profile_name,
+        SKIP_ENV
+    )
+}
+
+/// Revisions for `git log` covering the commits one line of pre-push input
+/// (`<local ref> <local sha> <remote ref> <remote sha>`) publishes.
+/// Deleting a ref publishes nothing.
+fn pushed_revisions(line: &str) -> Option<Vec<String>> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let [_, local, _, remote] = fields[..] else {
+        return None;
+    };
+    let is_null = |sha: &str| sha.bytes().all(|b| b == b'0');
+
+    if is_null(local) {
+        None
+    } else if is_null(remote) {
+        // A new branch publishes whatever no remote-tracking branch has yet
+        Some(vec![local.to_string(), "--not".to_string(), "--remotes".to_string()])
+    } else {
+        Some(vec![format!("{}..{}", remote, local)])
+    }
+}
+
+/// Configured profile an email belongs to, by the profile's own email or
+/// an email rule
+fn owning_profile<'a>(config: &'a Config, email: &str) -> Option<&'a str> {
+    config
+        .profile_names()
+        .into_iter()
+        .find(|name| {
+            config
+                .get_profile(name)
+                .is_some_and(|p| p.email.eq_ignore_ascii_case(email))
+        })
+        .map(|name| name.as_str())
+        .or_else(|| detect::profile_for_email(config, email))
+}
+
+/// Refuse pushing to a remote whose owner the given profiles'
+/// allow_owners/deny_owners rule out
+fn check_push_owners(config: &Config, profiles: &[&str], remote: &str, url: &str) -> Result<()> {
+    let remotes = [(remote.to_string(), url.to_string())];
+    for name in profiles {
+        let Some(profile) = config.get_profile(name) else {
+            continue;
+        };
+        if let Err(err) = remote::enforce_owners(name, profile, &remotes) {
+            bail!("Push blocked by gitid: {}\nSet {}=1 to bypass once.", err, SKIP_ENV);
+        }
+    }
+    Ok(())
+}
+
+/// Refuse pushes to an owner the applied or expected profile may not push
+/// to, or that would publish another profile's email to a remote that
+/// belongs to this one, and warn about other addresses
+fn pre_push(config: &Config, args: &[String]) -> Result<()> {
+    let Some(url) = args.get(1) else {
+        return Ok(());
+    };
+    let result = detect::detect_for_url(config, url);
+
+    let mut pushing: Vec<String> = prompt::get_current_profile(config)?.into_iter().collect();
+    pushing.extend(result.iter().map(|r| r.profile_name.clone()));
+    pushing.dedup();
+    let pushing: Vec<&str> = pushing.iter().map(String::as_str).collect();
+    check_push_owners(config, &pushing, &args[0], url)?;
+
+    let Some(result) = result else {
+        return Ok(());
+    };
+    let Some(profile) = config.get_profile(&result.profile_name) else {
+        return Ok(());
+    };
+
+    let mut updates = String::new();
+    std::io::stdin()
+        .read_to_string(&mut updates)
+        .context("Failed to read pushed refs")?;
+
+    let mut blocked = Vec::new();
+    let mut unknown = Vec::new();
+    for revisions in updates.lines().filter_map(pushed_revisions) {
+        let revisions: Vec<&str> = revisions.iter().map(String::as_str).collect();
+        for commit in git::commit_identities(&revisions)? {
+            let mut emails = vec![&commit.author_email, &commit.committer_email];
+            emails.dedup();
+            for email in emails {
+                if email.eq_ignore_ascii_case(&profile.email) {
+                    continue;
+                }
+                match owning_profile(config, email) {
+                    Some(owner) if owner != result.profile_name => {
+                        blocked.push(format!("{} {} (profile '{}')", commit.hash, email, owner))
+                    }
+                    // An email rule maps it to the expected profile
+                    Some(_) => {}
+                    None => unknown.push(format!("{} {}", commit.hash, email)),
+                }
+            }
+        }
+    }
+
+    if !unknown.is_empty() {
+        eprintln!(
+            "{} pushing commits by addresses that aren't '{}' <{}>:\n  {}",
+            "Warning:".yellow().bold(),
+            result.profile_name,
+            profile.email,
+            unknown.join("\n  ")
+        );
+    }
+    if !blocked.is_empty() {
+        bail!(
+            "Push blocked by gitid: {} belongs to profile '{}' <{}>, but these commits use another profile's email:\n  {}\n\
+             Rewrite them (e.g. 'git commit --amend --reset-author'), or set {}=1 to bypass once.",
+            url,
+            result.profile_name,
+            profile.email,
+            blocked.join("\n  "),
+            SKIP_ENV
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shim_script() {
+        let script = shim_script(HookKind::PreCommit);
+        assert!(script.starts_with("#!/bin/sh\n"));
+        assert!(script.contains(SHIM_MARKER));
+        assert!(script.contains("exec gitid hook run pre-commit"));
+    }
+
+    #[test]
+    fn test_check_commit_identity() {
+        assert!(check_commit_identity("work", "john@acme.com", Some("John@Acme.com")).is_ok());
+        assert!(check_commit_identity("work", "john@acme.com", Some("me@example.com")).is_err());
+        assert!(check_commit_identity("work", "john@acme.com", None).is_err());
+    }
+
+    #[test]
+    fn test_pushed_revisions() {
+        let zero = "0".repeat(40);
+        let new_branch = format!("refs/heads/topic abc123 refs/heads/topic {}", zero);
+        assert_eq!(
+            pushed_revisions(&new_branch),
+            Some(vec!["abc123".to_string(), "--not".to_string(), "--remotes".to_string()])
+        );
+        assert_eq!(
+            pushed_revisions("refs/heads/main abc123 refs/heads/main def456"),
+            Some(vec!["def456..abc123".to_string()])
+        );
+        let deletion = format!("(delete) {} refs/heads/old def456", zero);
+        assert_eq!(pushed_revisions(&deletion), None);
+    }
+
+    #[test]
+    fn test_owning_profile() {
+        use crate::profile::{Platform, Profile};
+
+        let mut config = Config::default();
+        for (name, email) in [("work", "john@acme.com"), ("personal", "john@example.com")] {
+            config.profiles.insert(
+                name.to_string(),
+                Profile::new(
+                    "John Doe".to_string(),
+                    email.to_string(),
+                    Platform::Github,
+                    format!("~/.ssh/id_{}", name),
+                    None,
+                    None,
+                ),
+            );
+        }
+        config.detect.email_domains.insert("*@acme.com".to_string(), "work".to_string());
+
+        assert_eq!(owning_profile(&config, "John@Example.com"), Some("personal"));
+        assert_eq!(owning_profile(&config, "jane@acme.com"), Some("work"));
+        assert_eq!(owning_profile(&config, "jane@elsewhere.org"), None);
+    }
+
+    #[test]
+    fn test_check_push_owners() {
+        use crate::profile::{Platform, Profile};
+
+        let mut config = Config::default();
+        let mut personal = Profile::new(
+            "John Doe".to_string(),
+            "john@example.com".to_string(),
+            Platform::Github,
+            "~/.ssh/id_personal".to_string(),
+            None,
+            None,
+        );
+        personal.deny_owners = vec!["acme-*".to_string()];
+        config.profiles.insert("personal".to_string(), personal);
+
+        let url = "git@github.com:acme-corp/api.git";
+        let err = check_push_owners(&config, &["personal"], "origin", url).unwrap_err();
+        assert!(err.to_string().starts_with("Push blocked by gitid"));
+        assert!(err.to_string().contains("deny_owners"));
+        assert!(check_push_owners(&config, &["personal"], "origin", "git@github.com:jdoe/dotfiles.git").is_ok());
+        assert!(check_push_owners(&config, &["missing"], "origin", url).is_ok());
+    }
+}
@@ -0,0 +1,93 @@
+use anyhow::{Result, bail};
+use inquire::{Confirm, Select, Text};
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static NO_INPUT: AtomicBool = AtomicBool::new(false);
+static ASSUME_YES: AtomicBool = AtomicBool::new(false);
+
+/// Apply the global --no-input and --yes flags
+pub fn configure(no_input: bool, yes: bool) {
+    NO_INPUT.store(no_input, Ordering::Relaxed);
+    ASSUME_YES.store(yes, Ordering::Relaxed);
+}
+
+/// Whether prompts can be shown: --no-input wasn't given and stdin is a
+/// terminal
+pub fn is_interactive() -> bool {
+    !NO_INPUT.load(Ordering::Relaxed) && std::io::stdin().is_terminal()
+}
+
+/// Whether --yes was given
+pub fn assume_yes() -> bool {
+    ASSUME_YES.load(Ordering::Relaxed)
+}
+
+/// Prompt message without its trailing colon, for error messages
+fn label(message: &str) -> &str {
+    message.trim_end_matches(':')
+}
+
+/// Ask a yes/no question that has a sensible default. Without a terminal
+/// the default is taken, or yes with --yes.
+pub fn confirm(prompt: Confirm) -> Result<bool> {
+    if assume_yes() {
+        return Ok(true);
+    }
+    if !is_interactive() {
+        return Ok(prompt.default.unwrap_or(false));
+    }
+    Ok(prompt.prompt()?)
+}
+
+/// Ask before doing something hard to undo. Without a terminal this fails
+/// unless --yes was given, rather than guessing.
+pub fn approve(prompt: Confirm) -> Result<bool> {
+    if assume_yes() {
+        return Ok(true);
+    }
+    if !is_interactive() {
+        bail!(
+            "Cannot ask \"{}\" in non-interactive mode; pass --yes to confirm",
+            prompt.message
+        );
+    }
+    Ok(prompt.prompt()?)
+}
+
+/// Ask for a required value. Without a terminal the prompt's default is
+/// used, and if there is none this fails naming the flag that supplies it.
+pub fn text(prompt: Text, flag: &str) -> Result<String> {
+    if !is_interactive() {
+        return match prompt.default {
+            Some(default) => Ok(default.to_string()),
+            None => bail!(
+                "Cannot prompt for '{}' in non-interactive mode; pass {}",
+                label(prompt.message),
+                flag
+            ),
+        };
+    }
+    Ok(prompt.prompt()?)
+}
+
+/// Ask for an optional value; empty when there is no terminal
+pub fn optional_text(prompt: Text) -> Result<String> {
+    if !is_interactive() {
+        return Ok(prompt.default.unwrap_or_default().to_string());
+    }
+    Ok(prompt.prompt()?)
+}
+
+/// Ask to pick one option. Without a terminal this fails naming the flag
+/// or argument that makes the choice.
+pub fn select<T: std::fmt::Display>(prompt: Select<T>, flag: &str) -> Result<T> {
+    if !is_interactive() {
+        bail!(
+            "Cannot prompt for '{}' in non-interactive mode; pass {}",
+            label(prompt.message),
+            flag
+        );
+    }
+    Ok(prompt.prompt()?)
+}
@@ -0,0 +1,491 @@
+use crate::config::Config;
+use crate::git::{self, ConfigScope};
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Number of operations kept in the journal before the oldest are dropped
+const MAX_OPERATIONS: usize = 50;
+
+/// A single change gitid made outside its own config, with enough
+/// information to undo it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Change {
+    /// A git config key was set or unset
+    GitConfig {
+        scope: ConfigScope,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        repo: Option<PathBuf>,
+        key: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        previous: Option<String>,
+    },
+    /// A file was written; `previous` is None if it did not exist before
+    File {
+        path: PathBuf,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        previous: Option<String>,
+    },
+    /// A file holding secrets was replaced after moving the original to
+    /// `backup`, so its content never enters the journal
+    Moved { path: PathBuf, backup: PathBuf },
+    /// glab's default host was changed; `previous` is None if it was unset.
+    /// Its config file holds tokens, so only the host is kept.
+    GlabHost {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        previous: Option<String>,
+    },
+}
+
+impl Change {
+    /// Short human-readable description of the change
+    pub fn describe(&self) -> String {
+        match self {
+            Change::GitConfig {
+                scope, repo, key, ..
+            } => match (scope, repo) {
+                (ConfigScope::Local, Some(repo)) => {
+                    format!("git config {} in {}", key, repo.display())
+                }
+                (ConfigScope::Local, None) => format!("git config {} (local)", key),
+                (ConfigScope::Global, _) => format!("git config {} (global)", key),
+            },
+            Change::File { path, .. } => format!("file {}", path.display()),
+            Change::Moved { path, .. } => format!("file {}", path.display()),
+            Change::GlabHost { .. } => "glab default host".to_string(),
+        }
+    }
+
+    /// Restore the state recorded before this change
+    pub fn revert(&self) -> Result<()> {
+        match self {
+            Change::GitConfig {
+                scope,
+                repo,
+                key,
+                previous,
+            } => git::restore_config(repo.as_deref(), key, previous.as_deref(), *scope),
+            Change::File { path, previous } => match previous {
+                Some(content) => fs::write(path, content)
+                    .with_context(|| format!("Failed to restore {}", path.display())),
+                None => {
+                    if path.exists() {
+                        fs::remove_file(path)
+                            .with_context(|| format!("Failed to remove {}", path.display()))?;
+                    }
+                    Ok(())
+                }
+            },
+            Change::Moved { path, backup } => fs::rename(backup, path).with_context(|| {
+                format!("Failed to move {} back to {}", backup.display(), path.display())
+            }),
+            Change::GlabHost { previous } => {
+                // gitlab.com is glab's own default when no host is set
+                let host = previous.as_deref().unwrap_or("gitlab.com");
+                let output = Command::new("glab")
+                    .args(["config", "set", "-g", "host", host])
+                    .output()
+                    .context("Failed to run glab config set")?;
+                if !output.status.success() {
+                    bail!(
+                        "glab config set failed: {}",
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    );
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A recorded change tagged with the gitid invocation that made it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub operation: String,
+    pub command: String,
+    pub timestamp: u64,
+    pub change: Change,
+}
+
+/// All entries belonging to one gitid invocation
+#[derive(Debug)]
+pub struct Operation {
+    pub command: String,
+    pub timestamp: u64,
+    pub changes: Vec<Change>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Journal {
+    #[serde(default)]
+    entries: Vec<JournalEntry>,
+}
+
+impl Journal {
+    /// Get the journal file path
+    pub fn path() -> Result<PathBuf> {
+        Ok(Config::state_dir()?.join("journal.toml"))
+    }
+
+    /// Load the journal, or an empty one if nothing has been recorded yet
+    pub fn load() -> Result<Self> {
+        let path = Self::path()?;
+        if !path.exists() {
+            return Ok(Journal::default());
+        }
+
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read journal: {}", path.display()))?;
+
+        toml::from_str(&content)
+            .with_context(|| format!("Failed to parse journal: {}", path.display()))
+    }
+
+    /// Save the journal
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path()?;
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create state directory: {}", parent.display()))?;
+        }
+
+        let content = toml::to_string_pretty(self).context("Failed to serialize journal")?;
+
+        fs::write(&path, content)
+            .with_context(|| format!("Failed to write journal: {}", path.display()))
+    }
+
+    /// Append an entry, dropping the oldest operations beyond the retention limit
+    fn push(&mut self, entry: JournalEntry) {
+        self.entries.push(entry);
+
+        let ids = self.operation_ids();
+        if ids.len() > MAX_OPERATIONS {
+            let keep = &ids[ids.len() - MAX_OPERATIONS..];
+            self.entries.retain(|e| keep.contains(&e.operation));
+        }
+    }
+
+    /// Operation ids in chronological order
+    fn operation_ids(&self) -> Vec<String> {
+        let mut ids: Vec<String> = Vec::new();
+        for entry in &self.entries {
+            if ids.last() != Some(&entry.operation) {
+                ids.push(entry.operation.clone());
+            }
+        }
+        ids
+    }
+
+    /// Recorded operations, most recent first
+    pub fn operations(&self) -> Vec<Operation> {
+        let mut operations: Vec<Operation> = Vec::new();
+        let mut last_id: Option<&str> = None;
+
+        for entry in &self.entries {
+            if last_id == Some(entry.operation.as_str()) {
+                if let Some(op) = operations.last_mut() {
+                    op.changes.push(entry.change.clone());
+                }
+            } else {
+                operations.push(Operation {
+                    command: entry.command.clone(),
+                    timestamp: entry.timestamp,
+                    changes: vec![entry.change.clone()],
+                });
+                last_id = Some(entry.operation.as_str());
+            }
+        }
+
+        operations.reverse();
+        operations
+    }
+
+    /// Ids of the last `count` operations, oldest first
+    fn last_ids(&self, count: usize) -> Vec<String> {
+        let ids = self.operation_ids();
+        ids[ids.len().saturating_sub(count)..].to_vec()
+    }
+
+    /// The operations with these ids, most recent first
+    fn operations_with(&self, ids: &[String]) -> Vec<Operation> {
+        Journal {
+            entries: self
+                .entries
+                .iter()
+                .filter(|e| ids.contains(&e.operation))
+                .cloned()
+                .collect(),
+        }
+        .operations()
+    }
+
+    /// Revert the changes of these operations, newest first. Each is
+    /// dropped and `checkpoint` called as soon as it is undone, so a
+    /// rollback that fails part way can be resumed without undoing anything
+    /// twice.
+    fn revert_operations(&mut self, ids: &[String], mut checkpoint: impl FnMut(&Self) -> Result<()>) -> Result<()> {
+        while let Some(index) = self.entries.iter().rposition(|e| ids.contains(&e.operation)) {
+            let change = &self.entries[index].change;
+            change
+                .revert()
+                .with_context(|| format!("Failed to revert {}", change.describe()))?;
+            self.entries.remove(index);
+            checkpoint(self)?;
+        }
+        Ok(())
+    }
+}
+
+/// Identifier shared by every change made in this process
+fn operation_id() -> &'static str {
+    static ID: OnceLock<String> = OnceLock::new();
+    ID.get_or_init(|| format!("{}-{}", now(), std::process::id()))
+}
+
+/// Seconds since the Unix epoch
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Format an age in seconds as a short relative time (e.g. "5m ago")
+pub fn format_age(timestamp: u64) -> String {
+    let secs = now().saturating_sub(timestamp);
+    match secs {
+        0..=59 => format!("{}s ago", secs),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86399 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
+
+/// Record a change made by the current command
+pub fn record(change: Change) -> Result<()> {
+    let command = std::iter::once("gitid".to_string())
+        .chain(std::env::args().skip(1))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let mut journal = Journal::load()?;
+    journal.push(JournalEntry {
+        operation: operation_id().to_string(),
+        command,
+        timestamp: now(),
+        change,
+    });
+    journal.save()
+}
+
+/// Capture a file's current content so it can be restored later
+pub fn snapshot_file(path: &Path) -> Result<Change> {
+    let previous = if path.exists() {
+        Some(
+            fs::read_to_string(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?,
+        )
+    } else {
+        None
+    };
+
+    Ok(Change::File {
+        path: path.to_path_buf(),
+        previous,
+    })
+}
+
+/// Record that a file is about to be overwritten with new content
+pub fn record_file(path: &Path) -> Result<()> {
+    record(snapshot_file(path)?)
+}
+
+/// Record that a file holding secrets is about to be replaced. An existing
+/// one is moved to `<path>.gitid-backup-<stamp>`, keeping its permissions,
+/// and rollback moves it back.
+pub fn record_private_file(path: &Path) -> Result<()> {
+    if !path.exists() {
+        return record(Change::File {
+            path: path.to_path_buf(),
+            previous: None,
+        });
+    }
+
+    let backup = PathBuf::from(format!("{}.gitid-backup-{}", path.display(), now()));
+    fs::rename(path, &backup)
+        .with_context(|| format!("Failed to move {} to {}", path.display(), backup.display()))?;
+    record(Change::Moved {
+        path: path.to_path_buf(),
+        backup,
+    })
+}
+
+/// Undo the last `count` operations, returning what was reverted
+pub fn rollback(count: usize) -> Result<Vec<Operation>> {
+    let mut journal = Journal::load()?;
+    let ids = journal.last_ids(count);
+    let operations = journal.operations_with(&ids);
+
+    journal.revert_operations(&ids, Journal::save)?;
+    Ok(operations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(operation: &str, key: &str) -> JournalEntry {
+        JournalEntry {
+            operation: operation.to_string(),
+            command: format!("gitid {}", operation),
+            timestamp: 0,
+            change: Change::GitConfig {
+                scope: ConfigScope::Global,
+                repo: None,
+                key: key.to_string(),
+                previous: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_operations_grouping() {
+        let mut journal = Journal::default();
+        journal.push(entry("a", "user.name"));
+        journal.push(entry("a", "user.email"));
+        journal.push(entry("b", "user.name"));
+
+        let ops = journal.operations();
+        assert_eq!(ops.len(), 2);
+        assert_eq!(ops[0].command, "gitid b");
+        assert_eq!(ops[1].changes.len(), 2);
+    }
+
+    #[test]
+    fn test_last_operations() {
+        let mut journal = Journal::default();
+        journal.push(entry("a", "user.name"));
+        journal.push(entry("b", "user.name"));
+        journal.push(entry("b", "user.email"));
+
+        let ids = journal.last_ids(1);
+        assert_eq!(ids, vec!["b"]);
+        let taken = journal.operations_with(&ids);
+        assert_eq!(taken.len(), 1);
+        assert_eq!(taken[0].changes.len(), 2);
+    }
+
+    #[test]
+    fn test_revert_operations_resumable() {
+        let dir = std::env::temp_dir().join(format!("gitid-rollback-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = |name: &str, previous: Option<&str>| JournalEntry {
+            operation: "a".to_string(),
+            command: "gitid a".to_string(),
+            timestamp: 0,
+            change: Change::File {
+                path: dir.join(name),
+                previous: previous.map(str::to_string),
+            },
+        };
+
+        let mut journal = Journal::default();
+        // Restoring into a missing directory fails
+        journal.push(file("missing/config", Some("old")));
+        journal.push(file("new", None));
+        fs::write(dir.join("new"), "created").unwrap();
+
+        let mut saved = Vec::new();
+        let result = journal.revert_operations(&["a".to_string()], |j| {
+            saved.push(j.entries.len());
+            Ok(())
+        });
+        assert!(result.is_err());
+        // The undone change was saved away; only the failed one is left
+        assert_eq!(saved, vec![1]);
+        assert_eq!(journal.entries.len(), 1);
+        assert!(!dir.join("new").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_retention_limit() {
+        let mut journal = Journal::default();
+        for i in 0..MAX_OPERATIONS + 5 {
+            journal.push(entry(&i.to_string(), "user.name"));
+        }
+        assert_eq!(journal.operations().len(), MAX_OPERATIONS);
+        assert_eq!(journal.entries[0].operation, "5");
+    }
+
+    #[test]
+    fn test_journal_roundtrip() {
+        let mut journal = Journal::default();
+        journal.push(JournalEntry {
+            operation: "a".to_string(),
+            command: "gitid ssh-sync".to_string(),
+            timestamp: 0,
+            change: Change::File {
+                path: PathBuf::from("/tmp/config"),
+                previous: Some("Host *\n".to_string()),
+            },
+        });
+
+        let toml_str = toml::to_string_pretty(&journal).unwrap();
+        let parsed: Journal = toml::from_str(&toml_str).unwrap();
+        assert!(matches!(
+            &parsed.entries[0].change,
+            Change::File { previous: Some(p), .. } if p == "Host *\n"
+        ));
+    }
+
+    #[test]
+    fn test_moved_revert() {
+        let dir = std::env::temp_dir().join(format!("gitid-journal-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("id_work");
+        let backup = dir.join("id_work.gitid-backup-1");
+        fs::write(&backup, "old key").unwrap();
+        fs::write(&path, "new key").unwrap();
+
+        let change = Change::Moved {
+            path: path.clone(),
+            backup: backup.clone(),
+        };
+        assert!(!toml::to_string(&change).unwrap().contains("old key"));
+        change.revert().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old key");
+        assert!(!backup.exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_glab_host_roundtrip() {
+        let mut journal = Journal::default();
+        journal.push(JournalEntry {
+            operation: "a".to_string(),
+            command: "gitid use work".to_string(),
+            timestamp: 0,
+            change: Change::GlabHost {
+                previous: Some("gitlab.company.com".to_string()),
+            },
+        });
+
+        let toml_str = toml::to_string_pretty(&journal).unwrap();
+        assert!(toml_str.contains("kind = \"glab_host\""));
+        let parsed: Journal = toml::from_str(&toml_str).unwrap();
+        assert!(matches!(
+            &parsed.entries[0].change,
+            Change::GlabHost { previous: Some(h) } if h == "gitlab.company.com"
+        ));
+    }
+}
@@ -0,0 +1,35 @@
+//! Core of gitid: profiles and their config file, repository detection,
+//! git config and SSH config management, and the change journal behind
+//! `gitid rollback`.
+//!
+//! The `gitid` binary is a CLI over this crate. Nothing here prompts or
+//! prints; functions return values and errors for the caller to present,
+//! so prompt plugins, editors and GUIs can embed the same logic instead of
+//! running the binary.
+//!
+//! ```no_run
+//! use gitid_core::{config::Config, detect};
+//!
+//! let config = Config::load()?;
+//! if let Some(result) = detect::detect_profile(&config)? {
+//!     println!("{} ({})", result.profile_name, result.reason);
+//! }
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+pub mod config;
+pub mod daemon;
+pub mod detect;
+pub mod diff;
+pub mod env;
+pub mod git;
+pub mod gitconfig;
+pub mod journal;
+pub mod managed;
+pub mod pattern;
+pub mod policy;
+pub mod profile;
+pub mod prompt;
+pub mod ssh;
+pub mod ssh_keys;
+pub mod usage;
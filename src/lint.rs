@@ -0,0 +1,337 @@
+use crate::config::Config;
+use crate::detect;
+use crate::profile::{Platform, Profile, SigningFormat};
+use crate::ssh_keys;
+
+/// How serious a lint finding is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A suspicious or broken configuration, with the profiles involved
+#[derive(Debug)]
+pub struct LintIssue {
+    pub severity: Severity,
+    pub profiles: Vec<String>,
+    pub message: String,
+    pub explanation: String,
+}
+
+impl LintIssue {
+    fn new(severity: Severity, profiles: Vec<String>, message: String, explanation: &str) -> Self {
+        Self {
+            severity,
+            profiles,
+            message,
+            explanation: explanation.to_string(),
+        }
+    }
+}
+
+/// Run every consistency check against the config
+pub fn lint_config(config: &Config) -> Vec<LintIssue> {
+    let mut issues = Vec::new();
+
+    if let Some(ref default) = config.default_profile {
+        if !config.has_profile(default) {
+            issues.push(LintIssue::new(
+                Severity::Error,
+                vec![default.clone()],
+                format!("Default profile '{}' does not exist", default),
+                "Set default_profile to one of the configured profiles or remove it.",
+            ));
+        }
+    }
+
+    if let (Some(pinned), Some(path)) = (config.project_profile(), config.project_path()) {
+        if !config.has_profile(pinned) {
+            issues.push(LintIssue::new(
+                Severity::Warning,
+                vec![pinned.to_string()],
+                format!("{} pins unknown profile '{}'", path.display(), pinned),
+                "Detection ignores the pin until a profile with that name is configured.",
+            ));
+        }
+    }
+
+    for (pattern, target) in &config.detect.email_domains {
+        if !config.has_profile(target) {
+            issues.push(LintIssue::new(
+                Severity::Error,
+                vec![target.clone()],
+                format!("Email rule '{}' points at unknown profile '{}'", pattern, target),
+                "Rules under [detect.email_domains] must name a configured profile.",
+            ));
+        }
+    }
+
+    for (pattern, target) in &config.detect.directories {
+        if !config.has_profile(target) {
+            issues.push(LintIssue::new(
+                Severity::Error,
+                vec![target.clone()],
+                format!("Directory rule '{}' points at unknown profile '{}'", pattern, target),
+                "Rules under [detect.directories] must name a configured profile.",
+            ));
+        }
+    }
+
+    if let Some(ref fallback) = config.detect.fallback {
+        if !config.has_profile(fallback) {
+            issues.push(LintIssue::new(
+                Severity::Error,
+                vec![fallback.clone()],
+                format!("Fallback profile '{}' does not exist", fallback),
+                "Set detect.fallback to one of the configured profiles or remove it.",
+            ));
+        }
+    }
+
+    for policy in &config.policies {
+        for target in &policy.profiles {
+            if !config.has_profile(target) {
+                issues.push(LintIssue::new(
+                    Severity::Error,
+                    vec![target.clone()],
+                    format!("Policy '{}' allows unknown profile '{}'", policy.label(), target),
+                    "Profiles listed in a policy must be configured, or no repository on that host can satisfy it.",
+                ));
+            }
+        }
+        if !policy.require_signing && policy.email.is_none() && policy.profiles.is_empty() {
+            issues.push(LintIssue::new(
+                Severity::Warning,
+                Vec::new(),
+                format!("Policy '{}' has no requirements", policy.label()),
+                "Set require_signing, email, or profiles, or remove the policy.",
+            ));
+        }
+    }
+
+    let names = config.profile_names();
+
+    for name in &names {
+        if let Some(profile) = config.get_profile(name) {
+            lint_profile(name, profile, &mut issues);
+
+            if let Some(target) = detect::profile_for_email(config, &profile.email) {
+                if target != name.as_str() {
+                    issues.push(LintIssue::new(
+                        Severity::Warning,
+                        vec![name.to_string(), target.to_string()],
+                        format!("Email {} matches a domain rule for '{}'", profile.email, target),
+                        "Repos with this profile applied look like they belong to the other profile, which skews detection and history warnings.",
+                    ));
+                }
+            }
+        }
+    }
+
+    // Pairwise checks, in sorted order so output is stable
+    for (i, a_name) in names.iter().enumerate() {
+        for b_name in &names[i + 1..] {
+            if let (Some(a), Some(b)) = (config.get_profile(a_name), config.get_profile(b_name)) {
+                lint_pair(a_name, a, b_name, b, &mut issues);
+            }
+        }
+    }
+
+    issues
+}
+
+/// Checks that only need a single profile
+fn lint_profile(name: &str, profile: &Profile, issues: &mut Vec<LintIssue>) {
+    if let Err(e) = profile.validate() {
+        issues.push(LintIssue::new(
+            Severity::Error,
+            vec![name.to_string()],
+            e.to_string(),
+            "Fix the field in config.toml or re-create the profile with 'gitid add'.",
+        ));
+    }
+
+    if let Some(ref key) = profile.gpg_key {
+        if key.trim().is_empty() {
+            issues.push(LintIssue::new(
+                Severity::Error,
+                vec![name.to_string()],
+                "Commit signing is enabled but no signing key is set".to_string(),
+                "An empty gpg_key still turns on commit.gpgsign, so every commit will fail to sign. Set a key ID or remove gpg_key.",
+            ));
+        }
+    }
+
+    if profile.signing == Some(SigningFormat::Gpg) && profile.gpg_key.is_none() {
+        issues.push(LintIssue::new(
+            Severity::Error,
+            vec![name.to_string()],
+            "GPG signing is selected but no gpg_key is set".to_string(),
+            "Commits from this profile won't be signed. Set gpg_key, or use signing = \"ssh\" to sign with the profile's SSH key.",
+        ));
+    }
+
+    if profile.github_app.is_some() && profile.platform != Platform::Github {
+        issues.push(LintIssue::new(
+            Severity::Error,
+            vec![name.to_string()],
+            format!("{} profile has a github_app", profile.platform),
+            "GitHub App installation tokens only work against GitHub. Set platform = \"github\" or remove github_app.",
+        ));
+    }
+
+    if profile.platform == Platform::Custom && profile.netrc && profile.username.is_none() {
+        issues.push(LintIssue::new(
+            Severity::Warning,
+            vec![name.to_string()],
+            "Custom platform profile enables netrc without a username".to_string(),
+            "gitid knows no token login convention for custom forges, so the netrc entry uses the profile's username. Set username to your account login.",
+        ));
+    }
+
+    if let Some(ref sendemail) = profile.sendemail {
+        if let Some(ref encryption) = sendemail.smtp_encryption {
+            if encryption != "ssl" && encryption != "tls" {
+                issues.push(LintIssue::new(
+                    Severity::Error,
+                    vec![name.to_string()],
+                    format!("Unknown smtp_encryption '{}'", encryption),
+                    "git send-email accepts \"ssl\" (SMTPS) or \"tls\" (STARTTLS).",
+                ));
+            }
+        }
+    }
+
+    if let Some(ref host) = profile.host {
+        if host.contains("://") || host.contains('/') || host.contains('@') {
+            issues.push(LintIssue::new(
+                Severity::Error,
+                vec![name.to_string()],
+                format!("Host '{}' is not a bare hostname", host),
+                "The host is written verbatim into the SSH HostName line. Use only the hostname, e.g. 'github.company.com'.",
+            ));
+        }
+
+        let mismatched = Platform::from_host(host).is_some_and(|p| !profile.platform.serves(&p));
+        if mismatched {
+            issues.push(LintIssue::new(
+                Severity::Warning,
+                vec![name.to_string()],
+                format!("{} profile uses host '{}'", profile.platform, host),
+                "The platform decides which CLI 'gitid auth' runs and which SSH alias is generated. Check that the platform and host belong together.",
+            ));
+        }
+    }
+}
+
+/// Checks that compare two profiles
+fn lint_pair(a_name: &str, a: &Profile, b_name: &str, b: &Profile, issues: &mut Vec<LintIssue>) {
+    let pair = vec![a_name.to_string(), b_name.to_string()];
+    let same_key = ssh_keys::expand_path(&a.ssh_key) == ssh_keys::expand_path(&b.ssh_key);
+
+    if same_key && a.email != b.email {
+        let a_hosts = a.served_hosts();
+        if let Some(host) = b.served_hosts().into_iter().find(|h| a_hosts.contains(h)) {
+            issues.push(LintIssue::new(
+                Severity::Error,
+                pair.clone(),
+                format!("Profiles share SSH key {} on {}", a.ssh_key, host),
+                "A forge ties each SSH key to exactly one account, so both aliases authenticate as the same user. Give each account its own key.",
+            ));
+        }
+    }
+
+    if a.name == b.name && a.email == b.email {
+        issues.push(LintIssue::new(
+            Severity::Warning,
+            pair,
+            format!("Profiles have the same identity <{}>", a.email),
+            "The current profile is recognised by user.name and user.email, so these profiles cannot be told apart once applied.",
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(email: &str, platform: Platform, ssh_key: &str, host: Option<&str>) -> Profile {
+        Profile::new(
+            "John Doe".to_string(),
+            email.to_string(),
+            platform,
+            ssh_key.to_string(),
+            None,
+            host.map(str::to_string),
+        )
+    }
+
+    #[test]
+    fn test_shared_key_across_accounts() {
+        let mut config = Config::default();
+        config.profiles.insert(
+            "personal".to_string(),
+            profile("john@example.com", Platform::Github, "~/.ssh/id_ed25519", None),
+        );
+        config.profiles.insert(
+            "work".to_string(),
+            profile("john@company.com", Platform::Both, "~/.ssh/id_ed25519", None),
+        );
+
+        let issues = lint_config(&config);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].severity, Severity::Error);
+        assert_eq!(issues[0].profiles, vec!["personal", "work"]);
+    }
+
+    #[test]
+    fn test_shared_key_on_different_hosts_is_fine() {
+        let mut config = Config::default();
+        config.profiles.insert(
+            "github".to_string(),
+            profile("john@example.com", Platform::Github, "~/.ssh/id_ed25519", None),
+        );
+        config.profiles.insert(
+            "gitlab".to_string(),
+            profile("john@company.com", Platform::Gitlab, "~/.ssh/id_ed25519", None),
+        );
+
+        assert!(lint_config(&config).is_empty());
+    }
+
+    #[test]
+    fn test_platform_host_mismatch() {
+        let mut config = Config::default();
+        config.profiles.insert(
+            "work".to_string(),
+            profile("john@company.com", Platform::Gitlab, "~/.ssh/id_work", Some("github.com")),
+        );
+
+        let issues = lint_config(&config);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn test_signing_without_key_and_missing_default() {
+        let mut config = Config::default();
+        config.default_profile = Some("missing".to_string());
+        let mut work = profile("john@company.com", Platform::Github, "~/.ssh/id_work", None);
+        work.gpg_key = Some(" ".to_string());
+        config.profiles.insert("work".to_string(), work);
+
+        let issues = lint_config(&config);
+        assert_eq!(issues.len(), 2);
+        assert!(issues.iter().all(|i| i.severity == Severity::Error));
+    }
+}
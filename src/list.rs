@@ -0,0 +1,237 @@
+use crate::auth::{github, gitlab};
+use crate::config::Config;
+use crate::gpg;
+use crate::profile::{Platform, Profile, SigningFormat};
+use crate::ssh;
+use crate::ssh_keys;
+use anyhow::{Context, Result, bail};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
+
+/// One profile as printed by `gitid list --json`: every profile field plus
+/// the profile's name and whether it is the default, current, or from the
+/// system config. `tag` limits the entries to profiles carrying it.
+pub fn profile_entries(config: &Config, tag: Option<&str>, current: Option<&str>) -> Result<Vec<Map<String, Value>>> {
+    let mut entries = Vec::new();
+    for name in config.tagged_profile_names(tag) {
+        let Some(profile) = config.get_profile(name) else {
+            continue;
+        };
+        let Value::Object(fields) = serde_json::to_value(profile)
+            .with_context(|| format!("Failed to serialize profile '{}'", name))?
+        else {
+            bail!("Profile '{}' did not serialize to an object", name);
+        };
+
+        let mut entry = Map::new();
+        entry.insert("profile".to_string(), Value::String(name.clone()));
+        entry.insert(
+            "default".to_string(),
+            Value::Bool(config.default_profile.as_ref() == Some(name)),
+        );
+        entry.insert("current".to_string(), Value::Bool(current == Some(name.as_str())));
+        entry.insert("system".to_string(), Value::Bool(config.is_system_profile(name)));
+        entry.extend(fields);
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// What `gitid list --verbose` checks about a profile
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Health {
+    /// Whether the SSH key (or, for agent-held keys, its public half) is on disk
+    pub key_exists: bool,
+    /// SHA256 fingerprint and algorithm, e.g. `SHA256:... (ED25519)`
+    pub fingerprint: Option<String>,
+    /// Whether the GPG signing key is in the keyring; None without GPG signing
+    pub gpg_key_present: Option<bool>,
+    /// Whether the profile's Host entries match what `ssh-sync` would write
+    pub ssh_synced: bool,
+    /// gh/glab sign-in by `<cli> <host>`; None when the CLI isn't installed
+    pub auth: BTreeMap<String, Option<bool>>,
+}
+
+/// Computes `Health` for each profile, asking gh/glab about each host once
+pub struct HealthCheck<'a> {
+    config: &'a Config,
+    ssh_config: String,
+    auth: HashMap<String, Option<bool>>,
+}
+
+impl<'a> HealthCheck<'a> {
+    pub fn new(config: &'a Config) -> Result<Self> {
+        let path = ssh::managed_config_path(config)?;
+        Ok(Self {
+            config,
+            ssh_config: std::fs::read_to_string(path).unwrap_or_default(),
+            auth: HashMap::new(),
+        })
+    }
+
+    pub fn check(&mut self, name: &str, profile: &Profile) -> Health {
+        let public_key = ssh_keys::public_key_path(&profile.ssh_key);
+        let private_key = ssh_keys::expand_path(&profile.ssh_key);
+        let key_file = [&public_key, &private_key].into_iter().find(|p| p.exists());
+        let fingerprint = key_file
+            .and_then(|path| ssh_keys::key_details(path).ok())
+            .map(|d| format!("{} ({})", d.fingerprint, d.algorithm));
+
+        let gpg_key_present = match profile.signing_key() {
+            Some((SigningFormat::Gpg, key)) => {
+                Some(gpg::is_installed() && gpg::has_secret_key(&key, profile.gnupghome.as_deref()))
+            }
+            _ => None,
+        };
+
+        // App profiles authenticate with installation tokens instead
+        let hosts = match profile.platform {
+            _ if profile.github_app.is_some() => Vec::new(),
+            Platform::Github | Platform::Gitlab => vec![(profile.platform.clone(), profile.default_host())],
+            Platform::Both => vec![(Platform::Github, "github.com"), (Platform::Gitlab, "gitlab.com")],
+            _ => Vec::new(),
+        };
+        let mut auth = BTreeMap::new();
+        for (platform, host) in hosts {
+            let cli = if platform == Platform::Github { "gh" } else { "glab" };
+            let label = format!("{} {}", cli, host);
+            let signed_in = *self.auth.entry(label.clone()).or_insert_with(|| match platform {
+                Platform::Github => github::is_gh_installed().then(|| github::is_authenticated(Some(host))),
+                _ => gitlab::is_glab_installed().then(|| gitlab::is_authenticated(Some(host))),
+            });
+            auth.insert(label, signed_in);
+        }
+
+        Health {
+            key_exists: key_file.is_some(),
+            fingerprint,
+            gpg_key_present,
+            ssh_synced: ssh::profile_synced(self.config, name, &self.ssh_config),
+            auth,
+        }
+    }
+}
+
+/// Fill a `--format` template from a profile entry. `{field}` is replaced by
+/// the field's value (empty when the profile doesn't set it), `{{` and `}}`
+/// are literal braces, and `\t` and `\n` are a tab and a newline.
+pub fn render_template(template: &str, entry: &Map<String, Value>) -> Result<String> {
+    let mut out = String::new();
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                out.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                out.push('}');
+            }
+            '{' => {
+                let mut field = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => field.push(c),
+                        None => bail!("Unclosed '{{' in format template"),
+                    }
+                }
+                match entry.get(field.trim()) {
+                    Some(Value::String(s)) => out.push_str(s),
+                    Some(Value::Null) | None => {}
+                    Some(other) => out.push_str(&other.to_string()),
+                }
+            }
+            '\\' if chars.peek() == Some(&'t') => {
+                chars.next();
+                out.push('\t');
+            }
+            '\\' if chars.peek() == Some(&'n') => {
+                chars.next();
+                out.push('\n');
+            }
+            c => out.push(c),
+        }
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        let mut config = Config::default();
+        config.default_profile = Some("work".to_string());
+        config.profiles.insert(
+            "work".to_string(),
+            Profile::new(
+                "John Doe".to_string(),
+                "john@acme.com".to_string(),
+                Platform::Github,
+                "~/.ssh/id_work".to_string(),
+                None,
+                Some("github.acme.com".to_string()),
+            ),
+        );
+        config.profiles.insert(
+            "personal".to_string(),
+            Profile::new(
+                "John Doe".to_string(),
+                "john@example.com".to_string(),
+                Platform::Gitlab,
+                "~/.ssh/id_ed25519".to_string(),
+                None,
+                None,
+            ),
+        );
+        config
+    }
+
+    #[test]
+    fn test_profile_entries() {
+        let entries = profile_entries(&config(), None, Some("personal")).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["profile"], "personal");
+        assert_eq!(entries[0]["current"], true);
+        assert_eq!(entries[0]["default"], false);
+        assert_eq!(entries[1]["profile"], "work");
+        assert_eq!(entries[1]["default"], true);
+        assert_eq!(entries[1]["email"], "john@acme.com");
+        assert_eq!(entries[1]["platform"], "github");
+
+        let mut config = config();
+        config.profiles.get_mut("work").unwrap().tags = vec!["client".to_string()];
+        let entries = profile_entries(&config, Some("Client"), None).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0]["tags"][0], "client");
+    }
+
+    #[test]
+    fn test_health_of_a_missing_key() {
+        let mut config = config();
+        let mut profile = config.profiles["personal"].clone();
+        profile.platform = Platform::Codeberg;
+        profile.ssh_key = "/nonexistent/gitid/id_test".to_string();
+        config.profiles.insert("personal".to_string(), profile.clone());
+
+        let health = HealthCheck::new(&config).unwrap().check("personal", &profile);
+        assert!(!health.key_exists);
+        assert_eq!(health.fingerprint, None);
+        assert_eq!(health.gpg_key_present, None);
+        assert!(health.auth.is_empty());
+    }
+
+    #[test]
+    fn test_render_template() {
+        let entries = profile_entries(&config(), None, None).unwrap();
+        let line = render_template("{profile}\\t<{email}> {host}{{{default}}}", &entries[1]).unwrap();
+        assert_eq!(line, "work\t<john@acme.com> github.acme.com{true}");
+        assert_eq!(render_template("{profile}:{host}", &entries[0]).unwrap(), "personal:");
+        assert!(render_template("{profile", &entries[0]).is_err());
+    }
+}
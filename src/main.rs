@@ -1,31 +1,78 @@
+mod audit;
 mod auth;
+mod bootstrap;
+mod bundle;
 mod cli;
-mod config;
-mod detect;
-mod git;
-mod profile;
-mod prompt;
-mod ssh;
-mod ssh_keys;
+mod credential;
+mod devcontainer;
+mod doctor;
+mod gpg;
+mod hooks;
+mod input;
+mod lint;
+mod list;
+mod migrate;
+mod netrc;
+mod registry;
+mod remote;
+mod secrets;
+mod shell;
+mod signing;
+mod transaction;
+mod uninstall;
+mod vault;
+
+// The core modules live in the library; importing them here also keeps
+// `crate::config` and friends resolving in the CLI's own modules
+use gitid_core::{
+    config, daemon, detect, env, git, gitconfig, journal, managed, policy, profile, prompt, ssh,
+    ssh_keys, usage,
+};
 
 use anyhow::{Context, Result, bail};
+use auth::hosts::{self, Source};
 use clap::Parser;
-use cli::{Cli, Commands};
+use cli::{Cli, Commands, ConfigAction, HookAction, KeyAction, ListSort, RemoteAction, TokenAction};
 use colored::Colorize;
 use config::Config;
 use git::ConfigScope;
-use inquire::{Confirm, Select, Text};
-use profile::{Platform, Profile};
+use inquire::{Confirm, Password, Select, Text};
+use profile::{Platform, Profile, SigningFormat, SshMode};
+use transaction::Transaction;
 
 fn main() {
+    // ssh-keygen runs gitid as its askpass program when generating a key
+    // with a passphrase
+    if let Ok(passphrase) = std::env::var(ssh_keys::ASKPASS_ENV) {
+        println!("{}", passphrase);
+        return;
+    }
+
     if let Err(e) = run() {
         eprintln!("{}: {}", "Error".red().bold(), e);
         std::process::exit(1);
     }
 }
 
+/// Command-line arguments, read as `gitid credential <operation>` when
+/// git runs gitid as `git-credential-gitid`
+fn cli_args() -> Vec<std::ffi::OsString> {
+    let mut args: Vec<_> = std::env::args_os().collect();
+    let invoked_as = args
+        .first()
+        .and_then(|arg| std::path::Path::new(arg).file_stem().map(|s| s.to_owned()));
+    if invoked_as.is_some_and(|name| name == credential::HELPER_BINARY) {
+        args.insert(1, "credential".into());
+    }
+    args
+}
+
 fn run() -> Result<()> {
-    let cli = Cli::parse();
+    let cli = Cli::parse_from(cli_args());
+    input::configure(cli.no_input, cli.yes);
+    if let Some(path) = cli.config {
+        config::set_config_path(path);
+    }
 
     match cli.command {
         Commands::Init => cmd_init(),
@@ -35,20 +82,210 @@ fn run() -> Result<()> {
             email,
             platform,
             ssh_key,
+            generate_key,
+            key_type,
+            resident,
+            passphrase_stdin,
+            gpg_key,
+            host,
+            username,
+            tags,
+            description,
+            from_git,
+        } => cmd_add(
+            name,
+            user_name,
+            email,
+            platform,
+            ssh_key,
+            generate_key,
+            KeyGen {
+                key_type,
+                resident,
+                passphrase_stdin,
+            },
             gpg_key,
             host,
-        } => cmd_add(name, user_name, email, platform, ssh_key, gpg_key, host),
+            username,
+            tags,
+            description,
+            from_git,
+        ),
         Commands::Remove {
             name,
             force,
             clean_ssh,
         } => cmd_remove(name, force, clean_ssh),
-        Commands::List => cmd_list(),
-        Commands::Use { name, global } => cmd_use(name, global),
-        Commands::Auth { name } => cmd_auth(name),
-        Commands::Current { porcelain } => cmd_current(porcelain),
-        Commands::Detect { auto } => cmd_detect(auto),
-        Commands::SshSync => cmd_ssh_sync(),
+        Commands::Rename { old, new } => cmd_rename(old, new),
+        Commands::Default { name, unset } => cmd_default(name, unset),
+        Commands::Pin { name, unset } => cmd_pin(name, unset),
+        Commands::List {
+            json,
+            format,
+            verbose,
+            tag,
+            sort,
+        } => cmd_list(json, format, verbose, tag, sort),
+        Commands::Stats { json } => cmd_stats(json),
+        Commands::Use {
+            name,
+            tag,
+            global,
+            ssh_command,
+            key,
+            agent,
+            no_agent,
+            rewrite_remote,
+            restore_remote,
+        } => {
+            let agent = (agent || no_agent).then_some(agent);
+            cmd_use(name, tag, global, ssh_command, key, agent, rewrite_remote, restore_remote)
+        }
+        Commands::Recent { global } => {
+            cmd_use(Some("-".to_string()), None, global, false, None, None, false, false)
+        }
+        Commands::Unset { global } => cmd_unset(global),
+        Commands::Switch {
+            name,
+            no_rewrite,
+            no_agent,
+        } => cmd_switch(name, no_rewrite, no_agent),
+        Commands::Auth {
+            name,
+            with_token,
+            token_env,
+            from_keyring,
+            keyring,
+            glab_host,
+        } => cmd_auth(name, with_token, token_env, from_keyring, keyring, glab_host),
+        Commands::Test { name, key } => cmd_test(name, key),
+        Commands::SignTest { name } => cmd_sign_test(name),
+        Commands::Whoami { name } => cmd_whoami(name),
+        Commands::Current { porcelain, json } => cmd_current(porcelain, json),
+        Commands::Status => cmd_status(),
+        Commands::Detect { auto, explain } => cmd_detect(auto, explain),
+        Commands::Clone {
+            url,
+            directory,
+            profile,
+            args,
+        } => cmd_clone(url, directory, profile, args),
+        Commands::SshSync { dry_run } => cmd_ssh_sync(dry_run),
+        Commands::SshRestore { stamp, list } => cmd_ssh_restore(stamp, list),
+        Commands::Env { name } => cmd_env(name),
+        Commands::Devcontainer {
+            name,
+            keys,
+            format,
+            home,
+        } => cmd_devcontainer(name, keys, format, home),
+        Commands::Remote { action } => match action {
+            RemoteAction::Fix {
+                profile,
+                restore,
+                dry_run,
+            } => cmd_remote_fix(profile, restore, dry_run),
+        },
+        Commands::RemoteInstall {
+            target,
+            profiles,
+            include_keys,
+            no_verify,
+        } => cmd_remote_install(target, profiles, include_keys, no_verify),
+        Commands::Exec { name, command } => cmd_exec(name, command),
+        Commands::SetupRepo => cmd_setup_repo(),
+        Commands::Hook { action } => match action {
+            HookAction::Install {
+                kinds,
+                pre_push,
+                force,
+            } => cmd_hook_install(kinds, pre_push, force),
+            HookAction::Run { kind, args } => hooks::run(kind, &args),
+        },
+        Commands::Rollback { count, list, force } => cmd_rollback(count, list, force),
+        Commands::NetrcSync => cmd_netrc_sync(),
+        Commands::Credential { operation } => credential::run(operation),
+        Commands::Migrate { remove, dry_run } => cmd_migrate(remove, dry_run),
+        Commands::GitconfigSync => cmd_gitconfig_sync(),
+        Commands::Token { action } => match action {
+            TokenAction::Set { name } => cmd_token_set(name),
+            TokenAction::Remove { name } => cmd_token_remove(name),
+            TokenAction::Show { name } => cmd_token_show(name),
+        },
+        Commands::Key { action } => match action {
+            KeyAction::Show {
+                name,
+                fingerprint,
+                qr,
+                url,
+            } => cmd_key_show(name, fingerprint, qr, url),
+            KeyAction::Upload { name, usage } => cmd_key_upload(name, usage),
+            KeyAction::Audit { max_age, tag } => cmd_key_audit(max_age, tag),
+            KeyAction::Rotate {
+                name,
+                upload,
+                delete_old,
+                key_type,
+                passphrase_stdin,
+            } => cmd_key_rotate(
+                name,
+                upload,
+                delete_old,
+                KeyGen {
+                    key_type,
+                    resident: false,
+                    passphrase_stdin,
+                },
+            ),
+            KeyAction::Import {
+                name,
+                from,
+                item,
+                export,
+            } => cmd_key_import(name, from, item, export),
+        },
+        Commands::Export {
+            bundle: Some(bundle),
+            encrypt,
+            ..
+        } => cmd_export_bundle(bundle, encrypt),
+        Commands::Export {
+            bundle: None,
+            format,
+            no_key_paths,
+            ..
+        } => cmd_export(format, no_key_paths),
+        Commands::Import {
+            bundle: Some(bundle),
+            force,
+            ..
+        } => cmd_import_bundle(bundle, force),
+        Commands::Import {
+            from: Some(source),
+            force,
+            ..
+        } => cmd_import_cli(source, force),
+        Commands::Import { file, force, .. } => cmd_import(file.context("No file to import")?, force),
+        Commands::Daemon => daemon::serve(|path| println!("Listening on {}", path.display())),
+        Commands::ShellInit { shell } => {
+            print!("{}", shell::init_script(shell));
+            Ok(())
+        }
+        Commands::ShellHook => shell::on_directory_change(),
+        Commands::Completions { shell } => {
+            cli::print_completions(shell);
+            Ok(())
+        }
+        Commands::FixCommits { last, profile } => cmd_fix_commits(last, profile),
+        Commands::Audit { dir, fix, registry } => cmd_audit(dir, fix, registry),
+        Commands::ApplyAll { dir, registry, dry_run } => cmd_apply_all(dir, registry, dry_run),
+        Commands::Scan { dirs } => cmd_scan(dirs),
+        Commands::Doctor { fix } => cmd_doctor(fix),
+        Commands::Uninstall { purge, force } => cmd_uninstall(purge, force),
+        Commands::Config { action } => match action {
+            ConfigAction::Validate => cmd_config_validate(),
+            ConfigAction::Schema { project } => cmd_config_schema(project),
+        },
     }
 }
 
@@ -66,26 +303,62 @@ fn cmd_init() -> Result<()> {
         println!("Config already exists at {}", path.display());
     }
 
+    // Accounts the user already signed in to are a head start on profiles
+    for source in [Source::Gh, Source::Glab] {
+        let count = hosts::accounts(source).map(|a| a.len()).unwrap_or(0);
+        if count > 0 {
+            println!(
+                "Found {} {} account(s); run {} to create profiles for them",
+                count,
+                source,
+                format!("gitid import --from {}", source).yellow()
+            );
+        }
+    }
+
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 fn cmd_add(
     name: Option<String>,
     user_name: Option<String>,
     email: Option<String>,
     platform: Option<String>,
     ssh_key: Option<String>,
+    generate_key: bool,
+    keygen: KeyGen,
     gpg_key: Option<String>,
     host: Option<String>,
+    username: Option<String>,
+    tags: Vec<String>,
+    description: Option<String>,
+    from_git: bool,
 ) -> Result<()> {
     let mut config = Config::load()?;
 
+    // Every step below registers its undo, so a failure part way through
+    // leaves the machine as it was before `add` started
+    let mut tx = Transaction::new();
+
+    // With the identity given as flags `add` is scripted: optional fields
+    // are left unset and its questions take their defaults
+    let scripted = name.is_some() && (from_git || (user_name.is_some() && email.is_some())) && platform.is_some();
+    let confirm = |prompt: Confirm| {
+        if scripted {
+            Ok(input::assume_yes() || prompt.default.unwrap_or(false))
+        } else {
+            input::confirm(prompt)
+        }
+    };
+
     // Get profile name
     let name = match name {
         Some(n) => n,
-        None => Text::new("Profile name:")
-            .with_help_message("e.g., 'work', 'personal', 'client-acme'")
-            .prompt()?,
+        None => input::text(
+            Text::new("Profile name:").with_help_message("e.g., 'work', 'personal', 'client-acme'"),
+            "a profile name",
+        )?,
     };
 
     if config.has_profile(&name) {
@@ -95,68 +368,125 @@ fn cmd_add(
         );
     }
 
+    // Start from the global git identity with --from-git, or offer it when
+    // this is the first profile
+    let global = if from_git || config.profiles.is_empty() {
+        migrate::global_profile()?
+    } else {
+        None
+    };
+    let prefill = match global {
+        None if from_git => bail!("The global git config sets no user.name and user.email to start from"),
+        Some(global) if from_git => Some(global),
+        Some(global) if input::is_interactive() && user_name.is_none() && email.is_none() => {
+            let message = format!("Start from your global git identity, {} <{}>?", global.name, global.email);
+            let confirmed = input::confirm(
+                Confirm::new(&message)
+                    .with_help_message("Takes user.name, user.email and the signing settings from ~/.gitconfig")
+                    .with_default(true),
+            )?;
+            confirmed.then_some(global)
+        }
+        _ => None,
+    };
+    if let (Some(global), true) = (&prefill, from_git) {
+        println!("Starting from the global git identity {} <{}>", global.name, global.email);
+    }
+
     // Get user name
-    let user_name = match user_name {
+    let user_name = match user_name.or_else(|| prefill.as_ref().map(|p| p.name.clone())) {
         Some(n) => n,
-        None => Text::new("Git user name:")
-            .with_help_message("This will be used for commit author")
-            .prompt()?,
+        None => input::text(
+            Text::new("Git user name:").with_help_message("This will be used for commit author"),
+            "--user-name",
+        )?,
     };
 
     // Get email
-    let email = match email {
+    let email = match email.or_else(|| prefill.as_ref().map(|p| p.email.clone())) {
         Some(e) => e,
-        None => Text::new("Git email:")
-            .with_help_message("This will be used for commit author")
-            .prompt()?,
+        None => input::text(
+            Text::new("Git email:").with_help_message("This will be used for commit author"),
+            "--email",
+        )?,
     };
 
     // Get platform
     let platform: Platform = match platform {
         Some(p) => p.parse()?,
         None => {
-            let options = vec!["github", "gitlab", "both"];
-            let selection = Select::new("Platform:", options)
-                .with_help_message("Select the Git hosting platform")
-                .prompt()?;
+            let options = vec!["github", "gitlab", "both", "codeberg", "gitea", "custom"];
+            let selection = input::select(
+                Select::new("Platform:", options).with_help_message("Select the Git hosting platform"),
+                "--platform",
+            )?;
             selection.parse()?
         }
     };
 
-    // Get SSH key
-    let ssh_key = match ssh_key {
-        Some(k) => k,
-        None => select_or_create_ssh_key(&name, &email)?,
+    // Get SSH key; one named by the global core.sshCommand or SSH signing
+    // key is taken if it exists
+    let known_key = prefill
+        .as_ref()
+        .map(|p| p.ssh_key.clone())
+        .filter(|k| ssh_keys::expand_path(k).exists());
+    let (ssh_key, generated_key) = match ssh_key.or(known_key) {
+        Some(k) => (k, false),
+        None if generate_key => (generate_ssh_key(&name, &email, keygen, &mut tx)?, true),
+        None => select_or_create_ssh_key(&name, &email, keygen, &mut tx)?,
     };
 
-    // Get GPG key (optional)
+    // Get GPG key (optional); the global config already says whether to sign
     let gpg_key = match gpg_key {
         Some(k) => Some(k),
+        None if prefill.is_some() => prefill.as_ref().and_then(|p| p.gpg_key.clone()),
+        None if scripted => None,
+        None if input::is_interactive() && gpg::is_installed() => select_gpg_key(&user_name, &email, &mut tx)?,
         None => {
-            let input = Text::new("GPG signing key (optional):")
-                .with_help_message("Press Enter to skip")
-                .prompt()?;
-            if input.is_empty() {
-                None
-            } else {
+            let input = input::optional_text(
+                Text::new("GPG signing key (optional):")
+                    .with_help_message("Press Enter to skip or to generate a new key"),
+            )?;
+            if !input.is_empty() {
                 Some(input)
+            } else if gpg::is_installed()
+                && input::confirm(
+                    Confirm::new("Generate a new GPG key for commit signing?").with_default(false),
+                )?
+            {
+                Some(generate_gpg_key(&user_name, &email, &mut tx)?)
+            } else {
+                None
             }
         }
     };
+    if let Some(ref key) = gpg_key {
+        for problem in gpg::signing_key_problems(key, &email, None)? {
+            println!("{} {}", "Warning:".yellow().bold(), problem);
+        }
+    }
 
-    // Get custom host (optional)
+    // Get custom host (optional, except for custom forges)
     let host = match host {
         Some(h) => Some(h),
+        None if platform == Platform::Custom => Some(input::text(
+            Text::new("Host:").with_help_message("Hostname of your forge, e.g. 'review.company.com'"),
+            "--host",
+        )?),
+        None if scripted => None,
         None => {
-            let needs_custom = Confirm::new("Use custom host?")
-                .with_help_message("For GitHub Enterprise or self-hosted GitLab")
-                .with_default(false)
-                .prompt()?;
+            let needs_custom = input::confirm(
+                Confirm::new("Use custom host?")
+                    .with_help_message("For GitHub Enterprise or self-hosted GitLab")
+                    .with_default(false),
+            )?;
 
             if needs_custom {
-                let h = Text::new("Custom host:")
-                    .with_help_message("e.g., 'github.company.com' or 'gitlab.myorg.com'")
-                    .prompt()?;
+                let h = input::text(
+                    Text::new("Custom host:")
+                        .with_help_message("e.g., 'github.company.com' or 'gitlab.myorg.com'"),
+                    "--host",
+                )?;
                 if h.is_empty() {
                     None
                 } else {
@@ -168,11 +498,84 @@ fn cmd_add(
         }
     };
 
-    let profile = Profile::new(user_name, email, platform, ssh_key, gpg_key, host);
+    // Get platform username (optional)
+    let username = match username {
+        Some(u) => Some(u),
+        None if scripted => None,
+        None => {
+            let input = input::optional_text(
+                Text::new("Platform username (optional):")
+                    .with_help_message("Your GitHub/GitLab login; press Enter to skip"),
+            )?;
+            if input.is_empty() {
+                None
+            } else {
+                Some(input)
+            }
+        }
+    };
+
+    // Get description (optional)
+    let description = match description {
+        Some(d) => Some(d.trim().to_string()).filter(|d| !d.is_empty()),
+        None if scripted => None,
+        None => {
+            let input = input::optional_text(
+                Text::new("Description (optional):")
+                    .with_help_message("What the profile is for, e.g. \"Acme contract until 2025-06\"; press Enter to skip"),
+            )?;
+            if input.is_empty() {
+                None
+            } else {
+                Some(input)
+            }
+        }
+    };
+
+    let mut profile = Profile::new(user_name, email, platform, ssh_key, gpg_key, host);
+    profile.username = username;
+    profile.tags = tags;
+    profile.description = description;
+    if prefill.as_ref().is_some_and(|p| p.signing == Some(SigningFormat::Ssh)) {
+        profile.signing = Some(SigningFormat::Ssh);
+    }
     profile.validate()?;
 
-    config.add_profile(name.clone(), profile)?;
+    let sync_ssh = confirm(Confirm::new("Sync SSH config now?").with_default(true))?;
+    // Logging in is interactive itself, so it is never the default
+    let run_auth = input::is_interactive()
+        && confirm(
+            Confirm::new("Authenticate CLI tools now?")
+                .with_help_message("Runs gh/glab/tea auth login for this profile")
+                .with_default(false),
+        )?;
+    let upload_message = format!("Upload the new key to {} now?", profile.platform.display_name());
+    let upload_key = generated_key
+        && matches!(profile.platform, Platform::Github | Platform::Both)
+        && confirm(
+            Confirm::new(&upload_message)
+                .with_help_message("Uses the profile's keyring token or the CLI's login")
+                .with_default(true),
+        )?;
+
+    let config_snapshot = journal::snapshot_file(&Config::config_path()?)?;
+    config.add_profile(name.clone(), profile.clone())?;
     config.save()?;
+    tx.on_rollback("profile added to config", move || config_snapshot.revert());
+
+    if sync_ssh {
+        let ssh_snapshots = ssh::snapshot_files(&config)?;
+        ssh::sync_ssh_config(&config)?;
+        tx.on_rollback("SSH config sync", move || {
+            ssh_snapshots.iter().try_for_each(|s| s.revert())
+        });
+    }
+
+    if run_auth {
+        auth::authenticate(&name, &profile)?;
+    }
+
+    tx.commit();
 
     println!();
     println!(
@@ -180,13 +583,248 @@ fn cmd_add(
         "Success:".green().bold(),
         name.cyan()
     );
-    println!("Run {} to sync SSH config", "gitid ssh-sync".yellow());
+
+    // The profile is kept either way; a failed upload can be retried
+    if upload_key {
+        match auth::upload_key(&name, &profile, auth::KeyUsage::Auth) {
+            Ok(()) => println!(
+                "{} Uploaded key as \"{}\"",
+                "Success:".green().bold(),
+                auth::key_title(&name)
+            ),
+            Err(e) => {
+                println!("{} {:#}", "Warning:".yellow().bold(), e);
+                println!("Run {} to retry", format!("gitid key upload {}", name).yellow());
+            }
+        }
+    }
+    if !sync_ssh {
+        println!("Run {} to sync SSH config", "gitid ssh-sync".yellow());
+    }
 
     Ok(())
 }
 
-/// Interactive SSH key selection or creation
-fn select_or_create_ssh_key(profile_name: &str, email: &str) -> Result<String> {
+/// Interactive GPG key selection from the keyring's signing keys, those
+/// with a user ID for `email` first. None means no signing.
+fn select_gpg_key(user_name: &str, email: &str, tx: &mut Transaction) -> Result<Option<String>> {
+    const SHOW_ALL: &str = "+ Show all keys";
+    const GENERATE: &str = "+ Generate new GPG key";
+    const MANUAL: &str = "+ Enter key ID manually";
+    const NONE: &str = "No commit signing";
+
+    let keys: Vec<gpg::SecretKey> = gpg::secret_keys(None)?.into_iter().filter(|k| k.usable()).collect();
+    let matching: Vec<&gpg::SecretKey> = keys
+        .iter()
+        .filter(|k| k.emails().iter().any(|e| e.eq_ignore_ascii_case(email)))
+        .collect();
+    let now = journal::now();
+
+    let mut show_all = matching.is_empty();
+    loop {
+        let shown: Vec<&gpg::SecretKey> = if show_all { keys.iter().collect() } else { matching.clone() };
+        let mut options: Vec<String> = shown.iter().map(|k| k.describe(now)).collect();
+        if !show_all {
+            options.push(SHOW_ALL.to_string());
+        }
+        options.extend([GENERATE, MANUAL, NONE].map(str::to_string));
+
+        let help = if show_all {
+            "Signing keys in your GnuPG keyring".to_string()
+        } else {
+            format!("Signing keys for {}", email)
+        };
+        let selection = input::select(
+            Select::new("GPG signing key:", options.clone()).with_help_message(&help),
+            "--gpg-key",
+        )?;
+
+        match selection.as_str() {
+            SHOW_ALL => show_all = true,
+            GENERATE => return Ok(Some(generate_gpg_key(user_name, email, tx)?)),
+            MANUAL => {
+                let key = input::text(Text::new("GPG key ID:"), "--gpg-key")?;
+                return Ok(Some(key).filter(|k| !k.trim().is_empty()));
+            }
+            NONE => return Ok(None),
+            _ => {
+                let idx = options.iter().position(|o| *o == selection).unwrap();
+                return Ok(Some(shown[idx].fingerprint.clone()));
+            }
+        }
+    }
+}
+
+/// Generate a GPG key for the profile, removed again if `add` is rolled back
+fn generate_gpg_key(user_name: &str, email: &str, tx: &mut Transaction) -> Result<String> {
+    println!("Generating new ed25519 GPG key...");
+    let fingerprint = gpg::generate_key(user_name, email)?;
+    let generated = fingerprint.clone();
+    tx.on_rollback(format!("generated GPG key {}", fingerprint), move || {
+        gpg::delete_key(&generated)
+    });
+
+    println!(
+        "{} Generated GPG key: {}",
+        "Success:".green().bold(),
+        fingerprint
+    );
+    Ok(fingerprint)
+}
+
+/// How a new SSH key is generated when a command needs one
+#[derive(Debug, Clone, Copy, Default)]
+struct KeyGen {
+    key_type: ssh_keys::KeyType,
+    /// Store a security key's credential on the device
+    resident: bool,
+    passphrase_stdin: bool,
+}
+
+/// Generate an SSH key for the profile, removed again if the command is
+/// rolled back. Returns the key path.
+fn generate_ssh_key(
+    profile_name: &str,
+    email: &str,
+    keygen: KeyGen,
+    tx: &mut Transaction,
+) -> Result<String> {
+    // ssh-keygen asks for a security key's PIN through the same prompt as
+    // the passphrase, so it has to ask for both itself
+    let passphrase = if keygen.key_type.is_hardware() {
+        if keygen.passphrase_stdin {
+            bail!("--passphrase-stdin is not supported for security keys; ssh-keygen asks for the PIN and passphrase itself");
+        }
+        if input::is_interactive() { None } else { Some(String::new()) }
+    } else {
+        Some(key_passphrase(keygen.passphrase_stdin)?)
+    };
+
+    println!("Generating new {} SSH key...", keygen.key_type.as_str());
+    if keygen.key_type.is_hardware() {
+        println!("{}", "Touch your security key when it blinks.".yellow());
+    }
+    let key = ssh_keys::generate_key(
+        profile_name,
+        email,
+        keygen.key_type,
+        keygen.resident,
+        passphrase.as_deref(),
+    )?;
+    let generated = key.clone();
+    tx.on_rollback(format!("generated key {}", key.path_display()), move || {
+        ssh_keys::delete_key(&generated)
+    });
+
+    println!(
+        "{} Generated SSH key: {}",
+        "Success:".green().bold(),
+        key.path_display()
+    );
+
+    // Show the public key
+    let public_key = ssh_keys::read_public_key(&key)?;
+    println!();
+    println!("{}", "Public key (add this to GitHub/GitLab):".yellow());
+    println!("{}", public_key.trim());
+    println!();
+
+    Ok(key.path_display())
+}
+
+/// Passphrase for a new SSH key: the first line of stdin with
+/// --passphrase-stdin, asked for twice at a terminal, and none otherwise
+fn key_passphrase(passphrase_stdin: bool) -> Result<String> {
+    if passphrase_stdin {
+        let mut line = String::new();
+        std::io::stdin()
+            .read_line(&mut line)
+            .context("Failed to read passphrase from stdin")?;
+        return Ok(line.trim_end_matches(['\r', '\n']).to_string());
+    }
+    if !input::is_interactive() {
+        return Ok(String::new());
+    }
+    Ok(Password::new("Key passphrase:")
+        .with_help_message("Press Enter for no passphrase")
+        .with_custom_confirmation_message("Confirm passphrase:")
+        .prompt()?)
+}
+
+/// A profile in a picker: its name, identity, platform, tags and
+/// description on one line, so typing filters on any of them
+struct ProfileChoice {
+    name: String,
+    label: String,
+}
+
+impl std::fmt::Display for ProfileChoice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.label)
+    }
+}
+
+/// Ask for a profile from a fuzzy-filterable list, starting on `cursor`.
+/// Without a terminal this fails naming `flag`.
+fn select_profile(config: &Config, message: &str, cursor: Option<&str>, flag: &str) -> Result<String> {
+    select_profile_from(config, &config.profile_names(), message, cursor, flag)
+}
+
+/// `select_profile` among just `names`
+fn select_profile_from(
+    config: &Config,
+    names: &[&String],
+    message: &str,
+    cursor: Option<&str>,
+    flag: &str,
+) -> Result<String> {
+    let width = names.iter().map(|n| n.len()).max().unwrap_or(0);
+    let choices: Vec<ProfileChoice> = names
+        .iter()
+        .filter_map(|name| {
+            let profile = config.get_profile(name)?;
+            let mut label = format!(
+                "{:<width$}  {} <{}>  {}",
+                name,
+                profile.name,
+                profile.email,
+                profile.platform,
+                width = width
+            );
+            if !profile.tags.is_empty() {
+                label.push_str(&format!("  [{}]", profile.tags.join(", ")));
+            }
+            if let Some(ref description) = profile.description {
+                label.push_str(&format!("  - {}", description));
+            }
+            Some(ProfileChoice {
+                name: name.to_string(),
+                label,
+            })
+        })
+        .collect();
+    let start = cursor
+        .and_then(|c| choices.iter().position(|p| p.name == c))
+        .unwrap_or(0);
+
+    let choice = input::select(
+        Select::new(message, choices)
+            .with_starting_cursor(start)
+            .with_formatter(&|choice| choice.value.name.clone())
+            .with_help_message("Type to filter by name, email, platform or tag, Enter to select"),
+        flag,
+    )?;
+    Ok(choice.name)
+}
+
+/// Interactive SSH key selection or creation. Returns the key path and
+/// whether the key was generated just now.
+fn select_or_create_ssh_key(
+    profile_name: &str,
+    email: &str,
+    keygen: KeyGen,
+    tx: &mut Transaction,
+) -> Result<(String, bool)> {
     let existing_keys = ssh_keys::discover_keys()?;
 
     // Build options list
@@ -195,42 +833,32 @@ fn select_or_create_ssh_key(profile_name: &str, email: &str) -> Result<String> {
         .map(|k| format!("{} ({})", k.path_display(), k.key_type))
         .collect();
 
-    options.push("+ Generate new SSH key".to_string());
+    let generate = format!("+ Generate new {} SSH key", keygen.key_type.as_str());
+    options.push(generate.clone());
+    options.push("+ Load resident keys from a security key".to_string());
     options.push("+ Enter path manually".to_string());
 
-    let selection = Select::new("SSH key:", options.clone())
-        .with_help_message("Select an existing key or create a new one")
-        .prompt()?;
-
-    if selection == "+ Generate new SSH key" {
-        // Generate a new key
-        println!("Generating new ed25519 SSH key...");
-        let key = ssh_keys::generate_key(profile_name, email)?;
-
-        println!(
-            "{} Generated SSH key: {}",
-            "Success:".green().bold(),
-            key.path_display()
-        );
-
-        // Show the public key
-        let public_key = ssh_keys::read_public_key(&key)?;
-        println!();
-        println!("{}", "Public key (add this to GitHub/GitLab):".yellow());
-        println!("{}", public_key.trim());
-        println!();
+    let selection = input::select(
+        Select::new("SSH key:", options.clone())
+            .with_help_message("Select an existing key or create a new one"),
+        "--ssh-key",
+    )?;
 
-        Ok(key.path_display())
+    if selection == generate {
+        Ok((generate_ssh_key(profile_name, email, keygen, tx)?, true))
+    } else if selection == "+ Load resident keys from a security key" {
+        println!("{}", "Touch your security key when it blinks.".yellow());
+        ssh_keys::load_resident_keys()?;
+        // Offer the list again with the downloaded keys in it
+        select_or_create_ssh_key(profile_name, email, keygen, tx)
     } else if selection == "+ Enter path manually" {
         let default_path = format!("~/.ssh/id_ed25519_{}", profile_name);
-        let path = Text::new("SSH key path:")
-            .with_default(&default_path)
-            .prompt()?;
-        Ok(path)
+        let path = input::text(Text::new("SSH key path:").with_default(&default_path), "--ssh-key")?;
+        Ok((path, false))
     } else {
         // Find the selected key
         let idx = options.iter().position(|o| o == &selection).unwrap();
-        Ok(existing_keys[idx].path_display())
+        Ok((existing_keys[idx].path_display(), false))
     }
 }
 
@@ -245,9 +873,7 @@ fn cmd_remove(name: Option<String>, force: bool, clean_ssh: bool) -> Result<()>
     let name = match name {
         Some(n) => n,
         None => {
-            let profiles: Vec<String> = config.profile_names().into_iter().cloned().collect();
-            Select::new("Select profile to remove:", profiles)
-                .prompt()?
+            select_profile(&config, "Select profile to remove:", None, "a profile name")?
         }
     };
 
@@ -255,10 +881,17 @@ fn cmd_remove(name: Option<String>, force: bool, clean_ssh: bool) -> Result<()>
         bail!("Profile '{}' not found", name);
     }
 
+    if config.is_system_profile(&name) {
+        bail!(
+            "Profile '{}' is defined in the system config ({}) and cannot be removed here",
+            name,
+            Config::system_config_path().display()
+        );
+    }
+
     if !force {
-        let confirmed = Confirm::new(&format!("Remove profile '{}'?", name))
-            .with_default(false)
-            .prompt()?;
+        let message = format!("Remove profile '{}'?", name);
+        let confirmed = input::approve(Confirm::new(&message).with_default(false))?;
 
         if !confirmed {
             println!("Cancelled");
@@ -268,6 +901,7 @@ fn cmd_remove(name: Option<String>, force: bool, clean_ssh: bool) -> Result<()>
 
     config.remove_profile(&name);
     config.save()?;
+    let _ = usage::forget(&name);
 
     println!("{} Removed profile '{}'", "Success:".green().bold(), name);
 
@@ -279,14 +913,245 @@ fn cmd_remove(name: Option<String>, force: bool, clean_ssh: bool) -> Result<()>
     Ok(())
 }
 
-fn cmd_list() -> Result<()> {
+fn cmd_default(name: Option<String>, unset: bool) -> Result<()> {
+    let mut config = Config::load()?;
+
+    if unset {
+        if config.default_profile.take().is_none() {
+            println!("No default profile set");
+            return Ok(());
+        }
+        config.save()?;
+        if let Some(ref system) = config.system_default() {
+            println!(
+                "{} Cleared the default profile; the system config's default '{}' still applies",
+                "Success:".green().bold(),
+                system
+            );
+        } else {
+            println!("{} Cleared the default profile", "Success:".green().bold());
+        }
+        return Ok(());
+    }
+
+    let Some(name) = name else {
+        match config.default_profile {
+            Some(ref default) => println!("{}", default),
+            None => println!("No default profile set. Run {} to set one", "gitid default <name>".yellow()),
+        }
+        return Ok(());
+    };
+
+    if !config.has_profile(&name) {
+        bail!("Profile '{}' not found", name);
+    }
+    config.default_profile = Some(name.clone());
+    config.save()?;
+
+    println!(
+        "{} Default profile is now '{}'",
+        "Success:".green().bold(),
+        name.cyan()
+    );
+    Ok(())
+}
+
+fn cmd_pin(name: Option<String>, unset: bool) -> Result<()> {
+    if !git::is_git_repo() {
+        bail!("Not in a git repository");
+    }
+    let config = Config::load()?;
+    let pinned = detect::pinned_profile()?;
+
+    if unset {
+        if pinned.is_none() {
+            println!("No profile pinned to this repository");
+            return Ok(());
+        }
+        git::unset_config(detect::PIN_KEY, ConfigScope::Local)?;
+        println!("{} Removed the pin; detection decides again", "Success:".green().bold());
+        return Ok(());
+    }
+
+    let Some(name) = name else {
+        match pinned {
+            Some(ref pinned) if !config.has_profile(pinned) => println!(
+                "{} (not a configured profile, so detection ignores it)",
+                pinned
+            ),
+            Some(ref pinned) => println!("{}", pinned),
+            None => println!("No profile pinned. Run {} to pin one", "gitid pin <name>".yellow()),
+        }
+        return Ok(());
+    };
+
+    if !config.has_profile(&name) {
+        bail!("Profile '{}' not found", name);
+    }
+    git::set_config(detect::PIN_KEY, &name, ConfigScope::Local)?;
+    println!(
+        "{} Pinned '{}' to this repository",
+        "Success:".green().bold(),
+        name.cyan()
+    );
+
+    if prompt::get_current_profile(&config)?.as_deref() != Some(name.as_str()) {
+        println!("Run {} to apply it", format!("gitid use {} --local", name).yellow());
+    }
+    Ok(())
+}
+
+fn cmd_rename(old: String, new: String) -> Result<()> {
+    let mut config = Config::load()?;
+
+    let profile = config
+        .get_profile(&old)
+        .context(format!("Profile '{}' not found", old))?
+        .clone();
+    if config.is_system_profile(&old) {
+        bail!(
+            "Profile '{}' is defined in the system config ({}) and cannot be renamed here",
+            old,
+            Config::system_config_path().display()
+        );
+    }
+
+    config.rename_profile(&old, &new)?;
+    config.save()?;
+    ssh::sync_ssh_config(&config)?;
+    let _ = usage::rename(&old, &new);
+
+    // Keyring entries are keyed by profile name unless the profile names
+    // another account
+    let moved_token = if profile.keyring.is_none() {
+        secrets::get_token(&old)
+    } else {
+        Ok(None)
+    };
+    if let Ok(Some(token)) = moved_token {
+        if let Err(e) = secrets::set_token(&new, &token).and_then(|_| secrets::delete_token(&old)) {
+            println!("{} Could not move the keyring token: {}", "Warning:".yellow().bold(), e);
+        }
+    }
+    if profile.github_app.is_some() {
+        let _ = secrets::delete_app_token(&old);
+    }
+
+    println!(
+        "{} Renamed profile '{}' to '{}'",
+        "Success:".green().bold(),
+        old,
+        new.cyan()
+    );
+    println!("SSH config updated");
+
+    // Remotes pointing at the old aliases no longer resolve
+    let old_aliases = profile.ssh_host_aliases(&old);
+    let mut repos = hooks::hooked_repos().unwrap_or_default();
+    if let Ok(Some(root)) = git::repo_root() {
+        repos.insert(0, root);
+    }
+
+    let mut stale = 0;
+    for repo in &repos {
+        for (remote, url) in git::remote_urls_in(repo).unwrap_or_default() {
+            let Some(parsed) = git::RemoteUrl::parse(&url) else {
+                continue;
+            };
+            let Some((_, hostname)) = old_aliases.iter().find(|(alias, _)| *alias == parsed.host) else {
+                continue;
+            };
+            if stale == 0 {
+                println!();
+            }
+            stale += 1;
+
+            let fixed = profile
+                .alias_for_host(&new, hostname)
+                .map(|alias| parsed.with_ssh_host(&alias))
+                .unwrap_or_default();
+            println!(
+                "{} {} remote '{}' still uses {}",
+                "Warning:".yellow().bold(),
+                repo.display(),
+                remote,
+                parsed.host
+            );
+            println!("    git -C {} remote set-url {} {}", env::shell_quote(&repo.display().to_string()), remote, fixed);
+        }
+    }
+
+    Ok(())
+}
+
+fn cmd_list(json: bool, format: Option<String>, verbose: bool, tag: Option<String>, sort: ListSort) -> Result<()> {
     let config = Config::load()?;
+    let tag = tag.as_deref();
+    let usage = usage::Usage::load()?;
+
+    if json || format.is_some() {
+        let current = if git::is_git_repo() {
+            prompt::get_current_profile(&config)?
+        } else {
+            None
+        };
+        let mut entries = list::profile_entries(&config, tag, current.as_deref())?;
+        for entry in &mut entries {
+            let name = entry["profile"].as_str().unwrap_or_default().to_string();
+            let used = usage.profiles.get(&name).copied().unwrap_or_default();
+            entry.insert("uses".to_string(), used.count.into());
+            entry.insert(
+                "last_used".to_string(),
+                usage.last_used(&name).map(Into::into).unwrap_or(serde_json::Value::Null),
+            );
+        }
+        if sort == ListSort::Recent {
+            entries.sort_by_key(|e| std::cmp::Reverse(e["last_used"].as_u64()));
+        }
+        if verbose {
+            let mut check = list::HealthCheck::new(&config)?;
+            for entry in &mut entries {
+                let name = entry["profile"].as_str().unwrap_or_default().to_string();
+                let Some(profile) = config.get_profile(&name) else {
+                    continue;
+                };
+                let health = serde_json::to_value(check.check(&name, profile))?;
+                if let serde_json::Value::Object(fields) = health {
+                    entry.extend(fields);
+                }
+            }
+        }
+
+        if let Some(template) = format {
+            for entry in &entries {
+                println!("{}", list::render_template(&template, entry)?);
+            }
+        } else {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&entries).context("Failed to serialize profiles")?
+            );
+        }
+        return Ok(());
+    }
 
     if config.profiles.is_empty() {
         println!("No profiles configured");
         println!("Run {} to add a profile", "gitid add".yellow());
         return Ok(());
     }
+    let mut names = config.tagged_profile_names(tag);
+    if let (Some(tag), true) = (tag, names.is_empty()) {
+        println!("No profiles are tagged '{}'", tag);
+        return Ok(());
+    }
+    if sort == ListSort::Recent {
+        usage.sort_recent(&mut names);
+    }
+
+    if verbose {
+        return print_health(&config, &names);
+    }
 
     // Get current profile if in a git repo
     let current = if git::is_git_repo() {
@@ -298,7 +1163,7 @@ fn cmd_list() -> Result<()> {
     println!("{}", "Profiles:".bold());
     println!();
 
-    for name in config.profile_names() {
+    for name in names {
         if let Some(profile) = config.get_profile(name) {
             let is_current = current.as_ref() == Some(name);
             let marker = if is_current {
@@ -312,19 +1177,39 @@ fn cmd_list() -> Result<()> {
             } else {
                 String::new()
             };
-
-            println!("{} {}{}", marker, name.cyan().bold(), default_marker);
+            let system_marker = if config.is_system_profile(name) {
+                " (system)".dimmed().to_string()
+            } else {
+                String::new()
+            };
+
+            println!("{} {}{}{}", marker, name.cyan().bold(), default_marker, system_marker);
+            if let Some(ref description) = profile.description {
+                println!("    {}", description.dimmed());
+            }
             println!("    Name:     {}", profile.name);
             println!("    Email:    {}", profile.email);
             println!("    Platform: {}", profile.platform);
+            if let Some(ref username) = profile.username {
+                println!("    Username: {}", username);
+            }
             println!("    SSH Key:  {}", profile.ssh_key);
 
-            if let Some(ref gpg) = profile.gpg_key {
-                println!("    GPG Key:  {}", gpg);
+            match profile.signing_key() {
+                Some((SigningFormat::Gpg, key)) => println!("    GPG Key:  {}", key),
+                Some((SigningFormat::Ssh, _)) => println!("    Signing:  ssh"),
+                None => {}
             }
             if let Some(ref host) = profile.host {
                 println!("    Host:     {}", host);
             }
+            if !profile.tags.is_empty() {
+                println!("    Tags:     {}", profile.tags.join(", "));
+            }
+            if sort == ListSort::Recent {
+                let last_used = usage.last_used(name).map(journal::format_age);
+                println!("    Used:     {}", last_used.as_deref().unwrap_or("never"));
+            }
             println!();
         }
     }
@@ -332,27 +1217,172 @@ fn cmd_list() -> Result<()> {
     Ok(())
 }
 
-fn cmd_use(name: Option<String>, global: bool) -> Result<()> {
+/// Health matrix for `gitid list --verbose`: one row per profile, with
+/// problems in red
+fn print_health(config: &Config, names: &[&String]) -> Result<()> {
+    let mut check = list::HealthCheck::new(config)?;
+    let headers = ["PROFILE", "SSH KEY", "GPG KEY", "SSH CONFIG", "FINGERPRINT", "AUTH"];
+
+    // Each cell is its text and whether it's fine
+    let mut rows: Vec<[(String, bool); 6]> = Vec::new();
+    for &name in names {
+        let Some(profile) = config.get_profile(name) else {
+            continue;
+        };
+        let health = check.check(name, profile);
+        let auth = health
+            .auth
+            .iter()
+            .map(|(host, signed_in)| match signed_in {
+                Some(true) => format!("{}: signed in", host),
+                Some(false) => format!("{}: signed out", host),
+                None => format!("{}: not installed", host),
+            })
+            .collect::<Vec<_>>();
+        rows.push([
+            (name.clone(), true),
+            (if health.key_exists { "ok" } else { "missing" }.to_string(), health.key_exists),
+            match health.gpg_key_present {
+                Some(true) => ("ok".to_string(), true),
+                Some(false) => ("not in keyring".to_string(), false),
+                None => ("-".to_string(), true),
+            },
+            (if health.ssh_synced { "synced" } else { "out of date" }.to_string(), health.ssh_synced),
+            (health.fingerprint.unwrap_or_else(|| "-".to_string()), true),
+            (
+                if auth.is_empty() { "-".to_string() } else { auth.join(", ") },
+                health.auth.values().all(|s| *s == Some(true)),
+            ),
+        ]);
+    }
+
+    let widths: Vec<usize> = (0..headers.len() - 1)
+        .map(|i| rows.iter().map(|r| r[i].0.len()).chain([headers[i].len()]).max().unwrap_or(0))
+        .collect();
+    let header: Vec<String> = headers[..headers.len() - 1]
+        .iter()
+        .zip(&widths)
+        .map(|(h, w)| format!("{:<w$}", h, w = w))
+        .collect();
+    println!("{}  {}", header.join("  "), headers[headers.len() - 1]);
+
+    for row in &rows {
+        let cells: Vec<String> = row
+            .iter()
+            .enumerate()
+            .map(|(i, (text, ok))| {
+                let text = match widths.get(i) {
+                    Some(w) => format!("{:<w$}", text, w = w),
+                    None => text.clone(),
+                };
+                if i == 0 {
+                    text.cyan().to_string()
+                } else if *ok {
+                    text
+                } else {
+                    text.red().to_string()
+                }
+            })
+            .collect();
+        println!("{}", cells.join("  "));
+    }
+
+    if rows.iter().any(|r| !r[3].1) {
+        println!();
+        println!("Run {} to update the SSH config", "gitid ssh-sync".yellow());
+    }
+    Ok(())
+}
+
+fn cmd_stats(json: bool) -> Result<()> {
     let config = Config::load()?;
+    let usage = usage::Usage::load()?;
+    let now = journal::now();
+    let mut names = config.profile_names();
+    usage.sort_recent(&mut names);
 
-    if config.profiles.is_empty() {
-        bail!("No profiles configured. Run 'gitid add' first.");
+    if json {
+        let entries: Vec<serde_json::Value> = names
+            .iter()
+            .map(|name| {
+                let used = usage.profiles.get(*name).copied().unwrap_or_default();
+                serde_json::json!({
+                    "profile": name,
+                    "uses": used.count,
+                    "last_used": usage.last_used(name),
+                    "stale": usage.is_stale(name, now),
+                })
+            })
+            .collect();
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&entries).context("Failed to serialize usage")?
+        );
+        return Ok(());
     }
 
-    // Get profile name (interactive if not provided)
-    let name = match name {
-        Some(n) => n,
-        None => {
-            let profiles: Vec<String> = config.profile_names().into_iter().cloned().collect();
-            Select::new("Select profile:", profiles)
-                .with_help_message("Use arrow keys to navigate, Enter to select")
-                .prompt()?
+    if names.is_empty() {
+        println!("No profiles configured");
+        println!("Run {} to add a profile", "gitid add".yellow());
+        return Ok(());
+    }
+
+    let rows: Vec<(&String, String, String)> = names
+        .iter()
+        .map(|&name| {
+            let used = usage.profiles.get(name).copied().unwrap_or_default();
+            let last_used = usage.last_used(name).map(journal::format_age);
+            (name, used.count.to_string(), last_used.unwrap_or_else(|| "never".to_string()))
+        })
+        .collect();
+    let name_width = rows.iter().map(|r| r.0.len()).chain(["PROFILE".len()]).max().unwrap_or(0);
+    let uses_width = rows.iter().map(|r| r.1.len()).chain(["USES".len()]).max().unwrap_or(0);
+
+    println!("{:<nw$}  {:>uw$}  LAST USED", "PROFILE", "USES", nw = name_width, uw = uses_width);
+    let mut stale = 0;
+    for (name, uses, last_used) in &rows {
+        let name_cell = format!("{:<w$}", name, w = name_width).cyan();
+        if usage.is_stale(name, now) {
+            stale += 1;
+            println!("{}  {:>w$}  {}", name_cell, uses, last_used.yellow(), w = uses_width);
+        } else {
+            println!("{}  {:>w$}  {}", name_cell, uses, last_used, w = uses_width);
         }
-    };
+    }
 
-    let profile = config
-        .get_profile(&name)
-        .context(format!("Profile '{}' not found", name))?;
+    if stale > 0 {
+        println!();
+        println!(
+            "{} profile(s) not used in {} days; remove any you no longer need with {}",
+            stale,
+            usage::STALE_DAYS,
+            "gitid remove".yellow()
+        );
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn cmd_use(
+    name: Option<String>,
+    tag: Option<String>,
+    global: bool,
+    ssh_command: bool,
+    key: Option<String>,
+    agent: Option<bool>,
+    rewrite_remote: bool,
+    restore_remote: bool,
+) -> Result<()> {
+    let config = Config::load()?;
+
+    if config.profiles.is_empty() {
+        bail!("No profiles configured. Run 'gitid add' first.");
+    }
+
+    let candidates = config.tagged_profile_names(tag.as_deref());
+    if let (Some(tag), true) = (&tag, candidates.is_empty()) {
+        bail!("No profiles are tagged '{}'", tag);
+    }
 
     let scope = if global {
         ConfigScope::Global
@@ -363,13 +1393,79 @@ fn cmd_use(name: Option<String>, global: bool) -> Result<()> {
         ConfigScope::Local
     };
 
+    // `-` is the profile applied before the current one, as with `cd -`
+    let name = match name.as_deref() {
+        Some("-") => {
+            let Some(previous) = usage::previous_profile(scope)? else {
+                match scope {
+                    ConfigScope::Global => bail!("No profile was applied globally before the current one"),
+                    ConfigScope::Local => bail!("No profile was applied in this repository before the current one"),
+                }
+            };
+            println!("Switching back to '{}'", previous.cyan());
+            Some(previous)
+        }
+        _ => name,
+    };
+
+    // Get profile name: picked interactively with the default preselected,
+    // or the default itself without a terminal
+    let default = config
+        .default_profile
+        .clone()
+        .filter(|d| candidates.contains(&d));
+    let name = match (name, default) {
+        (Some(n), _) => n,
+        (None, _) if tag.is_some() && candidates.len() == 1 => {
+            println!("Using '{}', the only profile with that tag", candidates[0].cyan());
+            candidates[0].clone()
+        }
+        (None, Some(default)) if !input::is_interactive() => {
+            println!("Using default profile '{}'", default.cyan());
+            default
+        }
+        (None, default) => {
+            select_profile_from(&config, &candidates, "Select profile:", default.as_deref(), "a profile name")?
+        }
+    };
+
+    let profile = config
+        .get_profile(&name)
+        .context(format!("Profile '{}' not found", name))?;
+
+    // The alias entries offer every key, so picking one takes core.sshCommand
+    let selected;
+    let profile = match key {
+        Some(ref key) => {
+            selected = profile.with_ssh_key(key)?;
+            &selected
+        }
+        None => profile,
+    };
+    let ssh_command = ssh_command || key.is_some() || config.ssh_mode(profile) == SshMode::Command;
+    let mut rewrites = Vec::new();
+    if !global {
+        warn_history_mismatch(&config, &name)?;
+        let remotes = git::remote_urls()?;
+        for violation in remote::owner_violations(profile, &remotes) {
+            println!("{} {}", "Warning:".yellow().bold(), violation);
+        }
+        warn_policy_violations(&config, &remotes, &name, profile);
+
+        if rewrite_remote {
+            rewrites = remote::plan_alias_rewrites(&name, profile, &remotes);
+        } else if restore_remote {
+            rewrites = remote::plan_canonical_rewrites(&config, &remotes);
+        }
+    }
+
     // Apply git configuration
-    git::apply_profile(
-        &profile.name,
-        &profile.email,
-        profile.gpg_key.as_deref(),
-        scope,
-    )?;
+    git::apply_profile(&config, &name, profile, scope)?;
+
+    apply_ssh_command(profile, ssh_command, scope)?;
+    let insteadof = config.ssh_mode(profile) == SshMode::InsteadOf;
+    remote::apply_insteadof(&config, &name, profile, insteadof, scope)?;
+    remote::apply_rewrites(&rewrites)?;
 
     let scope_str = if global { "globally" } else { "locally" };
     println!(
@@ -381,26 +1477,308 @@ fn cmd_use(name: Option<String>, global: bool) -> Result<()> {
     println!("  Name:  {}", profile.name);
     println!("  Email: {}", profile.email);
 
-    if profile.gpg_key.is_some() {
-        println!("  GPG signing: enabled");
+    match profile.signing_key() {
+        Some((SigningFormat::Gpg, _)) => println!("  GPG signing: enabled"),
+        Some((SigningFormat::Ssh, _)) => println!("  SSH signing: enabled"),
+        None => {}
+    }
+    if ssh_command {
+        println!("  SSH command: {}", env::ssh_command(profile));
+    }
+    if insteadof {
+        println!("  URL rewrites: remotes go through {}", profile.ssh_host_alias(&name));
+    }
+    for rewrite in &rewrites {
+        println!("  Remote {}: {} -> {}", rewrite.remote, rewrite.from, rewrite.to.cyan());
+    }
+    if (rewrite_remote || restore_remote) && rewrites.is_empty() {
+        println!("  Remotes: unchanged");
+    }
+    if agent.or(config.ssh_agent).unwrap_or(false) {
+        match swap_agent_keys(&config, profile) {
+            Ok((loaded, removed)) => {
+                let state = if loaded { "loaded" } else { "already loaded" };
+                println!("  Agent: {} {}", profile.ssh_key, state);
+                if removed > 0 {
+                    println!("  Agent: removed {} other profile key(s)", removed);
+                }
+            }
+            Err(e) => println!("  {} {}", "Warning:".yellow().bold(), e),
+        }
+    }
+    report_account_switch(profile);
+    if config.glab_host.unwrap_or(false) {
+        report_glab_host(profile);
+    }
+
+    Ok(())
+}
+
+/// Load the profile's key into ssh-agent and remove the keys of every other
+/// profile, so the agent offers only this identity. Returns whether the
+/// key was loaded and how many keys were removed.
+fn swap_agent_keys(config: &Config, profile: &Profile) -> Result<(bool, usize)> {
+    let own = profile.ssh_key_paths();
+    let mut removed = 0;
+    let mut seen = Vec::new();
+    for other in config.profiles.values() {
+        for path in other.ssh_key_paths() {
+            if own.contains(&path) || seen.contains(&path) {
+                continue;
+            }
+            seen.push(path);
+            // Keys that can't be read can't be in the agent either
+            if ssh_keys::agent_remove(path).unwrap_or(false) {
+                removed += 1;
+            }
+        }
+    }
+    let loaded = ssh_keys::agent_add(&profile.ssh_key)?;
+    Ok((loaded, removed))
+}
+
+/// Make the profile's account gh's active one, reporting the outcome
+fn report_account_switch(profile: &Profile) {
+    match auth::switch_account(profile) {
+        Ok(true) => println!(
+            "  GitHub CLI: {}",
+            profile.username.as_deref().unwrap_or_default()
+        ),
+        Ok(false) => {}
+        Err(e) => println!("  {} {}", "Warning:".yellow().bold(), e),
+    }
+}
+
+/// Make the profile's GitLab instance glab's default host, reporting the
+/// outcome
+fn report_glab_host(profile: &Profile) {
+    match auth::select_glab_host(profile) {
+        Ok(Some(host)) => println!("  GitLab CLI: default host {}", host),
+        Ok(None) => {}
+        Err(e) => println!("  {} {}", "Warning:".yellow().bold(), e),
+    }
+}
+
+fn cmd_unset(global: bool) -> Result<()> {
+    let (scope, scope_name) = if global {
+        (ConfigScope::Global, "global")
+    } else {
+        if !git::is_git_repo() {
+            bail!("Not in a git repository. Use --global to clear the global identity.");
+        }
+        (ConfigScope::Local, "local")
+    };
+
+    let config = Config::load()?;
+    let (name, email) = git::get_current_user(scope)?;
+    let profile = prompt::matching_profile(&config, name.as_deref(), email.as_deref());
+
+    let mut removed = git::clear_identity(scope)?;
+    if let Some(existing) = git::get_config("core.sshCommand", scope)? {
+        if env::is_managed_ssh_command(&existing) {
+            git::unset_config("core.sshCommand", scope)?;
+            removed.push("core.sshCommand".to_string());
+        }
+    }
+
+    if removed.is_empty() {
+        println!("No identity set in the {} git config", scope_name);
+        return Ok(());
+    }
+
+    match profile {
+        Some(ref profile) => println!(
+            "{} Cleared profile '{}' from the {} git config",
+            "Success:".green().bold(),
+            profile.cyan(),
+            scope_name
+        ),
+        None => println!(
+            "{} Cleared the identity from the {} git config",
+            "Success:".green().bold(),
+            scope_name
+        ),
+    }
+    println!("  Removed: {}", removed.join(", "));
+
+    if !global {
+        match git::get_current_user(ConfigScope::Global)? {
+            (Some(name), Some(email)) => println!("  Now using the global identity: {} <{}>", name, email),
+            _ => println!(
+                "  No global identity is set; run {} to set one",
+                "gitid use <name> --global".yellow()
+            ),
+        }
+    }
+    println!("  Run {} to undo", "gitid rollback".yellow());
+    Ok(())
+}
+
+fn cmd_switch(name: Option<String>, no_rewrite: bool, no_agent: bool) -> Result<()> {
+    if !git::is_git_repo() {
+        bail!("Not in a git repository");
+    }
+
+    let config = Config::load()?;
+    if config.profiles.is_empty() {
+        bail!("No profiles configured. Run 'gitid add' first.");
+    }
+
+    // Take the named profile, else the detected one, else ask
+    let (name, reason) = match name {
+        Some(n) => (n, "given on the command line".to_string()),
+        None => match detect::detect_and_suggest(&config)? {
+            Some(result) if !result.tied.is_empty() => {
+                (resolve_tie(&config, &result, false)?, "chosen from a tie".to_string())
+            }
+            Some(result) => (result.profile_name, result.reason),
+            None => {
+                let n = select_profile(&config, "No profile detected. Select profile:", None, "a profile name")?;
+                (n, "selected".to_string())
+            }
+        },
+    };
+    let profile = config
+        .get_profile(&name)
+        .context(format!("Profile '{}' not found", name))?;
+
+    let remotes = git::remote_urls()?;
+    remote::enforce_owners(&name, profile, &remotes)?;
+    warn_policy_violations(&config, &remotes, &name, profile);
+    warn_history_mismatch(&config, &name)?;
+
+    let command_mode = config.ssh_mode(profile) == SshMode::Command;
+    let insteadof = config.ssh_mode(profile) == SshMode::InsteadOf;
+    git::apply_profile(&config, &name, profile, ConfigScope::Local)?;
+    apply_ssh_command(profile, command_mode, ConfigScope::Local)?;
+    remote::apply_insteadof(&config, &name, profile, insteadof, ConfigScope::Local)?;
+
+    let remotes_status = if command_mode {
+        "left unchanged (core.sshCommand selects the key)".to_string()
+    } else if insteadof {
+        format!("left unchanged (url.insteadOf routes them through {})", profile.ssh_host_alias(&name))
+    } else if no_rewrite {
+        "left unchanged".to_string()
+    } else {
+        let rewrites = remote::plan_alias_rewrites(&name, profile, &remotes);
+        remote::apply_rewrites(&rewrites)?;
+        match rewrites.len() {
+            0 => "already use the profile's alias".to_string(),
+            n => format!("{} rewritten to {}", n, profile.ssh_host_alias(&name)),
+        }
+    };
+
+    let agent_status = if no_agent {
+        "skipped".to_string()
+    } else {
+        match ssh_keys::agent_add(&profile.ssh_key) {
+            Ok(true) => format!("loaded {}", profile.ssh_key),
+            Ok(false) => format!("{} already loaded", profile.ssh_key),
+            Err(e) => format!("{} {}", "not loaded:".yellow(), e),
+        }
+    };
+
+    println!(
+        "{} Switched to profile '{}' ({})",
+        "Success:".green().bold(),
+        name.cyan(),
+        reason
+    );
+    println!("  Identity: {} <{}>", profile.name, profile.email);
+    println!("  Remotes:  {}", remotes_status);
+    println!("  Agent:    {}", agent_status);
+    println!(
+        "  Signing:  {}",
+        match profile.signing_key() {
+            Some((SigningFormat::Gpg, _)) => "gpg",
+            Some((SigningFormat::Ssh, _)) => "ssh",
+            None => "off",
+        }
+    );
+    report_account_switch(profile);
+    if config.glab_host.unwrap_or(false) {
+        report_glab_host(profile);
+    }
+
+    Ok(())
+}
+
+/// Set core.sshCommand for the profile, or clear one gitid set for a
+/// different profile so it doesn't keep forcing the wrong key
+fn apply_ssh_command(profile: &Profile, enabled: bool, scope: ConfigScope) -> Result<()> {
+    if enabled {
+        git::set_config("core.sshCommand", &env::ssh_command(profile), scope)
+    } else {
+        match git::get_config("core.sshCommand", scope)? {
+            Some(existing) if env::is_managed_ssh_command(&existing) => {
+                git::unset_config("core.sshCommand", scope)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Print policies the profile would break in a repository with these remotes
+fn warn_policy_violations(config: &Config, remotes: &[(String, String)], name: &str, profile: &Profile) {
+    let identity = policy::Identity::for_profile(name, profile);
+    for violation in policy::evaluate(config, remotes, &identity) {
+        println!("{} {}", "Warning:".yellow().bold(), violation);
     }
+}
 
+/// Print a warning if the repo's history suggests a different profile
+fn warn_history_mismatch(config: &Config, profile_name: &str) -> Result<()> {
+    if let Some(warning) = detect::history_mismatch(config, profile_name)? {
+        println!("{} {}", "Warning:".yellow().bold(), warning);
+    }
     Ok(())
 }
 
-fn cmd_auth(name: Option<String>) -> Result<()> {
+fn cmd_auth(
+    name: Option<String>,
+    with_token: bool,
+    token_env: Option<String>,
+    from_keyring: bool,
+    keyring: bool,
+    glab_host: bool,
+) -> Result<()> {
     let config = Config::load()?;
+    let glab_host = glab_host || config.glab_host.unwrap_or(false);
 
     if config.profiles.is_empty() {
         bail!("No profiles configured. Run 'gitid add' first.");
     }
 
+    let token = if with_token {
+        // Piped in scripts; typed without echo when run by hand
+        Some(read_secret("Token:", false)?)
+    } else if let Some(ref var) = token_env {
+        Some(
+            std::env::var(var)
+                .with_context(|| format!("Environment variable {} is not set", var))?
+                .trim()
+                .to_string(),
+        )
+    } else {
+        None
+    };
+
+    if token.as_deref() == Some("") {
+        bail!("Token is empty");
+    }
+    if keyring && token.is_none() {
+        bail!("--keyring needs a token from --with-token or --token-env");
+    }
+
     // Get profile name (interactive if not provided)
     let name = match name {
         Some(n) => n,
+        None if token.is_some() => {
+            bail!("A profile name is required with --with-token or --token-env")
+        }
+        None if from_keyring => bail!("A profile name is required with --from-keyring"),
         None => {
-            let profiles: Vec<String> = config.profile_names().into_iter().cloned().collect();
-            Select::new("Select profile to authenticate:", profiles).prompt()?
+            select_profile(&config, "Select profile to authenticate:", None, "a profile name")?
         }
     };
 
@@ -408,6 +1786,38 @@ fn cmd_auth(name: Option<String>) -> Result<()> {
         .get_profile(&name)
         .context(format!("Profile '{}' not found", name))?;
 
+    let token = if from_keyring {
+        let account = profile.keyring_account(&name);
+        Some(secrets::get_token(account)?.with_context(|| {
+            format!("No token stored for '{}'. Run 'gitid token set {}'.", account, name)
+        })?)
+    } else {
+        token
+    };
+
+    if let Some(token) = token {
+        if keyring {
+            secrets::set_token(profile.keyring_account(&name), &token)?;
+            println!(
+                "{} Stored token for '{}' in the OS keyring",
+                "Success:".green().bold(),
+                name
+            );
+        } else {
+            auth::authenticate_with_token(&name, profile, &token)?;
+            println!(
+                "{} Authentication complete for '{}'",
+                "Success:".green().bold(),
+                name
+            );
+            if glab_host {
+                report_glab_host(profile);
+            }
+        }
+        report_token_owner(profile, &token);
+        return Ok(());
+    }
+
     println!(
         "Authenticating CLI tools for profile '{}'...",
         name.cyan()
@@ -422,45 +1832,540 @@ fn cmd_auth(name: Option<String>) -> Result<()> {
         "Success:".green().bold(),
         name
     );
+    if glab_host {
+        report_glab_host(profile);
+    }
+    for (platform, login) in auth::account_mismatches(profile)? {
+        println!(
+            "{} {} CLI is signed in as '{}', not '{}'",
+            "Warning:".yellow().bold(),
+            platform,
+            login,
+            profile.username.as_deref().unwrap_or_default()
+        );
+    }
 
     Ok(())
 }
 
-fn cmd_current(porcelain: bool) -> Result<()> {
+fn cmd_test(name: String, key: Option<String>) -> Result<()> {
     let config = Config::load()?;
+    let profile = config
+        .get_profile(&name)
+        .context(format!("Profile '{}' not found", name))?;
 
-    if porcelain {
-        prompt::output_porcelain(&config)
-    } else {
-        prompt::output_human(&config)
+    let selected;
+    let profile = match key {
+        Some(ref key) => {
+            selected = profile.with_ssh_key(key)?;
+            println!("Testing with {} only", selected.ssh_key);
+            &selected
+        }
+        None => profile,
+    };
+
+    // 'both' profiles get several aliases for the same hostname; one
+    // connection per hostname is enough
+    let mut hostnames: Vec<String> = Vec::new();
+    let mut failed = 0;
+    for (alias, hostname) in profile.ssh_host_aliases(&name) {
+        if hostnames.contains(&hostname) {
+            continue;
+        }
+        hostnames.push(hostname.clone());
+
+        let test = if key.is_some() {
+            ssh::test_key_connection(profile, &hostname)?
+        } else {
+            ssh::test_connection(&alias)?
+        };
+        match (test.authenticated, test.account, profile.username.as_deref()) {
+            (false, _, _) => {
+                failed += 1;
+                println!("{} {} ({}): {}", "Failed:".red(), alias, hostname, test.output);
+            }
+            (true, Some(account), Some(username)) if !account.eq_ignore_ascii_case(username) => {
+                failed += 1;
+                println!(
+                    "{} {} authenticates as '{}', but the profile's username is '{}'",
+                    "Mismatch:".red().bold(),
+                    alias,
+                    account,
+                    username
+                );
+            }
+            (true, Some(account), _) => {
+                println!("{} {} authenticates as '{}'", "OK".green(), alias, account.cyan())
+            }
+            (true, None, _) => println!("{} {}: {}", "OK".green(), alias, test.output),
+        }
+    }
+
+    if failed > 0 {
+        println!();
+        println!(
+            "Run {} if the alias is missing, and check that {} is uploaded to the right account ({})",
+            "gitid ssh-sync".yellow(),
+            ssh_keys::public_key_path(&profile.ssh_key).display(),
+            format!("gitid key upload {}", name).yellow()
+        );
+        bail!("{} connection(s) failed", failed);
+    }
+    if profile.username.is_none() {
+        println!("  Set username on the profile to have gitid check the account");
     }
+
+    Ok(())
 }
 
-fn cmd_detect(auto: bool) -> Result<()> {
-    if !git::is_git_repo() {
-        bail!("Not in a git repository");
+fn cmd_sign_test(name: String) -> Result<()> {
+    let config = Config::load()?;
+    let profile = config
+        .get_profile(&name)
+        .context(format!("Profile '{}' not found", name))?;
+
+    let test = signing::sign_test(&name, profile)?;
+    let format = match test.format {
+        SigningFormat::Gpg => "GPG",
+        SigningFormat::Ssh => "SSH",
+    };
+    let Some((stage, output)) = test.failure else {
+        println!(
+            "{} Signed and verified a commit as '{}' with {} key {}",
+            "OK".green(),
+            name.cyan(),
+            format,
+            test.key
+        );
+        if !test.output.is_empty() {
+            println!("  {}", test.output.lines().collect::<Vec<_>>().join("\n  "));
+        }
+        return Ok(());
+    };
+
+    let step = match stage {
+        signing::Stage::Sign => "Signing",
+        signing::Stage::Verify => "Verifying",
+    };
+    println!("{} {} with {} key {}:", "Failed:".red(), step, format, test.key);
+    for line in output.lines() {
+        println!("  {}", line);
+    }
+    if let Some(hint) = signing::hint(test.format, stage, &output, &profile.email) {
+        println!();
+        println!("{}", hint);
     }
+    bail!("Signing does not work for profile '{}'", name);
+}
 
+fn cmd_whoami(name: Option<String>) -> Result<()> {
     let config = Config::load()?;
 
-    match detect::detect_and_suggest(&config)? {
-        Some((profile_name, reason)) => {
+    // Get profile name: the one applied here, else ask
+    let name = match name.or(prompt::get_current_profile(&config)?) {
+        Some(n) => n,
+        None => {
+            select_profile(&config, "Select profile:", None, "a profile name")?
+        }
+    };
+    let profile = config
+        .get_profile(&name)
+        .context(format!("Profile '{}' not found", name))?;
+
+    let mut failed = 0;
+    for result in auth::whoami(&name, profile)? {
+        let Some(account) = result.account else {
+            failed += 1;
             println!(
-                "{} Detected profile: {}",
-                "Match:".green().bold(),
-                profile_name.cyan().bold()
+                "{} {}: no account; the {} is missing or was rejected",
+                "Failed:".red().bold(),
+                result.platform,
+                result.source
             );
-            println!("  Reason: {}", reason);
+            continue;
+        };
 
-            if auto {
+        println!(
+            "{}: '{}' via the {}",
+            result.platform,
+            account.login.cyan(),
+            result.source
+        );
+        if account.emails.is_empty() {
+            println!("  Emails: hidden from this token, so the profile email wasn't checked");
+        } else {
+            println!("  Emails: {}", account.emails.join(", "));
+        }
+
+        let problems = auth::account_problems(profile, &account);
+        if problems.is_empty() {
+            println!("  {} Matches profile '{}'", "OK".green().bold(), name);
+        } else {
+            failed += 1;
+            for problem in problems {
+                println!("  {} {}", "Mismatch:".red().bold(), problem);
+            }
+        }
+    }
+
+    if failed > 0 {
+        bail!("Credentials for '{}' don't belong to its account", name);
+    }
+    Ok(())
+}
+
+fn cmd_current(porcelain: bool, json: bool) -> Result<()> {
+    if porcelain {
+        if let Some(name) = prompt::cached_current_profile()? {
+            println!("{}", name);
+        }
+        return Ok(());
+    }
+
+    let config = Config::load()?;
+    if json {
+        let identity = prompt::current_identity(&config)?;
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&identity).context("Failed to serialize current identity")?
+        );
+        return Ok(());
+    }
+
+    if !git::is_git_repo() {
+        println!("Not in a git repository");
+        return Ok(());
+    }
+
+    if let Some(profile_name) = prompt::get_current_profile(&config)? {
+        if let Some(profile) = config.get_profile(&profile_name) {
+            println!("Current profile: {}", profile_name);
+            println!("  Name:  {}", profile.name);
+            println!("  Email: {}", profile.email);
+            println!("  Platform: {}", profile.platform);
+        }
+    } else {
+        // Show git config even if no profile matches
+        let (name, email) = git::get_current_user(ConfigScope::Local)?;
+        let (global_name, global_email) = git::get_current_user(ConfigScope::Global)?;
+
+        let name = name.or(global_name);
+        let email = email.or(global_email);
+
+        if name.is_some() || email.is_some() {
+            println!("Current git identity (no matching profile):");
+            if let Some(n) = name {
+                println!("  Name:  {}", n);
+            }
+            if let Some(e) = email {
+                println!("  Email: {}", e);
+            }
+        } else {
+            println!("No git identity configured");
+        }
+    }
+
+    Ok(())
+}
+
+/// Detection result for the current repository, read through the cache,
+/// or the fallback profile when nothing matches; the flag is set for the
+/// fallback
+fn detect_or_default(config: &Config) -> Result<Option<(detect::DetectionResult, bool)>> {
+    if let Some(result) = detect::detect_profile_cached(config)? {
+        return Ok(Some((result, false)));
+    }
+    Ok(detect::fallback_profile(config).map(|(profile_name, reason)| {
+        let result = detect::DetectionResult {
+            profile_name,
+            score: 0,
+            reason,
+            tied: Vec::new(),
+        };
+        (result, true)
+    }))
+}
+
+fn cmd_status() -> Result<()> {
+    if !git::is_git_repo() {
+        bail!("Not in a git repository");
+    }
+
+    let config = Config::load()?;
+    let identity = prompt::current_identity(&config)?;
+    let expected = detect_or_default(&config)?;
+
+    if let Some(root) = git::repo_root()? {
+        println!("Repository: {}", root.display());
+    }
+
+    let applied = match (&identity.name, &identity.email) {
+        (None, None) => "none".to_string(),
+        (name, email) => format!(
+            "{} <{}>",
+            name.as_deref().unwrap_or("unset"),
+            email.as_deref().unwrap_or("unset")
+        ),
+    };
+    let source = match identity.scope {
+        Some(ConfigScope::Local) => " from the local config",
+        Some(ConfigScope::Global) => " from the global config",
+        None => "",
+    };
+    match identity.profile {
+        Some(ref profile) => println!("  Applied:  {} {}{}", profile.cyan(), applied, source),
+        None => println!("  Applied:  {} (no matching profile){}", applied, source),
+    }
+    match expected {
+        Some((ref result, _)) if !result.tied.is_empty() => {
+            let names: Vec<&str> = result.candidates().iter().map(|c| c.profile_name.as_str()).collect();
+            println!("  Expected: one of {} (tie at score {})", names.join(", ").cyan(), result.score);
+        }
+        Some((ref result, _)) => println!("  Expected: {} ({})", result.profile_name.cyan(), result.reason),
+        None => println!("  Expected: none (no profile matches this repository)"),
+    }
+    println!("  Signing:  {}", identity.signing);
+
+    let remotes = git::remote_urls()?;
+    for (remote, url) in &remotes {
+        // Name the profile whose SSH alias the remote goes through, if any
+        let alias = git::RemoteUrl::parse(url).and_then(|parsed| {
+            config.profiles.iter().find_map(|(name, profile)| {
+                profile
+                    .ssh_host_aliases(name)
+                    .into_iter()
+                    .any(|(alias, _)| alias == parsed.host)
+                    .then(|| format!("alias {} of '{}'", parsed.host, name))
+            })
+        });
+        println!(
+            "  Remote:   {} {} ({})",
+            remote,
+            url,
+            alias.unwrap_or_else(|| "no gitid alias".to_string())
+        );
+    }
+    if remotes.is_empty() {
+        println!("  Remote:   none");
+    }
+    if let Some(command) = git::get_effective_config("core.sshCommand")? {
+        println!("  SSH:      {}", command);
+    }
+
+    let violations = policy::evaluate_repository(&config)?;
+    for violation in &violations {
+        println!("  Policy:   {} {}", "BROKEN".red().bold(), violation);
+    }
+
+    println!();
+    // On a tie any of the tied profiles is as expected as the others; with
+    // none of them applied there is no one profile to recommend
+    let expected = match expected {
+        Some((result, _)) if !result.tied.is_empty() => {
+            let candidates = result.candidates();
+            let applied = candidates
+                .iter()
+                .find(|c| Some(c.profile_name.as_str()) == identity.profile.as_deref());
+            match applied {
+                Some(candidate) => {
+                    println!("{} {}", "Warning:".yellow().bold(), result.tie_message().unwrap_or_default());
+                    Some(candidate.profile_name.clone())
+                }
+                None => {
+                    let names: Vec<String> = candidates.iter().map(|c| format!("'{}'", c.profile_name)).collect();
+                    println!(
+                        "{} Detection can't choose between {} and the applied identity is none of them",
+                        "TIE".red().bold(),
+                        names.join(", ")
+                    );
+                    println!("  Run {} to choose one, then apply it", "gitid pin <profile>".yellow());
+                    bail!("Detection tie is unresolved");
+                }
+            }
+        }
+        expected => expected.map(|(result, _)| result.profile_name),
+    };
+    match audit::compare(
+        &config,
+        expected.as_deref(),
+        identity.name.as_deref(),
+        identity.email.as_deref(),
+    ) {
+        audit::Status::Ok => println!("{} The applied identity is the expected one", "OK".green().bold()),
+        audit::Status::Undetected => println!(
+            "{} No profile matches this repository; add a rule or a default profile to check it",
+            "Unknown:".yellow().bold()
+        ),
+        audit::Status::Mismatch => {
+            let expected = expected.unwrap_or_default();
+            println!(
+                "{} Detection expects '{}' but the applied identity is {}",
+                "MISMATCH".red().bold(),
+                expected,
+                identity.profile.map_or(applied, |p| format!("'{}'", p))
+            );
+            println!("  Run {} to fix it", format!("gitid use {}", expected).yellow());
+            bail!("Applied identity does not match the expected profile");
+        }
+    }
+    if !violations.is_empty() {
+        bail!("Applied identity breaks {} policy requirement(s)", violations.len());
+    }
+    Ok(())
+}
+
+/// Print every profile's score against each remote, factor by factor, and
+/// what detection makes of them
+fn explain_detection(config: &Config) -> Result<()> {
+    let explanation = detect::explain(config)?;
+
+    if explanation.remotes.is_empty() {
+        println!("No remotes to score");
+    }
+    for remote in &explanation.remotes {
+        println!("{} {}", format!("Remote {}:", remote.remote).bold(), remote.url);
+        let name_width = remote
+            .scores
+            .iter()
+            .map(|s| s.profile_name.len())
+            .chain(["PROFILE".len()])
+            .max()
+            .unwrap_or(0);
+        println!("  {:<w$}  SCORE  FACTORS", "PROFILE", w = name_width);
+        for score in &remote.scores {
+            let factors: Vec<String> = score
+                .factors
+                .iter()
+                .map(|f| format!("+{} {}", f.points, f.label))
+                .collect();
+            let name_cell = format!("{:<w$}", score.profile_name, w = name_width);
+            if factors.is_empty() {
+                println!("  {}  {:>5}  {}", name_cell.dimmed(), 0, "no match".dimmed());
+            } else {
+                println!("  {}  {:>5}  {}", name_cell.cyan(), score.score(), factors.join(", "));
+            }
+        }
+        println!();
+    }
+
+    if let Some((name, factor)) = &explanation.history {
+        println!("{} +{} for {} ({})", "History:".bold(), factor.points, name.cyan(), factor.label);
+    }
+    if let Some(pin) = &explanation.pin {
+        println!("{} {} ({})", "Pinned:".bold(), pin.profile_name.cyan(), pin.reason);
+    }
+
+    match &explanation.result {
+        Some(result) if !result.tied.is_empty() => {
+            let names: Vec<String> = result.candidates().iter().map(|c| c.profile_name.clone()).collect();
+            println!(
+                "{} {} tie at score {}",
+                "Result:".yellow().bold(),
+                names.join(", "),
+                result.score
+            );
+        }
+        Some(result) => println!(
+            "{} {} with score {} ({})",
+            "Result:".green().bold(),
+            result.profile_name.cyan().bold(),
+            result.score,
+            result.reason
+        ),
+        None => match detect::fallback_profile(config) {
+            Some((name, reason)) => println!("{} {} ({})", "Result:".yellow().bold(), name.cyan().bold(), reason),
+            None => println!("{} No profile matches", "Result:".yellow().bold()),
+        },
+    }
+    Ok(())
+}
+
+/// Show the profiles detection can't choose between and ask which to use.
+/// With --auto or without a terminal this fails naming them, rather than
+/// picking one; `flag` is what to pass instead.
+fn pick_tied(config: &Config, result: &detect::DetectionResult, auto: bool, flag: &str) -> Result<String> {
+    let candidates = result.candidates();
+    println!("{} These profiles match this repository equally:", "Tie:".yellow().bold());
+    let width = candidates.iter().map(|c| c.profile_name.len()).max().unwrap_or(0);
+    for candidate in &candidates {
+        println!(
+            "  {}  score {}  {}",
+            format!("{:<w$}", candidate.profile_name, w = width).cyan(),
+            candidate.score,
+            candidate.reason
+        );
+    }
+
+    let message = result.tie_message().unwrap_or_default();
+    if auto || !input::is_interactive() {
+        bail!("{}", message);
+    }
+
+    println!();
+    let names: Vec<&String> = candidates.iter().map(|c| &c.profile_name).collect();
+    select_profile_from(config, &names, "Select profile:", None, flag)
+}
+
+/// Pick one of the tied profiles for the current repository, offering to
+/// pin it so detection stops asking
+fn resolve_tie(config: &Config, result: &detect::DetectionResult, auto: bool) -> Result<String> {
+    let name = pick_tied(config, result, auto, "a profile to 'gitid use'")?;
+    let pin = input::confirm(
+        Confirm::new(&format!("Pin '{}' to this repository?", name))
+            .with_help_message("Detection then picks it here without asking")
+            .with_default(true),
+    )?;
+    if pin {
+        git::set_config(detect::PIN_KEY, &name, ConfigScope::Local)?;
+        println!("{} Pinned '{}' to this repository", "Success:".green().bold(), name);
+    }
+    Ok(name)
+}
+
+fn cmd_detect(auto: bool, explain: bool) -> Result<()> {
+    if !git::is_git_repo() {
+        bail!("Not in a git repository");
+    }
+
+    let config = Config::load()?;
+    if explain {
+        return explain_detection(&config);
+    }
+
+    match detect_or_default(&config)? {
+        Some((result, _)) if !result.tied.is_empty() => {
+            let name = resolve_tie(&config, &result, auto)?;
+            let profile = config
+                .get_profile(&name)
+                .context(format!("Profile '{}' not found", name))?;
+            git::apply_profile(&config, &name, profile, ConfigScope::Local)?;
+            println!("{} Applied profile '{}'", "Success:".green().bold(), name);
+        }
+        Some((result, fallback)) => {
+            let (profile_name, reason) = (result.profile_name, result.reason);
+            if fallback {
+                println!("No matching profile detected for this repository");
+                if let Some(url) = git::get_remote_url("origin")? {
+                    println!("  Remote origin: {}", url);
+                }
+                println!(
+                    "{} Using {}",
+                    "Fallback:".yellow().bold(),
+                    profile_name.cyan().bold()
+                );
+            } else {
+                println!(
+                    "{} Detected profile: {}",
+                    "Match:".green().bold(),
+                    profile_name.cyan().bold()
+                );
+            }
+            println!("  Reason: {}", reason);
+
+            if auto {
                 // Auto-apply
                 if let Some(profile) = config.get_profile(&profile_name) {
-                    git::apply_profile(
-                        &profile.name,
-                        &profile.email,
-                        profile.gpg_key.as_deref(),
-                        ConfigScope::Local,
-                    )?;
+                    git::apply_profile(&config, &profile_name, profile, ConfigScope::Local)?;
                     println!();
                     println!(
                         "{} Applied profile '{}'",
@@ -469,19 +2374,11 @@ fn cmd_detect(auto: bool) -> Result<()> {
                     );
                 }
             } else {
-                // Ask for confirmation using inquire
-                let confirmed = Confirm::new("Apply this profile?")
-                    .with_default(true)
-                    .prompt()?;
+                let confirmed = input::confirm(Confirm::new("Apply this profile?").with_default(true))?;
 
                 if confirmed {
                     if let Some(profile) = config.get_profile(&profile_name) {
-                        git::apply_profile(
-                            &profile.name,
-                            &profile.email,
-                            profile.gpg_key.as_deref(),
-                            ConfigScope::Local,
-                        )?;
+                        git::apply_profile(&config, &profile_name, profile, ConfigScope::Local)?;
                         println!(
                             "{} Applied profile '{}'",
                             "Success:".green().bold(),
@@ -503,22 +2400,16 @@ fn cmd_detect(auto: bool) -> Result<()> {
 
             if !config.profiles.is_empty() {
                 println!();
-                let apply_manually = Confirm::new("Would you like to select a profile manually?")
-                    .with_default(true)
-                    .prompt()?;
+                let apply_manually = input::is_interactive()
+                    && input::confirm(
+                        Confirm::new("Would you like to select a profile manually?").with_default(true),
+                    )?;
 
                 if apply_manually {
-                    let profiles: Vec<String> =
-                        config.profile_names().into_iter().cloned().collect();
-                    let name = Select::new("Select profile:", profiles).prompt()?;
+                    let name = select_profile(&config, "Select profile:", None, "a profile to 'gitid use'")?;
 
                     if let Some(profile) = config.get_profile(&name) {
-                        git::apply_profile(
-                            &profile.name,
-                            &profile.email,
-                            profile.gpg_key.as_deref(),
-                            ConfigScope::Local,
-                        )?;
+                        git::apply_profile(&config, &name, profile, ConfigScope::Local)?;
                         println!(
                             "{} Applied profile '{}'",
                             "Success:".green().bold(),
@@ -533,7 +2424,111 @@ fn cmd_detect(auto: bool) -> Result<()> {
     Ok(())
 }
 
-fn cmd_ssh_sync() -> Result<()> {
+fn cmd_clone(
+    url: String,
+    directory: Option<std::path::PathBuf>,
+    profile_name: Option<String>,
+    args: Vec<String>,
+) -> Result<()> {
+    let config = Config::load()?;
+    if config.profiles.is_empty() {
+        bail!("No profiles configured. Run 'gitid add' first.");
+    }
+
+    let name = match profile_name {
+        Some(n) => n,
+        None => match detect::detect_for_url(&config, &url) {
+            Some(result) if !result.tied.is_empty() => {
+                // There is no repository to pin a choice to yet
+                if !input::is_interactive() {
+                    let names: Vec<String> =
+                        result.candidates().iter().map(|c| format!("'{}'", c.profile_name)).collect();
+                    bail!("Profiles {} match {} equally; pass --profile to choose one", names.join(", "), url);
+                }
+                pick_tied(&config, &result, false, "--profile")?
+            }
+            Some(result) => {
+                println!(
+                    "{} Detected profile: {} ({})",
+                    "Match:".green().bold(),
+                    result.profile_name.cyan().bold(),
+                    result.reason
+                );
+                result.profile_name
+            }
+            None => {
+                select_profile(&config, "No profile detected. Select profile:", None, "--profile")?
+            }
+        },
+    };
+    let profile = config
+        .get_profile(&name)
+        .context(format!("Profile '{}' not found", name))?;
+
+    let parsed = git::RemoteUrl::parse(&url).with_context(|| format!("Unrecognised repository URL: {}", url))?;
+    let remotes = vec![("origin".to_string(), url.clone())];
+    remote::enforce_owners(&name, profile, &remotes)?;
+
+    // Clone through the alias, or with the profile's sshCommand or
+    // insteadOf rules, so the profile's key is used from the start
+    let command_mode = config.ssh_mode(profile) == SshMode::Command;
+    let insteadof = config.ssh_mode(profile) == SshMode::InsteadOf;
+    let clone_url = match remote::plan_alias_rewrites(&name, profile, &remotes).pop() {
+        Some(rewrite) if !command_mode && !insteadof => rewrite.to,
+        _ => url.clone(),
+    };
+
+    let directory = match directory {
+        Some(dir) => dir,
+        None => std::path::PathBuf::from(parsed.repo_name().context("Could not tell the repository name from the URL; pass a directory")?),
+    };
+
+    let mut clone = std::process::Command::new("git");
+    clone.arg("clone");
+    if command_mode {
+        // -c is kept in the new repository's config as well
+        clone.arg("-c").arg(format!("core.sshCommand={}", env::ssh_command(profile)));
+    }
+    if insteadof {
+        for (key, value) in remote::insteadof_rules(&name, profile) {
+            clone.arg("-c").arg(format!("{}={}", key, value));
+        }
+    }
+    let status = clone
+        .args(&args)
+        .arg(&clone_url)
+        .arg(&directory)
+        .status()
+        .context("Failed to run git clone")?;
+    if !status.success() {
+        bail!("git clone failed");
+    }
+
+    std::env::set_current_dir(&directory)
+        .with_context(|| format!("Failed to enter {}", directory.display()))?;
+    git::apply_profile(&config, &name, profile, ConfigScope::Local)?;
+
+    println!();
+    println!(
+        "{} Cloned into {} with profile '{}'",
+        "Success:".green().bold(),
+        directory.display(),
+        name.cyan()
+    );
+    println!("  Remote: {}", clone_url);
+    if command_mode {
+        println!("  SSH:    {}", env::ssh_command(profile));
+    }
+    if insteadof {
+        println!("  SSH:    url.insteadOf routes it through {}", profile.ssh_host_alias(&name));
+    }
+    println!("  Name:   {}", profile.name);
+    println!("  Email:  {}", profile.email);
+
+    Ok(())
+}
+
+fn cmd_ssh_sync(dry_run: bool) -> Result<()> {
     let config = Config::load()?;
 
     if config.profiles.is_empty() {
@@ -541,6 +2536,27 @@ fn cmd_ssh_sync() -> Result<()> {
         return Ok(());
     }
 
+    match ssh::managed_block_diff(&config)? {
+        Some(diff) => {
+            print_diff(&diff);
+            println!();
+            if dry_run {
+                println!("Dry run: SSH config not changed");
+                return Ok(());
+            }
+            let message = format!("Write these changes to {}?", ssh::managed_config_path(&config)?.display());
+            if !input::approve(Confirm::new(&message).with_default(true))? {
+                println!("Cancelled");
+                return Ok(());
+            }
+        }
+        None if dry_run => {
+            println!("SSH config is up to date");
+            return Ok(());
+        }
+        None => {}
+    }
+
     let (count, was_update) = ssh::sync_ssh_config(&config)?;
 
     let action = if was_update { "Updated" } else { "Added" };
@@ -551,8 +2567,14 @@ fn cmd_ssh_sync() -> Result<()> {
         count
     );
 
-    let path = ssh::ssh_config_path()?;
+    let path = ssh::managed_config_path(&config)?;
     println!("  File: {}", path.display());
+    if config.ssh_layout == Some(ssh::SshLayout::Include) {
+        println!("  Included from: {}", ssh::ssh_config_path()?.display());
+    }
+    if config.profiles.values().any(|p| p.signing == Some(SigningFormat::Ssh)) {
+        println!("  Allowed signers: {}", ssh::allowed_signers_path()?.display());
+    }
 
     // Show the generated aliases
     println!();
@@ -566,3 +2588,1884 @@ fn cmd_ssh_sync() -> Result<()> {
 
     Ok(())
 }
+
+fn cmd_ssh_restore(stamp: Option<u64>, list: bool) -> Result<()> {
+    // Backups of both layouts' files, newest first
+    let mut backups = Vec::new();
+    for path in [ssh::ssh_config_path()?, ssh::include_path()?] {
+        for (taken, backup) in ssh::list_backups(&path)? {
+            backups.push((taken, path.clone(), backup));
+        }
+    }
+    backups.sort_by_key(|b| std::cmp::Reverse(b.0));
+    if backups.is_empty() {
+        bail!("No SSH config backups found. Set ssh_backups in the config to have ssh-sync take them");
+    }
+
+    if list {
+        for (taken, path, backup) in &backups {
+            println!(
+                "{}  {}  {} {}",
+                taken.to_string().cyan(),
+                path.display(),
+                backup.display(),
+                format!("({})", journal::format_age(*taken)).dimmed()
+            );
+        }
+        return Ok(());
+    }
+
+    let stamp = stamp.unwrap_or(backups[0].0);
+    let files: Vec<String> = backups
+        .iter()
+        .filter(|(taken, ..)| *taken == stamp)
+        .map(|(_, path, _)| path.display().to_string())
+        .collect();
+    if files.is_empty() {
+        bail!("No backup taken at {}; run 'gitid ssh-restore --list'", stamp);
+    }
+
+    let message = format!(
+        "Restore {} from the backup taken {}?",
+        files.join(" and "),
+        journal::format_age(stamp)
+    );
+    if !input::approve(Confirm::new(&message).with_default(false))? {
+        println!("Cancelled");
+        return Ok(());
+    }
+
+    for path in ssh::restore_backups(stamp)? {
+        println!("{} Restored {}", "Success:".green().bold(), path.display());
+    }
+    println!("Run {} to undo the restore", "gitid rollback".yellow());
+    Ok(())
+}
+
+/// Print a unified diff with removed lines red and added lines green
+fn print_diff(diff: &str) {
+    for line in diff.lines() {
+        if line.starts_with("+++") || line.starts_with("---") {
+            println!("{}", line.bold());
+        } else if line.starts_with("@@") {
+            println!("{}", line.cyan());
+        } else if line.starts_with('+') {
+            println!("{}", line.green());
+        } else if line.starts_with('-') {
+            println!("{}", line.red());
+        } else {
+            println!("{}", line);
+        }
+    }
+}
+
+fn cmd_env(name: String) -> Result<()> {
+    let config = Config::load()?;
+    let profile = config
+        .get_profile(&name)
+        .context(format!("Profile '{}' not found", name))?;
+
+    for (key, value) in env::profile_env(&name, profile) {
+        println!("export {}={}", key, env::shell_quote(&value));
+    }
+
+    Ok(())
+}
+
+fn cmd_devcontainer(
+    name: String,
+    keys: devcontainer::KeyMode,
+    format: devcontainer::Format,
+    home: String,
+) -> Result<()> {
+    let config = Config::load()?;
+    let profile = config
+        .get_profile(&name)
+        .context(format!("Profile '{}' not found", name))?;
+
+    let agent_socket = match (keys, format) {
+        (devcontainer::KeyMode::Mount, _) => String::new(),
+        // VS Code expands this on the host when starting the container
+        (_, devcontainer::Format::Json) => "${localEnv:SSH_AUTH_SOCK}".to_string(),
+        (_, devcontainer::Format::Docker) => std::env::var("SSH_AUTH_SOCK")
+            .context("SSH_AUTH_SOCK is not set. Start ssh-agent or use --keys mount.")?,
+    };
+
+    let spec = devcontainer::container_spec(&name, profile, keys, &agent_socket, &home)?;
+    match format {
+        devcontainer::Format::Json => println!("{}", devcontainer::render_json(&spec)?),
+        devcontainer::Format::Docker => println!("{}", devcontainer::render_docker(&spec)),
+    }
+
+    Ok(())
+}
+
+fn cmd_remote_fix(name: Option<String>, restore: bool, dry_run: bool) -> Result<()> {
+    if !git::is_git_repo() {
+        bail!("Not in a git repository");
+    }
+    let config = Config::load()?;
+    let remotes = git::remote_urls()?;
+    if remotes.is_empty() {
+        println!("No remotes configured");
+        return Ok(());
+    }
+
+    let rewrites = if restore {
+        remote::plan_canonical_rewrites(&config, &remotes)
+    } else {
+        let name = match name {
+            Some(name) => name,
+            None => match detect_or_default(&config)? {
+                Some((result, _)) if !result.tied.is_empty() => resolve_tie(&config, &result, false)?,
+                Some((result, _)) => {
+                    println!("Using '{}' ({})", result.profile_name.cyan(), result.reason);
+                    result.profile_name
+                }
+                None => select_profile(&config, "Select profile:", None, "--profile")?,
+            },
+        };
+        let profile = config
+            .get_profile(&name)
+            .context(format!("Profile '{}' not found", name))?;
+        remote::enforce_owners(&name, profile, &remotes)?;
+
+        if config.ssh_mode(profile) != SshMode::Alias {
+            // core.sshCommand or insteadOf rules pick the key, so remotes
+            // belong on the real host
+            println!("Profile '{}' does not rewrite remotes (ssh_mode); using real hostnames", name);
+            remote::plan_canonical_rewrites(&config, &remotes)
+        } else {
+            remote::plan_fix_rewrites(&config, &name, profile, &remotes)
+        }
+    };
+
+    if rewrites.is_empty() {
+        println!("{} Remotes already point at the right host", "OK".green());
+        return Ok(());
+    }
+    for rewrite in &rewrites {
+        println!("  {}: {} -> {}", rewrite.remote, rewrite.from, rewrite.to.cyan());
+    }
+    if dry_run {
+        return Ok(());
+    }
+
+    let confirmed = input::confirm(Confirm::new("Rewrite these remotes?").with_default(true))?;
+    if !confirmed {
+        println!("Cancelled");
+        return Ok(());
+    }
+    remote::apply_rewrites(&rewrites)?;
+    println!("{} Rewrote {} remote(s)", "Success:".green().bold(), rewrites.len());
+    Ok(())
+}
+
+fn cmd_remote_install(
+    target: String,
+    profiles: Vec<String>,
+    include_keys: bool,
+    no_verify: bool,
+) -> Result<()> {
+    let config = Config::load()?;
+    let names = if profiles.is_empty() {
+        config.profile_names().into_iter().cloned().collect()
+    } else {
+        profiles
+    };
+
+    if names.is_empty() {
+        bail!("No profiles configured");
+    }
+
+    let (remote_config, uploads) = bootstrap::remote_config(&config, &names, include_keys)?;
+
+    println!("{} Installing {} profile(s) on {}", "gitid".bold(), names.len(), target);
+    if include_keys {
+        println!(
+            "{} Private keys will be copied to {}",
+            "Warning:".yellow().bold(),
+            target
+        );
+    }
+
+    bootstrap::install_files(&target, &remote_config, &uploads)?;
+    println!("  {} {} key file(s) and gitid config", "Copied".green(), uploads.len());
+
+    bootstrap::sync_ssh_config(&target, &remote_config)?;
+    println!("  {} remote ~/.ssh/config", "Updated".green());
+
+    if let Some(default) = remote_config
+        .default_profile
+        .as_ref()
+        .and_then(|d| remote_config.get_profile(d))
+    {
+        bootstrap::apply_git_identity(&target, &default.name, &default.email)?;
+        println!("  {} global identity {} <{}>", "Applied".green(), default.name, default.email);
+    }
+
+    if no_verify {
+        return Ok(());
+    }
+
+    let mut failed = 0;
+    for name in &names {
+        if let Some(profile) = remote_config.get_profile(name) {
+            let alias = profile.ssh_host_alias(name);
+            let test = bootstrap::test_connection(&target, &alias)?;
+            if test.authenticated {
+                println!("  {} {} via {}", "Verified".green(), name, alias);
+            } else {
+                failed += 1;
+                println!("  {} {} via {}: {}", "Failed".red(), name, alias, test.output);
+            }
+        }
+    }
+
+    if failed > 0 {
+        println!();
+        println!(
+            "{} {} profile(s) could not authenticate from {}. Check that your agent holds the keys (ssh-add -l) or use --include-keys.",
+            "Warning:".yellow().bold(),
+            failed,
+            target
+        );
+    }
+
+    Ok(())
+}
+
+fn cmd_exec(name: String, command: Vec<String>) -> Result<()> {
+    let config = Config::load()?;
+    let profile = config
+        .get_profile(&name)
+        .context(format!("Profile '{}' not found", name))?;
+
+    let (program, args) = command.split_first().context("No command given")?;
+    let mut cmd = std::process::Command::new(program);
+    cmd.args(args).envs(env::profile_env(&name, profile));
+
+    // gh and most GitHub tooling pick the token up from GH_TOKEN
+    if profile.github_app.is_some() {
+        cmd.env("GH_TOKEN", auth::github_app::installation_token(&name, profile)?);
+    }
+
+    let status = cmd
+        .status()
+        .with_context(|| format!("Failed to run {}", program))?;
+
+    std::process::exit(status.code().unwrap_or(1));
+}
+
+fn cmd_setup_repo() -> Result<()> {
+    if !git::is_git_repo() {
+        bail!("Not in a git repository");
+    }
+
+    let config = Config::load()?;
+    if config.profiles.is_empty() {
+        bail!("No profiles configured. Run 'gitid add' first.");
+    }
+
+    // 1. Pick the profile
+    println!("{}", "Profile".bold());
+    let detected = match detect::detect_and_suggest(&config)? {
+        Some(result) if !result.tied.is_empty() => Some(resolve_tie(&config, &result, false)?),
+        Some(result) => {
+            println!("  Detected {} ({})", result.profile_name.cyan().bold(), result.reason);
+            let confirmed = input::confirm(
+                Confirm::new(&format!("Use profile '{}'?", result.profile_name)).with_default(true),
+            )?;
+            confirmed.then_some(result.profile_name)
+        }
+        None => {
+            println!("  No matching profile detected");
+            None
+        }
+    };
+    let name = match detected {
+        Some(name) => name,
+        None => select_profile(&config, "Select profile:", None, "'gitid use <profile>' first")?,
+    };
+    let profile = config
+        .get_profile(&name)
+        .context(format!("Profile '{}' not found", name))?;
+    let remotes = git::remote_urls()?;
+    remote::enforce_owners(&name, profile, &remotes)?;
+    warn_policy_violations(&config, &remotes, &name, profile);
+
+    // 2. Apply the identity
+    git::apply_profile(&config, &name, profile, ConfigScope::Local)?;
+    println!(
+        "  {} {} <{}>",
+        "Applied".green(),
+        profile.name,
+        profile.email
+    );
+    warn_history_mismatch(&config, &name)?;
+
+    // 3. Point remotes at the profile's SSH alias
+    println!();
+    println!("{}", "Remotes".bold());
+    let rewrites = remote::plan_alias_rewrites(&name, profile, &remotes);
+    if config.ssh_mode(profile) == SshMode::Command {
+        apply_ssh_command(profile, true, ConfigScope::Local)?;
+        println!("  {} core.sshCommand; remotes keep their URLs", "Set".green());
+    } else if config.ssh_mode(profile) == SshMode::InsteadOf {
+        remote::apply_insteadof(&config, &name, profile, true, ConfigScope::Local)?;
+        println!("  {} url.insteadOf rules; remotes keep their URLs", "Set".green());
+    } else if rewrites.is_empty() {
+        println!("  Remotes already use the profile's SSH alias (or none match)");
+    } else {
+        for rewrite in &rewrites {
+            println!("  {}: {} -> {}", rewrite.remote, rewrite.from, rewrite.to.cyan());
+        }
+        let confirmed = input::confirm(Confirm::new("Rewrite these remotes?").with_default(true))?;
+        if confirmed {
+            remote::apply_rewrites(&rewrites)?;
+            println!("  {} {} remote(s)", "Rewrote".green(), rewrites.len());
+        }
+    }
+
+    // 4. Install guard hooks
+    println!();
+    println!("{}", "Hooks".bold());
+    let install_hooks = input::confirm(
+        Confirm::new("Install the pre-commit identity guard?")
+            .with_help_message("Blocks commits whose email doesn't match the detected profile")
+            .with_default(true),
+    )?;
+    if install_hooks {
+        match hooks::install(hooks::HookKind::PreCommit, false) {
+            Ok(path) => println!("  {} {}", "Installed".green(), path.display()),
+            Err(e) => println!("  {} {}", "Skipped:".yellow(), e),
+        }
+    }
+
+    // 5. Verify SSH authentication
+    println!();
+    println!("{}", "SSH".bold());
+    let alias = profile.ssh_host_alias(&name);
+    let test_ssh =
+        input::confirm(Confirm::new(&format!("Test SSH connection to {}?", alias)).with_default(true))?;
+    if test_ssh {
+        let result = ssh::test_connection(&alias)?;
+        if result.authenticated {
+            println!("  {} {}", "OK".green(), result.output);
+        } else {
+            println!("  {} {}", "Failed".red(), result.output);
+            println!("  Run {} and check the key is uploaded", "gitid ssh-sync".yellow());
+        }
+    }
+
+    // 6. Verify signing
+    println!();
+    println!("{}", "Signing".bold());
+    let gnupghome = profile.gnupghome.as_deref();
+    if let Some(home) = gnupghome {
+        if !ssh_keys::expand_path(home).is_dir() {
+            println!(
+                "  {} GNUPGHOME {} does not exist",
+                "Warning:".yellow().bold(),
+                home
+            );
+        }
+    }
+    match profile.signing_key() {
+        Some((SigningFormat::Gpg, key)) if gpg::has_secret_key(&key, gnupghome) => {
+            println!("  {} GPG key {} found in keyring", "OK".green(), key)
+        }
+        Some((SigningFormat::Gpg, key)) => println!(
+            "  {} GPG key {} not found in keyring; signed commits will fail",
+            "Warning:".yellow().bold(),
+            key
+        ),
+        Some((SigningFormat::Ssh, key)) if std::path::Path::new(&key).exists() => {
+            println!("  {} Signing with SSH key {}", "OK".green(), key)
+        }
+        Some((SigningFormat::Ssh, key)) => println!(
+            "  {} SSH public key {} not found; signed commits will fail",
+            "Warning:".yellow().bold(),
+            key
+        ),
+        None => println!("  Signing not configured for this profile"),
+    }
+    if gnupghome.is_some() {
+        println!(
+            "  Note: git only signs with this keyring under {}; a plain 'git commit' uses the default one",
+            format!("gitid exec {} -- git commit", name).yellow()
+        );
+    }
+
+    println!();
+    println!(
+        "{} Repository set up with profile '{}'",
+        "Success:".green().bold(),
+        name.cyan()
+    );
+
+    Ok(())
+}
+
+fn cmd_hook_install(mut kinds: Vec<hooks::HookKind>, pre_push: bool, force: bool) -> Result<()> {
+    if !git::is_git_repo() {
+        bail!("Not in a git repository");
+    }
+
+    if pre_push && !kinds.contains(&hooks::HookKind::PrePush) {
+        kinds.push(hooks::HookKind::PrePush);
+    }
+
+    for kind in kinds {
+        let path = hooks::install(kind, force)?;
+        println!(
+            "{} Installed {} hook: {}",
+            "Success:".green().bold(),
+            kind.file_name(),
+            path.display()
+        );
+    }
+
+    Ok(())
+}
+
+fn cmd_rollback(count: usize, list: bool, force: bool) -> Result<()> {
+    let journal = journal::Journal::load()?;
+    let operations = journal.operations();
+
+    if operations.is_empty() {
+        println!("No recorded changes to roll back");
+        return Ok(());
+    }
+
+    let shown = if list {
+        &operations[..]
+    } else {
+        &operations[..count.min(operations.len())]
+    };
+
+    for op in shown {
+        println!(
+            "{} {}",
+            op.command.cyan(),
+            format!("({})", journal::format_age(op.timestamp)).dimmed()
+        );
+        for change in &op.changes {
+            println!("    {}", change.describe());
+        }
+    }
+
+    if list {
+        return Ok(());
+    }
+
+    if !force {
+        println!();
+        let message = format!("Undo {} operation(s)?", shown.len());
+        let confirmed = input::approve(Confirm::new(&message).with_default(false))?;
+
+        if !confirmed {
+            println!("Cancelled");
+            return Ok(());
+        }
+    }
+
+    let reverted = journal::rollback(count)?;
+    println!(
+        "{} Rolled back {} operation(s)",
+        "Success:".green().bold(),
+        reverted.len()
+    );
+
+    Ok(())
+}
+
+fn cmd_netrc_sync() -> Result<()> {
+    let config = Config::load()?;
+    let (written, skipped) = netrc::sync_netrc(&config)?;
+
+    println!(
+        "{} Synced {} with {} profile(s)",
+        "Success:".green().bold(),
+        netrc::netrc_path()?.display(),
+        written.len()
+    );
+    for name in &skipped {
+        println!(
+            "  {} '{}' has netrc enabled but no token; run {}",
+            "Skipped:".yellow(),
+            name,
+            format!("gitid token set {}", name).yellow()
+        );
+    }
+    if written.is_empty() && skipped.is_empty() {
+        println!("  No profiles have netrc = true");
+    }
+
+    Ok(())
+}
+
+fn cmd_gitconfig_sync() -> Result<()> {
+    let config = Config::load()?;
+    let (fragments, rules) = gitconfig::sync_gitconfig(&config)?;
+
+    println!(
+        "{} Wrote {} fragment(s) to {} and {} includeIf rule(s) to {}",
+        "Success:".green().bold(),
+        fragments,
+        gitconfig::fragments_dir()?.display(),
+        rules,
+        git::global_config_path()?.display()
+    );
+    if config.detect.directories.is_empty() {
+        println!(
+            "  No directory rules yet; add them under {} in config.toml",
+            "[detect.directories]".yellow()
+        );
+    }
+
+    Ok(())
+}
+
+fn cmd_migrate(remove: bool, dry_run: bool) -> Result<()> {
+    let mut config = Config::load()?;
+    let plan = migrate::plan(&config)?;
+
+    for (key, reason) in &plan.skipped {
+        println!("{} Skipping {}: {}", "Warning:".yellow().bold(), key, reason);
+    }
+    if plan.includes.is_empty() {
+        println!(
+            "No includeIf \"gitdir:...\" sections to migrate in {}",
+            git::global_config_path()?.display()
+        );
+        return Ok(());
+    }
+
+    for (name, profile) in &plan.profiles {
+        println!("  Profile {}: {} <{}>, key {}", name.cyan(), profile.name, profile.email, profile.ssh_key);
+    }
+    for include in &plan.includes {
+        println!(
+            "  Rule {} = {} (from includeIf \"{}\" -> {})",
+            include.pattern.cyan(),
+            include.profile,
+            include.condition,
+            include.path
+        );
+    }
+    if dry_run {
+        println!("Run without {} to import them", "--dry-run".yellow());
+        return Ok(());
+    }
+
+    migrate::apply(&plan, &mut config)?;
+    config.save()?;
+    println!(
+        "{} Imported {} profile(s) and {} directory rule(s)",
+        "Success:".green().bold(),
+        plan.profiles.len(),
+        plan.includes.len()
+    );
+    if !plan.profiles.is_empty() {
+        println!("  New profiles are set up for GitHub; edit config.toml for other platforms or hosts");
+    }
+
+    if remove {
+        // Keep the directories covered: gitid's managed includes replace the old ones
+        migrate::remove_includes(&plan)?;
+        ssh::sync_ssh_config(&config)?;
+        let (fragments, rules) = gitconfig::sync_gitconfig(&config)?;
+        println!(
+            "  Replaced {} includeIf section(s) with {} managed rule(s) and {} fragment(s)",
+            plan.includes.len(),
+            rules,
+            fragments
+        );
+    } else {
+        println!(
+            "Run {} to replace the old includeIf sections with gitid's",
+            "gitid migrate --remove".yellow()
+        );
+    }
+    Ok(())
+}
+
+fn cmd_token_set(name: String) -> Result<()> {
+    let config = Config::load()?;
+    let profile = config
+        .get_profile(&name)
+        .context(format!("Profile '{}' not found", name))?;
+
+    let token = read_secret("Token:", false)?;
+    if token.is_empty() {
+        bail!("Token cannot be empty");
+    }
+
+    secrets::set_token(profile.keyring_account(&name), &token)?;
+    println!(
+        "{} Stored token for '{}' in the OS keyring",
+        "Success:".green().bold(),
+        name
+    );
+
+    report_token_owner(profile, &token);
+    Ok(())
+}
+
+/// Print the account a token belongs to, warning if it isn't the
+/// profile's, to catch a token for the wrong account before anything
+/// relies on it
+fn report_token_owner(profile: &Profile, token: &str) {
+    match auth::token_user(profile, token) {
+        Ok(Some(login)) => match profile.username {
+            Some(ref username) if !login.eq_ignore_ascii_case(username) => println!(
+                "{} The token belongs to '{}', not '{}'",
+                "Warning:".yellow().bold(),
+                login,
+                username
+            ),
+            _ => println!("  Token belongs to '{}'", login),
+        },
+        Ok(None) => {}
+        Err(e) => println!("{} Could not verify the token: {}", "Warning:".yellow().bold(), e),
+    }
+}
+
+fn cmd_token_remove(name: String) -> Result<()> {
+    // A token outlives its profile, so removal also works by bare name
+    let config = Config::load()?;
+    let account = config
+        .get_profile(&name)
+        .map_or(name.as_str(), |p| p.keyring_account(&name));
+
+    if secrets::delete_token(account)? {
+        println!("{} Removed token for '{}'", "Success:".green().bold(), name);
+    } else {
+        println!("No token stored for '{}'", name);
+    }
+    Ok(())
+}
+
+fn cmd_token_show(name: String) -> Result<()> {
+    let config = Config::load()?;
+    let profile = config
+        .get_profile(&name)
+        .context(format!("Profile '{}' not found", name))?;
+
+    let token = if profile.github_app.is_some() {
+        auth::github_app::installation_token(&name, profile)?
+    } else {
+        secrets::get_token(profile.keyring_account(&name))?
+            .with_context(|| format!("No token stored for '{}'. Run 'gitid token set {}'.", name, name))?
+    };
+
+    println!("{}", token);
+    Ok(())
+}
+
+/// Read a secret from stdin when piped, otherwise prompt without echo
+fn read_secret(prompt: &str, confirm: bool) -> Result<String> {
+    use std::io::{IsTerminal, Read};
+
+    if std::io::stdin().is_terminal() {
+        if !input::is_interactive() {
+            bail!(
+                "Cannot prompt for '{}' in non-interactive mode; pipe it on stdin",
+                prompt.trim_end_matches(':')
+            );
+        }
+        let mut password = Password::new(prompt);
+        if !confirm {
+            password = password.without_confirmation();
+        }
+        Ok(password.prompt()?)
+    } else {
+        let mut input = String::new();
+        std::io::stdin()
+            .read_to_string(&mut input)
+            .context("Failed to read from stdin")?;
+        Ok(input.trim().to_string())
+    }
+}
+
+fn cmd_key_show(name: String, fingerprint: bool, qr: bool, url: bool) -> Result<()> {
+    use std::io::IsTerminal;
+
+    let config = Config::load()?;
+    let profile = config
+        .get_profile(&name)
+        .context(format!("Profile '{}' not found", name))?;
+
+    let path = ssh_keys::public_key_path(&profile.ssh_key);
+    let public_key = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read public key: {}", path.display()))?;
+    let public_key = public_key.trim();
+
+    if fingerprint {
+        println!("{}", ssh_keys::key_details(&path)?.fingerprint);
+        return Ok(());
+    }
+    if !qr {
+        println!("{}", public_key);
+        // Piped output stays just the key, ready for pbcopy or an API call
+        if std::io::stdout().is_terminal() {
+            let details = ssh_keys::key_details(&path)?;
+            println!();
+            println!("Fingerprint: {}", details.fingerprint);
+            println!("Type:        {} ({} bits)", details.algorithm, details.bits);
+            println!("File:        {}", path.display());
+        }
+        return Ok(());
+    }
+
+    if url {
+        for page in auth::key_settings_urls(profile) {
+            println!("{}", render_qr(&page)?);
+            println!("{}", page);
+            println!();
+        }
+    } else {
+        println!("{}", render_qr(public_key)?);
+        println!("{} ({})", profile.ssh_key, name);
+    }
+
+    Ok(())
+}
+
+/// Render data as a QR code using half-block characters
+fn render_qr(data: &str) -> Result<String> {
+    use qrcode::render::unicode::Dense1x2;
+
+    let code = qrcode::QrCode::new(data.as_bytes()).context("Data is too long for a QR code")?;
+    Ok(code
+        .render::<Dense1x2>()
+        .dark_color(Dense1x2::Light)
+        .light_color(Dense1x2::Dark)
+        .quiet_zone(true)
+        .build())
+}
+
+fn cmd_key_upload(name: String, usage: auth::KeyUsage) -> Result<()> {
+    let config = Config::load()?;
+    let profile = config
+        .get_profile(&name)
+        .context(format!("Profile '{}' not found", name))?;
+
+    auth::upload_key(&name, profile, usage)?;
+
+    println!(
+        "{} Uploaded {} as \"{}\"",
+        "Success:".green().bold(),
+        profile.ssh_key,
+        auth::key_title(&name)
+    );
+    Ok(())
+}
+
+fn cmd_key_audit(max_age: u64, tag: Option<String>) -> Result<()> {
+    let config = Config::load()?;
+
+    // Profiles using each key file, in the order keys are first seen
+    let mut keys: Vec<(String, std::path::PathBuf, Vec<String>)> = Vec::new();
+    for name in config.tagged_profile_names(tag.as_deref()) {
+        let Some(profile) = config.get_profile(name) else {
+            continue;
+        };
+        for key in profile.ssh_key_paths() {
+            let path = ssh_keys::expand_path(key);
+            match keys.iter_mut().find(|(_, p, _)| *p == path) {
+                Some((_, _, users)) => users.push(name.clone()),
+                None => keys.push((key.to_string(), path, vec![name.clone()])),
+            }
+        }
+    }
+    if keys.is_empty() {
+        println!("No SSH keys configured");
+        return Ok(());
+    }
+
+    let mut rows = Vec::new();
+    for (key, path, users) in &keys {
+        let public_key = ssh_keys::public_key_path(key);
+        let details = ssh_keys::key_details(&public_key).or_else(|_| ssh_keys::key_details(path));
+        let age = ssh_keys::key_age_days(path).or_else(|| ssh_keys::key_age_days(&public_key));
+        rows.push((key, users, details.ok(), age));
+    }
+
+    let mut flagged = 0;
+    let width = rows.iter().map(|(key, ..)| key.len()).chain(["KEY".len()]).max().unwrap_or(0);
+    println!("{:<width$}  {:<16}  {:>9}  PROFILES", "KEY", "TYPE", "AGE", width = width);
+    for (key, users, details, age) in &rows {
+        let kind = match details {
+            Some(d) => format!("{} {}", d.algorithm, d.bits),
+            None => "unreadable".to_string(),
+        };
+        let age_text = age.map(|days| format!("{}d", days)).unwrap_or_else(|| "?".to_string());
+        println!(
+            "{:<width$}  {:<16}  {:>9}  {}",
+            key,
+            kind,
+            age_text,
+            users.join(", ").cyan(),
+            width = width
+        );
+
+        let mut problems = match details {
+            Some(d) => ssh_keys::key_weaknesses(d, *age, max_age),
+            None => vec!["key file is missing or unreadable".to_string()],
+        };
+        // The same key material may also sit in several files
+        let fingerprint = details.as_ref().map(|d| &d.fingerprint);
+        let copies: Vec<&String> = rows
+            .iter()
+            .filter(|(other, _, d, _)| {
+                other != key && fingerprint.is_some() && d.as_ref().map(|d| &d.fingerprint) == fingerprint
+            })
+            .map(|(other, ..)| *other)
+            .collect();
+        if users.len() > 1 {
+            problems.push(format!("shared by {} profiles", users.len()));
+        }
+        if !copies.is_empty() {
+            problems.push(format!(
+                "same key as {}",
+                copies.iter().map(|c| c.as_str()).collect::<Vec<_>>().join(", ")
+            ));
+        }
+        if !problems.is_empty() {
+            flagged += 1;
+        }
+        for problem in problems {
+            println!("  {} {}", "Warning:".yellow().bold(), problem);
+        }
+    }
+
+    if flagged > 0 {
+        println!();
+        println!("Replace a key with {}", "gitid key rotate <profile>".yellow());
+        bail!("{} key(s) flagged", flagged);
+    }
+    println!();
+    println!("{} No weak, old or reused keys", "Success:".green().bold());
+    Ok(())
+}
+
+fn cmd_key_rotate(name: String, upload: bool, delete_old: bool, keygen: KeyGen) -> Result<()> {
+    let mut config = Config::load()?;
+    let profile = config
+        .get_profile(&name)
+        .context(format!("Profile '{}' not found", name))?
+        .clone();
+    if config.is_system_profile(&name) {
+        bail!(
+            "Profile '{}' is defined in the system config ({}) and cannot be changed here",
+            name,
+            Config::system_config_path().display()
+        );
+    }
+
+    let old_key = profile.ssh_key.clone();
+    let old_path = ssh_keys::expand_path(&old_key);
+    let shared: Vec<&String> = config
+        .profiles
+        .iter()
+        .filter(|(other, p)| {
+            *other != &name && p.ssh_key_paths().iter().any(|k| ssh_keys::expand_path(k) == old_path)
+        })
+        .map(|(other, _)| other)
+        .collect();
+
+    let upload_message = format!("Upload the new key to {} now?", profile.platform.display_name());
+    let upload = upload
+        || (input::is_interactive()
+            && matches!(profile.platform, Platform::Github | Platform::Gitlab | Platform::Both)
+            && input::confirm(Confirm::new(&upload_message).with_default(true))?);
+
+    // Every step registers its undo, so a failure (including a new key that
+    // doesn't authenticate) puts the old key back in place
+    let mut tx = Transaction::new();
+
+    // Move the old key aside first so the new one can take its file name
+    let archived = if shared.is_empty() {
+        let moved = ssh_keys::archive_key(&old_key)?;
+        let restore = moved.clone();
+        tx.on_rollback(format!("archived key {}", old_key), move || {
+            for (from, to) in &restore {
+                std::fs::rename(to, from)
+                    .with_context(|| format!("Failed to restore {}", from.display()))?;
+            }
+            Ok(())
+        });
+        moved
+    } else {
+        println!(
+            "{} {} is also used by {}; it stays in place",
+            "Warning:".yellow().bold(),
+            old_key,
+            shared.iter().map(|s| s.as_str()).collect::<Vec<_>>().join(", ")
+        );
+        Vec::new()
+    };
+
+    let new_key = generate_ssh_key(&name, &profile.email, keygen, &mut tx)?;
+    let mut rotated = profile.clone();
+    rotated.ssh_key = new_key.clone();
+
+    let config_snapshot = journal::snapshot_file(&Config::config_path()?)?;
+    journal::record_file(&Config::config_path()?)?;
+    config.profiles.insert(name.clone(), rotated.clone());
+    config.save()?;
+    tx.on_rollback("profile key updated in config", move || config_snapshot.revert());
+
+    let ssh_snapshots = ssh::snapshot_files(&config)?;
+    ssh::sync_ssh_config(&config)?;
+    tx.on_rollback("SSH config sync", move || {
+        ssh_snapshots.iter().try_for_each(|s| s.revert())
+    });
+
+    if upload {
+        let usage = if rotated.signing == Some(SigningFormat::Ssh) {
+            auth::KeyUsage::Both
+        } else {
+            auth::KeyUsage::Auth
+        };
+        auth::upload_key(&name, &rotated, usage)?;
+        println!("{} Uploaded key as \"{}\"", "Success:".green().bold(), auth::key_title(&name));
+
+        let mut hostnames: Vec<String> = Vec::new();
+        for (_, hostname) in rotated.ssh_host_aliases(&name) {
+            if hostnames.contains(&hostname) {
+                continue;
+            }
+            let test = ssh::test_key_connection(&rotated, &hostname)?;
+            if !test.authenticated {
+                bail!(
+                    "The new key did not authenticate with {}: {}\nThe uploaded key can be removed from the account's SSH key settings",
+                    hostname,
+                    test.output
+                );
+            }
+            println!("{} {} accepts the new key", "OK".green(), hostname);
+            hostnames.push(hostname);
+        }
+    }
+
+    tx.commit();
+
+    println!();
+    println!(
+        "{} Rotated key for '{}': {} -> {}",
+        "Success:".green().bold(),
+        name.cyan(),
+        old_key,
+        new_key
+    );
+
+    if delete_old {
+        for (_, archived) in &archived {
+            std::fs::remove_file(archived)
+                .with_context(|| format!("Failed to delete {}", archived.display()))?;
+        }
+        if !archived.is_empty() {
+            println!("  Deleted the old key");
+        }
+    } else if let Some((_, archived)) = archived.first() {
+        println!("  Old key archived as {}", archived.display());
+    }
+
+    if !upload {
+        println!(
+            "Run {} then {} to check it",
+            format!("gitid key upload {}", name).yellow(),
+            format!("gitid test {}", name).yellow()
+        );
+    }
+    println!("Remember to remove the old public key from your account's SSH key settings");
+
+    Ok(())
+}
+
+fn cmd_key_import(
+    name: String,
+    manager: vault::Manager,
+    item: Option<String>,
+    export: bool,
+) -> Result<()> {
+    let mut config = Config::load()?;
+    if !config.has_profile(&name) {
+        bail!("Profile '{}' not found", name);
+    }
+
+    let keys = vault::list_keys(manager)?;
+    if keys.is_empty() {
+        bail!("No SSH keys found in {}", manager);
+    }
+
+    let key = match item {
+        Some(item) => keys
+            .into_iter()
+            .find(|k| k.id == item || k.title == item)
+            .with_context(|| format!("No SSH key '{}' in {}", item, manager))?,
+        None => input::select(Select::new("Select SSH key:", keys), "--item")?,
+    };
+
+    let ssh_key = vault::import_key(manager, &key, &name, export)?;
+    if let Some(profile) = config.profiles.get_mut(&name) {
+        profile.ssh_key = ssh_key.clone();
+    }
+    config.save()?;
+    ssh::sync_ssh_config(&config)?;
+
+    println!(
+        "{} Attached '{}' from {} to profile '{}'",
+        "Success:".green().bold(),
+        key.title,
+        manager,
+        name.cyan()
+    );
+    println!("  SSH key: {}", ssh_key);
+    if !export {
+        println!(
+            "  Only the public key was saved; enable the {} SSH agent so ssh can use it",
+            manager
+        );
+    }
+
+    Ok(())
+}
+
+fn cmd_export(format: bundle::ExportFormat, no_key_paths: bool) -> Result<()> {
+    let config = Config::load()?;
+    if config.user_layer().profiles.is_empty() {
+        bail!("No profiles configured");
+    }
+
+    let export = bundle::export_config(&config, !no_key_paths);
+    print!("{}", bundle::render_export(&export, format)?);
+    Ok(())
+}
+
+fn cmd_export_bundle(path: std::path::PathBuf, encrypt: bool) -> Result<()> {
+    let config = Config::load()?;
+    if config.user_layer().profiles.is_empty() {
+        bail!("No profiles configured");
+    }
+
+    let (bundle, missing) = bundle::create(&config)?;
+    for file in &missing {
+        println!("{} {} not found, skipping", "Warning:".yellow().bold(), file.display());
+    }
+
+    let passphrase = if encrypt {
+        let passphrase = read_secret("Bundle passphrase:", true)?;
+        if passphrase.is_empty() {
+            bail!("Passphrase is empty");
+        }
+        Some(passphrase)
+    } else {
+        println!(
+            "{} The bundle contains private keys in plain text. Use --encrypt to protect it.",
+            "Warning:".yellow().bold()
+        );
+        None
+    };
+
+    let data = bundle::encode(&bundle, passphrase.as_deref())?;
+    std::fs::write(&path, data)
+        .with_context(|| format!("Failed to write bundle: {}", path.display()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+    }
+
+    println!(
+        "{} Exported {} profile(s) and {} key file(s) to {}",
+        "Success:".green().bold(),
+        bundle.config.profiles.len(),
+        bundle.files.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+/// How to resolve an imported profile that differs from an existing one
+fn resolve_import_conflict(name: &str, config: &Config) -> Result<Option<String>> {
+    const KEEP: &str = "Keep mine";
+    const REPLACE: &str = "Use imported";
+    const RENAME: &str = "Import under another name";
+
+    let message = format!("Profile '{}' differs from the imported one:", name);
+    let choice = input::select(Select::new(&message, vec![KEEP, REPLACE, RENAME]), "--force")?;
+
+    match choice {
+        KEEP => Ok(None),
+        REPLACE => Ok(Some(name.to_string())),
+        _ => {
+            let default_name = format!("{}-imported", name);
+            let new_name = input::text(Text::new("New profile name:").with_default(&default_name), "--force")?;
+            if config.has_profile(&new_name) {
+                bail!("Profile '{}' already exists", new_name);
+            }
+            Ok(Some(new_name))
+        }
+    }
+}
+
+fn cmd_import(file: std::path::PathBuf, force: bool) -> Result<()> {
+    let text = if file.as_os_str() == "-" {
+        std::io::read_to_string(std::io::stdin()).context("Failed to read stdin")?
+    } else {
+        std::fs::read_to_string(&file)
+            .with_context(|| format!("Failed to read {}", file.display()))?
+    };
+    let incoming = bundle::parse_export(&text)?;
+
+    let mut config = Config::load()?;
+    let mut tx = Transaction::new();
+    let mut imported = Vec::new();
+
+    for (name, mut profile) in incoming.profiles.clone() {
+        let mut target = name.clone();
+        if let Some(existing) = config.get_profile(&name) {
+            // An export without key paths keeps this machine's ones
+            if profile.ssh_key.is_empty() {
+                profile.ssh_key = existing.ssh_key.clone();
+                profile.extra_ssh_keys = existing.extra_ssh_keys.clone();
+                profile.gnupghome = existing.gnupghome.clone();
+            }
+            if existing == &profile {
+                continue;
+            }
+            if !force {
+                match resolve_import_conflict(&name, &config)? {
+                    Some(new_target) => target = new_target,
+                    None => continue,
+                }
+            }
+        }
+
+        if profile.ssh_key.is_empty() {
+            println!("Profile '{}' was exported without an SSH key path.", target);
+            profile.ssh_key = select_or_create_ssh_key(&target, &profile.email, KeyGen::default(), &mut tx)?.0;
+        }
+        profile
+            .validate()
+            .with_context(|| format!("Imported profile '{}' is invalid", name))?;
+
+        config.profiles.insert(target.clone(), profile);
+        imported.push(target);
+    }
+    bundle::merge_settings(&incoming, &mut config);
+
+    journal::record_file(&Config::config_path()?)?;
+    config.save()?;
+    ssh::sync_ssh_config(&config)?;
+    tx.commit();
+
+    println!(
+        "{} Imported {} profile(s)",
+        "Success:".green().bold(),
+        imported.len()
+    );
+    for name in &imported {
+        println!("  {}", name.cyan());
+    }
+    println!("SSH config updated");
+
+    Ok(())
+}
+
+fn cmd_import_bundle(path: std::path::PathBuf, force: bool) -> Result<()> {
+    let data = std::fs::read(&path)
+        .with_context(|| format!("Failed to read bundle: {}", path.display()))?;
+
+    let passphrase = if bundle::is_encrypted(&data) {
+        Some(read_secret("Bundle passphrase:", false)?)
+    } else {
+        None
+    };
+    let bundle = bundle::decode(&data, passphrase.as_deref())?;
+
+    let mut config = Config::load()?;
+    let conflicts = bundle::conflicts(&bundle, &config)?;
+    if !conflicts.is_empty() && !force {
+        bail!(
+            "The bundle would overwrite:\n  {}\nUse --force to replace them.",
+            conflicts.join("\n  ")
+        );
+    }
+
+    let restored = bundle::restore(&bundle, &mut config)?;
+    journal::record_file(&Config::config_path()?)?;
+    config.save()?;
+    ssh::sync_ssh_config(&config)?;
+
+    println!(
+        "{} Imported {} profile(s) and {} key file(s)",
+        "Success:".green().bold(),
+        restored.profiles.len(),
+        restored.files_written
+    );
+    for name in &restored.profiles {
+        println!("  {}", name.cyan());
+    }
+    println!("SSH config updated");
+
+    Ok(())
+}
+
+fn cmd_import_cli(source: Source, force: bool) -> Result<()> {
+    let accounts = hosts::accounts(source)?;
+    if accounts.is_empty() {
+        let path = hosts::config_path(source).map(|p| p.display().to_string()).unwrap_or_default();
+        println!("No {} accounts found in {}", source, path);
+        return Ok(());
+    }
+
+    let mut config = Config::load()?;
+    let mut tx = Transaction::new();
+    let mut imported = Vec::new();
+    let default_name = git::get_config("user.name", ConfigScope::Global)?;
+
+    for account in accounts {
+        let host = account.profile_host();
+        let label = format!("{}@{}", account.login, account.host);
+        let existing = config.profile_names().into_iter().find(|n| {
+            config.get_profile(n).is_some_and(|p| {
+                p.host == host && p.username.as_ref().is_some_and(|u| u.eq_ignore_ascii_case(&account.login))
+            })
+        });
+        if let Some(name) = existing {
+            println!("  {} is already profile '{}'", label, name);
+            continue;
+        }
+
+        println!("{} account {}", source, label.cyan());
+        let mut target = account.login.clone();
+        if config.has_profile(&target) && !force {
+            match resolve_import_conflict(&target, &config)? {
+                Some(new_target) => target = new_target,
+                None => continue,
+            }
+        }
+
+        let name = input::text(
+            Text::new("Git user name:").with_default(default_name.as_deref().unwrap_or(&account.login)),
+            "--force",
+        )?;
+        let email = match account.email() {
+            Ok(Some(email)) => email,
+            result => {
+                if let Err(e) = result {
+                    println!("{} Could not look up the email of {}: {}", "Warning:".yellow().bold(), label, e);
+                }
+                input::optional_text(Text::new("Git email:").with_help_message("This will be used for commit author"))?
+            }
+        };
+        if email.is_empty() {
+            println!("{} Skipping {}: no email for the account", "Warning:".yellow().bold(), label);
+            continue;
+        }
+
+        let ssh_key = if input::is_interactive() {
+            select_or_create_ssh_key(&target, &email, KeyGen::default(), &mut tx)?.0
+        } else {
+            ssh_keys::discover_keys()?
+                .first()
+                .map(|k| k.path_display())
+                .unwrap_or_else(|| "~/.ssh/id_ed25519".to_string())
+        };
+
+        let mut profile = Profile::new(name, email, account.platform.clone(), ssh_key, None, host);
+        profile.username = Some(account.login.clone());
+        profile
+            .validate()
+            .with_context(|| format!("Profile for {} is invalid", label))?;
+        config.profiles.insert(target.clone(), profile);
+        imported.push((target, account));
+    }
+
+    if imported.is_empty() {
+        println!("Nothing to import");
+        return Ok(());
+    }
+
+    journal::record_file(&Config::config_path()?)?;
+    config.save()?;
+    ssh::sync_ssh_config(&config)?;
+    tx.commit();
+
+    println!(
+        "{} Imported {} profile(s) from {}",
+        "Success:".green().bold(),
+        imported.len(),
+        source
+    );
+    for (name, account) in &imported {
+        let profile = &config.profiles[name];
+        println!("  {}: {} <{}>, key {}", name.cyan(), profile.name, profile.email, profile.ssh_key);
+        if account.git_protocol.as_deref() == Some("https") {
+            println!(
+                "    {} clones over HTTPS; store a token with {}",
+                source,
+                format!("gitid token set {}", name).yellow()
+            );
+        }
+    }
+    println!("SSH config updated");
+
+    Ok(())
+}
+
+fn cmd_fix_commits(last: usize, profile_name: Option<String>) -> Result<()> {
+    if !git::is_git_repo() {
+        bail!("Not in a git repository");
+    }
+    if last == 0 {
+        bail!("--last must be at least 1");
+    }
+
+    let config = Config::load()?;
+    let name = match profile_name {
+        Some(name) => name,
+        None => detect::detect_profile(&config)?
+            .map(|r| r.profile_name)
+            .context("No profile matches this repository; pass --profile")?,
+    };
+    let profile = config
+        .get_profile(&name)
+        .context(format!("Profile '{}' not found", name))?;
+
+    let head = git::head_commit()?;
+    let total = git::count_commits(&["HEAD"])?;
+    if last > total {
+        bail!("--last {} is more than the {} commit(s) on this branch", last, total);
+    }
+
+    // Rewriting the root commit needs --root; everything else rebases onto HEAD~N
+    let base = (last < total).then(|| format!("HEAD~{}", last));
+    let range = match base {
+        Some(ref base) => format!("{}..HEAD", base),
+        None => "HEAD".to_string(),
+    };
+    let rewritten = git::count_commits(&[&range])?;
+    let unpushed = git::count_commits(&[&range, "--not", "--remotes"])?;
+    if unpushed < rewritten {
+        bail!(
+            "{} of these commits are already on a remote; rewriting them would diverge from it",
+            rewritten - unpushed
+        );
+    }
+
+    println!("Commits to attribute to '{}' <{}>:", name.cyan(), profile.email);
+    for commit in git::commit_identities(&[&range])? {
+        println!("  {} {}", commit.hash.yellow(), commit.author_email);
+    }
+
+    if !input::approve(Confirm::new("Rewrite these commits?").with_default(false))? {
+        println!("Cancelled");
+        return Ok(());
+    }
+
+    git::rewrite_commits(base.as_deref(), &env::commit_env(&name, profile))?;
+
+    println!(
+        "{} Rewrote {} commit(s) as '{}'",
+        "Success:".green().bold(),
+        rewritten,
+        name.cyan()
+    );
+    println!("Undo with {}", format!("git reset --hard {}", &head[..12.min(head.len())]).yellow());
+    Ok(())
+}
+
+/// Repositories `audit` and `apply-all` work on, with the directory their
+/// paths are shown relative to: those under `dir`, or with `registry` the
+/// ones `gitid scan` recorded (shown in full). Empty, after saying so, when
+/// there are none.
+fn target_repos(
+    dir: Option<&std::path::Path>,
+    registry: bool,
+) -> Result<(std::path::PathBuf, Vec<std::path::PathBuf>)> {
+    if registry {
+        let repos = registry::Registry::load()?.repo_paths();
+        if repos.is_empty() {
+            println!("No repositories recorded. Run {} first", "gitid scan".yellow());
+        }
+        return Ok((std::path::PathBuf::new(), repos));
+    }
+
+    let dir = dir.unwrap_or(std::path::Path::new("."));
+    let root = dir
+        .canonicalize()
+        .with_context(|| format!("Failed to resolve {}", dir.display()))?;
+    let repos = audit::find_repos(&root)?;
+    if repos.is_empty() {
+        println!("No git repositories under {}", root.display());
+    }
+    Ok((root, repos))
+}
+
+fn cmd_audit(dir: std::path::PathBuf, fix: bool, registry: bool) -> Result<()> {
+    // Each repository gets its own project layer
+    let config = Config::load_user()?;
+    let (root, repos) = target_repos(Some(&dir), registry)?;
+    if repos.is_empty() {
+        return Ok(());
+    }
+
+    let mut entries = Vec::new();
+    for repo in &repos {
+        match audit::audit_repo(&config, repo) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => println!("{} {}: {:#}", "Warning:".yellow().bold(), repo.display(), e),
+        }
+    }
+
+    let display = |repo: &std::path::Path| {
+        let relative = repo.strip_prefix(&root).unwrap_or(repo).display().to_string();
+        if relative.is_empty() { ".".to_string() } else { relative }
+    };
+    let mismatched: Vec<_> = entries
+        .iter()
+        .filter(|e| e.status == audit::Status::Mismatch)
+        .collect();
+
+    if !mismatched.is_empty() {
+        let rows: Vec<[String; 3]> = mismatched
+            .iter()
+            .map(|e| {
+                let applied = format!(
+                    "{} <{}>",
+                    e.name.as_deref().unwrap_or("unset"),
+                    e.email.as_deref().unwrap_or("unset")
+                );
+                [display(&e.repo), e.expected.clone().unwrap_or_default(), applied]
+            })
+            .collect();
+        let headers = ["REPOSITORY", "EXPECTED", "APPLIED"];
+        let widths: Vec<usize> = (0..2)
+            .map(|i| rows.iter().map(|r| r[i].len()).chain([headers[i].len()]).max().unwrap_or(0))
+            .collect();
+
+        println!(
+            "{:<w0$}  {:<w1$}  {}",
+            headers[0],
+            headers[1],
+            headers[2],
+            w0 = widths[0],
+            w1 = widths[1]
+        );
+        for [repo, expected, applied] in &rows {
+            println!(
+                "{:<w0$}  {}  {}",
+                repo,
+                format!("{:<w1$}", expected, w1 = widths[1]).cyan(),
+                applied.red(),
+                w0 = widths[0]
+            );
+        }
+        println!();
+    }
+
+    let undetected = entries
+        .iter()
+        .filter(|e| e.status == audit::Status::Undetected)
+        .count();
+    println!(
+        "Scanned {} repositories: {} mismatched, {} without a matching profile",
+        entries.len(),
+        mismatched.len(),
+        undetected
+    );
+
+    if mismatched.is_empty() {
+        return Ok(());
+    }
+    if !fix {
+        let command = if registry { "gitid audit --registry --fix" } else { "gitid audit --fix" };
+        println!("Run {} to apply the expected profiles", command.yellow());
+        return Ok(());
+    }
+
+    println!();
+    for entry in mismatched {
+        let Some(name) = entry.expected.as_deref() else {
+            continue;
+        };
+        let Some(profile) = config.get_profile(name) else {
+            continue;
+        };
+        std::env::set_current_dir(&entry.repo)?;
+        git::apply_profile(&config, name, profile, ConfigScope::Local)?;
+        println!(
+            "  {} {} now uses '{}'",
+            "Fixed".green(),
+            display(&entry.repo),
+            name.cyan()
+        );
+    }
+
+    Ok(())
+}
+
+/// What `apply-all` did, or would do, in one repository
+enum ApplyOutcome {
+    Applied(String),
+    UpToDate(String),
+    Undetected,
+    Failed(anyhow::Error),
+}
+
+/// Detect the profile for one repository and apply it locally, unless the
+/// local identity already is that profile's. Changes the working directory
+/// to the repository.
+fn apply_repo(config: &Config, repo: &std::path::Path, dry_run: bool) -> Result<ApplyOutcome> {
+    std::env::set_current_dir(repo)
+        .with_context(|| format!("Failed to enter {}", repo.display()))?;
+    let config = config.clone().with_project(repo)?;
+
+    let Some(result) = detect::detect_profile(&config)? else {
+        return Ok(ApplyOutcome::Undetected);
+    };
+    if let Some(message) = result.tie_message() {
+        bail!("{}", message);
+    }
+    let name = result.profile_name;
+    let profile = config
+        .get_profile(&name)
+        .with_context(|| format!("Profile '{}' not found", name))?;
+
+    let (local_name, local_email) = git::get_current_user(ConfigScope::Local)?;
+    if audit::compare(&config, Some(&name), local_name.as_deref(), local_email.as_deref()) == audit::Status::Ok {
+        return Ok(ApplyOutcome::UpToDate(name));
+    }
+    if let Some(violation) = remote::owner_violations(profile, &git::remote_urls()?).into_iter().next() {
+        bail!("{}", violation);
+    }
+
+    if !dry_run {
+        git::apply_profile(&config, &name, profile, ConfigScope::Local)?;
+        let mode = config.ssh_mode(profile);
+        apply_ssh_command(profile, mode == SshMode::Command, ConfigScope::Local)?;
+        remote::apply_insteadof(&config, &name, profile, mode == SshMode::InsteadOf, ConfigScope::Local)?;
+    }
+    Ok(ApplyOutcome::Applied(name))
+}
+
+fn cmd_apply_all(dir: Option<std::path::PathBuf>, registry: bool, dry_run: bool) -> Result<()> {
+    // Each repository gets its own project layer
+    let config = Config::load_user()?;
+    let (root, repos) = target_repos(dir.as_deref(), registry)?;
+    if repos.is_empty() {
+        return Ok(());
+    }
+
+    let outcomes: Vec<ApplyOutcome> = repos
+        .iter()
+        .map(|repo| apply_repo(&config, repo, dry_run).unwrap_or_else(ApplyOutcome::Failed))
+        .collect();
+
+    let rows: Vec<[String; 2]> = repos
+        .iter()
+        .zip(&outcomes)
+        .map(|(repo, outcome)| {
+            let relative = repo.strip_prefix(&root).unwrap_or(repo).display().to_string();
+            let profile = match outcome {
+                ApplyOutcome::Applied(name) | ApplyOutcome::UpToDate(name) => name.clone(),
+                ApplyOutcome::Undetected | ApplyOutcome::Failed(_) => "-".to_string(),
+            };
+            [if relative.is_empty() { ".".to_string() } else { relative }, profile]
+        })
+        .collect();
+    let headers = ["REPOSITORY", "PROFILE", "RESULT"];
+    let widths: Vec<usize> = (0..2)
+        .map(|i| rows.iter().map(|r| r[i].len()).chain([headers[i].len()]).max().unwrap_or(0))
+        .collect();
+
+    println!(
+        "{:<w0$}  {:<w1$}  {}",
+        headers[0],
+        headers[1],
+        headers[2],
+        w0 = widths[0],
+        w1 = widths[1]
+    );
+    for ([repo, profile], outcome) in rows.iter().zip(&outcomes) {
+        let result = match outcome {
+            ApplyOutcome::Applied(_) if dry_run => "would apply".green(),
+            ApplyOutcome::Applied(_) => "applied".green(),
+            ApplyOutcome::UpToDate(_) => "up to date".dimmed(),
+            ApplyOutcome::Undetected => "no matching profile".yellow(),
+            ApplyOutcome::Failed(e) => format!("failed: {:#}", e).red(),
+        };
+        println!(
+            "{:<w0$}  {}  {}",
+            repo,
+            format!("{:<w1$}", profile, w1 = widths[1]).cyan(),
+            result,
+            w0 = widths[0]
+        );
+    }
+    println!();
+
+    let count = |f: fn(&ApplyOutcome) -> bool| outcomes.iter().filter(|o| f(o)).count();
+    let applied = count(|o| matches!(o, ApplyOutcome::Applied(_)));
+    let failed = count(|o| matches!(o, ApplyOutcome::Failed(_)));
+    println!(
+        "{} {} repositories: {} {}, {} up to date, {} without a matching profile, {} failed",
+        if dry_run { "Checked" } else { "Scanned" },
+        outcomes.len(),
+        applied,
+        if dry_run { "to apply" } else { "applied" },
+        count(|o| matches!(o, ApplyOutcome::UpToDate(_))),
+        count(|o| matches!(o, ApplyOutcome::Undetected)),
+        failed
+    );
+    if dry_run && applied > 0 {
+        println!("Run without {} to apply them", "--dry-run".yellow());
+    }
+    if failed > 0 {
+        bail!("{} repositories could not be updated", failed);
+    }
+    Ok(())
+}
+
+fn cmd_scan(dirs: Vec<std::path::PathBuf>) -> Result<()> {
+    // Each repository gets its own project layer
+    let config = Config::load_user()?;
+    let roots: Vec<std::path::PathBuf> = if dirs.is_empty() {
+        config.scan_roots.iter().map(|r| ssh_keys::expand_path(r)).collect()
+    } else {
+        dirs
+    };
+    if roots.is_empty() {
+        bail!("No directories to scan. Pass one, or list them in scan_roots in the config");
+    }
+
+    let mut registry = registry::Registry::load()?;
+    for dir in roots {
+        let root = dir
+            .canonicalize()
+            .with_context(|| format!("Failed to resolve {}", dir.display()))?;
+        let mut records = Vec::new();
+        for repo in audit::find_repos(&root)? {
+            match registry::scan_repo(&config, &repo) {
+                Ok(record) => records.push(record),
+                Err(e) => println!("{} {}: {:#}", "Warning:".yellow().bold(), repo.display(), e),
+            }
+        }
+
+        let found = records.len();
+        let (added, removed) = registry.update(&root, records);
+        println!(
+            "Scanned {}: {} repositories ({} new, {} gone)",
+            root.display(),
+            found,
+            added,
+            removed
+        );
+    }
+    registry.save()?;
+
+    let undetected = registry.repos.iter().filter(|r| r.detected.is_none()).count();
+    let mismatched = registry
+        .repos
+        .iter()
+        .filter(|r| r.detected.is_some() && r.applied != r.detected)
+        .count();
+    println!(
+        "{} Recorded {} repositories in {}",
+        "Success:".green().bold(),
+        registry.repos.len(),
+        registry::Registry::path()?.display()
+    );
+    println!(
+        "  {} without the detected profile applied, {} without a matching profile",
+        mismatched, undetected
+    );
+    if mismatched > 0 {
+        println!("Run {} to fix them", "gitid apply-all --registry".yellow());
+    }
+    Ok(())
+}
+
+fn cmd_doctor(fix: bool) -> Result<()> {
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            let findings = [doctor::config_error(&e)];
+            print_findings(&findings);
+            return finish_doctor(&findings);
+        }
+    };
+    let findings = doctor::diagnose(&config)?;
+
+    if findings.is_empty() {
+        println!("{} No problems found", "Success:".green().bold());
+        return Ok(());
+    }
+    print_findings(&findings);
+
+    if !fix {
+        let fixable = findings.iter().filter(|f| f.fix.is_some()).count();
+        if fixable > 0 {
+            println!();
+            println!(
+                "Run {} to repair {} of them",
+                "gitid doctor --fix".yellow(),
+                fixable
+            );
+        }
+        return finish_doctor(&findings);
+    }
+
+    println!();
+    for fix in findings.iter().filter_map(|f| f.fix.as_ref()) {
+        let message = format!("{}?", fix.describe());
+        let apply = input::approve(Confirm::new(&message).with_default(true))?;
+        if !apply {
+            continue;
+        }
+        match fix.apply(&config) {
+            Ok(()) => println!("  {} {}", "Fixed".green(), fix.describe()),
+            Err(e) => eprintln!("  {} {}: {}", "Failed:".red(), fix.describe(), e),
+        }
+    }
+
+    println!();
+    let remaining = doctor::diagnose(&config)?;
+    if remaining.is_empty() {
+        println!("{} No problems remain", "Success:".green().bold());
+        return Ok(());
+    }
+    println!("{}", "Still found:".bold());
+    print_findings(&remaining);
+    finish_doctor(&remaining)
+}
+
+fn print_findings(findings: &[doctor::Finding]) {
+    for finding in findings {
+        let label = match finding.severity {
+            lint::Severity::Error => finding.severity.to_string().red().bold(),
+            lint::Severity::Warning => finding.severity.to_string().yellow().bold(),
+        };
+        println!("{}: {}", label, finding.message);
+        if let Some(ref fix) = finding.fix {
+            println!("    fix: {}", fix.describe().dimmed());
+        }
+        if let Some(ref hint) = finding.hint {
+            println!("    hint: {}", hint.dimmed());
+        }
+    }
+}
+
+/// Fail if any errors are left, as `config validate` does
+fn finish_doctor(findings: &[doctor::Finding]) -> Result<()> {
+    let errors = findings
+        .iter()
+        .filter(|f| f.severity == lint::Severity::Error)
+        .count();
+    if errors > 0 {
+        bail!("{} error(s) found", errors);
+    }
+    Ok(())
+}
+
+fn cmd_uninstall(purge: bool, force: bool) -> Result<()> {
+    let config = Config::load()?;
+    let actions = uninstall::plan(&config, purge)?;
+
+    if actions.is_empty() {
+        println!("Nothing to remove");
+        return Ok(());
+    }
+
+    println!("{}", "This will:".bold());
+    for action in &actions {
+        println!("  - {}", action.describe());
+    }
+    if !purge {
+        println!(
+            "  (config is kept at {}; use --purge to remove it)",
+            Config::config_path()?.display()
+        );
+    }
+
+    if !force {
+        println!();
+        let confirmed =
+            input::approve(Confirm::new("Uninstall gitid from this machine?").with_default(false))?;
+
+        if !confirmed {
+            println!("Cancelled");
+            return Ok(());
+        }
+    }
+
+    let mut failed = 0;
+    for action in &actions {
+        if let Err(e) = action.apply() {
+            eprintln!("{} {}: {}", "Failed:".red(), action.describe(), e);
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        bail!("{} of {} step(s) failed", failed, actions.len());
+    }
+    println!("{} gitid has been removed", "Success:".green().bold());
+
+    Ok(())
+}
+
+fn cmd_config_validate() -> Result<()> {
+    let config = Config::load()?;
+    let path = Config::config_path()?;
+    let issues = lint::lint_config(&config);
+    if let Some(project) = config.project_path() {
+        println!("Project config: {}", project.display());
+    }
+
+    if issues.is_empty() {
+        println!(
+            "{} {} is valid ({} profile(s))",
+            "Success:".green().bold(),
+            path.display(),
+            config.profiles.len()
+        );
+        return Ok(());
+    }
+
+    for issue in &issues {
+        let label = match issue.severity {
+            lint::Severity::Error => issue.severity.to_string().red().bold(),
+            lint::Severity::Warning => issue.severity.to_string().yellow().bold(),
+        };
+        println!("{} [{}]: {}", label, issue.profiles.join(", "), issue.message);
+        println!("    {}", issue.explanation.dimmed());
+    }
+
+    let errors = issues
+        .iter()
+        .filter(|i| i.severity == lint::Severity::Error)
+        .count();
+    println!();
+    if errors > 0 {
+        bail!("{} error(s) found in {}", errors, path.display());
+    }
+    println!("{} warning(s) found", issues.len());
+
+    Ok(())
+}
+
+fn cmd_config_schema(project: bool) -> Result<()> {
+    if project {
+        println!("{}", Config::project_json_schema()?);
+    } else {
+        println!("{}", Config::json_schema()?);
+    }
+    Ok(())
+}
@@ -0,0 +1,91 @@
+/// Replace the block between the `start` and `end` markers in `current`,
+/// or append it if the file has none. `block` must include both markers.
+/// Returns the new content and whether an existing block was replaced.
+pub fn splice_block(current: &str, start: &str, end: &str, block: &str) -> (String, bool) {
+    // Check if managed block exists
+    if let (Some(start_idx), Some(end_idx)) = (current.find(start), current.find(end)) {
+        // Replace existing managed block
+        let end_idx = end_idx + end.len();
+        let mut new_content = String::new();
+        new_content.push_str(&current[..start_idx]);
+        new_content.push_str(block);
+
+        // Preserve any content after the managed block
+        if end_idx < current.len() {
+            new_content.push_str(&current[end_idx..]);
+        }
+
+        (new_content, true)
+    } else {
+        // Append new managed block
+        let mut new_content = current.to_string();
+
+        // Add newlines if the file doesn't end with one
+        if !new_content.is_empty() && !new_content.ends_with('\n') {
+            new_content.push('\n');
+        }
+        if !new_content.is_empty() {
+            new_content.push('\n');
+        }
+
+        new_content.push_str(block);
+        new_content.push('\n');
+
+        (new_content, false)
+    }
+}
+
+/// The managed block in `current`, markers included
+pub fn find_block<'a>(current: &'a str, start: &str, end: &str) -> Option<&'a str> {
+    let start_idx = current.find(start)?;
+    let end_idx = current.find(end)? + end.len();
+    current.get(start_idx..end_idx)
+}
+
+/// Remove the managed block (and the blank line `splice_block` put before
+/// it). Returns None if the content has no managed block.
+pub fn remove_block(current: &str, start: &str, end: &str) -> Option<String> {
+    let start_idx = current.find(start)?;
+    let end_idx = current.find(end)? + end.len();
+
+    let before = current[..start_idx].strip_suffix('\n').unwrap_or(&current[..start_idx]);
+    let after = current[end_idx..].strip_prefix('\n').unwrap_or(&current[end_idx..]);
+
+    let mut content = before.to_string();
+    if !content.is_empty() && !after.is_empty() {
+        content.push('\n');
+    }
+    content.push_str(after);
+    Some(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START: &str = "# start";
+    const END: &str = "# end";
+
+    #[test]
+    fn test_splice_appends() {
+        let (content, replaced) = splice_block("Host *\n", START, END, "# start\nnew\n# end");
+        assert!(!replaced);
+        assert_eq!(content, "Host *\n\n# start\nnew\n# end\n");
+    }
+
+    #[test]
+    fn test_splice_replaces_in_place() {
+        let current = "before\n# start\nold\n# end\nafter\n";
+        let (content, replaced) = splice_block(current, START, END, "# start\nnew\n# end");
+        assert!(replaced);
+        assert_eq!(content, "before\n# start\nnew\n# end\nafter\n");
+    }
+
+    #[test]
+    fn test_remove_undoes_append() {
+        let original = "Host *\n";
+        let (content, _) = splice_block(original, START, END, "# start\nnew\n# end");
+        assert_eq!(remove_block(&content, START, END).unwrap(), original);
+        assert!(remove_block(original, START, END).is_none());
+    }
+}
@@ -0,0 +1,305 @@
+use crate::config::Config;
+use crate::doctor;
+use crate::git::{self, ConfigScope};
+use crate::gitconfig;
+use crate::profile::{Platform, Profile, SigningFormat};
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Key given to imported profiles whose gitconfig doesn't name one
+const DEFAULT_SSH_KEY: &str = "~/.ssh/id_ed25519";
+
+/// A hand-written `[includeIf "gitdir:..."]` section and the directory
+/// rule it becomes
+#[derive(Debug)]
+pub struct Include {
+    /// The section's condition, e.g. `gitdir:~/work/`
+    pub condition: String,
+    /// The `path` value as written
+    pub path: String,
+    pub pattern: String,
+    /// Profile the included file's identity becomes, or already is
+    pub profile: String,
+}
+
+/// What `gitid migrate` turns the global gitconfig's includes into
+#[derive(Debug, Default)]
+pub struct Plan {
+    /// Profiles to create, keyed by name
+    pub profiles: BTreeMap<String, Profile>,
+    pub includes: Vec<Include>,
+    /// Includes left alone, as (key, reason)
+    pub skipped: Vec<(String, String)>,
+}
+
+/// Directory rule for an includeIf condition, following git's gitdir
+/// matching: a trailing `/` covers everything below, and a pattern not
+/// starting at `/` or `~/` may match anywhere. None for other conditions
+/// (onbranch:, hasconfig:) and for `./` patterns.
+fn directory_pattern(condition: &str) -> Option<String> {
+    let pattern = condition
+        .strip_prefix("gitdir:")
+        .or_else(|| condition.strip_prefix("gitdir/i:"))?;
+    if pattern.is_empty() || pattern.starts_with("./") {
+        return None;
+    }
+
+    // A pattern naming the .git directory itself means the repository
+    let mut pattern = pattern.strip_suffix("/.git").unwrap_or(pattern).to_string();
+    if pattern.ends_with('/') {
+        pattern.push_str("**");
+    }
+    if !pattern.starts_with('/') && !pattern.starts_with("~/") && !pattern.starts_with("**") {
+        pattern = format!("**/{}", pattern);
+    }
+    Some(pattern)
+}
+
+/// Key path after `-i` in a core.sshCommand
+fn ssh_key_from_command(command: &str) -> Option<String> {
+    let mut words = command.split_whitespace();
+    while let Some(word) = words.next() {
+        let key = match word.strip_prefix("-i") {
+            Some("") => words.next()?,
+            Some(joined) => joined,
+            None => continue,
+        };
+        return Some(key.trim_matches(['"', '\'']).to_string());
+    }
+    None
+}
+
+/// Profile for the identity an included file sets, or None without both
+/// user.name and user.email. The platform is unknown, so it is GitHub.
+fn fragment_profile(values: &BTreeMap<String, String>) -> Option<Profile> {
+    let name = values.get("user.name")?;
+    let email = values.get("user.email")?;
+    let signing_key = values.get("user.signingkey");
+    let ssh_signing = values.get("gpg.format").is_some_and(|f| f == "ssh");
+
+    let ssh_key = values
+        .get("core.sshcommand")
+        .and_then(|c| ssh_key_from_command(c))
+        .or_else(|| {
+            // An SSH signing key is the public half of the key
+            signing_key
+                .filter(|_| ssh_signing)
+                .map(|k| k.strip_suffix(".pub").unwrap_or(k).to_string())
+        })
+        .unwrap_or_else(|| DEFAULT_SSH_KEY.to_string());
+
+    let gpg_key = signing_key.filter(|_| !ssh_signing).cloned();
+    let mut profile = Profile::new(name.clone(), email.clone(), Platform::Github, ssh_key, gpg_key, None);
+    if ssh_signing && signing_key.is_some() {
+        profile.signing = Some(SigningFormat::Ssh);
+    }
+    Some(profile)
+}
+
+/// Profile for the identity the global gitconfig sets, for
+/// `gitid add --from-git`. None without both user.name and user.email.
+pub fn global_profile() -> Result<Option<Profile>> {
+    let mut values = BTreeMap::new();
+    for key in ["user.name", "user.email", "user.signingkey", "gpg.format", "core.sshcommand"] {
+        if let Some(value) = git::get_config(key, ConfigScope::Global)? {
+            values.insert(key.to_string(), value);
+        }
+    }
+    Ok(fragment_profile(&values))
+}
+
+/// Profile name suggested by an included file's name (`.gitconfig-work`,
+/// `work.gitconfig`), else by the last plain directory of the pattern
+fn base_name(file: &Path, pattern: &str) -> String {
+    let from_file = file
+        .file_name()
+        .map(|n| n.to_string_lossy().to_lowercase())
+        .map(|n| {
+            let n = n.trim_start_matches('.');
+            let n = n.strip_suffix(".gitconfig").or_else(|| n.strip_suffix(".inc")).unwrap_or(n);
+            n.strip_prefix("gitconfig").unwrap_or(n).trim_matches(['-', '_', '.']).to_string()
+        })
+        .filter(|n| !n.is_empty() && n != "config");
+
+    from_file
+        .or_else(|| {
+            pattern
+                .split('/')
+                .rev()
+                .find(|part| !part.is_empty() && *part != "~" && !part.contains('*'))
+                .map(str::to_string)
+        })
+        .unwrap_or_else(|| "imported".to_string())
+}
+
+/// Every key an included file sets, with lowercased names as git reports
+/// them. None if git can't read the file.
+fn read_file_config(file: &Path) -> Result<Option<BTreeMap<String, String>>> {
+    let output = Command::new("git")
+        .args(["config", "--file"])
+        .arg(file)
+        .args(["-z", "--list"])
+        .output()
+        .context("Failed to execute git config")?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+
+    Ok(Some(
+        String::from_utf8_lossy(&output.stdout)
+            .split('\0')
+            .filter_map(|entry| entry.split_once('\n'))
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect(),
+    ))
+}
+
+impl Plan {
+    /// Name for a profile with `profile`'s identity: an existing or planned
+    /// profile with the same name and email, else `base` with a number
+    /// added if taken. The flag says whether the profile is new.
+    fn name_for(&self, config: &Config, profile: &Profile, base: &str) -> (String, bool) {
+        let same = |p: &Profile| p.email.eq_ignore_ascii_case(&profile.email) && p.name == profile.name;
+        if let Some(name) = config.profile_names().into_iter().find(|n| config.get_profile(n).is_some_and(same)) {
+            return (name.clone(), false);
+        }
+        if let Some((name, _)) = self.profiles.iter().find(|(_, p)| same(p)) {
+            return (name.clone(), false);
+        }
+
+        let taken = |name: &str| config.has_profile(name) || self.profiles.contains_key(name);
+        let name = (1..)
+            .map(|i| if i == 1 { base.to_string() } else { format!("{}-{}", base, i) })
+            .find(|name| !taken(name))
+            .unwrap_or_default();
+        (name, true)
+    }
+}
+
+/// Work out the profiles and directory rules the global gitconfig's
+/// includeIf sections amount to. gitid's own managed includes are ignored.
+pub fn plan(config: &Config) -> Result<Plan> {
+    let global = git::global_config_path()?;
+    let fragments = gitconfig::fragments_dir()?;
+    let mut plan = Plan::default();
+    let mut by_file: BTreeMap<PathBuf, String> = BTreeMap::new();
+
+    for (key, path) in git::global_includes()? {
+        let Some(condition) = key.strip_prefix("includeif.").and_then(|k| k.strip_suffix(".path")) else {
+            continue;
+        };
+        let file = doctor::resolve_include(&path, &global);
+        if file.starts_with(&fragments) {
+            continue;
+        }
+        let Some(pattern) = directory_pattern(condition) else {
+            plan.skipped.push((key, "not a gitdir condition gitid can express".to_string()));
+            continue;
+        };
+
+        let profile = match by_file.get(&file) {
+            Some(name) => name.clone(),
+            None => {
+                let Some(values) = read_file_config(&file)? else {
+                    plan.skipped.push((key, format!("{} cannot be read", path)));
+                    continue;
+                };
+                let Some(profile) = fragment_profile(&values) else {
+                    plan.skipped.push((key, format!("{} sets no user.name and user.email", path)));
+                    continue;
+                };
+                let (name, new) = plan.name_for(config, &profile, &base_name(&file, &pattern));
+                if new {
+                    plan.profiles.insert(name.clone(), profile);
+                }
+                by_file.insert(file, name.clone());
+                name
+            }
+        };
+
+        if let Some(existing) = config.detect.directories.get(&pattern).filter(|t| **t != profile) {
+            plan.skipped.push((key, format!("{} is already a rule for '{}'", pattern, existing)));
+            continue;
+        }
+        plan.includes.push(Include {
+            condition: condition.to_string(),
+            path,
+            pattern,
+            profile,
+        });
+    }
+
+    Ok(plan)
+}
+
+/// Add the plan's profiles and rules to the config (not yet saved)
+pub fn apply(plan: &Plan, config: &mut Config) -> Result<()> {
+    for (name, profile) in &plan.profiles {
+        config.add_profile(name.clone(), profile.clone())?;
+    }
+    for include in &plan.includes {
+        config
+            .detect
+            .directories
+            .insert(include.pattern.clone(), include.profile.clone());
+    }
+    Ok(())
+}
+
+/// Remove the migrated includeIf sections from the global gitconfig
+pub fn remove_includes(plan: &Plan) -> Result<()> {
+    for include in &plan.includes {
+        git::remove_global_section(&format!("includeIf.{}", include.condition))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_directory_pattern() {
+        assert_eq!(directory_pattern("gitdir:~/work/").as_deref(), Some("~/work/**"));
+        assert_eq!(directory_pattern("gitdir/i:/src/Acme/").as_deref(), Some("/src/Acme/**"));
+        assert_eq!(directory_pattern("gitdir:~/oss/app/.git").as_deref(), Some("~/oss/app"));
+        assert_eq!(directory_pattern("gitdir:clients/").as_deref(), Some("**/clients/**"));
+        assert_eq!(directory_pattern("onbranch:main"), None);
+        assert_eq!(directory_pattern("gitdir:./work/"), None);
+    }
+
+    #[test]
+    fn test_fragment_profile() {
+        let values: BTreeMap<String, String> = [
+            ("user.name", "John Doe"),
+            ("user.email", "john@acme.com"),
+            ("user.signingkey", "~/.ssh/id_acme.pub"),
+            ("gpg.format", "ssh"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        let profile = fragment_profile(&values).unwrap();
+        assert_eq!(profile.ssh_key, "~/.ssh/id_acme");
+        assert_eq!(profile.signing, Some(SigningFormat::Ssh));
+        assert!(profile.gpg_key.is_none());
+
+        let mut values = values;
+        values.insert("core.sshcommand".to_string(), "ssh -i \"/keys/work\" -o IdentitiesOnly=yes".to_string());
+        assert_eq!(fragment_profile(&values).unwrap().ssh_key, "/keys/work");
+        values.remove("user.email");
+        assert!(fragment_profile(&values).is_none());
+
+        assert_eq!(ssh_key_from_command("ssh -i/keys/oss"), Some("/keys/oss".to_string()));
+        assert_eq!(ssh_key_from_command("ssh -o IdentitiesOnly=yes"), None);
+    }
+
+    #[test]
+    fn test_base_name() {
+        assert_eq!(base_name(Path::new("/home/j/.gitconfig-work"), "~/work/**"), "work");
+        assert_eq!(base_name(Path::new("/home/j/oss.gitconfig"), "~/oss/**"), "oss");
+        assert_eq!(base_name(Path::new("/home/j/.config/git/config"), "~/src/acme/**"), "acme");
+    }
+}
@@ -0,0 +1,187 @@
+use crate::config::Config;
+use crate::managed;
+use crate::profile::{Platform, Profile};
+use crate::secrets;
+use anyhow::{Context, Result, bail};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+pub const MANAGED_START: &str = "# === GITID MANAGED START ===";
+pub const MANAGED_END: &str = "# === GITID MANAGED END ===";
+
+/// Get the .netrc file path
+pub fn netrc_path() -> Result<PathBuf> {
+    let home = dirs::home_dir().context("Could not determine home directory")?;
+    Ok(home.join(".netrc"))
+}
+
+/// Login name forges accept alongside a personal access token
+pub fn token_login(profile: &Profile) -> &str {
+    match profile.platform {
+        Platform::Gitlab => "oauth2",
+        // Gitea and Forgejo ignore the login when the password is a token
+        Platform::Github | Platform::Both | Platform::Codeberg | Platform::Gitea => "x-access-token",
+        // Nothing to assume about other forges beyond the account's own login
+        Platform::Custom => profile.username.as_deref().unwrap_or("git"),
+    }
+}
+
+/// Generate a machine entry for a profile
+fn generate_machine_entry(profile: &Profile, token: &str) -> String {
+    format!(
+        "machine {}\n  login {}\n  password {}\n",
+        profile.default_host(),
+        token_login(profile),
+        token
+    )
+}
+
+/// Generate the managed block from (profile, token) pairs. Fails if two
+/// profiles claim the same host, since netrc clients only use the first.
+fn generate_managed_block(entries: &[(&str, &Profile, String)]) -> Result<String> {
+    let mut block = String::new();
+    block.push_str(MANAGED_START);
+    block.push('\n');
+
+    let mut hosts: Vec<(&str, &str)> = Vec::new();
+    for (name, profile, token) in entries {
+        let host = profile.default_host();
+        if let Some((other, _)) = hosts.iter().find(|(_, h)| *h == host) {
+            bail!(
+                "Profiles '{}' and '{}' both enable netrc for {}; only one can own a host",
+                other,
+                name,
+                host
+            );
+        }
+        hosts.push((name, host));
+        block.push_str(&generate_machine_entry(profile, token));
+    }
+
+    block.push_str(MANAGED_END);
+    Ok(block)
+}
+
+/// Sync the managed .netrc block with opted-in profiles.
+/// Returns the profiles written and those skipped for lack of a token.
+pub fn sync_netrc(config: &Config) -> Result<(Vec<String>, Vec<String>)> {
+    let mut entries = Vec::new();
+    let mut written = Vec::new();
+    let mut skipped = Vec::new();
+
+    for name in config.profile_names() {
+        if let Some(profile) = config.get_profile(name) {
+            if !profile.netrc {
+                continue;
+            }
+            match secrets::get_token(profile.keyring_account(name))? {
+                Some(token) => {
+                    entries.push((name.as_str(), profile, token));
+                    written.push(name.clone());
+                }
+                None => skipped.push(name.clone()),
+            }
+        }
+    }
+
+    let block = generate_managed_block(&entries)?;
+
+    let path = netrc_path()?;
+    let current = if path.exists() {
+        fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?
+    } else {
+        String::new()
+    };
+    let (content, _) = managed::splice_block(&current, MANAGED_START, MANAGED_END, &block);
+
+    // Not journaled: the previous content holds credentials that must not
+    // be copied into gitid's state directory
+    write_private(&path, &content)?;
+
+    Ok((written, skipped))
+}
+
+/// Write a file only its owner can read. The content goes into a
+/// temporary file created 0600 next to it, which then replaces it, so the
+/// tokens are never readable by others, even for a moment.
+pub fn write_private(path: &Path, content: &str) -> Result<()> {
+    let temp = path.with_extension("gitid-tmp");
+    let _ = fs::remove_file(&temp);
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let written = options
+        .open(&temp)
+        .and_then(|mut file| file.write_all(content.as_bytes()))
+        .and_then(|_| fs::rename(&temp, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    written.with_context(|| format!("Failed to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(platform: Platform, host: Option<&str>) -> Profile {
+        let mut profile = Profile::new(
+            "John Doe".to_string(),
+            "john@company.com".to_string(),
+            platform,
+            "~/.ssh/id_work".to_string(),
+            None,
+            host.map(str::to_string),
+        );
+        profile.netrc = true;
+        profile
+    }
+
+    #[test]
+    fn test_generate_managed_block() {
+        let work = profile(Platform::Gitlab, Some("gitlab.acme.com"));
+        let block = generate_managed_block(&[("work", &work, "glpat-123".to_string())]).unwrap();
+        assert!(block.starts_with(MANAGED_START));
+        assert!(block.contains("machine gitlab.acme.com\n  login oauth2\n  password glpat-123\n"));
+    }
+
+    #[test]
+    fn test_duplicate_host_rejected() {
+        let a = profile(Platform::Github, None);
+        let b = profile(Platform::Github, None);
+        let result = generate_managed_block(&[
+            ("a", &a, "t1".to_string()),
+            ("b", &b, "t2".to_string()),
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("gitid-netrc-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(".netrc");
+        fs::write(&path, "machine old\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+
+        write_private(&path, "machine github.com\n").unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode() & 0o777;
+        let content = fs::read_to_string(&path).unwrap();
+        let leftovers = fs::read_dir(&dir).unwrap().count();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(mode, 0o600);
+        assert_eq!(content, "machine github.com\n");
+        assert_eq!(leftovers, 1);
+    }
+}
@@ -0,0 +1,49 @@
+/// Match `text` against a glob pattern, case-insensitively.
+///
+/// `*` matches any run of characters except `/`, and `**` matches
+/// anything including `/`, so the same matcher works for emails
+/// ("*@acme.com") and paths ("~/work/**").
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    match_from(&pattern, &text)
+}
+
+fn match_from(pattern: &[char], text: &[char]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        ['*', '*', rest @ ..] => (0..=text.len()).any(|i| match_from(rest, &text[i..])),
+        ['*', rest @ ..] => {
+            for i in 0..=text.len() {
+                if match_from(rest, &text[i..]) {
+                    return true;
+                }
+                if text.get(i) == Some(&'/') {
+                    break;
+                }
+            }
+            false
+        }
+        [c, rest @ ..] => text.first() == Some(c) && match_from(rest, &text[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_email_patterns() {
+        assert!(glob_match("*@acme.com", "john@acme.com"));
+        assert!(glob_match("*@acme.com", "John@ACME.com"));
+        assert!(!glob_match("*@acme.com", "john@acme.com.evil"));
+        assert!(glob_match("*@*.acme.com", "john@eu.acme.com"));
+    }
+
+    #[test]
+    fn test_path_patterns() {
+        assert!(glob_match("/home/me/work/**", "/home/me/work/app/src"));
+        assert!(glob_match("/home/me/*/app", "/home/me/work/app"));
+        assert!(!glob_match("/home/me/*", "/home/me/work/app"));
+    }
+}
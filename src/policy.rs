@@ -0,0 +1,256 @@
+use crate::config::Config;
+use crate::git::{self, RemoteUrl};
+use crate::pattern;
+use crate::profile::Profile;
+use crate::prompt;
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// An identity requirement for repositories on matching remotes, e.g.
+/// "anything on gitlab.acme.com must be signed and use an @acme.com email"
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Policy {
+    /// Label shown in violation messages; defaults to the host pattern
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Glob matched against the remote's hostname, e.g. `gitlab.acme.com`
+    pub host: String,
+    /// Glob matched against the repository path on the host, e.g. `acme/**`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Commits must be signed
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub require_signing: bool,
+    /// Glob the commit email must match, e.g. `*@acme.com`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    /// Only these profiles may be used
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub profiles: Vec<String>,
+}
+
+impl Policy {
+    /// Name used when reporting this policy
+    pub fn label(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.host)
+    }
+
+    fn applies_to(&self, remote: &RemoteUrl) -> bool {
+        pattern::glob_match(&self.host, &remote.host)
+            && self
+                .path
+                .as_deref()
+                .is_none_or(|p| pattern::glob_match(p, remote.path.trim_end_matches(".git")))
+    }
+}
+
+/// The identity a repository is using, or is about to use
+#[derive(Debug)]
+pub struct Identity<'a> {
+    pub profile: Option<&'a str>,
+    pub email: Option<&'a str>,
+    pub signing: bool,
+}
+
+impl<'a> Identity<'a> {
+    /// The identity a profile applies
+    pub fn for_profile(name: &'a str, profile: &'a Profile) -> Self {
+        Self {
+            profile: Some(name),
+            email: Some(&profile.email),
+            signing: profile.signing_key().is_some(),
+        }
+    }
+}
+
+/// A broken policy requirement
+#[derive(Debug, PartialEq, Eq)]
+pub struct Violation {
+    pub policy: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "policy '{}': {}", self.policy, self.message)
+    }
+}
+
+/// Map SSH host aliases from the config back to the real hostname, so
+/// policies match remotes that have already been rewritten
+fn resolve_host(config: &Config, remote: RemoteUrl) -> RemoteUrl {
+    for name in config.profile_names() {
+        if let Some(profile) = config.get_profile(name) {
+            for (alias, hostname) in profile.ssh_host_aliases(name) {
+                if alias == remote.host {
+                    return RemoteUrl {
+                        host: hostname,
+                        ..remote
+                    };
+                }
+            }
+        }
+    }
+    remote
+}
+
+/// Check an identity against every policy that applies to the given remotes
+pub fn evaluate(
+    config: &Config,
+    remotes: &[(String, String)],
+    identity: &Identity,
+) -> Vec<Violation> {
+    let hosts: Vec<RemoteUrl> = remotes
+        .iter()
+        .filter_map(|(_, url)| RemoteUrl::parse(url))
+        .map(|r| resolve_host(config, r))
+        .collect();
+
+    let mut violations = Vec::new();
+    for policy in &config.policies {
+        if !hosts.iter().any(|h| policy.applies_to(h)) {
+            continue;
+        }
+        let mut violate = |message: String| {
+            violations.push(Violation {
+                policy: policy.label().to_string(),
+                message,
+            })
+        };
+
+        if !policy.profiles.is_empty()
+            && !identity
+                .profile
+                .is_some_and(|p| policy.profiles.iter().any(|allowed| allowed == p))
+        {
+            violate(format!(
+                "profile must be one of {} (using {})",
+                policy.profiles.join(", "),
+                identity.profile.unwrap_or("none")
+            ));
+        }
+
+        if let Some(ref pattern) = policy.email {
+            if !identity.email.is_some_and(|e| pattern::glob_match(pattern, e)) {
+                violate(format!(
+                    "email must match {} (using {})",
+                    pattern,
+                    identity.email.unwrap_or("unset")
+                ));
+            }
+        }
+
+        if policy.require_signing && !identity.signing {
+            violate("commit signing must be enabled".to_string());
+        }
+    }
+    violations
+}
+
+/// Check the identity the current repository has applied against the
+/// policies for its remotes
+pub fn evaluate_repository(config: &Config) -> Result<Vec<Violation>> {
+    let profile = prompt::get_current_profile(config)?;
+    let email = git::get_effective_config("user.email")?;
+    let signing = git::get_effective_config("commit.gpgsign")?;
+    let identity = Identity {
+        profile: profile.as_deref(),
+        email: email.as_deref(),
+        signing: signing.as_deref() == Some("true"),
+    };
+    Ok(evaluate(config, &git::remote_urls()?, &identity))
+}
+
+/// Profiles that meet every policy for the given remotes
+pub fn compliant_profiles<'a>(config: &'a Config, remotes: &[(String, String)]) -> Vec<&'a String> {
+    config
+        .profile_names()
+        .into_iter()
+        .filter(|name| {
+            config
+                .get_profile(name)
+                .is_some_and(|p| evaluate(config, remotes, &Identity::for_profile(name, p)).is_empty())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::Platform;
+
+    fn acme_config() -> Config {
+        let mut config: Config = toml::from_str(
+            r#"
+[[policies]]
+host = "gitlab.acme.com"
+require_signing = true
+email = "*@acme.com"
+"#,
+        )
+        .unwrap();
+        config.profiles.insert(
+            "work".to_string(),
+            Profile::new(
+                "John Doe".to_string(),
+                "john@acme.com".to_string(),
+                Platform::Gitlab,
+                "~/.ssh/id_work".to_string(),
+                None,
+                Some("gitlab.acme.com".to_string()),
+            ),
+        );
+        config
+    }
+
+    fn remotes(url: &str) -> Vec<(String, String)> {
+        vec![("origin".to_string(), url.to_string())]
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let config = acme_config();
+        let unsigned = Identity {
+            profile: Some("work"),
+            email: Some("john@example.com"),
+            signing: false,
+        };
+
+        let violations = evaluate(&config, &remotes("git@gitlab.acme.com:acme/app.git"), &unsigned);
+        assert_eq!(violations.len(), 2);
+        assert_eq!(violations[0].policy, "gitlab.acme.com");
+
+        // Other hosts are not covered
+        assert!(evaluate(&config, &remotes("git@github.com:me/app.git"), &unsigned).is_empty());
+
+        let compliant = Identity {
+            profile: Some("work"),
+            email: Some("john@acme.com"),
+            signing: true,
+        };
+        assert!(evaluate(&config, &remotes("git@gitlab.acme.com:acme/app.git"), &compliant).is_empty());
+    }
+
+    #[test]
+    fn test_evaluate_resolves_aliases() {
+        let config = acme_config();
+        let profile = config.get_profile("work").unwrap();
+        let identity = Identity::for_profile("work", profile);
+
+        let violations = evaluate(&config, &remotes("git@gitlab-work:acme/app.git"), &identity);
+        assert_eq!(violations.len(), 1);
+    }
+
+    #[test]
+    fn test_compliant_profiles() {
+        let mut config = acme_config();
+        let mut signed = config.get_profile("work").unwrap().clone();
+        signed.gpg_key = Some("ABCD1234".to_string());
+        config.profiles.insert("work-signed".to_string(), signed);
+
+        let acme = remotes("git@gitlab.acme.com:acme/app.git");
+        assert_eq!(compliant_profiles(&config, &acme), vec!["work-signed"]);
+        assert_eq!(compliant_profiles(&config, &remotes("git@github.com:me/app.git")).len(), 2);
+    }
+}
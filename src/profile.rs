@@ -1,12 +1,22 @@
+use crate::pattern::glob_match;
+use crate::ssh_keys;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use thiserror::Error;
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum Platform {
     Github,
     Gitlab,
     Both,
+    /// codeberg.org (Forgejo)
+    Codeberg,
+    /// Gitea or Forgejo, usually self-hosted with a custom host
+    Gitea,
+    /// Any other forge; requires a host and gets no CLI integration
+    Custom,
 }
 
 impl std::fmt::Display for Platform {
@@ -15,6 +25,51 @@ impl std::fmt::Display for Platform {
             Platform::Github => write!(f, "github"),
             Platform::Gitlab => write!(f, "gitlab"),
             Platform::Both => write!(f, "both"),
+            Platform::Codeberg => write!(f, "codeberg"),
+            Platform::Gitea => write!(f, "gitea"),
+            Platform::Custom => write!(f, "custom"),
+        }
+    }
+}
+
+impl Platform {
+    /// Platform a hostname names, e.g. "gitlab.acme.com" or
+    /// "forgejo.internal", for hosts that say what they run
+    pub fn from_host(host: &str) -> Option<Platform> {
+        let host = host.to_lowercase();
+        if host.contains("github") {
+            Some(Platform::Github)
+        } else if host.contains("gitlab") {
+            Some(Platform::Gitlab)
+        } else if host.contains("codeberg") {
+            Some(Platform::Codeberg)
+        } else if host.contains("gitea") || host.contains("forgejo") {
+            Some(Platform::Gitea)
+        } else {
+            None
+        }
+    }
+
+    /// Whether a profile on this platform can talk to `other`'s hosts.
+    /// Codeberg runs Forgejo, so Gitea profiles cover it too.
+    pub fn serves(&self, other: &Platform) -> bool {
+        self == other
+            || matches!(
+                (self, other),
+                (Platform::Both, Platform::Github | Platform::Gitlab)
+                    | (Platform::Gitea, Platform::Codeberg)
+            )
+    }
+
+    /// Product name for messages
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            Platform::Github => "GitHub",
+            Platform::Gitlab => "GitLab",
+            Platform::Both => "GitHub/GitLab",
+            Platform::Codeberg => "Codeberg",
+            Platform::Gitea => "Gitea/Forgejo",
+            Platform::Custom => "custom forge",
         }
     }
 }
@@ -27,26 +82,168 @@ impl std::str::FromStr for Platform {
             "github" => Ok(Platform::Github),
             "gitlab" => Ok(Platform::Gitlab),
             "both" => Ok(Platform::Both),
+            "codeberg" => Ok(Platform::Codeberg),
+            "gitea" | "forgejo" => Ok(Platform::Gitea),
+            "custom" => Ok(Platform::Custom),
             _ => Err(ProfileError::InvalidPlatform(s.to_string())),
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct Profile {
+    /// Git user.name for commits
     pub name: String,
+    /// Git user.email for commits
     pub email: String,
+    /// Hosting platform the profile authenticates against
     pub platform: Platform,
+    /// Path to the SSH private key
     pub ssh_key: String,
+    /// Further keys offered after ssh_key, e.g. a file key for automation
+    /// next to a FIDO key for interactive use
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra_ssh_keys: Vec<String>,
+    /// How git is pointed at ssh_key; overrides the config-wide ssh_mode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ssh_mode: Option<SshMode>,
+    /// Extra ssh_config options for the profile's Host entries, e.g.
+    /// `{ Port = "2222", ProxyJump = "bastion" }`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub ssh_options: BTreeMap<String, String>,
+    /// GPG key ID used for commit signing
     #[serde(skip_serializing_if = "Option::is_none")]
     pub gpg_key: Option<String>,
+    /// Sign with the SSH key instead of gpg_key; defaults to GPG when a
+    /// gpg_key is set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signing: Option<SigningFormat>,
+    /// Custom host for enterprise or self-hosted instances
     #[serde(skip_serializing_if = "Option::is_none")]
     pub host: Option<String>,
+    /// Account login on the platform (GitHub/GitLab username)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    /// Labels grouping profiles, e.g. `["client", "acme"]`, for `--tag`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Free-text note on what the profile is for, shown in list and pickers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// GnuPG home directory holding this profile's keyring
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gnupghome: Option<String>,
+    /// Write this profile's keyring token into the managed ~/.netrc block
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub netrc: bool,
+    /// Keyring account holding this profile's platform token, when it is
+    /// not the profile name (e.g. one token shared by two profiles)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keyring: Option<String>,
+    /// Repository owners (users/orgs/groups) this profile may push to; empty allows all
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow_owners: Vec<String>,
+    /// Repository owners this profile must never push to
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deny_owners: Vec<String>,
+    /// Git Credential Manager account used for HTTPS remotes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gcm: Option<GcmConfig>,
+    /// Authenticate API calls as a GitHub App installation (bot profiles)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub github_app: Option<GithubApp>,
+    /// git send-email settings for patch submission as this profile
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sendemail: Option<SendEmail>,
+}
+
+/// SMTP settings written to a `sendemail.<identity>` block on switch
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct SendEmail {
+    /// SMTP server hostname (sendemail.smtpServer)
+    pub smtp_server: String,
+    /// SMTP port (sendemail.smtpServerPort)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub smtp_server_port: Option<u16>,
+    /// SMTP login (sendemail.smtpUser)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub smtp_user: Option<String>,
+    /// "ssl" or "tls" (sendemail.smtpEncryption)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub smtp_encryption: Option<String>,
+    /// Envelope sender, if not the profile's name and email (sendemail.from)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+}
+
+impl SendEmail {
+    /// git config keys of the identity block with their values; None
+    /// means the key should be cleared
+    pub fn settings(&self) -> Vec<(&'static str, Option<String>)> {
+        vec![
+            ("smtpServer", Some(self.smtp_server.clone())),
+            ("smtpServerPort", self.smtp_server_port.map(|p| p.to_string())),
+            ("smtpUser", self.smtp_user.clone()),
+            ("smtpEncryption", self.smtp_encryption.clone()),
+            ("from", self.from.clone()),
+        ]
+    }
+}
+
+/// GitHub App whose installation tokens a bot profile uses
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct GithubApp {
+    /// App ID from the app's settings page
+    pub app_id: u64,
+    /// Installation to mint tokens for
+    pub installation_id: u64,
+    /// Path to the app's PEM private key
+    pub private_key: String,
+}
+
+/// How git picks the SSH key for a repository's remotes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SshMode {
+    /// Rewrite remotes to the profile's ~/.ssh/config host alias
+    #[default]
+    Alias,
+    /// Keep remote URLs and set core.sshCommand to use the profile's key
+    Command,
+    /// Keep remote URLs and write url.<alias>.insteadOf rules routing the
+    /// profile's hosts through its aliases
+    InsteadOf,
+}
+
+/// Key type git signs commits with (gpg.format)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SigningFormat {
+    /// OpenPGP signatures with the profile's gpg_key
+    Gpg,
+    /// SSH signatures with the profile's ssh_key (git 2.34+)
+    Ssh,
+}
+
+/// Git Credential Manager account selection, applied on switch so HTTPS
+/// pushes use the right stored account
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct GcmConfig {
+    /// Account GCM should pick (credential.<url>.username)
+    pub username: String,
+    /// GCM host provider (e.g. "github", "gitlab"); inferred from the
+    /// platform for custom hosts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
 }
 
 #[derive(Debug, Error)]
 pub enum ProfileError {
-    #[error("Invalid platform: {0}. Must be 'github', 'gitlab', or 'both'")]
+    #[error("Invalid platform: {0}. Must be 'github', 'gitlab', 'both', 'codeberg', 'gitea', or 'custom'")]
     InvalidPlatform(String),
     #[error("Profile name cannot be empty")]
     EmptyName,
@@ -54,6 +251,16 @@ pub enum ProfileError {
     EmptyEmail,
     #[error("SSH key path cannot be empty")]
     EmptySshKey,
+    #[error("Custom platform profiles must set a host")]
+    MissingHost,
+    #[error("Invalid SSH option '{0}': keys must be a single word and values a single non-empty line")]
+    InvalidSshOption(String),
+    #[error("'{0}' is not one of this profile's SSH keys")]
+    UnknownSshKey(String),
+    #[error("Owner '{0}' is in this profile's deny_owners")]
+    OwnerDenied(String),
+    #[error("Owner '{0}' is not in this profile's allow_owners")]
+    OwnerNotAllowed(String),
 }
 
 impl Profile {
@@ -70,8 +277,23 @@ impl Profile {
             email,
             platform,
             ssh_key,
+            extra_ssh_keys: Vec::new(),
+            ssh_mode: None,
+            ssh_options: BTreeMap::new(),
             gpg_key,
+            signing: None,
             host,
+            username: None,
+            tags: Vec::new(),
+            description: None,
+            gnupghome: None,
+            netrc: false,
+            keyring: None,
+            allow_owners: Vec::new(),
+            deny_owners: Vec::new(),
+            gcm: None,
+            github_app: None,
+            sendemail: None,
         }
     }
 
@@ -85,6 +307,90 @@ impl Profile {
         if self.ssh_key.trim().is_empty() {
             return Err(ProfileError::EmptySshKey);
         }
+        if self.platform == Platform::Custom && self.host.as_deref().is_none_or(|h| h.trim().is_empty()) {
+            return Err(ProfileError::MissingHost);
+        }
+        self.check_ssh_options()
+    }
+
+    /// Check that ssh_options can be written as ssh_config lines
+    pub fn check_ssh_options(&self) -> Result<(), ProfileError> {
+        for (key, value) in &self.ssh_options {
+            let key_ok = !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric());
+            let value_ok = !value.trim().is_empty() && !value.contains(['\n', '\r']);
+            if !key_ok || !value_ok {
+                return Err(ProfileError::InvalidSshOption(key.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Signing format and user.signingkey value, if the profile signs
+    /// commits. SSH signing points git at the public half of ssh_key.
+    pub fn signing_key(&self) -> Option<(SigningFormat, String)> {
+        match self.signing {
+            Some(SigningFormat::Ssh) => Some((
+                SigningFormat::Ssh,
+                ssh_keys::public_key_path(&self.ssh_key).display().to_string(),
+            )),
+            _ => self.gpg_key.clone().map(|key| (SigningFormat::Gpg, key)),
+        }
+    }
+
+    /// Whether the profile carries `tag` (case-insensitive)
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t.eq_ignore_ascii_case(tag))
+    }
+
+    /// ssh_key followed by extra_ssh_keys, in the order ssh offers them
+    pub fn ssh_key_paths(&self) -> Vec<&str> {
+        std::iter::once(self.ssh_key.as_str())
+            .chain(self.extra_ssh_keys.iter().map(String::as_str))
+            .collect()
+    }
+
+    /// This profile restricted to one of its keys. `key` may be a configured
+    /// path, the same path written differently, or just the key's file name.
+    pub fn with_ssh_key(&self, key: &str) -> Result<Profile, ProfileError> {
+        let wanted = ssh_keys::expand_path(key);
+        let chosen = self
+            .ssh_key_paths()
+            .into_iter()
+            .find(|path| {
+                *path == key
+                    || ssh_keys::expand_path(path) == wanted
+                    || std::path::Path::new(path).file_name() == Some(std::ffi::OsStr::new(key))
+            })
+            .ok_or_else(|| ProfileError::UnknownSshKey(key.to_string()))?
+            .to_string();
+
+        let mut profile = self.clone();
+        profile.ssh_key = chosen;
+        profile.extra_ssh_keys.clear();
+        Ok(profile)
+    }
+
+    /// Keyring account the profile's platform token is stored under
+    pub fn keyring_account<'a>(&'a self, profile_name: &'a str) -> &'a str {
+        self.keyring.as_deref().unwrap_or(profile_name)
+    }
+
+    /// Whether a repository owner is this profile's own account
+    pub fn is_own_account(&self, owner: &str) -> bool {
+        self.username
+            .as_deref()
+            .is_some_and(|u| u.eq_ignore_ascii_case(owner))
+    }
+
+    /// Check a repository owner against allow_owners/deny_owners.
+    /// Entries may use `*` wildcards and match case-insensitively.
+    pub fn check_owner(&self, owner: &str) -> Result<(), ProfileError> {
+        if self.deny_owners.iter().any(|p| glob_match(p, owner)) {
+            return Err(ProfileError::OwnerDenied(owner.to_string()));
+        }
+        if !self.allow_owners.is_empty() && !self.allow_owners.iter().any(|p| glob_match(p, owner)) {
+            return Err(ProfileError::OwnerNotAllowed(owner.to_string()));
+        }
         Ok(())
     }
 
@@ -95,6 +401,10 @@ impl Profile {
             match self.platform {
                 Platform::Github | Platform::Both => "github.com",
                 Platform::Gitlab => "gitlab.com",
+                Platform::Codeberg => "codeberg.org",
+                Platform::Gitea => "gitea.com",
+                // Rejected by validate(); only reachable for unchecked profiles
+                Platform::Custom => "localhost",
             }
         }
     }
@@ -104,10 +414,82 @@ impl Profile {
         let platform_prefix = match self.platform {
             Platform::Github => "github",
             Platform::Gitlab => "gitlab",
-            Platform::Both => "git",
+            Platform::Both | Platform::Custom => "git",
+            Platform::Codeberg => "codeberg",
+            Platform::Gitea => "gitea",
         };
         format!("{}-{}", platform_prefix, profile_name)
     }
+
+    /// Every (alias, hostname) pair the SSH config gets for this profile.
+    /// The primary alias comes first; 'both' profiles also get
+    /// platform-specific aliases.
+    pub fn ssh_host_aliases(&self, profile_name: &str) -> Vec<(String, String)> {
+        let mut aliases = vec![(
+            self.ssh_host_alias(profile_name),
+            self.default_host().to_string(),
+        )];
+
+        if matches!(self.platform, Platform::Both) {
+            aliases.push((format!("github-{}", profile_name), "github.com".to_string()));
+            aliases.push((format!("gitlab-{}", profile_name), "gitlab.com".to_string()));
+        }
+
+        aliases
+    }
+
+    /// Hosts this profile's SSH entries and credentials point at
+    pub fn served_hosts(&self) -> Vec<&str> {
+        let mut hosts = vec![self.default_host()];
+        if matches!(self.platform, Platform::Both) {
+            for host in ["github.com", "gitlab.com"] {
+                if !hosts.contains(&host) {
+                    hosts.push(host);
+                }
+            }
+        }
+        hosts
+    }
+
+    /// `credential.https://<host>.username` and `.provider` keys `use` sets
+    /// or clears for this profile's hosts
+    pub fn credential_keys(&self) -> Vec<String> {
+        self.served_hosts()
+            .into_iter()
+            .flat_map(|host| {
+                [
+                    format!("credential.https://{}.username", host),
+                    format!("credential.https://{}.provider", host),
+                ]
+            })
+            .collect()
+    }
+
+    /// GCM provider for a host: the configured one, or the platform's for
+    /// custom hosts GCM can't identify by name
+    pub fn gcm_provider(&self, host: &str) -> Option<String> {
+        let gcm = self.gcm.as_ref()?;
+        if gcm.provider.is_some() {
+            return gcm.provider.clone();
+        }
+        if host == "github.com" || host == "gitlab.com" {
+            return None;
+        }
+        match self.platform {
+            Platform::Github => Some("github".to_string()),
+            Platform::Gitlab => Some("gitlab".to_string()),
+            // GCM has no Gitea provider; its generic one needs no hint
+            Platform::Both | Platform::Codeberg | Platform::Gitea | Platform::Custom => None,
+        }
+    }
+
+    /// SSH alias that routes to `host` through this profile's key
+    pub fn alias_for_host(&self, profile_name: &str, host: &str) -> Option<String> {
+        self.ssh_host_aliases(profile_name)
+            .into_iter()
+            .find(|(_, hostname)| hostname == host)
+            .map(|(alias, _)| alias)
+    }
 }
 
 #[cfg(test)]
@@ -120,6 +502,8 @@ mod tests {
         assert_eq!("gitlab".parse::<Platform>().unwrap(), Platform::Gitlab);
         assert_eq!("both".parse::<Platform>().unwrap(), Platform::Both);
         assert_eq!("GITHUB".parse::<Platform>().unwrap(), Platform::Github);
+        assert_eq!("codeberg".parse::<Platform>().unwrap(), Platform::Codeberg);
+        assert_eq!("forgejo".parse::<Platform>().unwrap(), Platform::Gitea);
     }
 
     #[test]
@@ -147,4 +531,145 @@ mod tests {
         );
         assert!(matches!(profile.validate(), Err(ProfileError::EmptyName)));
     }
+
+    #[test]
+    fn test_custom_platform_requires_host() {
+        let mut profile = Profile::new(
+            "John Doe".to_string(),
+            "john@company.com".to_string(),
+            Platform::Custom,
+            "~/.ssh/id_work".to_string(),
+            None,
+            None,
+        );
+        assert!(matches!(profile.validate(), Err(ProfileError::MissingHost)));
+
+        profile.host = Some("review.company.com".to_string());
+        assert!(profile.validate().is_ok());
+        assert_eq!(profile.ssh_host_alias("work"), "git-work");
+        assert_eq!(profile.served_hosts(), vec!["review.company.com"]);
+    }
+
+    #[test]
+    fn test_ssh_options_validation() {
+        let mut profile = Profile::new(
+            "John Doe".to_string(),
+            "john@company.com".to_string(),
+            Platform::Gitlab,
+            "~/.ssh/id_work".to_string(),
+            None,
+            None,
+        );
+        profile.ssh_options.insert("ProxyJump".to_string(), "bastion".to_string());
+        assert!(profile.validate().is_ok());
+
+        profile.ssh_options.insert("Port".to_string(), "22\nHost *".to_string());
+        assert!(matches!(profile.validate(), Err(ProfileError::InvalidSshOption(_))));
+
+        profile.ssh_options.clear();
+        profile.ssh_options.insert("Proxy Command".to_string(), "nc".to_string());
+        assert!(matches!(profile.validate(), Err(ProfileError::InvalidSshOption(_))));
+    }
+
+    #[test]
+    fn test_with_ssh_key() {
+        let mut profile = Profile::new(
+            "John Doe".to_string(),
+            "john@company.com".to_string(),
+            Platform::Github,
+            "~/.ssh/id_work_sk".to_string(),
+            None,
+            None,
+        );
+        profile.extra_ssh_keys = vec!["~/.ssh/id_work_ci".to_string()];
+        assert_eq!(profile.ssh_key_paths(), vec!["~/.ssh/id_work_sk", "~/.ssh/id_work_ci"]);
+
+        let ci = profile.with_ssh_key("id_work_ci").unwrap();
+        assert_eq!(ci.ssh_key_paths(), vec!["~/.ssh/id_work_ci"]);
+        let sk = profile.with_ssh_key("~/.ssh/id_work_sk").unwrap();
+        assert_eq!(sk.ssh_key_paths(), vec!["~/.ssh/id_work_sk"]);
+        assert!(matches!(profile.with_ssh_key("id_other"), Err(ProfileError::UnknownSshKey(_))));
+    }
+
+    #[test]
+    fn test_signing_key() {
+        let mut profile = Profile::new(
+            "John Doe".to_string(),
+            "john@company.com".to_string(),
+            Platform::Github,
+            "/keys/id_work".to_string(),
+            Some("ABC123".to_string()),
+            None,
+        );
+        assert_eq!(
+            profile.signing_key(),
+            Some((SigningFormat::Gpg, "ABC123".to_string()))
+        );
+
+        profile.signing = Some(SigningFormat::Ssh);
+        assert_eq!(
+            profile.signing_key(),
+            Some((SigningFormat::Ssh, "/keys/id_work.pub".to_string()))
+        );
+
+        profile.signing = Some(SigningFormat::Gpg);
+        profile.gpg_key = None;
+        assert_eq!(profile.signing_key(), None);
+    }
+
+    #[test]
+    fn test_check_owner() {
+        let mut profile = Profile::new(
+            "John Doe".to_string(),
+            "john@example.com".to_string(),
+            Platform::Github,
+            "~/.ssh/id_ed25519".to_string(),
+            None,
+            None,
+        );
+        assert!(profile.check_owner("anyone").is_ok());
+
+        profile.deny_owners = vec!["acme-*".to_string()];
+        assert!(matches!(
+            profile.check_owner("ACME-corp"),
+            Err(ProfileError::OwnerDenied(_))
+        ));
+
+        profile.allow_owners = vec!["johndoe".to_string()];
+        assert!(profile.check_owner("johndoe").is_ok());
+        assert!(matches!(
+            profile.check_owner("someone-else"),
+            Err(ProfileError::OwnerNotAllowed(_))
+        ));
+    }
+
+    #[test]
+    fn test_gcm_provider() {
+        let mut profile = Profile::new(
+            "John Doe".to_string(),
+            "john@company.com".to_string(),
+            Platform::Gitlab,
+            "~/.ssh/id_work".to_string(),
+            None,
+            Some("gitlab.company.com".to_string()),
+        );
+        assert_eq!(profile.gcm_provider("gitlab.company.com"), None);
+
+        profile.gcm = Some(GcmConfig {
+            username: "jdoe".to_string(),
+            provider: None,
+        });
+        assert_eq!(profile.gcm_provider("gitlab.company.com").as_deref(), Some("gitlab"));
+        assert_eq!(profile.gcm_provider("gitlab.com"), None);
+
+        // Keys are listed whether or not the profile uses GCM, so switching
+        // to it can clear them
+        assert_eq!(
+            profile.credential_keys(),
+            vec![
+                "credential.https://gitlab.company.com.username",
+                "credential.https://gitlab.company.com.provider"
+            ]
+        );
+    }
 }
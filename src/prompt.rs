@@ -1,73 +1,232 @@
 use crate::config::Config;
+use crate::daemon;
+use crate::detect;
 use crate::git::{self, ConfigScope};
+use crate::profile::Platform;
 use anyhow::Result;
+use serde::Serialize;
+use std::fs;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 
-/// Get the current profile name based on git config
-pub fn get_current_profile(config: &Config) -> Result<Option<String>> {
+/// Files modified this recently are not trusted to have a distinct mtime
+/// from a follow-up write, so answers depending on them aren't cached
+const RACY_WINDOW: Duration = Duration::from_secs(2);
+
+/// The identity git applies here and the scope it comes from: local config
+/// if it sets either field, otherwise global
+fn current_user() -> Result<(Option<ConfigScope>, Option<String>, Option<String>)> {
     // First try local config
     let (name, email) = git::get_current_user(ConfigScope::Local)?;
+    if name.is_some() || email.is_some() {
+        return Ok((Some(ConfigScope::Local), name, email));
+    }
 
     // If no local config, try global
-    let (name, email) = if name.is_none() && email.is_none() {
-        git::get_current_user(ConfigScope::Global)?
-    } else {
-        (name, email)
-    };
+    let (name, email) = git::get_current_user(ConfigScope::Global)?;
+    let scope = (name.is_some() || email.is_some()).then_some(ConfigScope::Global);
+    Ok((scope, name, email))
+}
 
-    // Match against profiles
-    if let (Some(name), Some(email)) = (name, email) {
-        for (profile_name, profile) in &config.profiles {
-            if profile.name == name && profile.email == email {
-                return Ok(Some(profile_name.clone()));
-            }
+/// Profile whose name and email are exactly the given identity
+pub fn matching_profile(config: &Config, name: Option<&str>, email: Option<&str>) -> Option<String> {
+    let (name, email) = (name?, email?);
+    config
+        .profiles
+        .iter()
+        .find(|(_, profile)| profile.name == name && profile.email == email)
+        .map(|(profile_name, _)| profile_name.clone())
+}
+
+/// Get the current profile name based on git config. When several profiles
+/// share the identity, the one pinned to the repository wins.
+pub fn get_current_profile(config: &Config) -> Result<Option<String>> {
+    let (_, name, email) = current_user()?;
+    let (name, email) = (name.as_deref(), email.as_deref());
+    if let Some(pinned) = detect::pinned_profile()? {
+        let profile = config.get_profile(&pinned);
+        if profile.is_some_and(|p| Some(p.name.as_str()) == name && Some(p.email.as_str()) == email) {
+            return Ok(Some(pinned));
         }
     }
+    Ok(matching_profile(config, name, email))
+}
 
-    Ok(None)
+/// Current identity as reported by `gitid current --json`
+#[derive(Debug, Serialize)]
+pub struct CurrentIdentity {
+    pub profile: Option<String>,
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub platform: Option<Platform>,
+    pub scope: Option<ConfigScope>,
+    /// "gpg", "ssh", or "off", from the effective commit.gpgsign and gpg.format
+    pub signing: &'static str,
+    pub matched: bool,
 }
 
-/// Output current profile for shell prompt (porcelain mode)
-pub fn output_porcelain(config: &Config) -> Result<()> {
-    if let Some(profile_name) = get_current_profile(config)? {
-        println!("{}", profile_name);
+/// How commits are signed given commit.gpgsign and gpg.format
+fn signing_status(gpgsign: Option<&str>, format: Option<&str>) -> &'static str {
+    let enabled = gpgsign
+        .is_some_and(|v| matches!(v.to_ascii_lowercase().as_str(), "true" | "yes" | "on" | "1"));
+    match (enabled, format) {
+        (false, _) => "off",
+        (true, Some("ssh")) => "ssh",
+        (true, _) => "gpg",
     }
-    Ok(())
 }
 
-/// Output current profile with formatting (human-readable mode)
-pub fn output_human(config: &Config) -> Result<()> {
-    if !git::is_git_repo() {
-        println!("Not in a git repository");
-        return Ok(());
+/// Gather the identity git applies in the working directory
+pub fn current_identity(config: &Config) -> Result<CurrentIdentity> {
+    let (scope, name, email) = current_user()?;
+    let profile = matching_profile(config, name.as_deref(), email.as_deref());
+    let platform = profile
+        .as_deref()
+        .and_then(|p| config.get_profile(p))
+        .map(|p| p.platform.clone());
+    let signing = signing_status(
+        git::get_effective_config("commit.gpgsign")?.as_deref(),
+        git::get_effective_config("gpg.format")?.as_deref(),
+    );
+
+    Ok(CurrentIdentity {
+        matched: profile.is_some(),
+        profile,
+        name,
+        email,
+        platform,
+        scope,
+        signing,
+    })
+}
+
+/// Config files the current profile is derived from: the repository's
+/// local config, the global gitconfig, and the gitid configs
+fn stamp_files(common_dir: Option<&Path>) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    if let Some(dir) = common_dir {
+        files.push(dir.join("config"));
+        files.push(dir.join("config.worktree"));
     }
+    files.extend(daemon::global_git_configs());
+    files.push(Config::config_path()?);
+    files.push(Config::system_config_path());
+    Ok(files)
+}
 
-    if let Some(profile_name) = get_current_profile(config)? {
-        if let Some(profile) = config.get_profile(&profile_name) {
-            println!("Current profile: {}", profile_name);
-            println!("  Name:  {}", profile.name);
-            println!("  Email: {}", profile.email);
-            println!("  Platform: {}", profile.platform);
+/// Hash of the mtimes and sizes of `files`, or None if one of them changed
+/// too recently to be told apart from the next write
+fn stamp(files: &[PathBuf], now: SystemTime) -> Option<u64> {
+    let mut hasher = DefaultHasher::new();
+    for path in files {
+        let meta = fs::metadata(path).ok();
+        let modified = meta.as_ref().and_then(|m| m.modified().ok());
+        if modified.is_some_and(|m| now.duration_since(m).map_or(true, |age| age < RACY_WINDOW)) {
+            return None;
         }
-    } else {
-        // Show git config even if no profile matches
-        let (name, email) = git::get_current_user(ConfigScope::Local)?;
-        let (global_name, global_email) = git::get_current_user(ConfigScope::Global)?;
-
-        let name = name.or(global_name);
-        let email = email.or(global_email);
-
-        if name.is_some() || email.is_some() {
-            println!("Current git identity (no matching profile):");
-            if let Some(n) = name {
-                println!("  Name:  {}", n);
-            }
-            if let Some(e) = email {
-                println!("  Email: {}", e);
+        modified.hash(&mut hasher);
+        meta.map(|m| m.len()).hash(&mut hasher);
+    }
+    Some(hasher.finish())
+}
+
+/// Split a cache file into its stamp and answer
+fn parse_cache_entry(contents: &str) -> Option<(u64, Option<String>)> {
+    let (stamp, answer) = contents.split_once('\n')?;
+    let stamp = u64::from_str_radix(stamp, 16).ok()?;
+    let answer = answer.trim_end_matches('\n');
+    Some((stamp, (!answer.is_empty()).then(|| answer.to_string())))
+}
+
+/// Cache file for the repository containing the working directory
+fn cache_path(common_dir: Option<&Path>) -> Result<PathBuf> {
+    let mut hasher = DefaultHasher::new();
+    common_dir.hash(&mut hasher);
+    Ok(Config::state_dir()?
+        .join("prompt")
+        .join(format!("{:016x}", hasher.finish())))
+}
+
+/// Current profile name for the prompt, answered from a per-repository
+/// cache in the state directory while none of the config files it depends
+/// on have changed. Falls back to the query server and then to reading
+/// git config, storing the result for the next call.
+pub fn cached_current_profile() -> Result<Option<String>> {
+    // GIT_DIR and friends point git somewhere the filesystem walk can't see
+    let cacheable = std::env::var_os("GIT_DIR").is_none();
+    let cwd = std::env::current_dir()?;
+    let common_dir = daemon::find_common_dir(&cwd);
+    let files = stamp_files(common_dir.as_deref())?;
+    let path = cache_path(common_dir.as_deref())?;
+    let stamp = stamp(&files, SystemTime::now()).filter(|_| cacheable);
+
+    if let Some(stamp) = stamp {
+        let cached = fs::read_to_string(&path).ok();
+        if let Some((cached_stamp, answer)) = cached.as_deref().and_then(parse_cache_entry) {
+            if cached_stamp == stamp {
+                return Ok(answer);
             }
-        } else {
-            println!("No git identity configured");
         }
     }
 
-    Ok(())
+    let answer = match daemon::query("current") {
+        Some(daemon::Reply::Found(name)) => Some(name),
+        Some(daemon::Reply::Nothing) => None,
+        None => get_current_profile(&Config::load()?)?,
+    };
+
+    if let Some(stamp) = stamp {
+        // A cache that can't be written only costs speed
+        let entry = format!("{:x}\n{}\n", stamp, answer.as_deref().unwrap_or(""));
+        let _ = write_cache_entry(&path, &entry);
+    }
+    Ok(answer)
+}
+
+fn write_cache_entry(path: &Path, entry: &str) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // Written aside and renamed so concurrent prompts never read half an entry
+    let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+    fs::write(&tmp, entry)?;
+    fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signing_status() {
+        assert_eq!(signing_status(None, None), "off");
+        assert_eq!(signing_status(Some("false"), Some("ssh")), "off");
+        assert_eq!(signing_status(Some("true"), None), "gpg");
+        assert_eq!(signing_status(Some("Yes"), Some("openpgp")), "gpg");
+        assert_eq!(signing_status(Some("true"), Some("ssh")), "ssh");
+    }
+
+    #[test]
+    fn test_parse_cache_entry() {
+        assert_eq!(parse_cache_entry("ff\nwork\n"), Some((255, Some("work".to_string()))));
+        assert_eq!(parse_cache_entry("ff\n\n"), Some((255, None)));
+        assert_eq!(parse_cache_entry("zz\nwork\n"), None);
+        assert_eq!(parse_cache_entry(""), None);
+    }
+
+    #[test]
+    fn test_stamp_skips_recent_changes() {
+        let path = std::env::temp_dir().join(format!("gitid-prompt-test-{}", std::process::id()));
+        fs::write(&path, "[user]\n").unwrap();
+        let files = vec![path.clone(), path.with_extension("missing")];
+
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+        assert_eq!(stamp(&files, modified), None);
+        let later = modified + Duration::from_secs(60);
+        assert!(stamp(&files, later).is_some());
+        assert_eq!(stamp(&files, later), stamp(&files, later + Duration::from_secs(60)));
+
+        fs::remove_file(&path).unwrap();
+    }
 }
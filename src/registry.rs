@@ -0,0 +1,157 @@
+use crate::audit;
+use crate::config::Config;
+use crate::git;
+use crate::journal;
+use crate::prompt;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// What `gitid scan` recorded about one repository
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RepoRecord {
+    pub path: PathBuf,
+    /// Remote URLs keyed by remote name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub remotes: BTreeMap<String, String>,
+    /// user.name and user.email as git resolves them in the repository
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    /// Profile whose identity is applied, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub applied: Option<String>,
+    /// Profile detection picks for the repository
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detected: Option<String>,
+}
+
+/// Inventory of repositories built by `gitid scan`, so `audit` and
+/// `apply-all` can use it instead of walking the filesystem again
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Registry {
+    /// When the last scan finished
+    #[serde(default)]
+    pub scanned: u64,
+    #[serde(default)]
+    pub repos: Vec<RepoRecord>,
+}
+
+impl Registry {
+    /// Get the registry file path
+    pub fn path() -> Result<PathBuf> {
+        Ok(Config::state_dir()?.join("repos.toml"))
+    }
+
+    /// Load the registry, or an empty one if nothing has been scanned yet
+    pub fn load() -> Result<Self> {
+        let path = Self::path()?;
+        if !path.exists() {
+            return Ok(Registry::default());
+        }
+
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read repository registry: {}", path.display()))?;
+
+        toml::from_str(&content)
+            .with_context(|| format!("Failed to parse repository registry: {}", path.display()))
+    }
+
+    /// Save the registry
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path()?;
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create state directory: {}", parent.display()))?;
+        }
+
+        let content = toml::to_string_pretty(self).context("Failed to serialize repository registry")?;
+
+        fs::write(&path, content)
+            .with_context(|| format!("Failed to write repository registry: {}", path.display()))
+    }
+
+    /// Replace what was recorded under `root` with a fresh scan of it.
+    /// Returns how many repositories are new and how many are gone.
+    pub fn update(&mut self, root: &Path, records: Vec<RepoRecord>) -> (usize, usize) {
+        let (old, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut self.repos)
+            .into_iter()
+            .partition(|r| r.path.starts_with(root));
+
+        let added = records
+            .iter()
+            .filter(|r| !old.iter().any(|o| o.path == r.path))
+            .count();
+        let removed = old
+            .iter()
+            .filter(|o| !records.iter().any(|r| r.path == o.path))
+            .count();
+
+        self.repos = kept;
+        self.repos.extend(records);
+        self.repos.sort_by(|a, b| a.path.cmp(&b.path));
+        self.scanned = journal::now();
+        (added, removed)
+    }
+
+    /// Recorded repositories that still exist
+    pub fn repo_paths(&self) -> Vec<PathBuf> {
+        self.repos
+            .iter()
+            .map(|r| r.path.clone())
+            .filter(|path| path.join(".git").exists())
+            .collect()
+    }
+}
+
+/// Record one repository. `config` is the user config, as for
+/// `audit::audit_repo`. Changes the working directory to the repository.
+pub fn scan_repo(config: &Config, repo: &Path) -> Result<RepoRecord> {
+    let entry = audit::audit_repo(config, repo)?;
+    let applied = prompt::matching_profile(config, entry.name.as_deref(), entry.email.as_deref());
+
+    Ok(RepoRecord {
+        path: entry.repo,
+        remotes: git::remote_urls()?.into_iter().collect(),
+        name: entry.name,
+        email: entry.email,
+        applied,
+        detected: entry.expected,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(path: &str) -> RepoRecord {
+        RepoRecord {
+            path: PathBuf::from(path),
+            remotes: BTreeMap::new(),
+            name: None,
+            email: None,
+            applied: None,
+            detected: None,
+        }
+    }
+
+    #[test]
+    fn test_update_replaces_only_the_scanned_root() {
+        let mut registry = Registry::default();
+        registry.update(Path::new("/src"), vec![record("/src/api"), record("/src/web")]);
+        registry.update(Path::new("/work"), vec![record("/work/app")]);
+
+        let mut web = record("/src/web");
+        web.detected = Some("work".to_string());
+        let (added, removed) = registry.update(Path::new("/src"), vec![web.clone(), record("/src/cli")]);
+        assert_eq!((added, removed), (1, 1));
+
+        let paths: Vec<_> = registry.repos.iter().map(|r| r.path.to_str().unwrap()).collect();
+        assert_eq!(paths, vec!["/src/cli", "/src/web", "/work/app"]);
+        assert_eq!(registry.repos[1], web);
+    }
+}
@@ -0,0 +1,350 @@
+use crate::config::Config;
+use crate::git::{self, ConfigScope, RemoteUrl};
+use crate::profile::Profile;
+use anyhow::{Result, bail};
+
+/// A planned change to a remote's URL
+#[derive(Debug, PartialEq, Eq)]
+pub struct RemoteRewrite {
+    pub remote: String,
+    pub from: String,
+    pub to: String,
+}
+
+/// Plan rewrites that point remotes at the profile's SSH host aliases.
+/// Remotes on hosts the profile doesn't serve are left alone.
+pub fn plan_alias_rewrites(
+    profile_name: &str,
+    profile: &Profile,
+    remotes: &[(String, String)],
+) -> Vec<RemoteRewrite> {
+    remotes
+        .iter()
+        .filter_map(|(remote, url)| {
+            let parsed = RemoteUrl::parse(url)?;
+            let alias = profile.alias_for_host(profile_name, &parsed.host)?;
+            let to = parsed.with_ssh_host(&alias);
+            (&to != url).then(|| RemoteRewrite {
+                remote: remote.clone(),
+                from: url.clone(),
+                to,
+            })
+        })
+        .collect()
+}
+
+/// Real hostname behind `host` if it is one of any profile's SSH aliases
+fn alias_hostname(config: &Config, host: &str) -> Option<String> {
+    config.profiles.iter().find_map(|(name, profile)| {
+        profile
+            .ssh_host_aliases(name)
+            .into_iter()
+            .find(|(alias, _)| alias == host)
+            .map(|(_, hostname)| hostname)
+    })
+}
+
+/// Plan rewrites for `gitid remote fix`: like `plan_alias_rewrites`, but
+/// remotes on another profile's alias for the same host are moved over too
+pub fn plan_fix_rewrites(
+    config: &Config,
+    profile_name: &str,
+    profile: &Profile,
+    remotes: &[(String, String)],
+) -> Vec<RemoteRewrite> {
+    remotes
+        .iter()
+        .filter_map(|(remote, url)| {
+            let parsed = RemoteUrl::parse(url)?;
+            let host = alias_hostname(config, &parsed.host).unwrap_or_else(|| parsed.host.clone());
+            let alias = profile.alias_for_host(profile_name, &host)?;
+            let to = parsed.with_ssh_host(&alias);
+            (&to != url).then(|| RemoteRewrite {
+                remote: remote.clone(),
+                from: url.clone(),
+                to,
+            })
+        })
+        .collect()
+}
+
+/// Plan rewrites that point remotes using any profile's SSH host alias back
+/// at the real hostname, undoing `plan_alias_rewrites`
+pub fn plan_canonical_rewrites(config: &Config, remotes: &[(String, String)]) -> Vec<RemoteRewrite> {
+    remotes
+        .iter()
+        .filter_map(|(remote, url)| {
+            let parsed = RemoteUrl::parse(url)?;
+            let host = alias_hostname(config, &parsed.host)?;
+            Some(RemoteRewrite {
+                remote: remote.clone(),
+                from: url.clone(),
+                to: parsed.with_ssh_host(&host),
+            })
+        })
+        .collect()
+}
+
+/// `url.<base>.insteadOf` rules routing the profile's hosts through its SSH
+/// aliases, as (key, value). The HTTPS rule's base is the `ssh://` form of
+/// the alias so that each key holds a single value.
+pub fn insteadof_rules(profile_name: &str, profile: &Profile) -> Vec<(String, String)> {
+    profile
+        .ssh_host_aliases(profile_name)
+        .into_iter()
+        .flat_map(|(alias, hostname)| {
+            [
+                (format!("url.git@{}:.insteadOf", alias), format!("git@{}:", hostname)),
+                (format!("url.ssh://git@{}/.insteadOf", alias), format!("https://{}/", hostname)),
+            ]
+        })
+        .collect()
+}
+
+/// Whether `key` is an insteadOf rule gitid writes for some profile
+pub fn is_managed_rule(config: &Config, key: &str) -> bool {
+    config.profiles.iter().any(|(name, profile)| {
+        insteadof_rules(name, profile)
+            .iter()
+            .any(|(rule, _)| rule.eq_ignore_ascii_case(key))
+    })
+}
+
+/// Write the profile's insteadOf rules at `scope`, removing those of other
+/// profiles; with `enabled` false only the removal happens
+pub fn apply_insteadof(
+    config: &Config,
+    profile_name: &str,
+    profile: &Profile,
+    enabled: bool,
+    scope: ConfigScope,
+) -> Result<()> {
+    let rules = if enabled {
+        insteadof_rules(profile_name, profile)
+    } else {
+        Vec::new()
+    };
+
+    for (key, _) in git::config_entries(r"^url\..*\.insteadof$", scope)? {
+        let kept = rules.iter().any(|(rule, _)| rule.eq_ignore_ascii_case(&key));
+        if !kept && is_managed_rule(config, &key) {
+            git::unset_config(&key, scope)?;
+        }
+    }
+    for (key, value) in &rules {
+        git::set_config(key, value, scope)?;
+    }
+    Ok(())
+}
+
+/// Remotes whose owner the profile is not allowed to push to, with the reason
+pub fn owner_violations(profile: &Profile, remotes: &[(String, String)]) -> Vec<String> {
+    remotes
+        .iter()
+        .filter_map(|(remote, url)| {
+            let parsed = RemoteUrl::parse(url)?;
+            let err = profile.check_owner(parsed.owner()?).err()?;
+            Some(format!("{} ({}): {}", remote, url, err))
+        })
+        .collect()
+}
+
+/// Fail if any remote violates the profile's owner constraints
+pub fn enforce_owners(profile_name: &str, profile: &Profile, remotes: &[(String, String)]) -> Result<()> {
+    let violations = owner_violations(profile, remotes);
+    if !violations.is_empty() {
+        bail!(
+            "Profile '{}' may not be used with these remotes:\n  {}",
+            profile_name,
+            violations.join("\n  ")
+        );
+    }
+    Ok(())
+}
+
+/// Apply planned rewrites to the current repository
+pub fn apply_rewrites(rewrites: &[RemoteRewrite]) -> Result<()> {
+    for rewrite in rewrites {
+        git::set_remote_url(&rewrite.remote, &rewrite.to)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::Platform;
+
+    fn remotes(list: &[(&str, &str)]) -> Vec<(String, String)> {
+        list.iter()
+            .map(|(r, u)| (r.to_string(), u.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_plan_alias_rewrites() {
+        let profile = Profile::new(
+            "John Doe".to_string(),
+            "john@company.com".to_string(),
+            Platform::Github,
+            "~/.ssh/id_work".to_string(),
+            None,
+            None,
+        );
+
+        let rewrites = plan_alias_rewrites(
+            "work",
+            &profile,
+            &remotes(&[
+                ("origin", "https://github.com/acme/app.git"),
+                ("upstream", "git@github-work:acme/app.git"),
+                ("mirror", "git@gitlab.com:acme/app.git"),
+            ]),
+        );
+
+        assert_eq!(
+            rewrites,
+            vec![RemoteRewrite {
+                remote: "origin".to_string(),
+                from: "https://github.com/acme/app.git".to_string(),
+                to: "git@github-work:acme/app.git".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_plan_alias_rewrites_both_platform() {
+        let profile = Profile::new(
+            "John Doe".to_string(),
+            "john@example.com".to_string(),
+            Platform::Both,
+            "~/.ssh/id_ed25519".to_string(),
+            None,
+            None,
+        );
+
+        let rewrites = plan_alias_rewrites(
+            "personal",
+            &profile,
+            &remotes(&[("origin", "git@gitlab.com:me/app.git")]),
+        );
+        assert_eq!(rewrites[0].to, "git@gitlab-personal:me/app.git");
+    }
+
+    #[test]
+    fn test_plan_canonical_rewrites() {
+        let mut config = Config::default();
+        config.profiles.insert(
+            "work".to_string(),
+            Profile::new(
+                "John Doe".to_string(),
+                "john@company.com".to_string(),
+                Platform::Github,
+                "~/.ssh/id_work".to_string(),
+                None,
+                Some("github.company.com".to_string()),
+            ),
+        );
+
+        let rewrites = plan_canonical_rewrites(
+            &config,
+            &remotes(&[
+                ("origin", "git@github-work:acme/app.git"),
+                ("upstream", "git@github.com:acme/app.git"),
+                ("other", "git@github-personal:me/app.git"),
+            ]),
+        );
+        assert_eq!(
+            rewrites,
+            vec![RemoteRewrite {
+                remote: "origin".to_string(),
+                from: "git@github-work:acme/app.git".to_string(),
+                to: "git@github.company.com:acme/app.git".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_plan_fix_rewrites() {
+        let mut config = Config::default();
+        for (name, email) in [("work", "john@company.com"), ("personal", "john@example.com")] {
+            config.profiles.insert(
+                name.to_string(),
+                Profile::new(
+                    "John Doe".to_string(),
+                    email.to_string(),
+                    Platform::Github,
+                    format!("~/.ssh/id_{}", name),
+                    None,
+                    None,
+                ),
+            );
+        }
+
+        let rewrites = plan_fix_rewrites(
+            &config,
+            "work",
+            &config.profiles["work"],
+            &remotes(&[
+                ("origin", "git@github-personal:acme/app.git"),
+                ("upstream", "https://github.com/acme/app.git"),
+                ("fork", "git@github-work:john/app.git"),
+                ("mirror", "git@gitlab.com:acme/app.git"),
+            ]),
+        );
+        let targets: Vec<_> = rewrites.iter().map(|r| (r.remote.as_str(), r.to.as_str())).collect();
+        assert_eq!(
+            targets,
+            vec![
+                ("origin", "git@github-work:acme/app.git"),
+                ("upstream", "git@github-work:acme/app.git"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_insteadof_rules() {
+        let profile = Profile::new(
+            "John Doe".to_string(),
+            "john@company.com".to_string(),
+            Platform::Github,
+            "~/.ssh/id_work".to_string(),
+            None,
+            None,
+        );
+        assert_eq!(
+            insteadof_rules("work", &profile),
+            vec![
+                ("url.git@github-work:.insteadOf".to_string(), "git@github.com:".to_string()),
+                ("url.ssh://git@github-work/.insteadOf".to_string(), "https://github.com/".to_string()),
+            ]
+        );
+
+        let mut config = Config::default();
+        config.profiles.insert("work".to_string(), profile);
+        assert!(is_managed_rule(&config, "url.git@github-work:.insteadof"));
+        assert!(!is_managed_rule(&config, "url.git@github.com:.insteadof"));
+    }
+
+    #[test]
+    fn test_owner_violations() {
+        let mut profile = Profile::new(
+            "John Doe".to_string(),
+            "john@example.com".to_string(),
+            Platform::Github,
+            "~/.ssh/id_ed25519".to_string(),
+            None,
+            None,
+        );
+        profile.deny_owners = vec!["acme-corp".to_string()];
+
+        let list = remotes(&[
+            ("origin", "git@github.com:acme-corp/app.git"),
+            ("fork", "git@github.com:john/app.git"),
+        ]);
+        let violations = owner_violations(&profile, &list);
+        assert_eq!(violations.len(), 1);
+        assert!(violations[0].starts_with("origin"));
+        assert!(enforce_owners("personal", &profile, &list).is_err());
+    }
+}
@@ -0,0 +1,68 @@
+use anyhow::{Result, anyhow};
+use keyring::Entry;
+
+/// Keyring service name all gitid secrets are stored under
+const SERVICE: &str = "gitid";
+
+fn entry(profile_name: &str) -> Result<Entry> {
+    Entry::new(SERVICE, profile_name).map_err(|e| anyhow!("Failed to access the OS keyring: {}", e))
+}
+
+/// Keyring entry caching a profile's GitHub App installation token
+fn app_token_entry(profile_name: &str) -> Result<Entry> {
+    entry(&format!("{}:app-token", profile_name))
+}
+
+/// Get the platform token stored for a profile
+pub fn get_token(profile_name: &str) -> Result<Option<String>> {
+    match entry(profile_name)?.get_password() {
+        Ok(token) => Ok(Some(token)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(anyhow!("Failed to read token from the OS keyring: {}", e)),
+    }
+}
+
+/// Store a platform token for a profile
+pub fn set_token(profile_name: &str, token: &str) -> Result<()> {
+    entry(profile_name)?
+        .set_password(token)
+        .map_err(|e| anyhow!("Failed to store token in the OS keyring: {}", e))
+}
+
+/// Delete a profile's token. Returns false if none was stored.
+pub fn delete_token(profile_name: &str) -> Result<bool> {
+    match entry(profile_name)?.delete_credential() {
+        Ok(()) => Ok(true),
+        Err(keyring::Error::NoEntry) => Ok(false),
+        Err(e) => Err(anyhow!("Failed to delete token from the OS keyring: {}", e)),
+    }
+}
+
+/// Cached installation token for a GitHub App profile, with the Unix time
+/// it expires at
+pub fn get_app_token(profile_name: &str) -> Result<Option<(u64, String)>> {
+    let cached = match app_token_entry(profile_name)?.get_password() {
+        Ok(cached) => cached,
+        Err(keyring::Error::NoEntry) => return Ok(None),
+        Err(e) => return Err(anyhow!("Failed to read token from the OS keyring: {}", e)),
+    };
+    Ok(cached
+        .split_once('\n')
+        .and_then(|(expires, token)| Some((expires.parse().ok()?, token.to_string()))))
+}
+
+/// Cache an installation token until `expires`
+pub fn set_app_token(profile_name: &str, expires: u64, token: &str) -> Result<()> {
+    app_token_entry(profile_name)?
+        .set_password(&format!("{}\n{}", expires, token))
+        .map_err(|e| anyhow!("Failed to store token in the OS keyring: {}", e))
+}
+
+/// Drop a cached installation token. Returns false if none was cached.
+pub fn delete_app_token(profile_name: &str) -> Result<bool> {
+    match app_token_entry(profile_name)?.delete_credential() {
+        Ok(()) => Ok(true),
+        Err(keyring::Error::NoEntry) => Ok(false),
+        Err(e) => Err(anyhow!("Failed to delete token from the OS keyring: {}", e)),
+    }
+}
@@ -0,0 +1,135 @@
+use crate::config::Config;
+use crate::detect;
+use crate::git::{self, ConfigScope};
+use crate::prompt;
+use anyhow::Result;
+use clap::ValueEnum;
+use colored::Colorize;
+
+/// Set to `0` to turn the cd hook off, or `suggest` to only print the
+/// matching profile instead of applying it
+pub const AUTO_SWITCH_ENV: &str = "GITID_AUTO_SWITCH";
+
+/// Shells `gitid shell-init` has a hook for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum HookShell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+// Each hook only calls gitid when the working directory changes, so
+// prompts that render repeatedly in one directory spawn nothing.
+
+const BASH_HOOK: &str = r#"# gitid auto-switch: eval "$(gitid shell-init bash)"
+_gitid_hook() {
+  [ "${GITID_AUTO_SWITCH:-1}" = 0 ] && return
+  [ "$PWD" = "${_GITID_LAST_PWD:-}" ] && return
+  _GITID_LAST_PWD=$PWD
+  command gitid shell-hook
+}
+case ";${PROMPT_COMMAND:-};" in
+  *";_gitid_hook;"*) ;;
+  *) PROMPT_COMMAND="_gitid_hook${PROMPT_COMMAND:+;$PROMPT_COMMAND}" ;;
+esac
+"#;
+
+const ZSH_HOOK: &str = r#"# gitid auto-switch: eval "$(gitid shell-init zsh)"
+_gitid_hook() {
+  [[ "${GITID_AUTO_SWITCH:-1}" == 0 ]] && return
+  command gitid shell-hook
+}
+autoload -Uz add-zsh-hook
+add-zsh-hook chpwd _gitid_hook
+_gitid_hook
+"#;
+
+const FISH_HOOK: &str = r#"# gitid auto-switch: gitid shell-init fish | source
+function _gitid_hook --on-variable PWD
+    test "$GITID_AUTO_SWITCH" = 0; and return
+    command gitid shell-hook
+end
+_gitid_hook
+"#;
+
+/// Script that installs the cd hook for a shell
+pub fn init_script(shell: HookShell) -> &'static str {
+    match shell {
+        HookShell::Bash => BASH_HOOK,
+        HookShell::Zsh => ZSH_HOOK,
+        HookShell::Fish => FISH_HOOK,
+    }
+}
+
+/// Called by the hook after a directory change: apply the detected profile
+/// to the repository if it isn't applied yet, or only suggest it when
+/// GITID_AUTO_SWITCH=suggest. When nothing matches, the fallback profile is
+/// applied to repositories without an identity of their own. Silent outside
+/// repositories and when the right profile is already in place.
+pub fn on_directory_change() -> Result<()> {
+    if !git::is_git_repo() {
+        return Ok(());
+    }
+
+    let config = Config::load()?;
+    let (profile_name, reason) = match detect::detect_profile_cached(&config)? {
+        Some(result) if !result.tied.is_empty() => {
+            // Leave the choice to `gitid detect` rather than guess
+            let current = prompt::get_current_profile(&config)?;
+            if !result.candidates().iter().any(|c| current.as_deref() == Some(c.profile_name.as_str())) {
+                let message = result.tie_message().unwrap_or_default();
+                println!("gitid: {}; run {} to choose", message, "gitid detect".yellow());
+            }
+            return Ok(());
+        }
+        Some(result) => (result.profile_name, result.reason),
+        None => {
+            // An identity set by hand in an unmatched repository stays
+            if git::get_current_user(ConfigScope::Local)?.1.is_some() {
+                return Ok(());
+            }
+            let Some(fallback) = detect::fallback_profile(&config) else {
+                return Ok(());
+            };
+            fallback
+        }
+    };
+    if prompt::get_current_profile(&config)?.as_deref() == Some(profile_name.as_str()) {
+        return Ok(());
+    }
+    let Some(profile) = config.get_profile(&profile_name) else {
+        return Ok(());
+    };
+
+    if std::env::var(AUTO_SWITCH_ENV).is_ok_and(|v| v == "suggest") {
+        println!(
+            "gitid: this repository matches '{}' ({}); run {}",
+            profile_name.cyan(),
+            reason,
+            format!("gitid use {}", profile_name).yellow()
+        );
+        return Ok(());
+    }
+
+    git::apply_profile(&config, &profile_name, profile, ConfigScope::Local)?;
+    println!(
+        "gitid: switched to '{}' <{}>",
+        profile_name.cyan(),
+        profile.email
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_init_scripts_call_hook() {
+        for shell in [HookShell::Bash, HookShell::Zsh, HookShell::Fish] {
+            let script = init_script(shell);
+            assert!(script.contains("command gitid shell-hook"));
+            assert!(script.contains(AUTO_SWITCH_ENV));
+        }
+    }
+}
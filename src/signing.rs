@@ -0,0 +1,165 @@
+use crate::env;
+use crate::gpg;
+use crate::profile::{Profile, SigningFormat};
+use anyhow::{Context, Result, bail};
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Step of `gitid sign-test` that failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Sign,
+    Verify,
+}
+
+/// What signing and verifying a throwaway commit showed
+#[derive(Debug)]
+pub struct SignTest {
+    pub format: SigningFormat,
+    /// user.signingkey as the profile sets it
+    pub key: String,
+    /// The failed step with git's output, or None if both steps worked
+    pub failure: Option<(Stage, String)>,
+    /// What verification reported, e.g. `Good "git" signature for ...`
+    pub output: String,
+}
+
+/// Sign an empty commit in a scratch repository with the profile's
+/// signing settings and verify it, the same way `git log --show-signature`
+/// would. gpg may ask for the passphrase through pinentry.
+pub fn sign_test(name: &str, profile: &Profile) -> Result<SignTest> {
+    let Some((format, key)) = profile.signing_key() else {
+        bail!("Profile '{}' does not sign commits; set gpg_key or signing = \"ssh\"", name);
+    };
+
+    let dir = std::env::temp_dir().join(format!("gitid-sign-test-{}", std::process::id()));
+    let result = run_in(&dir, name, profile);
+    let _ = fs::remove_dir_all(&dir);
+    let (failure, output) = result?;
+
+    Ok(SignTest {
+        format,
+        key,
+        failure,
+        output,
+    })
+}
+
+/// Run both steps in `dir`, returning the step that failed, if any, and
+/// what verification reported
+fn run_in(dir: &Path, name: &str, profile: &Profile) -> Result<(Option<(Stage, String)>, String)> {
+    let init = Command::new("git")
+        .args(["init", "-q"])
+        .arg(dir)
+        .output()
+        .context("Failed to execute git init")?;
+    if !init.status.success() {
+        bail!(
+            "Failed to create a scratch repository: {}",
+            String::from_utf8_lossy(&init.stderr).trim()
+        );
+    }
+
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .envs(env::commit_env(name, profile))
+            // pinentry-curses needs the terminal
+            .stdin(Stdio::inherit())
+            .output()
+            .context("Failed to execute git")
+    };
+
+    let commit = git(&["commit", "--allow-empty", "-q", "-m", "gitid sign-test"])?;
+    if !commit.status.success() {
+        let mut output = String::from_utf8_lossy(&commit.stderr).trim().to_string();
+        if let Some((SigningFormat::Gpg, key)) = profile.signing_key() {
+            if let Some(error) = gpg::sign_error(&key, profile.gnupghome.as_deref())? {
+                output = format!("{}\n{}", output, error.trim());
+            }
+        }
+        return Ok((Some((Stage::Sign, output)), String::new()));
+    }
+
+    let verify = git(&["verify-commit", "HEAD"])?;
+    let output = String::from_utf8_lossy(&verify.stderr).trim().to_string();
+    if !verify.status.success() {
+        return Ok((Some((Stage::Verify, output)), String::new()));
+    }
+    Ok((None, output))
+}
+
+/// What to do about a failed step, recognised from git's output
+pub fn hint(format: SigningFormat, stage: Stage, output: &str, email: &str) -> Option<String> {
+    let has = |needle: &str| output.to_lowercase().contains(&needle.to_lowercase());
+    let hint = match (format, stage) {
+        (SigningFormat::Gpg, Stage::Sign) if has("no secret key") || has("secret key not available") => {
+            "The key is not in the GnuPG keyring; check 'gpg --list-secret-keys' or the profile's gnupghome".to_string()
+        }
+        (SigningFormat::Gpg, Stage::Sign) if has("inappropriate ioctl") || has("pinentry") => {
+            "gpg could not ask for the passphrase; add 'export GPG_TTY=$(tty)' to your shell profile or install a graphical pinentry".to_string()
+        }
+        (SigningFormat::Gpg, Stage::Sign) if has("expired") => {
+            "The key has expired; extend it with 'gpg --quick-set-expire'".to_string()
+        }
+        (SigningFormat::Gpg, Stage::Sign) if has("agent") => {
+            "gpg-agent is not answering; restart it with 'gpgconf --kill gpg-agent'".to_string()
+        }
+        (SigningFormat::Gpg, Stage::Verify) if has("no public key") => {
+            "The public key is not in the keyring; import it with 'gpg --import'".to_string()
+        }
+        (SigningFormat::Ssh, Stage::Sign) if has("no such file") => {
+            "The public key file is missing; recreate it with 'ssh-keygen -y -f <key> > <key>.pub'".to_string()
+        }
+        (SigningFormat::Ssh, Stage::Sign) if has("agent") || has("passphrase") => {
+            "ssh-keygen could not use the private key; load it into the agent with 'ssh-add'".to_string()
+        }
+        (SigningFormat::Ssh, Stage::Verify) if has("allowedsignersfile") => {
+            "No allowed signers file is configured; run 'gitid ssh-sync' to write it and set gpg.ssh.allowedSignersFile".to_string()
+        }
+        (SigningFormat::Ssh, Stage::Verify) if has("no principal matched") => format!(
+            "The allowed signers file has no entry for {} with this key; run 'gitid ssh-sync'",
+            email
+        ),
+        _ => return None,
+    };
+    Some(hint)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hint() {
+        let hint = |format, stage, output| hint(format, stage, output, "john@acme.com");
+        assert!(
+            hint(SigningFormat::Gpg, Stage::Sign, "gpg: signing failed: Inappropriate ioctl for device")
+                .unwrap()
+                .contains("GPG_TTY")
+        );
+        assert!(
+            hint(SigningFormat::Gpg, Stage::Sign, "gpg: skipped \"ABCD\": No secret key")
+                .unwrap()
+                .contains("keyring")
+        );
+        assert!(
+            hint(
+                SigningFormat::Ssh,
+                Stage::Verify,
+                "error: gpg.ssh.allowedSignersFile needs to be configured and exist for ssh signature verification"
+            )
+            .unwrap()
+            .contains("gitid ssh-sync")
+        );
+        assert!(
+            hint(SigningFormat::Ssh, Stage::Verify, "No principal matched.")
+                .unwrap()
+                .contains("john@acme.com")
+        );
+        assert_eq!(hint(SigningFormat::Gpg, Stage::Verify, "something else"), None);
+    }
+}
@@ -1,11 +1,21 @@
 use crate::config::Config;
-use crate::profile::{Platform, Profile};
+use crate::diff;
+use crate::env;
+use crate::git::{self, ConfigScope};
+use crate::gitconfig;
+use crate::journal;
+use crate::managed;
+use crate::profile::{Profile, SigningFormat};
+use crate::ssh_keys;
 use anyhow::{Context, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-const MANAGED_START: &str = "# === GITID MANAGED START ===";
-const MANAGED_END: &str = "# === GITID MANAGED END ===";
+pub const MANAGED_START: &str = "# === GITID MANAGED START ===";
+pub const MANAGED_END: &str = "# === GITID MANAGED END ===";
 
 /// Get the SSH config file path
 pub fn ssh_config_path() -> Result<PathBuf> {
@@ -13,38 +23,188 @@ pub fn ssh_config_path() -> Result<PathBuf> {
     Ok(home.join(".ssh").join("config"))
 }
 
-/// Generate SSH Host entry for a profile
-fn generate_host_entry(profile_name: &str, profile: &Profile) -> String {
-    let alias = profile.ssh_host_alias(profile_name);
-    let hostname = profile.default_host();
-    let ssh_key = &profile.ssh_key;
+/// Where `ssh-sync` writes the profiles' Host entries
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SshLayout {
+    /// A managed block inside ~/.ssh/config
+    #[default]
+    Inline,
+    /// ~/.ssh/gitid_config, pulled into ~/.ssh/config by one Include line
+    Include,
+}
+
+/// Line that pulls the entries file into ~/.ssh/config
+pub const INCLUDE_LINE: &str = "Include ~/.ssh/gitid_config";
+
+/// Entries file used by the include layout
+pub fn include_path() -> Result<PathBuf> {
+    let home = dirs::home_dir().context("Could not determine home directory")?;
+    Ok(home.join(".ssh").join("gitid_config"))
+}
+
+/// File holding the managed block for the configured layout
+pub fn managed_config_path(config: &Config) -> Result<PathBuf> {
+    match config.ssh_layout.unwrap_or_default() {
+        SshLayout::Inline => ssh_config_path(),
+        SshLayout::Include => include_path(),
+    }
+}
+
+/// Every file `sync_ssh_config` may write
+fn config_paths(config: &Config) -> Result<Vec<PathBuf>> {
+    let mut paths = vec![ssh_config_path()?];
+    if config.ssh_layout.unwrap_or_default() == SshLayout::Include {
+        paths.push(include_path()?);
+    }
+    Ok(paths)
+}
+
+/// Capture the files `sync_ssh_config` may write, to restore them if a
+/// later step fails
+pub fn snapshot_files(config: &Config) -> Result<Vec<journal::Change>> {
+    config_paths(config)?.iter().map(|p| journal::snapshot_file(p)).collect()
+}
+
+/// Whether an SSH config already includes the entries file
+pub fn has_include(content: &str) -> bool {
+    let absolute = include_path().map(|p| p.display().to_string()).unwrap_or_default();
+    content.lines().any(|line| {
+        let mut words = line.split_whitespace();
+        words.next().is_some_and(|w| w.eq_ignore_ascii_case("include"))
+            && words.any(|w| w == "~/.ssh/gitid_config" || w == absolute)
+    })
+}
+
+/// SSH config with the Include line added at the top, where it applies to
+/// every host rather than only the Host block above it
+fn with_include(content: &str) -> String {
+    if has_include(content) {
+        return content.to_string();
+    }
+    format!("{}\n{}", INCLUDE_LINE, content)
+}
+
+/// Comment closing gitid's options in each Host entry. Lines the user adds
+/// below it are carried over when the block is regenerated.
+pub const USER_OPTIONS_MARKER: &str = "# Your options below this line are kept by gitid ssh-sync";
+
+/// Generate SSH Host entry for a profile, followed by the lines the user
+/// added to each alias's entry
+fn generate_host_entry(
+    profile_name: &str,
+    profile: &Profile,
+    user_lines: &HashMap<String, Vec<String>>,
+) -> String {
+    // For 'both' platform this includes specific aliases for GitHub and GitLab
+    profile
+        .ssh_host_aliases(profile_name)
+        .iter()
+        .map(|(alias, hostname)| {
+            let mut entry = format!("Host {}\n", alias);
+            for (key, value) in host_options(profile, hostname) {
+                entry.push_str(&format!("  {} {}\n", key, value));
+            }
+            entry.push_str(&format!("  {}\n", USER_OPTIONS_MARKER));
+            for line in user_lines.get(alias).into_iter().flatten() {
+                entry.push_str(line);
+                entry.push('\n');
+            }
+            entry
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// First word of an ssh_config line, e.g. `Host` or `IdentityFile`
+fn keyword(line: &str) -> &str {
+    line.trim()
+        .split(|c: char| c.is_whitespace() || c == '=')
+        .next()
+        .unwrap_or("")
+}
+
+/// Lines the user added to the Host entries of an existing managed block,
+/// by alias. Normally these are the lines below USER_OPTIONS_MARKER. Entries
+/// written before the marker existed keep every line whose option gitid
+/// doesn't generate for that alias, so upgrading loses nothing.
+fn user_lines(config: &Config, block: &str) -> HashMap<String, Vec<String>> {
+    let mut generated: HashMap<String, Vec<String>> = HashMap::new();
+    for (name, profile) in &config.profiles {
+        for (alias, hostname) in profile.ssh_host_aliases(name) {
+            let keys = host_options(profile, &hostname).iter().map(|(k, _)| k.to_string()).collect();
+            generated.insert(alias, keys);
+        }
+    }
 
-    let mut entry = format!(
-        "Host {}\n  HostName {}\n  User git\n  IdentityFile {}\n  IdentitiesOnly yes\n",
-        alias, hostname, ssh_key
-    );
+    let mut entries = HashMap::new();
+    // Alias of the entry being read, whether its marker has been seen,
+    // and the user's lines so far
+    let mut current: Option<(String, bool, Vec<String>)> = None;
+    let mut finish = |entry: Option<(String, bool, Vec<String>)>| {
+        if let Some((alias, _, lines)) = entry {
+            entries.insert(alias, lines);
+        }
+    };
+    for line in block.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed == MANAGED_START || trimmed == MANAGED_END {
+            continue;
+        }
+        if keyword(trimmed).eq_ignore_ascii_case("host") {
+            finish(current.take());
+            let alias = trimmed[4..].trim_start_matches([' ', '\t', '=']).to_string();
+            current = Some((alias, false, Vec::new()));
+            continue;
+        }
+        let Some((alias, marked, lines)) = current.as_mut() else {
+            continue;
+        };
+        if trimmed == USER_OPTIONS_MARKER {
+            // Everything above the marker is gitid's own
+            *marked = true;
+            lines.clear();
+            continue;
+        }
 
-    // For 'both' platform, generate entries for both GitHub and GitLab
-    if matches!(profile.platform, Platform::Both) {
-        // Also generate specific aliases for github and gitlab
-        let github_alias = format!("github-{}", profile_name);
-        let gitlab_alias = format!("gitlab-{}", profile_name);
+        let key = keyword(trimmed);
+        let own = generated
+            .get(alias.as_str())
+            .is_some_and(|keys| keys.iter().any(|k| k.eq_ignore_ascii_case(key)));
+        if *marked || !own {
+            lines.push(line.trim_end().to_string());
+        }
+    }
+    finish(current);
+    entries
+}
 
-        entry.push_str(&format!(
-            "\nHost {}\n  HostName github.com\n  User git\n  IdentityFile {}\n  IdentitiesOnly yes\n",
-            github_alias, ssh_key
-        ));
-        entry.push_str(&format!(
-            "\nHost {}\n  HostName gitlab.com\n  User git\n  IdentityFile {}\n  IdentitiesOnly yes\n",
-            gitlab_alias, ssh_key
-        ));
+/// Options of one Host entry: gitid's own, with the profile's ssh_options
+/// replacing any of them they name (ssh keeps the first value it reads)
+/// and adding the rest
+fn host_options<'a>(profile: &'a Profile, hostname: &'a str) -> Vec<(&'a str, &'a str)> {
+    let mut options = vec![("HostName", hostname), ("User", "git")];
+    for path in profile.ssh_key_paths() {
+        options.push(("IdentityFile", path));
     }
+    options.push(("IdentitiesOnly", "yes"));
 
-    entry
+    for (key, value) in &profile.ssh_options {
+        match options.iter_mut().find(|(k, _)| k.eq_ignore_ascii_case(key)) {
+            Some(option) => option.1 = value.as_str(),
+            None => options.push((key.as_str(), value.as_str())),
+        }
+    }
+    options
 }
 
-/// Generate the managed block content for all profiles
-pub fn generate_managed_block(config: &Config) -> String {
+/// Generate the managed block content for all profiles. Lines the user
+/// added to the entries of the managed block in `current` (the existing
+/// SSH config) are kept.
+pub fn generate_managed_block(config: &Config, current: &str) -> String {
+    let existing = managed::find_block(current, MANAGED_START, MANAGED_END).unwrap_or("");
+    let user_lines = user_lines(config, existing);
+
     let mut block = String::new();
     block.push_str(MANAGED_START);
     block.push('\n');
@@ -54,7 +214,7 @@ pub fn generate_managed_block(config: &Config) -> String {
 
     for name in profile_names {
         if let Some(profile) = config.profiles.get(name) {
-            block.push_str(&generate_host_entry(name, profile));
+            block.push_str(&generate_host_entry(name, profile, &user_lines));
         }
     }
 
@@ -62,80 +222,395 @@ pub fn generate_managed_block(config: &Config) -> String {
     block
 }
 
-/// Read the current SSH config content
-fn read_ssh_config() -> Result<String> {
-    let path = ssh_config_path()?;
+/// Host entries of a managed block by alias, without blank lines
+fn host_entries(block: &str) -> HashMap<String, Vec<&str>> {
+    let mut entries: HashMap<String, Vec<&str>> = HashMap::new();
+    let mut alias = None;
+    for line in block.lines().map(str::trim_end) {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed == MANAGED_START || trimmed == MANAGED_END {
+            continue;
+        }
+        if keyword(trimmed).eq_ignore_ascii_case("host") {
+            alias = Some(trimmed[4..].trim_start_matches([' ', '\t', '=']).to_string());
+        }
+        if let Some(ref alias) = alias {
+            entries.entry(alias.clone()).or_default().push(line);
+        }
+    }
+    entries
+}
+
+/// Whether a profile's Host entries in `current` (the file holding the
+/// managed block) are what `ssh-sync` would write
+pub fn profile_synced(config: &Config, name: &str, current: &str) -> bool {
+    let Some(profile) = config.profiles.get(name) else {
+        return false;
+    };
+    let expected = generate_managed_block(config, current);
+    let expected = host_entries(&expected);
+    let actual = host_entries(managed::find_block(current, MANAGED_START, MANAGED_END).unwrap_or(""));
+    profile
+        .ssh_host_aliases(name)
+        .iter()
+        .all(|(alias, _)| actual.get(alias).is_some_and(|entry| expected.get(alias) == Some(entry)))
+}
+
+/// Read an SSH config file, empty if it doesn't exist
+fn read_ssh_config(path: &Path) -> Result<String> {
     if path.exists() {
-        fs::read_to_string(&path)
+        fs::read_to_string(path)
             .with_context(|| format!("Failed to read SSH config: {}", path.display()))
     } else {
         Ok(String::new())
     }
 }
 
-/// Write the SSH config content
-fn write_ssh_config(content: &str) -> Result<()> {
-    let path = ssh_config_path()?;
+/// Infix of backup file names, e.g. `config.gitid-backup-1718000000`
+const BACKUP_INFIX: &str = ".gitid-backup-";
+
+/// Timestamp of a backup of `file_name`, if `name` is one
+fn backup_stamp(name: &str, file_name: &str) -> Option<u64> {
+    name.strip_prefix(file_name)?.strip_prefix(BACKUP_INFIX)?.parse().ok()
+}
+
+/// Backups of an SSH config file as (timestamp, path), newest first
+pub fn list_backups(path: &Path) -> Result<Vec<(u64, PathBuf)>> {
+    let (Some(dir), Some(file_name)) = (path.parent(), path.file_name()) else {
+        return Ok(Vec::new());
+    };
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let file_name = file_name.to_string_lossy();
+
+    let mut backups: Vec<(u64, PathBuf)> = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read {}", dir.display()))?
+        .flatten()
+        .filter_map(|entry| {
+            let stamp = backup_stamp(&entry.file_name().to_string_lossy(), &file_name)?;
+            Some((stamp, entry.path()))
+        })
+        .collect();
+    backups.sort_by_key(|b| std::cmp::Reverse(b.0));
+    Ok(backups)
+}
+
+/// Copy a file to `<path>.gitid-backup-<stamp>` and delete all but the
+/// newest `keep` backups
+fn backup_ssh_config(path: &Path, stamp: u64, keep: usize) -> Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+    let backup = PathBuf::from(format!("{}{}{}", path.display(), BACKUP_INFIX, stamp));
+    fs::copy(path, &backup)
+        .with_context(|| format!("Failed to back up {} to {}", path.display(), backup.display()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&backup, fs::Permissions::from_mode(0o600))?;
+    }
+
+    for (_, old) in list_backups(path)?.into_iter().skip(keep) {
+        fs::remove_file(&old).with_context(|| format!("Failed to delete {}", old.display()))?;
+    }
+    Ok(())
+}
+
+/// Put the backups taken at `stamp` back in place of the files they were
+/// taken from. Returns the restored paths.
+pub fn restore_backups(stamp: u64) -> Result<Vec<PathBuf>> {
+    let mut restored = Vec::new();
+    for path in [ssh_config_path()?, include_path()?] {
+        let Some((_, backup)) = list_backups(&path)?.into_iter().find(|(s, _)| *s == stamp) else {
+            continue;
+        };
+        let content = fs::read_to_string(&backup)
+            .with_context(|| format!("Failed to read {}", backup.display()))?;
+        write_ssh_config(&path, &content, None)?;
+        restored.push(path);
+    }
+    Ok(restored)
+}
 
+/// Write an SSH config file. With `backup` set to (timestamp, count), the
+/// old content is saved first and only the newest `count` backups are kept.
+fn write_ssh_config(path: &Path, content: &str, backup: Option<(u64, usize)>) -> Result<()> {
     // Ensure .ssh directory exists
     if let Some(parent) = path.parent() {
         fs::create_dir_all(parent)
             .with_context(|| format!("Failed to create .ssh directory: {}", parent.display()))?;
     }
 
-    fs::write(&path, content)
+    // Nothing to do (or to journal) if the content is unchanged
+    if path.exists() && read_ssh_config(path)? == content {
+        return Ok(());
+    }
+
+    journal::record_file(path)?;
+    if let Some((stamp, keep)) = backup {
+        backup_ssh_config(path, stamp, keep)?;
+    }
+
+    fs::write(path, content)
         .with_context(|| format!("Failed to write SSH config: {}", path.display()))
 }
 
+/// A file `sync_ssh_config` writes, with its current and new content
+struct PlannedWrite {
+    path: PathBuf,
+    current: String,
+    new: String,
+}
+
+/// Work out what syncing writes for the configured layout. Also returns
+/// whether an existing managed block is replaced.
+fn plan_sync(config: &Config) -> Result<(Vec<PlannedWrite>, bool)> {
+    let main_path = ssh_config_path()?;
+    let main = read_ssh_config(&main_path)?;
+
+    match config.ssh_layout.unwrap_or_default() {
+        SshLayout::Inline => {
+            let block = generate_managed_block(config, &main);
+            let (new, replaced) = managed::splice_block(&main, MANAGED_START, MANAGED_END, &block);
+            let write = PlannedWrite {
+                path: main_path,
+                current: main,
+                new,
+            };
+            Ok((vec![write], replaced))
+        }
+        SshLayout::Include => {
+            let path = include_path()?;
+            let current = read_ssh_config(&path)?;
+            // On the first sync, user lines come from the old inline block
+            let source = if managed::find_block(&current, MANAGED_START, MANAGED_END).is_some() {
+                &current
+            } else {
+                &main
+            };
+            let block = generate_managed_block(config, source);
+            let (new, replaced) = managed::splice_block(&current, MANAGED_START, MANAGED_END, &block);
+
+            let inline_removed = managed::remove_block(&main, MANAGED_START, MANAGED_END);
+            let main_new = with_include(inline_removed.as_deref().unwrap_or(&main));
+            let writes = vec![
+                PlannedWrite { path, current, new },
+                PlannedWrite {
+                    path: main_path,
+                    current: main,
+                    new: main_new,
+                },
+            ];
+            Ok((writes, replaced))
+        }
+    }
+}
+
+/// Unified diff of what `sync_ssh_config` would change: the managed block
+/// against the newly generated one, and for the include layout the
+/// Include line in ~/.ssh/config. None when nothing would change.
+pub fn managed_block_diff(config: &Config) -> Result<Option<String>> {
+    let (writes, _) = plan_sync(config)?;
+
+    let mut out = String::new();
+    for write in &writes {
+        let (current, new) = match managed::find_block(&write.new, MANAGED_START, MANAGED_END) {
+            Some(new_block) => (
+                managed::find_block(&write.current, MANAGED_START, MANAGED_END).unwrap_or(""),
+                new_block,
+            ),
+            None => (write.current.as_str(), write.new.as_str()),
+        };
+        let diff = diff::unified_diff(
+            current,
+            new,
+            &format!("{} (current)", write.path.display()),
+            &format!("{} (generated)", write.path.display()),
+        );
+        out.push_str(&diff.unwrap_or_default());
+    }
+    Ok((!out.is_empty()).then_some(out))
+}
+
 /// Sync SSH config with all profiles
 /// Returns (added_count, updated)
 pub fn sync_ssh_config(config: &Config) -> Result<(usize, bool)> {
-    let current_content = read_ssh_config()?;
-    let new_block = generate_managed_block(config);
+    for (name, profile) in &config.profiles {
+        profile
+            .check_ssh_options()
+            .with_context(|| format!("Profile '{}' has invalid ssh_options", name))?;
+    }
+
+    let (writes, replaced) = plan_sync(config)?;
+    // One timestamp for the whole sync, so its backups restore together
+    let backup = config
+        .ssh_backups
+        .filter(|keep| *keep > 0)
+        .map(|keep| (journal::now(), keep));
+    // The entries file goes first, so ssh never includes a missing file
+    for write in &writes {
+        write_ssh_config(&write.path, &write.new, backup)?;
+    }
+    sync_allowed_signers(config)?;
+    Ok((config.profiles.len(), replaced))
+}
 
-    let profile_count = config.profiles.len();
+/// Git config key naming the allowed signers file
+pub const ALLOWED_SIGNERS_KEY: &str = "gpg.ssh.allowedSignersFile";
 
-    // Check if managed block exists
-    if let (Some(start_idx), Some(end_idx)) = (
-        current_content.find(MANAGED_START),
-        current_content.find(MANAGED_END),
-    ) {
-        // Replace existing managed block
-        let end_idx = end_idx + MANAGED_END.len();
-        let mut new_content = String::new();
-        new_content.push_str(&current_content[..start_idx]);
-        new_content.push_str(&new_block);
+/// Get the allowed signers file path (~/.config/git/allowed_signers)
+pub fn allowed_signers_path() -> Result<PathBuf> {
+    Ok(gitconfig::git_config_dir()?.join("allowed_signers"))
+}
 
-        // Preserve any content after the managed block
-        if end_idx < current_content.len() {
-            new_content.push_str(&current_content[end_idx..]);
-        }
+/// Email and public key of every profile that signs with its SSH key.
+/// Profiles whose public key can't be read are left out.
+fn allowed_signers(config: &Config) -> Vec<(&str, String)> {
+    config
+        .profile_names()
+        .into_iter()
+        .filter_map(|name| config.get_profile(name))
+        .filter_map(|profile| match profile.signing_key() {
+            Some((SigningFormat::Ssh, path)) => {
+                let key = fs::read_to_string(path).ok()?;
+                Some((profile.email.as_str(), key.trim().to_string()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Generate the managed block of allowed_signers entries
+fn generate_allowed_signers_block(signers: &[(&str, String)]) -> String {
+    let mut block = String::new();
+    block.push_str(MANAGED_START);
+    block.push('\n');
+
+    for (email, key) in signers {
+        block.push_str(&format!("{} namespaces=\"git\" {}\n", email, key));
+    }
+
+    block.push_str(MANAGED_END);
+    block
+}
 
-        write_ssh_config(&new_content)?;
-        Ok((profile_count, true))
+/// Keep the allowed signers file in step with the SSH-signing profiles
+/// and point `gpg.ssh.allowedSignersFile` at it, so signatures verify
+/// locally. Nothing is written until a profile signs with SSH.
+/// Returns the number of signers listed.
+pub fn sync_allowed_signers(config: &Config) -> Result<usize> {
+    let signers = allowed_signers(config);
+    let path = allowed_signers_path()?;
+    if signers.is_empty() && !path.exists() {
+        return Ok(0);
+    }
+
+    let current = if path.exists() {
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?
     } else {
-        // Append new managed block
-        let mut new_content = current_content;
+        String::new()
+    };
+    let block = generate_allowed_signers_block(&signers);
+    let (content, _) = managed::splice_block(&current, MANAGED_START, MANAGED_END, &block);
 
-        // Add newlines if the file doesn't end with one
-        if !new_content.is_empty() && !new_content.ends_with('\n') {
-            new_content.push('\n');
-        }
-        if !new_content.is_empty() {
-            new_content.push('\n');
+    if content != current {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
         }
+        journal::record_file(&path)?;
+        fs::write(&path, &content)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+
+    if !signers.is_empty() && git::get_config(ALLOWED_SIGNERS_KEY, ConfigScope::Global)?.is_none() {
+        git::set_config(ALLOWED_SIGNERS_KEY, &path.display().to_string(), ConfigScope::Global)?;
+    }
+
+    Ok(signers.len())
+}
+
+/// Result of an SSH authentication check against a host alias
+#[derive(Debug)]
+pub struct ConnectionTest {
+    pub authenticated: bool,
+    /// Account the forge greeted the key as
+    pub account: Option<String>,
+    pub output: String,
+}
+
+/// Run `ssh -T git@<alias>` and check for the forge's greeting
+pub fn test_connection(alias: &str) -> Result<ConnectionTest> {
+    run_connection_test(&[], alias)
+}
+
+/// Like `test_connection`, but against the real hostname with only the
+/// profile's ssh_key, ignoring `~/.ssh/config` so no other key is offered
+pub fn test_key_connection(profile: &Profile, hostname: &str) -> Result<ConnectionTest> {
+    let mut args = vec![
+        "-F".to_string(),
+        "none".to_string(),
+        "-i".to_string(),
+        ssh_keys::expand_path(&profile.ssh_key).display().to_string(),
+        "-o".to_string(),
+        "IdentitiesOnly=yes".to_string(),
+    ];
+    for option in env::ssh_option_args(profile) {
+        args.push("-o".to_string());
+        args.push(option);
+    }
+    run_connection_test(&args, hostname)
+}
 
-        new_content.push_str(&new_block);
-        new_content.push('\n');
+fn run_connection_test(args: &[String], host: &str) -> Result<ConnectionTest> {
+    let output = std::process::Command::new("ssh")
+        .args(["-T", "-o", "BatchMode=yes", "-o", "ConnectTimeout=10"])
+        .args(args)
+        .arg(format!("git@{}", host))
+        .output()
+        .context("Failed to run ssh. Is OpenSSH installed?")?;
 
-        write_ssh_config(&new_content)?;
-        Ok((profile_count, false))
+    Ok(ConnectionTest::from_output(&output))
+}
+
+impl ConnectionTest {
+    /// Interpret the output of `ssh -T git@<host>`
+    pub fn from_output(output: &std::process::Output) -> Self {
+        // Forges close the session with a non-zero status even on success,
+        // so the greeting is the only reliable signal
+        let text = format!(
+            "{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let authenticated =
+            text.contains("successfully authenticated") || text.contains("Welcome to");
+
+        Self {
+            authenticated,
+            account: if authenticated { greeted_account(&text) } else { None },
+            output: text.trim().to_string(),
+        }
     }
 }
 
+/// Pull the account name out of a forge's SSH greeting:
+/// - GitHub: "Hi jdoe! You've successfully authenticated, ..."
+/// - Gitea/Forgejo: "Hi there, jdoe! You've successfully authenticated ..."
+/// - GitLab: "Welcome to GitLab, @jdoe!"
+fn greeted_account(text: &str) -> Option<String> {
+    let rest = ["Hi there, ", "Hi ", ", @"]
+        .iter()
+        .find_map(|prefix| text.split_once(prefix).map(|(_, rest)| rest))?;
+    let account = rest.split('!').next()?.trim();
+    (!account.is_empty() && !account.contains(char::is_whitespace)).then(|| account.to_string())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::profile::Platform;
 
     #[test]
     fn test_generate_host_entry() {
@@ -148,12 +623,59 @@ mod tests {
             None,
         );
 
-        let entry = generate_host_entry("work", &profile);
+        let entry = generate_host_entry("work", &profile, &HashMap::new());
         assert!(entry.contains("Host github-work"));
         assert!(entry.contains("HostName github.com"));
         assert!(entry.contains("IdentityFile ~/.ssh/id_ed25519_work"));
     }
 
+    #[test]
+    fn test_host_entry_ssh_options() {
+        let mut profile = Profile::new(
+            "John Doe".to_string(),
+            "john@company.com".to_string(),
+            Platform::Custom,
+            "~/.ssh/id_work".to_string(),
+            None,
+            Some("review.company.com".to_string()),
+        );
+        profile.ssh_options.insert("Port".to_string(), "29418".to_string());
+        profile.ssh_options.insert("ProxyJump".to_string(), "bastion.company.com".to_string());
+        profile.ssh_options.insert("user".to_string(), "jdoe".to_string());
+
+        let entry = generate_host_entry("work", &profile, &HashMap::new());
+        assert_eq!(
+            entry,
+            format!(
+                "Host git-work\n  HostName review.company.com\n  User jdoe\n  IdentityFile ~/.ssh/id_work\n  IdentitiesOnly yes\n  Port 29418\n  ProxyJump bastion.company.com\n  {}\n",
+                USER_OPTIONS_MARKER
+            )
+        );
+    }
+
+    #[test]
+    fn test_allowed_signers_block() {
+        let signers = vec![("john@company.com", "ssh-ed25519 AAAAC3Nz john@company.com".to_string())];
+        let block = generate_allowed_signers_block(&signers);
+        assert!(block.starts_with(MANAGED_START));
+        assert!(block.contains("\njohn@company.com namespaces=\"git\" ssh-ed25519 AAAAC3Nz john@company.com\n"));
+        assert!(block.ends_with(MANAGED_END));
+    }
+
+    #[test]
+    fn test_greeted_account() {
+        assert_eq!(
+            greeted_account("Hi jdoe! You've successfully authenticated, but GitHub does not provide shell access."),
+            Some("jdoe".to_string())
+        );
+        assert_eq!(
+            greeted_account("Hi there, jdoe! You've successfully authenticated with the key named work, but Forgejo does not provide shell access."),
+            Some("jdoe".to_string())
+        );
+        assert_eq!(greeted_account("Welcome to GitLab, @jdoe-work!"), Some("jdoe-work".to_string()));
+        assert_eq!(greeted_account("git@github.com: Permission denied (publickey)."), None);
+    }
+
     #[test]
     fn test_generate_managed_block() {
         let mut config = Config::default();
@@ -169,9 +691,87 @@ mod tests {
             ),
         );
 
-        let block = generate_managed_block(&config);
+        let block = generate_managed_block(&config, "");
         assert!(block.starts_with(MANAGED_START));
         assert!(block.ends_with(MANAGED_END));
         assert!(block.contains("Host github-work"));
     }
+
+    #[test]
+    fn test_profile_synced() {
+        let mut config = Config::default();
+        for (name, key) in [("work", "~/.ssh/id_work"), ("oss", "~/.ssh/id_oss")] {
+            config.profiles.insert(
+                name.to_string(),
+                Profile::new(
+                    "John Doe".to_string(),
+                    "john@example.com".to_string(),
+                    Platform::Github,
+                    key.to_string(),
+                    None,
+                    None,
+                ),
+            );
+        }
+
+        let current = format!("Host *\n  User me\n{}\n", generate_managed_block(&config, ""));
+        assert!(profile_synced(&config, "work", &current));
+        assert!(!profile_synced(&config, "work", ""));
+
+        config.profiles.get_mut("work").unwrap().ssh_key = "~/.ssh/id_new".to_string();
+        assert!(!profile_synced(&config, "work", &current));
+        assert!(profile_synced(&config, "oss", &current));
+        assert!(!profile_synced(&config, "missing", &current));
+    }
+
+    #[test]
+    fn test_backup_stamp() {
+        assert_eq!(backup_stamp("config.gitid-backup-1718000000", "config"), Some(1718000000));
+        assert_eq!(backup_stamp("gitid_config.gitid-backup-17", "gitid_config"), Some(17));
+        assert_eq!(backup_stamp("gitid_config.gitid-backup-17", "config"), None);
+        assert_eq!(backup_stamp("config.gitid-backup-", "config"), None);
+        assert_eq!(backup_stamp("config", "config"), None);
+    }
+
+    #[test]
+    fn test_with_include() {
+        assert_eq!(with_include(""), "Include ~/.ssh/gitid_config\n");
+        assert_eq!(with_include("Host *\n  User me\n"), "Include ~/.ssh/gitid_config\nHost *\n  User me\n");
+        // An existing Include is left alone, wherever it is
+        let content = "Host *\n  User me\ninclude   config.d/* ~/.ssh/gitid_config\n";
+        assert!(has_include(content));
+        assert_eq!(with_include(content), content);
+        assert!(!has_include("Include ~/.ssh/other_config\n"));
+    }
+
+    #[test]
+    fn test_managed_block_keeps_user_lines() {
+        let mut config = Config::default();
+        let mut profile = Profile::new(
+            "John Doe".to_string(),
+            "john@company.com".to_string(),
+            Platform::Github,
+            "~/.ssh/id_work".to_string(),
+            None,
+            None,
+        );
+        config.profiles.insert("work".to_string(), profile.clone());
+        profile.platform = Platform::Gitlab;
+        profile.ssh_options.insert("Port".to_string(), "2222".to_string());
+        config.profiles.insert("lab".to_string(), profile);
+
+        // work's entry has the marker; lab's predates it
+        let current = format!(
+            "Host *\n  ServerAliveInterval 60\n\n{}\nHost github-work\n  HostName github.com\n  IdentityFile ~/.ssh/old\n  {}\n  ControlMaster auto\n  # my note\nHost gitlab-lab\n  HostName gitlab.com\n  IdentityFile ~/.ssh/old\n  Port 22\n  ForwardAgent no\n{}\n",
+            MANAGED_START, USER_OPTIONS_MARKER, MANAGED_END
+        );
+        let block = generate_managed_block(&config, &current);
+
+        let marker = format!("  {}\n", USER_OPTIONS_MARKER);
+        assert!(block.contains(&format!("IdentitiesOnly yes\n{}  ControlMaster auto\n  # my note\n", marker)));
+        assert!(block.contains(&format!("Port 2222\n{}  ForwardAgent no\n", marker)));
+        assert!(!block.contains("~/.ssh/old"));
+        assert!(!block.contains("Port 22\n"));
+        assert!(!block.contains("ServerAliveInterval"));
+    }
 }
@@ -1,4 +1,5 @@
 use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
 use std::fs;
 use std::path::PathBuf;
 use std::process::Command;
@@ -24,6 +25,46 @@ impl SshKey {
     }
 }
 
+/// Algorithm for a generated key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum KeyType {
+    #[default]
+    Ed25519,
+    /// ed25519 backed by a FIDO2 security key
+    #[value(name = "ed25519-sk")]
+    Ed25519Sk,
+}
+
+impl KeyType {
+    /// Name passed to `ssh-keygen -t`, also used in key file names
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            KeyType::Ed25519 => "ed25519",
+            KeyType::Ed25519Sk => "ed25519-sk",
+        }
+    }
+
+    /// Whether the private key lives on a hardware security key
+    pub fn is_hardware(&self) -> bool {
+        matches!(self, KeyType::Ed25519Sk)
+    }
+}
+
+/// Expand a leading `~/` in a configured key path to the home directory
+pub fn expand_path(path: &str) -> PathBuf {
+    if let Some(rest) = path.strip_prefix("~/") {
+        if let Some(home) = dirs::home_dir() {
+            return home.join(rest);
+        }
+    }
+    PathBuf::from(path)
+}
+
+/// Public half of a configured private key path
+pub fn public_key_path(private_key: &str) -> PathBuf {
+    PathBuf::from(format!("{}.pub", expand_path(private_key).display()))
+}
+
 /// Get the SSH directory path
 pub fn ssh_dir() -> Result<PathBuf> {
     let home = dirs::home_dir().context("Could not determine home directory")?;
@@ -85,32 +126,43 @@ pub fn discover_keys() -> Result<Vec<SshKey>> {
     Ok(keys)
 }
 
-/// Detect the type of SSH key (ed25519, rsa, ecdsa, etc.)
+/// Key type named by the algorithm at the start of a public key line
+fn public_key_type(public_key: &str) -> Option<&'static str> {
+    let algorithm = public_key.split_whitespace().next()?;
+    Some(match algorithm {
+        "ssh-ed25519" => "ed25519",
+        "sk-ssh-ed25519@openssh.com" => "ed25519-sk",
+        "sk-ecdsa-sha2-nistp256@openssh.com" => "ecdsa-sk",
+        "ssh-rsa" => "rsa",
+        "ssh-dss" => "dsa",
+        a if a.starts_with("ecdsa-") => "ecdsa",
+        _ => return None,
+    })
+}
+
+/// Detect the type of SSH key (ed25519, ed25519-sk, rsa, ecdsa, etc.)
 fn detect_key_type(path: &PathBuf) -> String {
-    // Try to read the first line of the private key to determine type
+    // The public key names the algorithm, including for security key
+    // handles whose private half is only a stub
+    let pub_path = PathBuf::from(format!("{}.pub", path.display()));
+    if let Some(key_type) = fs::read_to_string(&pub_path).ok().as_deref().and_then(public_key_type) {
+        return key_type.to_string();
+    }
+
+    // Otherwise fall back to the private key's header and file name
     if let Ok(content) = fs::read_to_string(path) {
         let first_line = content.lines().next().unwrap_or("");
         if first_line.contains("OPENSSH PRIVATE KEY") {
-            // Modern OpenSSH format - need to check the public key or filename
             let filename = path.file_name().unwrap_or_default().to_str().unwrap_or("");
-            if filename.contains("ed25519") {
+            if filename.contains("ed25519_sk") {
+                return "ed25519-sk".to_string();
+            } else if filename.contains("ed25519") {
                 return "ed25519".to_string();
             } else if filename.contains("ecdsa") {
                 return "ecdsa".to_string();
             } else if filename.contains("rsa") {
                 return "rsa".to_string();
             }
-            // Try to detect from public key
-            let pub_path = PathBuf::from(format!("{}.pub", path.display()));
-            if let Ok(pub_content) = fs::read_to_string(&pub_path) {
-                if pub_content.starts_with("ssh-ed25519") {
-                    return "ed25519".to_string();
-                } else if pub_content.starts_with("ssh-rsa") {
-                    return "rsa".to_string();
-                } else if pub_content.starts_with("ecdsa-") {
-                    return "ecdsa".to_string();
-                }
-            }
             return "openssh".to_string();
         } else if first_line.contains("RSA PRIVATE KEY") {
             return "rsa".to_string();
@@ -121,8 +173,25 @@ fn detect_key_type(path: &PathBuf) -> String {
     "unknown".to_string()
 }
 
-/// Generate a new SSH key pair
-pub fn generate_key(name: &str, email: &str) -> Result<SshKey> {
+/// Environment variable holding the passphrase for a key being generated.
+/// ssh-keygen runs gitid itself as its SSH_ASKPASS program, which prints
+/// the value, so the passphrase never appears on a command line.
+pub const ASKPASS_ENV: &str = "GITID_ASKPASS_PASSPHRASE";
+
+/// Generate a new SSH key pair. The key is protected by the passphrase
+/// unless it is empty; with None, ssh-keygen asks on the terminal. A
+/// resident hardware key is stored on the security key under an
+/// application name for the profile, so `load_resident_keys` can recover it.
+pub fn generate_key(
+    name: &str,
+    email: &str,
+    key_type: KeyType,
+    resident: bool,
+    passphrase: Option<&str>,
+) -> Result<SshKey> {
+    if resident && !key_type.is_hardware() {
+        bail!("Only security key types can be resident (use --key-type ed25519-sk)");
+    }
     let ssh_path = ssh_dir()?;
 
     // Ensure .ssh directory exists with correct permissions
@@ -138,7 +207,7 @@ pub fn generate_key(name: &str, email: &str) -> Result<SshKey> {
         }
     }
 
-    let key_filename = format!("id_ed25519_{}", name);
+    let key_filename = format!("id_{}_{}", key_type.as_str().replace('-', "_"), name);
     let private_key_path = ssh_path.join(&key_filename);
     let public_key_path = ssh_path.join(format!("{}.pub", key_filename));
 
@@ -148,13 +217,32 @@ pub fn generate_key(name: &str, email: &str) -> Result<SshKey> {
     }
 
     // Generate the key using ssh-keygen
-    let status = Command::new("ssh-keygen")
-        .args([
-            "-t", "ed25519",
-            "-C", email,
-            "-f", private_key_path.to_str().unwrap(),
-            "-N", "",  // Empty passphrase (user can change later)
-        ])
+    let mut keygen = Command::new("ssh-keygen");
+    keygen.args([
+        "-t", key_type.as_str(),
+        "-C", email,
+        "-f", private_key_path.to_str().unwrap(),
+    ]);
+    if resident {
+        keygen.args(["-O", "resident", "-O"]).arg(format!("application=ssh:gitid-{}", name));
+    }
+    match passphrase {
+        None => {}
+        Some("") => {
+            keygen.args(["-N", ""]);
+        }
+        Some(passphrase) => {
+        // ssh-keygen asks for the passphrase twice; both answers come from
+        // the askpass program
+            let exe = std::env::current_exe().context("Failed to locate the gitid executable")?;
+            keygen
+                .env("SSH_ASKPASS", exe)
+                .env("SSH_ASKPASS_REQUIRE", "force")
+                .env(ASKPASS_ENV, passphrase)
+                .stdin(std::process::Stdio::null());
+        }
+    }
+    let status = keygen
         .status()
         .context("Failed to run ssh-keygen. Is OpenSSH installed?")?;
 
@@ -166,12 +254,242 @@ pub fn generate_key(name: &str, email: &str) -> Result<SshKey> {
         name: key_filename,
         private_key_path,
         public_key_path,
-        key_type: "ed25519".to_string(),
+        key_type: key_type.as_str().to_string(),
     })
 }
 
+/// Download the resident keys on a connected security key into ~/.ssh
+/// with `ssh-keygen -K`, which asks for the PIN and a touch on the
+/// terminal. Keys already present are kept.
+pub fn load_resident_keys() -> Result<()> {
+    let ssh_path = ssh_dir()?;
+    fs::create_dir_all(&ssh_path)
+        .with_context(|| format!("Failed to create SSH directory: {}", ssh_path.display()))?;
+
+    let status = Command::new("ssh-keygen")
+        .arg("-K")
+        .current_dir(&ssh_path)
+        .status()
+        .context("Failed to run ssh-keygen. Is OpenSSH installed?")?;
+    if !status.success() {
+        bail!("ssh-keygen could not load resident keys from the security key");
+    }
+    Ok(())
+}
+
 /// Get the public key content (for display/copying)
 pub fn read_public_key(key: &SshKey) -> Result<String> {
     fs::read_to_string(&key.public_key_path)
         .with_context(|| format!("Failed to read public key: {}", key.public_key_path.display()))
 }
+
+/// A key as described by `ssh-keygen -l`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyDetails {
+    pub bits: u32,
+    pub fingerprint: String,
+    /// Algorithm as ssh-keygen names it, e.g. ED25519, RSA, ECDSA-SK
+    pub algorithm: String,
+}
+
+/// Parse a line of `ssh-keygen -l` output: `<bits> <fingerprint> <comment> (<algorithm>)`
+fn parse_key_details(line: &str) -> Option<KeyDetails> {
+    let mut fields = line.split_whitespace();
+    let bits = fields.next()?.parse().ok()?;
+    let fingerprint = fields.next()?.to_string();
+    let algorithm = fields.last()?.strip_prefix('(')?.strip_suffix(')')?.to_string();
+    Some(KeyDetails {
+        bits,
+        fingerprint,
+        algorithm,
+    })
+}
+
+/// Size, fingerprint and algorithm of a key file
+pub fn key_details(path: &std::path::Path) -> Result<KeyDetails> {
+    let output = Command::new("ssh-keygen")
+        .arg("-lf")
+        .arg(path)
+        .output()
+        .context("Failed to run ssh-keygen. Is OpenSSH installed?")?;
+    if !output.status.success() {
+        bail!("Could not read key fingerprint: {}", path.display());
+    }
+    let text = String::from_utf8_lossy(&output.stdout);
+    text.lines()
+        .next()
+        .and_then(parse_key_details)
+        .with_context(|| format!("Unexpected ssh-keygen output for {}", path.display()))
+}
+
+/// SHA256 fingerprint of a key file, as printed by `ssh-keygen -l`
+fn fingerprint(path: &std::path::Path) -> Result<String> {
+    key_details(path).map(|d| d.fingerprint)
+}
+
+/// Reasons `gitid key audit` flags a key: a weak algorithm or size, or an
+/// age over `max_age_days`
+pub fn key_weaknesses(details: &KeyDetails, age_days: Option<u64>, max_age_days: u64) -> Vec<String> {
+    let mut weaknesses = Vec::new();
+    match details.algorithm.as_str() {
+        "DSA" => weaknesses.push("DSA keys are insecure and no longer accepted by OpenSSH".to_string()),
+        "RSA" if details.bits < 3072 => {
+            weaknesses.push(format!("{}-bit RSA is below the recommended 3072 bits", details.bits))
+        }
+        _ => {}
+    }
+    if let Some(age) = age_days.filter(|age| *age > max_age_days) {
+        weaknesses.push(format!("created {} days ago (older than {} days)", age, max_age_days));
+    }
+    weaknesses
+}
+
+/// Days since a key file was created (or last modified, where the
+/// filesystem doesn't record creation)
+pub fn key_age_days(path: &std::path::Path) -> Option<u64> {
+    let metadata = fs::metadata(path).ok()?;
+    let created = metadata.created().or_else(|_| metadata.modified()).ok()?;
+    let age = std::time::SystemTime::now().duration_since(created).ok()?;
+    Some(age.as_secs() / 86400)
+}
+
+/// Whether the running ssh-agent holds a configured key. Fails if no
+/// agent is reachable.
+pub fn agent_has(private_key: &str) -> Result<bool> {
+    let listed = Command::new("ssh-add")
+        .arg("-l")
+        .output()
+        .context("Failed to run ssh-add. Is OpenSSH installed?")?;
+    // ssh-add exits 1 for an empty agent and 2 when no agent is reachable
+    if listed.status.code() == Some(2) {
+        bail!("No ssh-agent is running (SSH_AUTH_SOCK is not set or stale)");
+    }
+
+    let wanted = fingerprint(&public_key_path(private_key))
+        .or_else(|_| fingerprint(&expand_path(private_key)))?;
+    Ok(String::from_utf8_lossy(&listed.stdout)
+        .lines()
+        .any(|line| line.split_whitespace().nth(1) == Some(wanted.as_str())))
+}
+
+/// Load a configured private key into the running ssh-agent. Returns
+/// false if the agent already holds it.
+pub fn agent_add(private_key: &str) -> Result<bool> {
+    if agent_has(private_key)? {
+        return Ok(false);
+    }
+
+    let path = expand_path(private_key);
+
+    // Inherit the terminal so ssh-add can ask for a passphrase
+    let status = Command::new("ssh-add")
+        .arg(&path)
+        .status()
+        .context("Failed to run ssh-add. Is OpenSSH installed?")?;
+    if !status.success() {
+        bail!("ssh-add failed to load {}", path.display());
+    }
+    Ok(true)
+}
+
+/// Remove a configured key from the running ssh-agent. Returns false if
+/// the agent doesn't hold it.
+pub fn agent_remove(private_key: &str) -> Result<bool> {
+    if !agent_has(private_key)? {
+        return Ok(false);
+    }
+
+    // ssh-add -d identifies the key by its public half
+    let public_key = public_key_path(private_key);
+    let path = if public_key.exists() { public_key } else { expand_path(private_key) };
+    let output = Command::new("ssh-add")
+        .arg("-d")
+        .arg(&path)
+        .output()
+        .context("Failed to run ssh-add. Is OpenSSH installed?")?;
+    if !output.status.success() {
+        bail!("ssh-add failed to remove {}", path.display());
+    }
+    Ok(true)
+}
+
+/// Move both halves of a configured key pair into ~/.ssh/archive, suffixed
+/// with the current time. Returns each file's original and archived path.
+pub fn archive_key(private_key: &str) -> Result<Vec<(PathBuf, PathBuf)>> {
+    let archive = ssh_dir()?.join("archive");
+    fs::create_dir_all(&archive)
+        .with_context(|| format!("Failed to create {}", archive.display()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&archive, fs::Permissions::from_mode(0o700))?;
+    }
+
+    let stamp = crate::journal::now();
+    let mut moved = Vec::new();
+    for path in [expand_path(private_key), public_key_path(private_key)] {
+        if !path.exists() {
+            continue;
+        }
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let target = archive.join(format!("{}.{}", file_name, stamp));
+        fs::rename(&path, &target)
+            .with_context(|| format!("Failed to move {} to {}", path.display(), target.display()))?;
+        moved.push((path, target));
+    }
+    Ok(moved)
+}
+
+/// Delete both halves of a key pair
+pub fn delete_key(key: &SshKey) -> Result<()> {
+    for path in [&key.private_key_path, &key.public_key_path] {
+        if path.exists() {
+            fs::remove_file(path)
+                .with_context(|| format!("Failed to delete key file: {}", path.display()))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_public_key_type() {
+        assert_eq!(public_key_type("ssh-ed25519 AAAA me@x.com"), Some("ed25519"));
+        assert_eq!(public_key_type("sk-ssh-ed25519@openssh.com AAAA me@x.com"), Some("ed25519-sk"));
+        assert_eq!(public_key_type("sk-ecdsa-sha2-nistp256@openssh.com AAAA"), Some("ecdsa-sk"));
+        assert_eq!(public_key_type("ecdsa-sha2-nistp384 AAAA"), Some("ecdsa"));
+        assert_eq!(public_key_type("ssh-rsa AAAA"), Some("rsa"));
+        assert_eq!(public_key_type("garbage"), None);
+        assert_eq!(public_key_type(""), None);
+    }
+
+    #[test]
+    fn test_parse_key_details() {
+        let details = parse_key_details("256 SHA256:1fMWP9/QiHyhp root@vm (ED25519)").unwrap();
+        assert_eq!(details.bits, 256);
+        assert_eq!(details.fingerprint, "SHA256:1fMWP9/QiHyhp");
+        assert_eq!(details.algorithm, "ED25519");
+        // Comments may contain spaces or be missing
+        let details = parse_key_details("2048 SHA256:9l5g John Doe (work) (RSA)").unwrap();
+        assert_eq!(details.algorithm, "RSA");
+        assert_eq!(parse_key_details("3072 SHA256:abc (RSA)").unwrap().bits, 3072);
+        assert_eq!(parse_key_details("not a key"), None);
+    }
+
+    #[test]
+    fn test_key_weaknesses() {
+        let key = |bits, algorithm: &str| KeyDetails {
+            bits,
+            fingerprint: "SHA256:x".to_string(),
+            algorithm: algorithm.to_string(),
+        };
+        assert!(key_weaknesses(&key(256, "ED25519"), Some(10), 730).is_empty());
+        assert!(key_weaknesses(&key(3072, "RSA"), None, 730).is_empty());
+        assert_eq!(key_weaknesses(&key(2048, "RSA"), Some(10), 730).len(), 1);
+        assert_eq!(key_weaknesses(&key(1024, "DSA"), Some(1000), 730).len(), 2);
+        assert_eq!(key_weaknesses(&key(256, "ED25519-SK"), Some(731), 730).len(), 1);
+    }
+}
@@ -0,0 +1,90 @@
+use anyhow::Result;
+use colored::Colorize;
+
+type UndoFn = Box<dyn FnOnce() -> Result<()>>;
+
+/// Undo log for a multi-step command.
+///
+/// Each completed step registers how to undo itself. If the transaction is
+/// dropped without `commit` (e.g. because a later step returned an error),
+/// the registered steps are undone in reverse order.
+#[derive(Default)]
+pub struct Transaction {
+    undo: Vec<(String, UndoFn)>,
+    committed: bool,
+}
+
+impl Transaction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register how to undo a step that has just succeeded
+    pub fn on_rollback(
+        &mut self,
+        description: impl Into<String>,
+        undo: impl FnOnce() -> Result<()> + 'static,
+    ) {
+        self.undo.push((description.into(), Box::new(undo)));
+    }
+
+    /// Keep all changes made so far
+    pub fn commit(mut self) {
+        self.committed = true;
+    }
+
+    fn rollback(&mut self) {
+        if self.undo.is_empty() {
+            return;
+        }
+
+        eprintln!("{} Rolling back partial changes...", "Warning:".yellow().bold());
+        while let Some((description, undo)) = self.undo.pop() {
+            match undo() {
+                Ok(()) => eprintln!("  Undid: {}", description),
+                Err(e) => eprintln!("  {} could not undo {}: {}", "Failed:".red(), description, e),
+            }
+        }
+    }
+}
+
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        if !self.committed {
+            self.rollback();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn recording_tx(log: &Rc<RefCell<Vec<&'static str>>>) -> Transaction {
+        let mut tx = Transaction::new();
+        for step in ["first", "second"] {
+            let log = Rc::clone(log);
+            tx.on_rollback(step, move || {
+                log.borrow_mut().push(step);
+                Ok(())
+            });
+        }
+        tx
+    }
+
+    #[test]
+    fn test_drop_rolls_back_in_reverse() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        drop(recording_tx(&log));
+        assert_eq!(*log.borrow(), vec!["second", "first"]);
+    }
+
+    #[test]
+    fn test_commit_keeps_changes() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        recording_tx(&log).commit();
+        assert!(log.borrow().is_empty());
+    }
+}
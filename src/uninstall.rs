@@ -0,0 +1,328 @@
+use crate::config::Config;
+use crate::detect;
+use crate::env;
+use crate::git::{self, ConfigScope};
+use crate::gitconfig;
+use crate::hooks;
+use crate::managed;
+use crate::netrc;
+use crate::registry::Registry;
+use crate::remote;
+use crate::secrets;
+use crate::ssh;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// One teardown step
+#[derive(Debug)]
+pub enum Action {
+    /// Strip a managed block from a file gitid shares with the user
+    RemoveBlock {
+        path: PathBuf,
+        start: &'static str,
+        end: &'static str,
+    },
+    /// Delete a line gitid added to a file the user owns
+    RemoveLine { path: PathBuf, line: &'static str },
+    /// Delete a file gitid created
+    RemoveFile(PathBuf),
+    /// Delete a directory gitid owns
+    RemoveDir(PathBuf),
+    /// Unset a global git config key gitid set
+    UnsetGlobal(String),
+    /// Unset a key gitid set in a repository's config file
+    UnsetRepo { config: PathBuf, key: String },
+    /// Delete a profile's token from the OS keyring
+    DeleteToken(String),
+    /// Delete a profile's cached GitHub App token from the OS keyring
+    DeleteAppToken(String),
+}
+
+impl Action {
+    pub fn describe(&self) -> String {
+        match self {
+            Action::RemoveBlock { path, .. } => {
+                format!("Remove gitid block from {}", path.display())
+            }
+            Action::RemoveLine { path, line } => {
+                format!("Remove \"{}\" from {}", line, path.display())
+            }
+            Action::RemoveFile(path) => format!("Delete {}", path.display()),
+            Action::RemoveDir(path) => format!("Delete directory {}", path.display()),
+            Action::UnsetGlobal(key) => format!("Unset global git config {}", key),
+            Action::UnsetRepo { config, key } => format!("Unset {} in {}", key, config.display()),
+            Action::DeleteToken(name) => format!("Delete keyring token for '{}'", name),
+            Action::DeleteAppToken(name) => {
+                format!("Delete cached GitHub App token for '{}'", name)
+            }
+        }
+    }
+
+    pub fn apply(&self) -> Result<()> {
+        match self {
+            Action::RemoveBlock { path, start, end } => {
+                let content = fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?;
+                if let Some(stripped) = managed::remove_block(&content, start, end) {
+                    fs::write(path, stripped)
+                        .with_context(|| format!("Failed to write {}", path.display()))?;
+                }
+                Ok(())
+            }
+            Action::RemoveLine { path, line } => {
+                let content = fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?;
+                let kept: String = content
+                    .lines()
+                    .filter(|l| l.trim() != *line)
+                    .map(|l| format!("{}\n", l))
+                    .collect();
+                fs::write(path, kept).with_context(|| format!("Failed to write {}", path.display()))
+            }
+            Action::RemoveFile(path) => fs::remove_file(path)
+                .with_context(|| format!("Failed to delete {}", path.display())),
+            Action::RemoveDir(path) => fs::remove_dir_all(path)
+                .with_context(|| format!("Failed to delete {}", path.display())),
+            Action::UnsetGlobal(key) => {
+                git::restore_config(None, key, None, ConfigScope::Global)
+            }
+            Action::UnsetRepo { config, key } => git::unset_file_config(config, key),
+            Action::DeleteToken(name) => secrets::delete_token(name).map(|_| ()),
+            Action::DeleteAppToken(name) => secrets::delete_app_token(name).map(|_| ()),
+        }
+    }
+}
+
+/// Add a RemoveBlock action if the file contains a gitid block
+fn plan_block(
+    actions: &mut Vec<Action>,
+    path: PathBuf,
+    start: &'static str,
+    end: &'static str,
+) {
+    let has_block = fs::read_to_string(&path)
+        .map(|c| c.contains(start) && c.contains(end))
+        .unwrap_or(false);
+    if has_block {
+        actions.push(Action::RemoveBlock { path, start, end });
+    }
+}
+
+/// Git directories of the repositories gitid may have configured: the
+/// current one, those with gitid hooks and those `gitid scan` recorded
+fn known_repos() -> Result<Vec<PathBuf>> {
+    let mut dirs = hooks::hooked_repos()?;
+    dirs.extend(Registry::load()?.repo_paths().into_iter().map(|root| root.join(".git")));
+    dirs.extend(git::common_dir()?);
+
+    let mut dirs: Vec<PathBuf> = dirs
+        .into_iter()
+        .filter(|dir| dir.join("config").is_file())
+        .map(|dir| fs::canonicalize(&dir).unwrap_or(dir))
+        .collect();
+    dirs.sort();
+    dirs.dedup();
+    Ok(dirs)
+}
+
+/// Whether a config entry is one gitid writes: a pin, a core.sshCommand
+/// forcing a profile key, a profile's insteadOf rule, or its GCM account
+/// selection
+fn is_managed_key(config: &Config, key: &str, value: &str) -> bool {
+    let key_is = |k: &str| key.eq_ignore_ascii_case(k);
+    key_is(detect::PIN_KEY)
+        || (key_is("core.sshCommand") && env::is_managed_ssh_command(value))
+        || remote::is_managed_rule(config, key)
+        || config
+            .profiles
+            .values()
+            .any(|profile| profile.credential_keys().iter().any(|k| key_is(k)))
+}
+
+/// Unset actions for the keys gitid wrote into one repository's config
+fn plan_repo_keys(config: &Config, file: &Path, entries: &[(String, String)]) -> Vec<Action> {
+    let mut keys: Vec<&String> = entries
+        .iter()
+        .filter(|(key, value)| is_managed_key(config, key, value))
+        .map(|(key, _)| key)
+        .collect();
+    keys.dedup();
+    keys.into_iter()
+        .map(|key| Action::UnsetRepo {
+            config: file.to_path_buf(),
+            key: key.clone(),
+        })
+        .collect()
+}
+
+/// Work out everything that gitid has left on this machine. With `purge`,
+/// the config file and keyring tokens are removed as well.
+pub fn plan(config: &Config, purge: bool) -> Result<Vec<Action>> {
+    let mut actions = Vec::new();
+
+    plan_block(
+        &mut actions,
+        ssh::ssh_config_path()?,
+        ssh::MANAGED_START,
+        ssh::MANAGED_END,
+    );
+    let ssh_config = ssh::ssh_config_path()?;
+    if fs::read_to_string(&ssh_config).is_ok_and(|c| c.lines().any(|l| l.trim() == ssh::INCLUDE_LINE)) {
+        actions.push(Action::RemoveLine {
+            path: ssh_config,
+            line: ssh::INCLUDE_LINE,
+        });
+    }
+    let include = ssh::include_path()?;
+    if include.exists() {
+        actions.push(Action::RemoveFile(include));
+    }
+    plan_block(
+        &mut actions,
+        netrc::netrc_path()?,
+        netrc::MANAGED_START,
+        netrc::MANAGED_END,
+    );
+    plan_block(
+        &mut actions,
+        git::global_config_path()?,
+        gitconfig::MANAGED_START,
+        gitconfig::MANAGED_END,
+    );
+
+    plan_block(
+        &mut actions,
+        ssh::allowed_signers_path()?,
+        ssh::MANAGED_START,
+        ssh::MANAGED_END,
+    );
+
+    let fragments = gitconfig::fragments_dir()?;
+    if fragments.exists() {
+        actions.push(Action::RemoveDir(fragments));
+    }
+
+    for hook in hooks::installed_hooks()? {
+        actions.push(Action::RemoveFile(hook));
+    }
+
+    if let Some(cmd) = git::get_config("core.sshCommand", ConfigScope::Global)? {
+        if env::is_managed_ssh_command(&cmd) {
+            actions.push(Action::UnsetGlobal("core.sshCommand".to_string()));
+        }
+    }
+
+    let allowed_signers = ssh::allowed_signers_path()?.display().to_string();
+    let signers_key = git::get_config(ssh::ALLOWED_SIGNERS_KEY, ConfigScope::Global)?;
+    if signers_key.as_deref() == Some(allowed_signers.as_str()) {
+        actions.push(Action::UnsetGlobal(ssh::ALLOWED_SIGNERS_KEY.to_string()));
+    }
+
+    // insteadOf rules and GCM account selection (a global core.sshCommand
+    // is handled above)
+    for (key, value) in git::config_entries(r"^(url\..*\.insteadof|credential\..*)$", ConfigScope::Global)? {
+        if is_managed_key(config, &key, &value) {
+            actions.push(Action::UnsetGlobal(key));
+        }
+    }
+
+    // Set by `doctor --fix`
+    if git::get_config("user.useConfigOnly", ConfigScope::Global)?.as_deref() == Some("true") {
+        actions.push(Action::UnsetGlobal("user.useConfigOnly".to_string()));
+    }
+
+    for git_dir in known_repos()? {
+        let file = git_dir.join("config");
+        actions.extend(plan_repo_keys(config, &file, &git::file_config_entries(&file)?));
+
+        let cache_dir = git_dir.join("gitid");
+        if cache_dir.exists() {
+            actions.push(Action::RemoveDir(cache_dir));
+        }
+    }
+
+    let state_dir = Config::state_dir()?;
+    if state_dir.exists() {
+        actions.push(Action::RemoveDir(state_dir));
+    }
+
+    // Cached app tokens are state, not config, so they go even without --purge
+    for (name, profile) in &config.profiles {
+        if profile.github_app.is_some() && secrets::get_app_token(name).unwrap_or(None).is_some() {
+            actions.push(Action::DeleteAppToken(name.clone()));
+        }
+    }
+
+    if purge {
+        let mut accounts: Vec<&str> = config
+            .profiles
+            .iter()
+            .map(|(name, profile)| profile.keyring_account(name))
+            .collect();
+        accounts.sort();
+        accounts.dedup();
+        for account in accounts {
+            if secrets::get_token(account).unwrap_or(None).is_some() {
+                actions.push(Action::DeleteToken(account.to_string()));
+            }
+        }
+
+        let config_path = Config::config_path()?;
+        if config_path.exists() {
+            actions.push(Action::RemoveFile(config_path));
+        }
+    }
+
+    Ok(actions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::{Platform, Profile};
+
+    #[test]
+    fn test_plan_repo_keys() {
+        let mut config = Config::default();
+        let profile = Profile::new(
+            "John Doe".to_string(),
+            "john@acme.com".to_string(),
+            Platform::Gitlab,
+            "~/.ssh/id_work".to_string(),
+            None,
+            Some("gitlab.acme.com".to_string()),
+        );
+        let ssh_command = env::ssh_command(&profile);
+        config.profiles.insert("work".to_string(), profile);
+
+        let entries: Vec<(String, String)> = [
+            ("core.bare", "false".to_string()),
+            ("user.email", "john@acme.com".to_string()),
+            ("gitid.profile", "work".to_string()),
+            ("core.sshcommand", ssh_command),
+            ("url.git@gitlab-work:.insteadof", "git@gitlab.acme.com:".to_string()),
+            ("url.git@example:.insteadof", "git@example.com:".to_string()),
+            ("credential.https://gitlab.acme.com.provider", "gitlab".to_string()),
+            ("credential.helper", "manager".to_string()),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect();
+
+        let file = Path::new("/src/app/.git/config");
+        let described: Vec<String> = plan_repo_keys(&config, file, &entries)
+            .iter()
+            .map(Action::describe)
+            .collect();
+        assert_eq!(
+            described,
+            vec![
+                "Unset gitid.profile in /src/app/.git/config",
+                "Unset core.sshcommand in /src/app/.git/config",
+                "Unset url.git@gitlab-work:.insteadof in /src/app/.git/config",
+                "Unset credential.https://gitlab.acme.com.provider in /src/app/.git/config",
+            ]
+        );
+    }
+}
@@ -0,0 +1,218 @@
+use crate::config::Config;
+use crate::git::{self, ConfigScope};
+use crate::journal;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+/// Days without use after which `gitid stats` calls a profile stale
+pub const STALE_DAYS: u64 = 90;
+
+/// How often and how recently a profile was applied
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProfileUsage {
+    /// Times the profile was applied
+    pub count: u64,
+    /// When it was last applied
+    pub last_used: u64,
+}
+
+/// The last two profiles applied in one scope, for `gitid use -`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScopeHistory {
+    pub current: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous: Option<String>,
+}
+
+/// Usage of each profile, recorded whenever `use`, `detect --auto`, the
+/// hooks or the shell integration apply one, for `gitid stats` and
+/// `list --sort recent`
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Usage {
+    #[serde(default)]
+    pub profiles: BTreeMap<String, ProfileUsage>,
+    /// History keyed by `global` or the repository's common git directory
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub scopes: BTreeMap<String, ScopeHistory>,
+}
+
+impl Usage {
+    /// Get the usage file path
+    pub fn path() -> Result<PathBuf> {
+        Ok(Config::state_dir()?.join("usage.toml"))
+    }
+
+    /// Load usage, or none if no profile has been applied yet
+    pub fn load() -> Result<Self> {
+        let path = Self::path()?;
+        if !path.exists() {
+            return Ok(Usage::default());
+        }
+
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read usage: {}", path.display()))?;
+
+        toml::from_str(&content).with_context(|| format!("Failed to parse usage: {}", path.display()))
+    }
+
+    /// Save usage
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path()?;
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create state directory: {}", parent.display()))?;
+        }
+
+        let content = toml::to_string_pretty(self).context("Failed to serialize usage")?;
+
+        fs::write(&path, content).with_context(|| format!("Failed to write usage: {}", path.display()))
+    }
+
+    /// Count one use of `name` at `at`
+    pub fn add_use(&mut self, name: &str, at: u64) {
+        let usage = self.profiles.entry(name.to_string()).or_default();
+        usage.count += 1;
+        usage.last_used = usage.last_used.max(at);
+    }
+
+    /// Note that `name` is now applied in `scope`; re-applying the current
+    /// profile keeps the previous one
+    pub fn push_scope(&mut self, scope: &str, name: &str) {
+        match self.scopes.get_mut(scope) {
+            Some(history) if history.current == name => {}
+            Some(history) => {
+                history.previous = Some(std::mem::replace(&mut history.current, name.to_string()));
+            }
+            None => {
+                let history = ScopeHistory {
+                    current: name.to_string(),
+                    previous: None,
+                };
+                self.scopes.insert(scope.to_string(), history);
+            }
+        }
+    }
+
+    /// Profile applied in `scope` before the current one
+    pub fn previous(&self, scope: &str) -> Option<&str> {
+        self.scopes.get(scope)?.previous.as_deref()
+    }
+
+    /// When `name` was last applied, if ever
+    pub fn last_used(&self, name: &str) -> Option<u64> {
+        self.profiles.get(name).map(|u| u.last_used)
+    }
+
+    /// Whether `name` has not been applied in `STALE_DAYS` before `now`
+    pub fn is_stale(&self, name: &str, now: u64) -> bool {
+        self.last_used(name)
+            .is_none_or(|at| now.saturating_sub(at) > STALE_DAYS * 86400)
+    }
+
+    /// Sort profile names most recently used first; never-used profiles
+    /// keep their order at the end
+    pub fn sort_recent(&self, names: &mut [&String]) {
+        names.sort_by_key(|name| std::cmp::Reverse(self.last_used(name)));
+    }
+}
+
+/// Key of a scope's history: `global`, or the current repository's common
+/// git directory, shared by its worktrees like the local config is
+pub fn scope_key(scope: ConfigScope) -> Result<Option<String>> {
+    Ok(match scope {
+        ConfigScope::Global => Some("global".to_string()),
+        ConfigScope::Local => git::common_dir()?.map(|dir| dir.display().to_string()),
+    })
+}
+
+/// Record that `name` was just applied in `scope`
+pub fn record(name: &str, scope: ConfigScope) -> Result<()> {
+    let mut usage = Usage::load()?;
+    usage.add_use(name, journal::now());
+    if let Some(key) = scope_key(scope)? {
+        usage.push_scope(&key, name);
+    }
+    usage.save()
+}
+
+/// Profile applied in `scope` before the current one, for `gitid use -`
+pub fn previous_profile(scope: ConfigScope) -> Result<Option<String>> {
+    let Some(key) = scope_key(scope)? else {
+        return Ok(None);
+    };
+    Ok(Usage::load()?.previous(&key).map(str::to_string))
+}
+
+/// Carry a renamed profile's usage over to its new name
+pub fn rename(old: &str, new: &str) -> Result<()> {
+    let mut usage = Usage::load()?;
+    if let Some(entry) = usage.profiles.remove(old) {
+        usage.profiles.insert(new.to_string(), entry);
+    }
+    for history in usage.scopes.values_mut() {
+        if history.current == old {
+            history.current = new.to_string();
+        }
+        if history.previous.as_deref() == Some(old) {
+            history.previous = Some(new.to_string());
+        }
+    }
+    usage.save()
+}
+
+/// Drop a removed profile's usage
+pub fn forget(name: &str) -> Result<()> {
+    let mut usage = Usage::load()?;
+    usage.profiles.remove(name);
+    usage.scopes.retain(|_, history| history.current != name);
+    for history in usage.scopes.values_mut() {
+        if history.previous.as_deref() == Some(name) {
+            history.previous = None;
+        }
+    }
+    usage.save()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sort_recent() {
+        let mut usage = Usage::default();
+        usage.add_use("work", 100);
+        usage.add_use("oss", 300);
+        usage.add_use("work", 200);
+        assert_eq!(usage.profiles["work"], ProfileUsage { count: 2, last_used: 200 });
+
+        let (acme, oss, personal, work) =
+            ("acme".to_string(), "oss".to_string(), "personal".to_string(), "work".to_string());
+        let mut names = vec![&acme, &oss, &personal, &work];
+        usage.sort_recent(&mut names);
+        assert_eq!(names, vec!["oss", "work", "acme", "personal"]);
+
+        let later = 200 + STALE_DAYS * 86400 + 1;
+        assert!(usage.is_stale("work", later));
+        assert!(!usage.is_stale("oss", later));
+        assert!(usage.is_stale("acme", 0));
+    }
+
+    #[test]
+    fn test_push_scope() {
+        let mut usage = Usage::default();
+        usage.push_scope("global", "personal");
+        assert_eq!(usage.previous("global"), None);
+        usage.push_scope("global", "work");
+        usage.push_scope("global", "work");
+        assert_eq!(usage.previous("global"), Some("personal"));
+
+        // Switching back toggles between the two
+        usage.push_scope("global", "personal");
+        assert_eq!(usage.previous("global"), Some("work"));
+        assert_eq!(usage.previous("/src/app/.git"), None);
+    }
+}